@@ -0,0 +1,104 @@
+//! Compares encoding the public game state for each recipient of a play against encoding it once per play.
+//! The public state of each play is built before measuring, as the server builds it once per play either way.
+//! Encoding once still copies the bytes for each recipient, since every connection is handed a message it owns.
+//! Sending itself isn't measured. Run with `cargo bench -p palace_server --bench public_state`.
+
+use palace_server::ai::{self, PalaceAi};
+use palace_server::data::{GameStartEvent, PalaceOutMessage};
+use palace_server::game::{GameState, PublicGameState};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+   unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+      ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+      System.alloc(layout)
+   }
+
+   unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+      System.dealloc(ptr, layout)
+   }
+
+   unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+      ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+      System.realloc(ptr, layout, new_size)
+   }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const NUM_PLAYERS: u8 = 4;
+const NUM_SPECTATORS: usize = 4;
+const NUM_GAMES: usize = 200;
+
+/// Every position reached over some random games, so both strategies encode the same states
+fn positions() -> Vec<GameState> {
+   let mut positions = Vec::new();
+   for _ in 0..NUM_GAMES {
+      let mut gs = GameState::new(NUM_PLAYERS);
+      loop {
+         let mut ai = ai::random::new();
+         ai.on_game_start(GameStartEvent {
+            hand: gs.get_hand(gs.active_player),
+            turn_number: gs.active_player,
+            players: &HashMap::new(),
+         });
+         ai.on_game_state_update(&gs.public_state());
+         let play = ai::get_turn(&gs, &mut ai);
+         let done = gs.take_turn(&play).unwrap().game_over;
+         positions.push(gs.clone());
+         if done {
+            break;
+         }
+      }
+   }
+   positions
+}
+
+/// Returns the allocations and time taken per play
+fn measure(states: &[PublicGameState], encode: impl Fn(&PublicGameState) -> Vec<Vec<u8>>) -> (f64, Duration) {
+   let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+   let start = Instant::now();
+   let mut total_bytes = 0;
+   for public_gs in states {
+      total_bytes += encode(public_gs).iter().map(Vec::len).sum::<usize>();
+   }
+   let elapsed = start.elapsed();
+   let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+   assert!(total_bytes > 0);
+   (allocations as f64 / states.len() as f64, elapsed / states.len() as u32)
+}
+
+fn main() {
+   let positions = positions();
+   let states: Vec<PublicGameState> = positions.iter().map(GameState::public_state).collect();
+   let recipients = NUM_PLAYERS as usize + NUM_SPECTATORS;
+
+   let per_recipient = measure(&states, |public_gs| {
+      (0..recipients)
+         .map(|_| serde_json::to_vec(&PalaceOutMessage::PublicGameStateEvent(public_gs)).unwrap())
+         .collect()
+   });
+   // As the server does, keeping the encoded event and copying it out for each connection
+   let once_per_play = measure(&states, |public_gs| {
+      let kept = serde_json::to_vec(&PalaceOutMessage::PublicGameStateEvent(public_gs)).unwrap();
+      (0..recipients).map(|_| kept.as_slice().to_vec()).collect()
+   });
+
+   println!("{} plays, {} recipients each", states.len(), recipients);
+   println!(
+      "encoded per recipient: {:.1} allocations, {:?} per play",
+      per_recipient.0, per_recipient.1
+   );
+   println!(
+      "encoded once per play: {:.1} allocations, {:?} per play",
+      once_per_play.0, once_per_play.1
+   );
+}
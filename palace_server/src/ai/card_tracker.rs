@@ -0,0 +1,343 @@
+use crate::data::GameStartEvent;
+use crate::game::{self, Card, CardZone, Phase, PublicGameState};
+use std::collections::BTreeMap;
+
+pub fn remove_item<T: PartialEq>(v: &mut Vec<T>, item: &T) -> Option<T> {
+   let index = v.iter().position(|x| x == item);
+   index.map(|i| v.remove(i))
+}
+
+/// A card in someone's hand, as far as we can tell
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrackedCard {
+   Known(Card),
+   Unknown,
+}
+
+impl From<Card> for TrackedCard {
+   fn from(c: Card) -> TrackedCard {
+      TrackedCard::Known(c)
+   }
+}
+
+/// Card counting for AIs, from what a player can see: every card we know is in someone's hand,
+/// every face up card, the pile, and how many of each card haven't been seen yet.
+/// Feed it every game start and state update the AI receives.
+#[derive(Debug)]
+pub struct CardTracker {
+   /// Indexed by turn number
+   pub hands: Vec<Vec<TrackedCard>>,
+   pub face_up: Vec<Vec<Card>>,
+   pub face_down: Vec<u8>,
+   pub pile: Vec<Card>,
+   pub turn_number: u8,
+   pub cur_phase: Phase,
+   /// Players who gave up, and so take no more turns
+   pub conceded: Vec<u8>,
+   /// Ordered, so that a seeded AI guesses at the hidden cards the same way every time
+   unseen: BTreeMap<Card, usize>,
+   last_player: u8,
+   last_phase: Option<Phase>,
+}
+
+impl CardTracker {
+   pub fn new() -> CardTracker {
+      CardTracker {
+         hands: vec![],
+         face_up: vec![],
+         face_down: vec![],
+         pile: vec![],
+         turn_number: 0,
+         cur_phase: Phase::Setup,
+         conceded: Vec::new(),
+         unseen: BTreeMap::new(),
+         last_player: 0,
+         last_phase: None,
+      }
+   }
+
+   fn see(&mut self, card: &Card) {
+      let num_unseen = self.unseen.get_mut(card).unwrap();
+      debug_assert!(*num_unseen > 0);
+      *num_unseen -= 1;
+   }
+
+   /// Every card we haven't seen, with duplicates
+   pub fn unseen_cards(&self) -> Vec<Card> {
+      self
+         .unseen
+         .iter()
+         .flat_map(|(k, v)| std::iter::repeat_n(*k, *v))
+         .collect()
+   }
+
+   pub fn num_unseen(&self) -> usize {
+      self.unseen.values().sum()
+   }
+
+   pub fn on_game_start(&mut self, game_start_event: &GameStartEvent) {
+      let num_players = game_start_event.players.len();
+
+      for card in game::new_deck(num_players) {
+         *self.unseen.entry(card).or_insert(0) += 1;
+      }
+      self.hands.reserve_exact(num_players);
+      self.face_up.reserve_exact(num_players);
+      for _ in 0..num_players {
+         self.hands.push(vec![TrackedCard::Unknown; crate::game::HAND_SIZE]);
+         self.face_up.push(Vec::with_capacity(3));
+      }
+      self.turn_number = game_start_event.turn_number;
+      for card in game_start_event.hand {
+         self.see(card);
+      }
+      let my_hand = &mut self.hands[self.turn_number as usize];
+      my_hand.clear();
+      my_hand.extend(game_start_event.hand.iter().map(|x| TrackedCard::from(*x)));
+   }
+
+   pub fn on_game_state_update(&mut self, new_state: &PublicGameState) {
+      self.face_down.clear();
+      self.face_down.extend_from_slice(&new_state.face_down_three);
+
+      if self.last_phase.is_none() {
+         for i in 0..new_state.face_up_three.len() {
+            self.hands[i].extend(new_state.face_up_three[i].iter().map(|x| TrackedCard::from(*x)));
+            for card in new_state.face_up_three[i] {
+               self.see(card);
+            }
+         }
+         self.last_phase = Some(Phase::Setup)
+      } else if self.last_phase == Some(Phase::Setup) {
+         let i = self.last_player as usize;
+         let new_fup3 = &new_state.face_up_three[i];
+         self.face_up[i].extend_from_slice(new_fup3);
+         for card in new_fup3.iter() {
+            let remove_result = remove_item(&mut self.hands[i], &(*card).into());
+            if remove_result.is_none() {
+               remove_item(&mut self.hands[i], &TrackedCard::Unknown);
+               self.see(card);
+            }
+         }
+         if new_state.cur_phase == Phase::Play {
+            self.last_phase = Some(Phase::Play);
+            self.cur_phase = Phase::Play;
+         }
+      }
+
+      // update pile based on cards played
+      self.pile.extend_from_slice(new_state.last_cards_played);
+
+      let last_player = self.last_player as usize;
+
+      // update hand based on cards played
+      match new_state.last_played_zone {
+         Some(CardZone::Hand) => {
+            for card in new_state.last_cards_played {
+               let remove_result = remove_item(&mut self.hands[last_player], &(*card).into());
+               if remove_result.is_none() {
+                  remove_item(&mut self.hands[last_player], &TrackedCard::Unknown).unwrap();
+                  self.see(card);
+               }
+            }
+         }
+         Some(CardZone::FaceUpThree) => {
+            for card in new_state.last_cards_played {
+               remove_item(&mut self.face_up[last_player], card).unwrap();
+            }
+         }
+         Some(CardZone::FaceDownThree) => {
+            debug_assert_eq!(new_state.last_cards_played.len(), 1);
+            for card in new_state.last_cards_played {
+               self.see(card);
+            }
+         }
+         None => (),
+      }
+
+      // If pile got picked up, update hand
+      if new_state.pile_size == 0 {
+         // update hand of last player to include all of the pile cards
+         // IF they did not clear it
+         if self.last_player != new_state.active_player {
+            let pile = std::mem::take(&mut self.pile);
+            self.hands[last_player].extend(pile.iter().map(|x| TrackedCard::from(*x)));
+         }
+         self.pile.clear();
+      }
+
+      self.last_player = new_state.active_player;
+   }
+
+   /// Start tracking a game that is already underway. Anything we would have seen opponents pick up is lost,
+   /// so their hands are unknown
+   pub fn on_player_conceded(&mut self, slot: u8) {
+      self.conceded.push(slot);
+   }
+
+   pub fn assume_position(
+      &mut self,
+      game_start_event: &GameStartEvent,
+      state: &PublicGameState,
+      pile: &[Card],
+      cleared: &[Card],
+   ) {
+      self.on_game_start(game_start_event);
+      let me = self.turn_number as usize;
+      for i in 0..state.hands.len() {
+         if i != me {
+            self.hands[i] = vec![TrackedCard::Unknown; state.hands[i] as usize];
+         }
+         for card in state.face_up_three[i] {
+            self.see(card);
+         }
+         // during setup, face up cards are still part of the hand until the player has chosen
+         let has_chosen = state.cur_phase == Phase::Play || i < state.active_player as usize;
+         if has_chosen {
+            self.face_up[i] = state.face_up_three[i].to_vec();
+         } else {
+            self.hands[i].extend(state.face_up_three[i].iter().map(|x| TrackedCard::from(*x)));
+         }
+      }
+      self.face_down.clear();
+      self.face_down.extend_from_slice(&state.face_down_three);
+      // Cleared cards are out of the game, so they must never be dealt to anyone when guessing at hidden cards
+      for card in pile.iter().chain(cleared) {
+         self.see(card);
+      }
+      self.pile = pile.to_vec();
+      self.cur_phase = state.cur_phase;
+      self.last_phase = Some(state.cur_phase);
+      self.last_player = state.active_player;
+   }
+}
+
+impl Default for CardTracker {
+   fn default() -> CardTracker {
+      CardTracker::new()
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::data::PlayerProfile;
+
+   #[cfg(test)]
+   fn unaccounted_for(tracker: &CardTracker, num_players: usize) -> usize {
+      let num_undealt = game::new_deck(num_players).count() - num_players * (game::HAND_SIZE + 6);
+      let num_unknown = tracker
+         .hands
+         .iter()
+         .flatten()
+         .filter(|x| **x == TrackedCard::Unknown)
+         .count();
+      let num_facedown: usize = tracker.face_down.iter().map(|x| *x as usize).sum();
+      num_unknown + num_facedown + num_undealt
+   }
+
+   #[cfg(test)]
+   fn known_hand(tracker: &CardTracker, player: usize) -> Vec<Card> {
+      let mut hand: Vec<Card> = tracker.hands[player]
+         .iter()
+         .filter_map(|x| match x {
+            TrackedCard::Known(c) => Some(*c),
+            TrackedCard::Unknown => None,
+         })
+         .collect();
+      hand.sort_unstable();
+      hand
+   }
+
+   #[test]
+   fn sees_own_hand_and_face_up_cards() {
+      let gs = game::GameState::new(2);
+      let players = (0..2).map(|x| (x, PlayerProfile::new(x.to_string(), true))).collect();
+      let mut tracker = CardTracker::new();
+      tracker.on_game_start(&GameStartEvent {
+         hand: gs.get_hand(0),
+         turn_number: 0,
+         players: &players,
+      });
+      tracker.on_game_state_update(&gs.public_state());
+
+      let num_seen = game::HAND_SIZE + 2 * 3;
+      assert_eq!(tracker.num_unseen(), game::new_deck(2).count() - num_seen);
+      assert_eq!(tracker.unseen_cards().len(), tracker.num_unseen());
+      assert_eq!(tracker.num_unseen(), unaccounted_for(&tracker, 2));
+   }
+
+   #[test]
+   fn follows_plays_and_pickups() {
+      let mut gs = game::GameState::new(2);
+      let players = (0..2).map(|x| (x, PlayerProfile::new(x.to_string(), true))).collect();
+      let mut tracker = CardTracker::new();
+      tracker.on_game_start(&GameStartEvent {
+         hand: gs.get_hand(0),
+         turn_number: 0,
+         players: &players,
+      });
+      tracker.on_game_state_update(&gs.public_state());
+
+      let mut saw_pickup = false;
+      for _ in 0..500 {
+         let p = gs.active_player as usize;
+         let zone = if gs.hands[p].is_empty() {
+            &gs.face_up_three[p]
+         } else {
+            &gs.hands[p]
+         };
+         let cards = match gs.cur_phase {
+            Phase::Setup => zone[..3].to_vec(),
+            // prefer a card that has to be picked up
+            Phase::Play => zone
+               .iter()
+               .find(|x| !game::is_playable_without_pickup(x.value, &gs.pile_cards))
+               .or_else(|| zone.first())
+               .map(|x| vec![*x])
+               .unwrap_or_default(),
+         };
+         let outcome = gs.take_turn(&cards).unwrap();
+         saw_pickup |= outcome.picked_up;
+         tracker.on_game_state_update(&gs.public_state());
+
+         assert_eq!(tracker.num_unseen(), unaccounted_for(&tracker, 2));
+         if gs.cur_phase == Phase::Setup {
+            // face up cards are still part of the hand until everyone has chosen
+            continue;
+         }
+         assert_eq!(known_hand(&tracker, 0), gs.hands[0]);
+         assert_eq!(tracker.hands[1].len(), gs.hands[1].len());
+         assert_eq!(tracker.face_up, gs.face_up_three.to_vec());
+         assert_eq!(tracker.pile, gs.pile_cards);
+         if outcome.game_over {
+            break;
+         }
+      }
+      assert!(saw_pickup);
+   }
+   #[test]
+   fn assumed_position_has_seen_cleared_cards() {
+      let mut gs = game::GameState::new(2);
+      gs.cur_phase = Phase::Play;
+      // as if the second player had played these and cleared the pile
+      let burned = gs.hands[1].split_off(gs.hands[1].len() - 2);
+      gs.cleared_cards.extend_from_slice(&burned);
+      let players = (0..2).map(|x| (x, PlayerProfile::new(x.to_string(), true))).collect();
+      let mut tracker = CardTracker::new();
+      tracker.assume_position(
+         &GameStartEvent {
+            hand: gs.get_hand(0),
+            turn_number: 0,
+            players: &players,
+         },
+         &gs.public_state(),
+         &gs.pile_cards,
+         &gs.cleared_cards,
+      );
+
+      assert_eq!(tracker.num_unseen(), unaccounted_for(&tracker, 2));
+      assert!(tracker.unseen_cards().iter().all(|x| !burned.contains(x)));
+   }
+}
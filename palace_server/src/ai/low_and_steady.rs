@@ -96,4 +96,17 @@ impl PalaceAi for LowAndSteadyAi {
       self.hand.extend_from_slice(new_hand);
       sort_zone_low_to_high_specials_in_back(&mut self.hand);
    }
+
+   fn assume_position(
+      &mut self,
+      game_start_event: GameStartEvent,
+      state: &PublicGameState,
+      pile: &[Card],
+      _cleared: &[Card],
+   ) {
+      self.on_game_start(game_start_event);
+      self.on_game_state_update(state);
+      self.cur_pile.clear();
+      self.cur_pile.extend_from_slice(pile);
+   }
 }
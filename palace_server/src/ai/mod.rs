@@ -1,13 +1,23 @@
-use crate::data::GameStartEvent;
-use crate::game::{Card, GameState, Phase, PublicGameState};
+use crate::data::{GameStartEvent, PlayerProfile};
+use crate::game::{Card, CardZone, GameState, Phase, PublicGameState};
 use rand::seq::SliceRandom;
-use rand::{self, thread_rng, Rng};
+use rand::{self, thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(feature = "neural")]
+use std::sync::Arc;
 
 use lazy_static::lazy_static;
 
+pub mod card_tracker;
 pub mod low_and_steady;
 pub mod monty;
+#[cfg(feature = "neural")]
+pub mod neural;
 pub mod random;
+pub mod scripted;
 
 lazy_static! {
    static ref NAMES: Vec<&'static str> = include_str!("names.txt").lines().collect();
@@ -20,6 +30,92 @@ static LETTERS: [char; 26] = [
    'X', 'Y', 'Z',
 ];
 
+/// The strategies that can be requested to fill a seat
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub enum AiStrategy {
+   #[default]
+   Monty,
+   LowAndSteady,
+   Random,
+   #[cfg(feature = "neural")]
+   Neural,
+}
+
+/// Tuning for a requested Monty AI, trading strength for speed
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct MontyParameters {
+   /// Games simulated for each decision, in `MIN_MONTY_SIMULATIONS..=MAX_MONTY_SIMULATIONS`
+   pub simulations: u16,
+   /// How many of those simulations share each guess at the cards the AI can't see. At least 1
+   pub simulations_per_determinization: u16,
+}
+
+pub const MIN_MONTY_SIMULATIONS: u16 = 10;
+pub const MAX_MONTY_SIMULATIONS: u16 = 5000;
+
+/// Ratings are on the Elo scale. Lobbies rated below this get clandestine bots that play at random
+const LOW_AND_STEADY_RATING: u32 = 1200;
+/// Lobbies rated at least this get Monty, thinking harder the higher the rating
+const MONTY_RATING: u32 = 1500;
+/// Where Monty reaches its usual number of simulations
+const FULL_MONTY_RATING: u32 = 2100;
+
+impl MontyParameters {
+   pub fn is_valid(self) -> bool {
+      (MIN_MONTY_SIMULATIONS..=MAX_MONTY_SIMULATIONS).contains(&self.simulations)
+         && (1..=self.simulations).contains(&self.simulations_per_determinization)
+   }
+}
+
+/// Anything that is expensive to load and shared between every AI of a strategy
+#[derive(Clone, Default)]
+pub struct AiResources {
+   #[cfg(feature = "neural")]
+   pub policy_model: Option<Arc<neural::PolicyModel>>,
+}
+
+impl AiStrategy {
+   /// Like `instantiate`, but Monty AIs are tuned by `monty` if given
+   pub fn instantiate_tuned(
+      self,
+      resources: &AiResources,
+      monty: Option<MontyParameters>,
+   ) -> Option<Box<dyn PalaceAi + Send + Sync>> {
+      match (self, monty) {
+         (AiStrategy::Monty, Some(monty)) => Some(Box::new(monty::with_parameters(
+            monty::DEFAULT_EXPLORATION_VAL,
+            monty.simulations as usize,
+            monty.simulations_per_determinization as usize,
+            monty::RolloutPolicy::Random,
+         ))),
+         _ => self.instantiate(resources),
+      }
+   }
+
+   /// Returns None if the strategy's resources aren't loaded
+   #[cfg_attr(not(feature = "neural"), allow(unused_variables))]
+   pub fn instantiate(self, resources: &AiResources) -> Option<Box<dyn PalaceAi + Send + Sync>> {
+      match self {
+         AiStrategy::Monty => Some(Box::new(monty::new())),
+         AiStrategy::LowAndSteady => Some(Box::new(low_and_steady::new())),
+         AiStrategy::Random => Some(Box::new(random::new())),
+         #[cfg(feature = "neural")]
+         AiStrategy::Neural => resources
+            .policy_model
+            .as_ref()
+            .map(|model| -> Box<dyn PalaceAi + Send + Sync> { Box::new(neural::new(model.clone())) }),
+      }
+   }
+}
+
+/// A move that an AI considered, and how promising it looked
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct CandidateMove {
+   pub cards: Box<[Card]>,
+   pub visits: u64,
+   pub win_rate: f64,
+}
+
 pub trait PalaceAi {
    fn strategy_name(&self) -> &'static str;
 
@@ -32,6 +128,81 @@ pub trait PalaceAi {
    fn on_game_start(&mut self, _game_start_event: GameStartEvent) {}
 
    fn on_hand_update(&mut self, _new_hand: &[Card]) {}
+
+   /// Called when another player makes a play, before the resulting state update. `picked_up` is true
+   /// if the cards couldn't go on the pile, so the player took the whole pile into their hand
+   fn on_opponent_play(&mut self, _slot: u8, _cards: &[Card], _zone: CardZone, _picked_up: bool) {}
+
+   /// Called when it becomes this AI's turn, after the state update for the play before
+   fn on_your_turn(&mut self) {}
+
+   /// Called when another player concedes, before the resulting state update
+   fn on_player_conceded(&mut self, _slot: u8) {}
+
+   /// Checked before each of this AI's plays. Conceding takes the AI out of the game, placed last among those left
+   fn should_concede(&self) -> bool {
+      false
+   }
+
+   /// How hard to think, from 1 for as configured down to 0 for as little as possible. Lowered while the server is
+   /// overloaded
+   fn set_strength(&mut self, _strength: f64) {}
+
+   /// Makes every choice left to chance from here on follow from `seed`, so that the AI plays the same game the
+   /// same way every time. Strategies that never choose at random can ignore this
+   fn reseed(&mut self, _seed: u64) {}
+
+   /// Take over a seat in a game that is already underway. `pile` is every card currently on the pile,
+   /// and `cleared` every card that has been cleared from it.
+   /// By default this replays the start of the game followed by the current state,
+   /// which is only enough for strategies that don't track the pile.
+   fn assume_position(
+      &mut self,
+      game_start_event: GameStartEvent,
+      state: &PublicGameState,
+      _pile: &[Card],
+      _cleared: &[Card],
+   ) {
+      self.on_game_start(game_start_event);
+      self.on_game_state_update(state);
+   }
+
+   /// The moves considered for the most recent decision, best first, if the strategy keeps track of them
+   fn decision_explanation(&self) -> Option<&[CandidateMove]> {
+      None
+   }
+}
+
+/// What an AI chooses with until it is reseeded
+fn unseeded_rng() -> ChaChaRng {
+   ChaChaRng::seed_from_u64(rand::random())
+}
+
+/// The seed for an AI taking over `slot` at this point in the game, so that each seat, and each AI to sit in it,
+/// chooses differently
+pub(crate) fn seat_seed(gs: &GameState, slot: u8) -> u64 {
+   gs.seed.wrapping_add(u64::from(gs.turns_taken) << 8 | u64::from(slot))
+}
+
+/// A clandestine bot to match humans whose ratings average `rating`. Random if none of them are rated
+pub(crate) fn clandestine_bot_for(rating: Option<u32>) -> Box<dyn PalaceAi + Send + Sync> {
+   match rating {
+      Some(rating) if rating >= MONTY_RATING => Box::new(monty::with_parameters(
+         monty::DEFAULT_EXPLORATION_VAL,
+         clandestine_monty_simulations(rating),
+         1,
+         monty::RolloutPolicy::Random,
+      )),
+      Some(rating) if rating >= LOW_AND_STEADY_RATING => Box::new(low_and_steady::new()),
+      _ => Box::new(random::new()),
+   }
+}
+
+/// From the fewest simulations Monty can be asked for at `MONTY_RATING`, up to its usual number
+fn clandestine_monty_simulations(rating: u32) -> usize {
+   let min = usize::from(MIN_MONTY_SIMULATIONS);
+   let above = (rating.min(FULL_MONTY_RATING) - MONTY_RATING) as usize;
+   min + (monty::DEFAULT_NUM_SIMS - min) * above / (FULL_MONTY_RATING - MONTY_RATING) as usize
 }
 
 pub(crate) fn get_bot_name() -> String {
@@ -78,6 +249,27 @@ pub(crate) fn get_bot_name_clandestine() -> String {
    name
 }
 
+/// Puts `ai` in `slot` of a game underway, knowing only what that player would. Unlike when seating an AI in a
+/// lobby, the other players are nameless
+pub fn assume_seat(ai: &mut dyn PalaceAi, gs: &GameState, slot: u8) {
+   let players: HashMap<u8, PlayerProfile> = (0..gs.num_players)
+      .map(|x| (x, PlayerProfile::new(String::new(), false)))
+      .collect();
+   ai.assume_position(
+      GameStartEvent {
+         hand: gs.get_hand(slot),
+         turn_number: slot,
+         players: &players,
+      },
+      &gs.public_state(),
+      &gs.pile_cards,
+      &gs.cleared_cards,
+   );
+   for player in gs.conceded_players.iter() {
+      ai.on_player_conceded(*player);
+   }
+}
+
 pub fn get_turn(gs: &GameState, ai_core: &mut (dyn PalaceAi + Send + Sync)) -> Box<[Card]> {
    if gs.hands[gs.active_player as usize].is_empty() && gs.face_up_three[gs.active_player as usize].is_empty() {
       vec![].into_boxed_slice()
@@ -88,3 +280,37 @@ pub fn get_turn(gs: &GameState, ai_core: &mut (dyn PalaceAi + Send + Sync)) -> B
       }
    }
 }
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn monty_parameters_are_bounded() {
+      let parameters = |simulations, simulations_per_determinization| MontyParameters {
+         simulations,
+         simulations_per_determinization,
+      };
+      assert!(parameters(1000, 1).is_valid());
+      assert!(parameters(1000, 1000).is_valid());
+      assert!(!parameters(1000, 0).is_valid());
+      assert!(!parameters(1000, 1001).is_valid());
+      assert!(!parameters(MIN_MONTY_SIMULATIONS - 1, 1).is_valid());
+      assert!(!parameters(MAX_MONTY_SIMULATIONS + 1, 1).is_valid());
+   }
+
+   #[test]
+   fn clandestine_bots_match_the_lobby_rating() {
+      let strategy = |rating| clandestine_bot_for(rating).strategy_name();
+      assert_eq!(strategy(None), random::new().strategy_name());
+      assert_eq!(strategy(Some(1000)), random::new().strategy_name());
+      assert_eq!(strategy(Some(1300)), low_and_steady::new().strategy_name());
+      assert_eq!(strategy(Some(1800)), monty::new().strategy_name());
+
+      let (min, full) = (usize::from(MIN_MONTY_SIMULATIONS), monty::DEFAULT_NUM_SIMS);
+      assert_eq!(clandestine_monty_simulations(MONTY_RATING), min);
+      assert_eq!(clandestine_monty_simulations(1800), 505);
+      assert_eq!(clandestine_monty_simulations(FULL_MONTY_RATING), full);
+      assert_eq!(clandestine_monty_simulations(3000), full);
+   }
+}
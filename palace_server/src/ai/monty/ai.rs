@@ -1,28 +1,33 @@
 // This AI plays random cards
 use super::multivec::MultiVec;
-use crate::ai::PalaceAi;
+use crate::ai::card_tracker::{CardTracker, TrackedCard};
+use crate::ai::{CandidateMove, PalaceAi};
 use crate::data::GameStartEvent;
-use crate::game::{self, Card, CardZone, Phase, PublicGameState};
-use crate::monte_game;
+use crate::game::{is_playable_without_pickup, Card, CardValue, Phase, PublicGameState};
+use crate::monte_game::{self, PackedCard};
 use noisy_float::prelude::*;
 use rand::seq::SliceRandom;
-use rand::{self, thread_rng};
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use serde_derive::{Deserialize, Serialize};
+use std::ops::{Index, IndexMut, Range};
 
-pub fn remove_item<T: PartialEq>(v: &mut Vec<T>, item: &T) -> Option<T> {
-   let index = v.iter().position(|x| x == item);
-   index.map(|i| v.remove(i))
-}
+/// How many of the most visited moves are kept to explain a decision
+const MAX_EXPLAINED_CANDIDATES: usize = 5;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum MontyCard {
-   Known(Card),
-   Unknown,
-}
+/// Monty gives up when the move it chose won less often than this in the search
+const CONCEDE_WIN_RATE: f64 = 0.01;
+
+/// Searches with fewer simulations behind the chosen move are too noisy to give up on
+const CONCEDE_MIN_VISITS: u64 = 100;
+
+/// Upper bound on the face up choices explored during Setup, so that large hands don't explode the tree
+const MAX_SETUP_MOVES: usize = 20;
 
 #[derive(Debug)]
 struct Node {
-   last_move: Option<Box<[Card]>>,
+   /// Where this node's move is in `Tree::move_cards`. Empty for the root
+   last_move: Range<usize>,
    last_player: u8,
    parent: usize,
    simulations: u64,
@@ -30,131 +35,198 @@ struct Node {
    children: Vec<usize>,
 }
 
-fn ucb1(exploration_val: f64, node: &Node, parent_simulations: u64) -> f64 {
-   (node.wins as f64 / node.simulations as f64)
-      + exploration_val * ((parent_simulations as f64).ln() / node.simulations as f64).sqrt()
+/// The search tree. Like `MultiVec`, resetting it keeps every allocation around for the next search
+struct Tree {
+   len: usize,
+   nodes: Vec<Node>,
+   /// Every node's move, back to back
+   move_cards: Vec<PackedCard>,
 }
 
-impl From<Card> for MontyCard {
-   fn from(c: Card) -> MontyCard {
-      MontyCard::Known(c)
+impl Tree {
+   fn new() -> Tree {
+      Tree {
+         len: 0,
+         nodes: Vec::new(),
+         move_cards: Vec::new(),
+      }
+   }
+
+   /// Leaves only a fresh root
+   fn reset(&mut self) {
+      self.len = 0;
+      self.move_cards.clear();
+      self.push(0, 0, &[]);
+   }
+
+   fn len(&self) -> usize {
+      self.len
+   }
+
+   fn push(&mut self, parent: usize, last_player: u8, last_move: &[PackedCard]) {
+      let start = self.move_cards.len();
+      self.move_cards.extend_from_slice(last_move);
+      let node = Node {
+         last_move: start..self.move_cards.len(),
+         last_player,
+         parent,
+         simulations: 0,
+         wins: 0,
+         children: Vec::new(),
+      };
+      if self.len < self.nodes.len() {
+         let old_node = std::mem::replace(&mut self.nodes[self.len], node);
+         let mut children = old_node.children;
+         children.clear();
+         self.nodes[self.len].children = children;
+      } else {
+         self.nodes.push(node);
+      }
+      self.len += 1;
+   }
+
+   fn last_move(&self, node: usize) -> &[PackedCard] {
+      &self.move_cards[self.nodes[node].last_move.clone()]
    }
 }
 
-#[derive(Debug)]
-struct InformationSet {
-   everyone_faceup_cards: Vec<Vec<Card>>,
-   everyone_hands: Vec<Vec<MontyCard>>,
-   everyone_facedown_cards: Vec<u8>,
-   cur_pile: Vec<Card>,
-   turn_number: u8,
-   cur_phase: Phase,
+impl Index<usize> for Tree {
+   type Output = Node;
+
+   fn index(&self, node: usize) -> &Node {
+      &self.nodes[..self.len][node]
+   }
 }
 
-impl InformationSet {
-   fn new() -> InformationSet {
-      InformationSet {
-         everyone_faceup_cards: vec![],
-         everyone_hands: vec![],
-         everyone_facedown_cards: vec![],
-         cur_pile: vec![],
-         turn_number: 0,
-         cur_phase: Phase::Setup,
-      }
+impl IndexMut<usize> for Tree {
+   fn index_mut(&mut self, node: usize) -> &mut Node {
+      &mut self.nodes[..self.len][node]
    }
+}
 
-   fn determine(&self, unseen_cards: &mut [Card]) -> monte_game::GameState {
-      unseen_cards.shuffle(&mut thread_rng());
-      let mut unseen_i = 0;
+fn ucb1(exploration_val: f64, node: &Node, parent_simulations: u64) -> f64 {
+   (node.wins as f64 / node.simulations as f64)
+      + exploration_val * ((parent_simulations as f64).ln() / node.simulations as f64).sqrt()
+}
 
-      let num_players = self.everyone_hands.len();
+/// Deal the cards we haven't seen into every unknown position
+fn determine(tracker: &CardTracker, unseen_cards: &mut [PackedCard], rng: &mut impl Rng) -> monte_game::GameState {
+   unseen_cards.shuffle(rng);
+   let mut unseen_i = 0;
 
-      // replace all unknown cards with unseen cards
+   let num_players = tracker.hands.len();
 
-      // first in hand
-      let mut determined_hands = Vec::with_capacity(num_players);
+   // replace all unknown cards with unseen cards
 
-      for known_hand in self.everyone_hands.iter() {
-         let mut determined_hand = Vec::with_capacity(known_hand.len());
-         for card in known_hand {
-            let determined_card = match card {
-               MontyCard::Known(c) => *c,
-               MontyCard::Unknown => {
-                  let c = unseen_cards[unseen_i];
-                  unseen_i += 1;
-                  c
-               }
-            };
-            determined_hand.push(determined_card)
-         }
-         determined_hand.sort_unstable();
-         determined_hands.push(determined_hand);
+   // first in hand
+   let mut determined_hands = Vec::with_capacity(num_players);
+
+   for known_hand in tracker.hands.iter() {
+      let mut determined_hand = monte_game::Hand::with_capacity(known_hand.len());
+      for card in known_hand {
+         let determined_card = match card {
+            TrackedCard::Known(c) => PackedCard::from(*c),
+            TrackedCard::Unknown => {
+               let c = unseen_cards[unseen_i];
+               unseen_i += 1;
+               c
+            }
+         };
+         determined_hand.push(determined_card)
       }
+      determined_hand.sort_unstable();
+      determined_hands.push(determined_hand);
+   }
 
-      // then, face down cards
-      let mut determined_fdt = Vec::with_capacity(num_players);
+   // then, face down cards
+   let mut determined_fdt = Vec::with_capacity(num_players);
 
-      for len in self.everyone_facedown_cards.iter().copied() {
-         let mut a_determined_fdt = Vec::with_capacity(len as usize);
-         for _ in 0..len {
-            a_determined_fdt.push(unseen_cards[unseen_i]);
-            unseen_i += 1;
-         }
-         determined_fdt.push(a_determined_fdt);
+   for len in tracker.face_down.iter().copied() {
+      let mut a_determined_fdt = monte_game::ThreeCards::new();
+      for _ in 0..len {
+         a_determined_fdt.push(unseen_cards[unseen_i]);
+         unseen_i += 1;
       }
+      determined_fdt.push(a_determined_fdt);
+   }
 
-      monte_game::GameState {
-         active_player: self.turn_number,
-         num_players: num_players as u8,
-         hands: determined_hands.into_boxed_slice(),
-         face_up_three: self.everyone_faceup_cards.clone().into_boxed_slice(),
-         face_down_three: determined_fdt.into_boxed_slice(),
-         pile_cards: self.cur_pile.clone(),
-         cur_phase: self.cur_phase,
-         out_players: vec![],
-      }
+   monte_game::GameState {
+      active_player: tracker.turn_number,
+      num_players: num_players as u8,
+      hands: determined_hands.into_boxed_slice(),
+      face_up_three: tracker
+         .face_up
+         .iter()
+         .map(|x| x.iter().copied().map(PackedCard::from).collect())
+         .collect(),
+      face_down_three: determined_fdt.into_boxed_slice(),
+      pile_cards: tracker.pile.iter().copied().map(PackedCard::from).collect(),
+      cur_phase: tracker.cur_phase,
+      out_players: tracker.conceded.iter().copied().collect(),
    }
 }
 
+/// How moves are chosen when playing out a simulated game past the edge of the search tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RolloutPolicy {
+   /// Any legal move
+   Random,
+   /// The lowest cards that can be played without picking up, holding back Tens, Twos and Fours. Setup is random
+   LowAndSteady,
+}
+
+pub const DEFAULT_EXPLORATION_VAL: f64 = 0.7;
+pub const DEFAULT_NUM_SIMS: usize = 1000;
+
 pub struct MontyAi {
-   information_set: InformationSet,
-   last_player: u8,
-   unseen_cards: HashMap<Card, usize>,
-   last_phase: Option<Phase>,
+   tracker: CardTracker,
    exploration_val: f64,
    num_sims: usize,
+   /// How many simulations share each guess at the hidden cards
+   sims_per_determinization: usize,
+   rollout_policy: RolloutPolicy,
+   /// Share of `num_sims` to run. With too few to search, moves are chosen like LowAndSteady rollouts instead
+   strength: f64,
+   last_candidates: Vec<CandidateMove>,
+   tree: Tree,
+   moves: MultiVec<PackedCard>,
+   rng: ChaChaRng,
 }
 
 pub fn new() -> MontyAi {
-   MontyAi {
-      information_set: InformationSet::new(),
-      last_player: 0,
-      unseen_cards: HashMap::new(),
-      last_phase: None,
-      exploration_val: 0.7,
-      num_sims: 1000,
-   }
+   with_parameters(DEFAULT_EXPLORATION_VAL, DEFAULT_NUM_SIMS, 1, RolloutPolicy::Random)
 }
 
-pub fn with_parameters(exploration_val: f64, num_sims: usize) -> MontyAi {
+/// Guessing the hidden cards once per simulation explores the most possibilities, while sharing guesses between
+/// simulations searches each guess more deeply
+pub fn with_parameters(
+   exploration_val: f64,
+   num_sims: usize,
+   sims_per_determinization: usize,
+   rollout_policy: RolloutPolicy,
+) -> MontyAi {
    MontyAi {
-      information_set: InformationSet::new(),
-      last_player: 0,
-      unseen_cards: HashMap::new(),
-      last_phase: None,
+      tracker: CardTracker::new(),
       exploration_val,
       num_sims,
+      sims_per_determinization: sims_per_determinization.max(1),
+      rollout_policy,
+      strength: 1.0,
+      last_candidates: Vec::new(),
+      tree: Tree::new(),
+      moves: MultiVec::new(),
+      rng: crate::ai::unseeded_rng(),
    }
 }
 
 /// relies on zone being sorted
-fn all_moves_zone(zone: &[Card], v: &mut MultiVec<Card>) {
+fn all_moves_zone(zone: &[PackedCard], v: &mut MultiVec<PackedCard>) {
    let mut window_size: usize = 1;
    let mut found_window_at_size = true;
    while found_window_at_size {
       found_window_at_size = false;
       for window in zone.windows(window_size) {
-         if window.iter().any(|x| x.value != window[0].value) {
+         if window.iter().any(|x| x.value() != window[0].value()) {
             continue;
          }
          v.add_items(window);
@@ -164,16 +236,35 @@ fn all_moves_zone(zone: &[Card], v: &mut MultiVec<Card>) {
    }
 }
 
-fn all_moves(g: &monte_game::GameState, v: &mut MultiVec<Card>) {
+/// Every way of picking three face up cards out of the hand and current face up cards.
+/// Suits don't matter in palace, so only one combination per set of values is kept.
+/// Combinations are generated highest cards first, so the cap drops the least sensible choices.
+fn all_setup_moves(hand: &[PackedCard], face_up_three: &[PackedCard], v: &mut MultiVec<PackedCard>) {
+   use itertools::Itertools;
+   let mut all_cards = hand.to_vec();
+   all_cards.extend_from_slice(face_up_three);
+   all_cards.sort_unstable_by(|a, b| b.cmp(a));
+   let mut seen_values = Vec::with_capacity(MAX_SETUP_MOVES);
+   for combo in all_cards.iter().copied().tuple_combinations::<(_, _, _)>() {
+      let values = (combo.0.value(), combo.1.value(), combo.2.value());
+      if seen_values.contains(&values) {
+         continue;
+      }
+      if seen_values.len() == MAX_SETUP_MOVES {
+         break;
+      }
+      seen_values.push(values);
+      v.push_to_last_bucket(combo.0);
+      v.push_to_last_bucket(combo.1);
+      v.push_to_last_bucket(combo.2);
+      v.finalize_last_bucket();
+   }
+}
+
+fn all_moves(g: &monte_game::GameState, v: &mut MultiVec<PackedCard>) {
    let active_player_hand = &g.hands[g.active_player as usize];
    if g.cur_phase == Phase::Setup {
-      use itertools::Itertools;
-      for combo in active_player_hand.iter().copied().tuple_combinations::<(_, _, _)>() {
-         v.push_to_last_bucket(combo.0);
-         v.push_to_last_bucket(combo.1);
-         v.push_to_last_bucket(combo.2);
-         v.finalize_last_bucket();
-      }
+      all_setup_moves(active_player_hand, &g.face_up_three[g.active_player as usize], v);
       return;
    }
    if g.out_players.len() as u8 == g.num_players {
@@ -191,43 +282,92 @@ fn all_moves(g: &monte_game::GameState, v: &mut MultiVec<Card>) {
    }
 }
 
-fn ismcts(num_sims: usize, exploration_val: f64, root: &InformationSet, mut unseen_cards: Vec<Card>) -> Box<[Card]> {
-   let mut tree: Vec<Node> = Vec::with_capacity(num_sims);
-   tree.push(Node {
-      last_move: None,
-      parent: 0,
-      wins: 0,
-      simulations: 0,
-      children: vec![],
-      last_player: 0,
-   });
-
-   let mut moves = MultiVec::new();
-   for _ in 0..num_sims {
+/// Held back by the LowAndSteady rollout policy, last held back first
+fn special_rank(value: CardValue) -> u8 {
+   match value {
+      CardValue::Ten => 1,
+      CardValue::Two => 2,
+      CardValue::Four => 3,
+      _ => 0,
+   }
+}
+
+fn rollout_move<'a>(
+   policy: RolloutPolicy,
+   g: &monte_game::GameState,
+   moves: &'a [Vec<PackedCard>],
+   rng: &mut impl Rng,
+) -> &'a [PackedCard] {
+   if policy == RolloutPolicy::Random || g.cur_phase == Phase::Setup {
+      return moves.choose(rng).unwrap();
+   }
+   moves
+      .iter()
+      .min_by_key(|cards| match cards.first() {
+         Some(card) => (
+            !is_playable_without_pickup(card.value(), &g.pile_cards),
+            special_rank(card.value()),
+            card.value(),
+            std::cmp::Reverse(cards.len()),
+         ),
+         // the facedown play is the only move when it's available
+         None => (false, 0, CardValue::Two, std::cmp::Reverse(0)),
+      })
+      .unwrap()
+}
+
+/// The only legal move, such as flipping a face down card, if there's nothing to choose between
+fn forced_move(g: &monte_game::GameState, moves: &mut MultiVec<PackedCard>) -> Option<Box<[Card]>> {
+   moves.reset();
+   all_moves(g, moves);
+   if moves.len() == 1 {
+      Some(unpack(&moves.get_valid_inner()[0]))
+   } else {
+      None
+   }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ismcts(
+   num_sims: usize,
+   sims_per_determinization: usize,
+   exploration_val: f64,
+   rollout_policy: RolloutPolicy,
+   root: &CardTracker,
+   mut unseen_cards: Vec<PackedCard>,
+   candidates: &mut Vec<CandidateMove>,
+   tree: &mut Tree,
+   moves: &mut MultiVec<PackedCard>,
+   rng: &mut ChaChaRng,
+) -> Box<[Card]> {
+   tree.reset();
+   let mut determinization = determine(root, &mut unseen_cards, rng);
+   // Our own moves don't depend on how the hidden cards are guessed, so a forced move is forced in every game
+   if let Some(cards) = forced_move(&determinization, moves) {
+      candidates.clear();
+      return cards;
+   }
+   for sim in 0..num_sims {
       // determine state
-      let mut g = root.determine(&mut unseen_cards);
+      if sim > 0 && sim % sims_per_determinization == 0 {
+         determinization = determine(root, &mut unseen_cards, rng);
+      }
+      let mut g = determinization.clone();
       // select
       let mut cur_node = 0;
       'outer: while tree[cur_node].simulations > 0 {
          moves.reset();
-         all_moves(&g, &mut moves);
+         all_moves(&g, moves);
 
          for a_move in moves.get_valid_inner().iter() {
             if !tree[cur_node]
                .children
                .iter()
-               .any(|x| tree[*x].last_move.as_deref() == Some(a_move.as_slice()))
+               .any(|x| tree.last_move(*x) == a_move.as_slice())
             {
                let newl = tree.len();
                tree[cur_node].children.push(newl);
-               tree.push(Node {
-                  last_move: Some(a_move.clone().into_boxed_slice()),
-                  parent: cur_node,
-                  wins: 0,
-                  simulations: 0,
-                  children: vec![],
-                  last_player: g.active_player,
-               });
+               tree.push(cur_node, g.active_player, a_move);
                cur_node = newl;
                g.take_turn(a_move).unwrap();
                break 'outer;
@@ -238,10 +378,10 @@ fn ismcts(num_sims: usize, exploration_val: f64, root: &InformationSet, mut unse
          cur_node = *tree[cur_node]
             .children
             .iter()
-            .filter(|x| moves.contains_items(tree[**x].last_move.as_ref().unwrap().as_ref()))
+            .filter(|x| moves.contains_items(tree.last_move(**x)))
             .max_by_key(|x| r64(ucb1(exploration_val, &tree[**x], tree[cur_node].simulations)))
             .unwrap();
-         g.take_turn(tree[cur_node].last_move.as_ref().unwrap()).unwrap();
+         g.take_turn(tree.last_move(cur_node)).unwrap();
          if tree[cur_node].children.is_empty() {
             // terminal node
             break;
@@ -251,14 +391,13 @@ fn ismcts(num_sims: usize, exploration_val: f64, root: &InformationSet, mut unse
       // simulate
       let mut winner = tree[cur_node].last_player;
       while (g.out_players.len() as u8) < g.num_players {
-         // make a random move
-         let rand_move = {
+         let rollout_move = {
             moves.reset();
-            all_moves(&g, &mut moves);
-            moves.get_valid_inner().choose(&mut thread_rng()).unwrap()
+            all_moves(&g, moves);
+            rollout_move(rollout_policy, &g, moves.get_valid_inner(), rng)
          };
          winner = g.active_player;
-         g.take_turn(rand_move).unwrap();
+         g.take_turn(rollout_move).unwrap();
       }
       // backprop
       loop {
@@ -273,19 +412,53 @@ fn ismcts(num_sims: usize, exploration_val: f64, root: &InformationSet, mut unse
       }
    }
 
-   // simulations done, choose best move
+   // simulations done, record the most promising moves and choose the best
+   candidates.clear();
+   candidates.extend(tree[0].children.iter().map(|x| CandidateMove {
+      cards: unpack(tree.last_move(*x)),
+      visits: tree[*x].simulations,
+      win_rate: tree[*x].wins as f64 / tree[*x].simulations as f64,
+   }));
+   candidates.sort_unstable_by_key(|x| std::cmp::Reverse(x.visits));
+   candidates.truncate(MAX_EXPLAINED_CANDIDATES);
+
    let best_child = tree[0].children.iter().max_by_key(|x| tree[**x].simulations).unwrap();
-   tree[*best_child].last_move.clone().unwrap()
+   unpack(tree.last_move(*best_child))
+}
+
+fn unpack(cards: &[PackedCard]) -> Box<[Card]> {
+   cards.iter().copied().map(Card::from).collect()
 }
 
 impl MontyAi {
-   fn get_unseen_cards_as_vec(&self) -> Vec<Card> {
-      use std::iter;
-      self
-         .unseen_cards
-         .iter()
-         .flat_map(|(k, v)| iter::repeat(*k).take(*v))
-         .collect()
+   fn decide(&mut self, full_sims: usize) -> Box<[Card]> {
+      let num_sims = (full_sims as f64 * self.strength) as usize;
+      let mut unseen_cards: Vec<PackedCard> = self.tracker.unseen_cards().into_iter().map(PackedCard::from).collect();
+      if num_sims < usize::from(crate::ai::MIN_MONTY_SIMULATIONS) {
+         // our own moves don't depend on how the hidden cards are guessed
+         let g = determine(&self.tracker, &mut unseen_cards, &mut self.rng);
+         self.moves.reset();
+         all_moves(&g, &mut self.moves);
+         self.last_candidates.clear();
+         return unpack(rollout_move(
+            RolloutPolicy::LowAndSteady,
+            &g,
+            self.moves.get_valid_inner(),
+            &mut self.rng,
+         ));
+      }
+      ismcts(
+         num_sims,
+         self.sims_per_determinization,
+         self.exploration_val,
+         self.rollout_policy,
+         &self.tracker,
+         unseen_cards,
+         &mut self.last_candidates,
+         &mut self.tree,
+         &mut self.moves,
+         &mut self.rng,
+      )
    }
 }
 
@@ -295,142 +468,303 @@ impl PalaceAi for MontyAi {
    }
 
    fn choose_three_faceup(&mut self) -> Box<[Card]> {
-      let unseen_cards = self.get_unseen_cards_as_vec();
-      ismcts(
-         self.num_sims * 2,
-         self.exploration_val,
-         &self.information_set,
-         unseen_cards,
-      )
+      self.decide(self.num_sims * 2)
    }
 
    fn make_play(&mut self) -> Box<[Card]> {
-      let unseen_cards = self.get_unseen_cards_as_vec();
-      ismcts(self.num_sims, self.exploration_val, &self.information_set, unseen_cards)
+      self.decide(self.num_sims)
+   }
+
+   fn set_strength(&mut self, strength: f64) {
+      self.strength = strength.clamp(0.0, 1.0);
+   }
+
+   fn reseed(&mut self, seed: u64) {
+      self.rng = ChaChaRng::seed_from_u64(seed);
    }
 
    fn on_game_state_update(&mut self, new_state: &PublicGameState) {
-      self.information_set.everyone_facedown_cards.clear();
-      self
-         .information_set
-         .everyone_facedown_cards
-         .extend_from_slice(&*new_state.face_down_three);
-
-      if self.last_phase.is_none() {
-         for i in 0..new_state.face_up_three.len() {
-            self.information_set.everyone_hands[i].extend(new_state.face_up_three[i].iter().map(|x| {
-               let y: MontyCard = (*x).into();
-               y
-            }));
-            for card in new_state.face_up_three[i] {
-               let num_unseen = self.unseen_cards.get_mut(card).unwrap();
-               debug_assert!(*num_unseen > 0);
-               *num_unseen -= 1;
-            }
-         }
-         self.last_phase = Some(Phase::Setup)
-      } else if self.last_phase == Some(Phase::Setup) {
-         let i = self.last_player as usize;
-         let new_fup3 = &new_state.face_up_three[i];
-         self.information_set.everyone_faceup_cards[i].extend_from_slice(new_fup3);
-         for card in new_fup3.iter() {
-            let remove_result = remove_item(&mut self.information_set.everyone_hands[i], &(*card).into());
-            if remove_result.is_none() {
-               remove_item(&mut self.information_set.everyone_hands[i], &MontyCard::Unknown);
-               let num_unseen = self.unseen_cards.get_mut(card).unwrap();
-               debug_assert!(*num_unseen > 0);
-               *num_unseen -= 1;
-            }
-         }
-         if new_state.cur_phase == Phase::Play {
-            self.last_phase = Some(Phase::Play);
-            self.information_set.cur_phase = Phase::Play;
-         }
-      }
+      self.tracker.on_game_state_update(new_state);
+   }
 
-      // update pile based on cards played
-      self
-         .information_set
-         .cur_pile
-         .extend_from_slice(new_state.last_cards_played);
-
-      let last_player_hand = &mut self.information_set.everyone_hands[self.last_player as usize];
-
-      // update hand based on cards played
-      match new_state.last_played_zone {
-         Some(CardZone::Hand) => {
-            for card in new_state.last_cards_played {
-               let remove_result = remove_item(last_player_hand, &(*card).into());
-               if remove_result.is_none() {
-                  remove_item(last_player_hand, &MontyCard::Unknown).unwrap();
-                  let num_unseen = self.unseen_cards.get_mut(card).unwrap();
-                  debug_assert!(*num_unseen > 0);
-                  *num_unseen -= 1;
-               }
-            }
-         }
-         Some(CardZone::FaceUpThree) => {
-            let last_player_faceup = &mut self.information_set.everyone_faceup_cards[self.last_player as usize];
-            for card in new_state.last_cards_played {
-               remove_item(last_player_faceup, card).unwrap();
-            }
-         }
-         Some(CardZone::FaceDownThree) => {
-            debug_assert_eq!(new_state.last_cards_played.len(), 1);
-            for card in new_state.last_cards_played {
-               let num_unseen = self.unseen_cards.get_mut(card).unwrap();
-               debug_assert!(*num_unseen > 0);
-               *num_unseen -= 1;
-            }
-         }
-         None => (),
-      }
+   fn on_game_start(&mut self, game_start_event: GameStartEvent) {
+      self.tracker.on_game_start(&game_start_event);
+   }
+
+   fn on_hand_update(&mut self, _new_hand: &[Card]) {
+      // we just manage our own hand naturally
+   }
+
+   fn on_player_conceded(&mut self, slot: u8) {
+      self.tracker.on_player_conceded(slot);
+   }
+
+   fn should_concede(&self) -> bool {
+      matches!(
+         self.last_candidates.first(),
+         Some(best) if best.visits >= CONCEDE_MIN_VISITS && best.win_rate < CONCEDE_WIN_RATE
+      )
+   }
+
+   fn assume_position(
+      &mut self,
+      game_start_event: GameStartEvent,
+      state: &PublicGameState,
+      pile: &[Card],
+      cleared: &[Card],
+   ) {
+      self.tracker.assume_position(&game_start_event, state, pile, cleared);
+   }
+
+   fn decision_explanation(&self) -> Option<&[CandidateMove]> {
+      Some(&self.last_candidates)
+   }
+}
 
-      // If pile got picked up, update hand
-      if new_state.pile_size == 0 {
-         // update hand of last player to include all of the pile cards
-         // IF they did not clear it
-         if self.last_player != new_state.active_player {
-            last_player_hand.extend(self.information_set.cur_pile.iter().map(|x| {
-               let y: MontyCard = (*x).into();
-               y
-            }));
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::game::{self, CardSuit, CardValue};
+
+   #[cfg(test)]
+   fn setup_game(cards: &[(CardValue, CardSuit)]) -> monte_game::GameState {
+      let mut g = monte_game::GameState::new(2);
+      let mut cards: monte_game::Hand = cards
+         .iter()
+         .map(|&(value, suit)| PackedCard::from(Card { value, suit, id: None }))
+         .collect();
+      g.face_up_three[0] = cards.drain(6..).collect();
+      g.hands[0] = cards;
+      g
+   }
+
+   #[cfg(test)]
+   fn setup_move_count(g: &monte_game::GameState) -> usize {
+      let mut moves = MultiVec::new();
+      all_moves(g, &mut moves);
+      moves.get_valid_inner().len()
+   }
+
+   #[test]
+   fn setup_moves_ignore_suits() {
+      let mut cards = vec![];
+      for &value in &[CardValue::Four, CardValue::Nine, CardValue::King] {
+         for &suit in &[CardSuit::Clubs, CardSuit::Hearts, CardSuit::Spades] {
+            cards.push((value, suit));
          }
-         self.information_set.cur_pile.clear();
       }
+      // multisets of size 3 over 3 values, instead of 9 choose 3
+      assert_eq!(setup_move_count(&setup_game(&cards)), 10);
+   }
 
-      self.last_player = new_state.active_player;
+   #[test]
+   fn setup_moves_include_face_up_cards() {
+      let cards = [
+         (CardValue::Two, CardSuit::Clubs),
+         (CardValue::Two, CardSuit::Hearts),
+         (CardValue::Two, CardSuit::Spades),
+         (CardValue::Two, CardSuit::Diamonds),
+         (CardValue::Three, CardSuit::Clubs),
+         (CardValue::Three, CardSuit::Hearts),
+         (CardValue::Ace, CardSuit::Clubs),
+         (CardValue::Ace, CardSuit::Hearts),
+         (CardValue::Ace, CardSuit::Spades),
+      ];
+      let g = setup_game(&cards);
+      let mut moves = MultiVec::new();
+      all_moves(&g, &mut moves);
+      assert!(moves
+         .get_valid_inner()
+         .iter()
+         .any(|x| x.iter().all(|c| c.value() == CardValue::Ace)));
    }
 
-   fn on_game_start(&mut self, game_start_event: GameStartEvent) {
-      let num_players = game_start_event.players.len();
+   #[test]
+   fn setup_moves_are_capped_for_distinct_hands() {
+      let cards = [
+         (CardValue::Two, CardSuit::Clubs),
+         (CardValue::Three, CardSuit::Clubs),
+         (CardValue::Four, CardSuit::Clubs),
+         (CardValue::Five, CardSuit::Clubs),
+         (CardValue::Six, CardSuit::Clubs),
+         (CardValue::Seven, CardSuit::Clubs),
+         (CardValue::Eight, CardSuit::Clubs),
+         (CardValue::Nine, CardSuit::Clubs),
+         (CardValue::Ten, CardSuit::Clubs),
+      ];
+      let g = setup_game(&cards);
+      assert_eq!(setup_move_count(&g), MAX_SETUP_MOVES);
+
+      // the highest cards survive the cap
+      let mut moves = MultiVec::new();
+      all_moves(&g, &mut moves);
+      let highest = [
+         Card {
+            value: CardValue::Ten,
+            suit: CardSuit::Clubs,
+            id: None,
+         },
+         Card {
+            value: CardValue::Nine,
+            suit: CardSuit::Clubs,
+            id: None,
+         },
+         Card {
+            value: CardValue::Eight,
+            suit: CardSuit::Clubs,
+            id: None,
+         },
+      ]
+      .map(PackedCard::from);
+      assert!(moves.contains_items(&highest));
+   }
 
-      self.unseen_cards.reserve(num_players * 13);
-      for card in game::new_deck(num_players) {
-         *self.unseen_cards.entry(card).or_insert(0) += 1;
-      }
-      self.information_set.everyone_hands.reserve_exact(num_players);
-      self.information_set.everyone_faceup_cards.reserve_exact(num_players);
-      for _ in 0..num_players {
-         self
-            .information_set
-            .everyone_hands
-            .push(vec![MontyCard::Unknown; crate::game::HAND_SIZE]);
-         self.information_set.everyone_faceup_cards.push(Vec::with_capacity(3));
+   #[test]
+   fn setup_moves_stay_capped_with_many_players() {
+      let mut g = monte_game::GameState::new(8);
+      for player in 0..g.num_players {
+         g.active_player = player;
+         assert!(setup_move_count(&g) <= MAX_SETUP_MOVES);
       }
-      self.information_set.turn_number = game_start_event.turn_number;
-      for card in game_start_event.hand {
-         *self.unseen_cards.get_mut(card).unwrap() -= 1;
+   }
+
+   #[test]
+   fn assumes_position_during_setup() {
+      let mut gs = game::GameState::new(4);
+      // the first two players have already chosen
+      for _ in 0..2 {
+         let mut hand = gs.get_hand(gs.active_player).to_vec();
+         hand.truncate(3);
+         gs.take_turn(&hand).unwrap();
       }
-      let my_hand = &mut self.information_set.everyone_hands[self.information_set.turn_number as usize];
-      my_hand.clear();
-      my_hand.extend(game_start_event.hand.iter().map(|x| {
-         let y: MontyCard = (*x).into();
-         y
-      }));
+
+      let mut ai = with_parameters(0.7, 50, 1, RolloutPolicy::Random);
+      let players = (0..4)
+         .map(|x| (x, crate::data::PlayerProfile::new(x.to_string(), true)))
+         .collect();
+      ai.assume_position(
+         GameStartEvent {
+            hand: gs.get_hand(gs.active_player),
+            turn_number: gs.active_player,
+            players: &players,
+         },
+         &gs.public_state(),
+         &gs.pile_cards,
+         &gs.cleared_cards,
+      );
+
+      // every card we haven't seen must be in someone's hand, face down, or never dealt
+      let num_undealt = game::new_deck(4).count() - 4 * (game::HAND_SIZE + 6);
+      let num_unseen = ai.tracker.num_unseen();
+      let num_unknown = ai
+         .tracker
+         .hands
+         .iter()
+         .flatten()
+         .filter(|x| **x == TrackedCard::Unknown)
+         .count();
+      let num_facedown: usize = ai.tracker.face_down.iter().map(|x| *x as usize).sum();
+      assert_eq!(num_unseen, num_unknown + num_facedown + num_undealt);
+
+      let choice = crate::ai::get_turn(&gs, &mut ai);
+      gs.take_turn(&choice).unwrap();
    }
 
-   fn on_hand_update(&mut self, _new_hand: &[Card]) {
-      // we just manage our own hand naturally
+   #[test]
+   fn plays_without_searching_at_no_strength() {
+      let mut gs = game::GameState::new(2);
+      let mut ai = new();
+      ai.set_strength(0.0);
+      let players = (0..2)
+         .map(|x| (x, crate::data::PlayerProfile::new(x.to_string(), true)))
+         .collect();
+      ai.on_game_start(GameStartEvent {
+         hand: gs.get_hand(0),
+         turn_number: 0,
+         players: &players,
+      });
+      ai.on_game_state_update(&gs.public_state());
+
+      let choice = crate::ai::get_turn(&gs, &mut ai);
+      gs.take_turn(&choice).unwrap();
+      assert!(ai.tree.nodes.is_empty());
+   }
+
+   #[test]
+   fn tree_reuses_nodes_after_reset() {
+      let card = PackedCard::from(Card {
+         value: CardValue::Five,
+         suit: CardSuit::Clubs,
+         id: None,
+      });
+      let mut tree = Tree::new();
+      tree.reset();
+      tree[0].children.push(1);
+      tree.push(0, 1, &[card, card]);
+      tree[0].simulations = 3;
+      assert_eq!(tree.last_move(1), &[card, card]);
+
+      tree.reset();
+      assert_eq!(tree.len(), 1);
+      assert_eq!(tree.nodes.len(), 2);
+      assert!(tree[0].children.is_empty());
+      assert_eq!(tree[0].simulations, 0);
+      assert!(tree.last_move(0).is_empty());
+   }
+
+   #[test]
+   fn low_and_steady_rollouts_play_the_lowest_playable_cards() {
+      let card = |value, suit| PackedCard::from(Card { value, suit, id: None });
+      let mut g = monte_game::GameState::new(2);
+      g.cur_phase = Phase::Play;
+      g.hands[0] = [
+         card(CardValue::Five, CardSuit::Clubs),
+         card(CardValue::Five, CardSuit::Hearts),
+         card(CardValue::Eight, CardSuit::Clubs),
+         card(CardValue::Ten, CardSuit::Clubs),
+         card(CardValue::Two, CardSuit::Clubs),
+      ]
+      .iter()
+      .copied()
+      .collect();
+      g.hands[0].sort_unstable();
+      let mut moves = MultiVec::new();
+      let mut rng = rand::thread_rng();
+
+      g.pile_cards = std::iter::once(card(CardValue::Three, CardSuit::Spades)).collect();
+      all_moves(&g, &mut moves);
+      let cards = rollout_move(RolloutPolicy::LowAndSteady, &g, moves.get_valid_inner(), &mut rng);
+      assert_eq!(cards.len(), 2);
+      assert!(cards.iter().all(|c| c.value() == CardValue::Five));
+
+      g.pile_cards[0] = card(CardValue::Six, CardSuit::Spades);
+      moves.reset();
+      all_moves(&g, &mut moves);
+      let cards = rollout_move(RolloutPolicy::LowAndSteady, &g, moves.get_valid_inner(), &mut rng);
+      assert_eq!(cards.len(), 1);
+      assert_eq!(cards[0].value(), CardValue::Eight);
+   }
+
+   #[test]
+   fn forced_moves_skip_the_search() {
+      let card = |value| {
+         PackedCard::from(Card {
+            value,
+            suit: CardSuit::Clubs,
+            id: None,
+         })
+      };
+      let mut g = monte_game::GameState::new(2);
+      g.cur_phase = Phase::Play;
+      let mut moves = MultiVec::new();
+      assert!(forced_move(&g, &mut moves).is_none());
+
+      g.hands[0] = std::iter::once(card(CardValue::Nine)).collect();
+      assert_eq!(forced_move(&g, &mut moves).unwrap().len(), 1);
+
+      g.hands[0].clear();
+      g.face_up_three[0].clear();
+      assert_eq!(forced_move(&g, &mut moves).unwrap().len(), 0);
    }
 }
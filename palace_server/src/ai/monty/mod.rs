@@ -1,4 +1,4 @@
 pub mod ai;
 mod multivec;
 
-pub use ai::{new, with_parameters};
+pub use ai::{new, with_parameters, RolloutPolicy, DEFAULT_EXPLORATION_VAL, DEFAULT_NUM_SIMS};
@@ -0,0 +1,224 @@
+// This AI plays whichever card value a small neural network scores the highest, out of those that go on the pile
+//
+// The model is an ONNX file taking a single [1, NUM_FEATURES] f32 tensor and producing a [1, 13] f32 tensor,
+// one score per card value (Two through Ace.) Features are, in order:
+// * our hand, as a count of cards per value (13)
+// * our face up cards, as a count of cards per value (13)
+// * the pile, as a count of cards per value (13)
+// * the effective top card of the pile, one-hot (13)
+// * pile size, number of players, and the fewest cards any opponent has left (3)
+use super::PalaceAi;
+use crate::data::GameStartEvent;
+use crate::game::{effective_top_card, is_playable_without_pickup, Card, CardValue, PublicGameState};
+use std::path::Path;
+use std::sync::Arc;
+use tract_onnx::prelude::*;
+
+const NUM_VALUES: usize = 13;
+pub const NUM_FEATURES: usize = NUM_VALUES * 4 + 3;
+
+pub struct PolicyModel {
+   plan: TypedRunnableModel<TypedModel>,
+}
+
+impl PolicyModel {
+   pub fn load(path: &Path) -> TractResult<PolicyModel> {
+      let plan = tract_onnx::onnx()
+         .model_for_path(path)?
+         .with_input_fact(0, f32::fact([1, NUM_FEATURES]).into())?
+         .into_optimized()?
+         .into_runnable()?;
+      Ok(PolicyModel { plan })
+   }
+
+   fn scores(&self, features: [f32; NUM_FEATURES]) -> TractResult<[f32; NUM_VALUES]> {
+      let input: Tensor = tract_ndarray::Array2::from_shape_vec((1, NUM_FEATURES), features.to_vec())?.into();
+      let output = self.plan.run(tvec!(input.into()))?;
+      let output = output[0].to_array_view::<f32>()?;
+      let mut scores = [0.0; NUM_VALUES];
+      for (score, value) in scores.iter_mut().zip(output.iter()) {
+         *score = *value;
+      }
+      Ok(scores)
+   }
+}
+
+pub struct NeuralAi {
+   model: Arc<PolicyModel>,
+   hand: Vec<Card>,
+   faceup_cards: Vec<Card>,
+   turn_number: u8,
+   num_players: u8,
+   fewest_opponent_cards: u16,
+   cur_pile: Vec<Card>,
+}
+
+pub fn new(model: Arc<PolicyModel>) -> NeuralAi {
+   NeuralAi {
+      model,
+      hand: vec![],
+      faceup_cards: vec![],
+      turn_number: 0,
+      num_players: 0,
+      fewest_opponent_cards: 0,
+      cur_pile: vec![],
+   }
+}
+
+fn count_values(cards: &[Card], features: &mut [f32]) {
+   for card in cards {
+      features[card.value as usize] += 1.0;
+   }
+}
+
+impl NeuralAi {
+   fn features(&self) -> [f32; NUM_FEATURES] {
+      let mut features = [0.0; NUM_FEATURES];
+      count_values(&self.hand, &mut features[0..NUM_VALUES]);
+      count_values(&self.faceup_cards, &mut features[NUM_VALUES..NUM_VALUES * 2]);
+      count_values(&self.cur_pile, &mut features[NUM_VALUES * 2..NUM_VALUES * 3]);
+      features[NUM_VALUES * 3 + effective_top_card(&self.cur_pile) as usize] = 1.0;
+      features[NUM_VALUES * 4] = self.cur_pile.len() as f32;
+      features[NUM_VALUES * 4 + 1] = f32::from(self.num_players);
+      features[NUM_VALUES * 4 + 2] = f32::from(self.fewest_opponent_cards);
+      features
+   }
+
+   /// If the model can't be evaluated, every value scores the same
+   /// and we fall back to the lowest card
+   fn scores(&self) -> [f32; NUM_VALUES] {
+      match self.model.scores(self.features()) {
+         Ok(scores) => scores,
+         Err(e) => {
+            log::error!("Failed to evaluate policy model: {:?}", e);
+            [0.0; NUM_VALUES]
+         }
+      }
+   }
+}
+
+fn score_of(scores: &[f32; NUM_VALUES], value: CardValue) -> f32 {
+   scores[value as usize]
+}
+
+/// Plays every card of the best scoring value that goes on the pile,
+/// only picking the pile up when nothing in `zone` can be played on it
+fn choose_play(zone: &[Card], pile: &[Card], scores: &[f32; NUM_VALUES]) -> Box<[Card]> {
+   let best_value = zone
+      .iter()
+      .map(|x| x.value)
+      .max_by(|a, b| {
+         is_playable_without_pickup(*a, pile)
+            .cmp(&is_playable_without_pickup(*b, pile))
+            .then(
+               score_of(scores, *a)
+                  .partial_cmp(&score_of(scores, *b))
+                  .unwrap_or(std::cmp::Ordering::Equal),
+            )
+      })
+      .unwrap();
+   zone
+      .iter()
+      .filter(|x| x.value == best_value)
+      .copied()
+      .collect::<Vec<_>>()
+      .into_boxed_slice()
+}
+
+impl PalaceAi for NeuralAi {
+   fn strategy_name(&self) -> &'static str {
+      "Neural"
+   }
+
+   fn choose_three_faceup(&mut self) -> Box<[Card]> {
+      let scores = self.scores();
+      let mut all_cards = self.hand.clone();
+      all_cards.extend_from_slice(&self.faceup_cards);
+      // Put the cards the model likes least face up, so the best ones stay in hand
+      all_cards.sort_by(|a, b| {
+         score_of(&scores, a.value)
+            .partial_cmp(&score_of(&scores, b.value))
+            .unwrap_or(std::cmp::Ordering::Equal)
+      });
+      all_cards.truncate(3);
+      all_cards.into_boxed_slice()
+   }
+
+   fn make_play(&mut self) -> Box<[Card]> {
+      let scores = self.scores();
+      let zone = if !self.hand.is_empty() {
+         &self.hand
+      } else {
+         &self.faceup_cards
+      };
+      choose_play(zone, &self.cur_pile, &scores)
+   }
+
+   fn on_game_state_update(&mut self, new_state: &PublicGameState) {
+      self.faceup_cards.clear();
+      self
+         .faceup_cards
+         .extend_from_slice(new_state.face_up_three[self.turn_number as usize]);
+      self.fewest_opponent_cards = (0..new_state.hands.len())
+         .filter(|x| *x != self.turn_number as usize)
+         .map(|x| {
+            new_state.hands[x] + new_state.face_up_three[x].len() as u16 + u16::from(new_state.face_down_three[x])
+         })
+         .min()
+         .unwrap_or(0);
+      if new_state.pile_size == 0 {
+         self.cur_pile.clear();
+      } else {
+         self.cur_pile.extend_from_slice(new_state.last_cards_played);
+      }
+   }
+
+   fn on_game_start(&mut self, game_start_event: GameStartEvent) {
+      self.hand.extend_from_slice(game_start_event.hand);
+      self.turn_number = game_start_event.turn_number;
+      self.num_players = game_start_event.players.len() as u8;
+   }
+
+   fn on_hand_update(&mut self, new_hand: &[Card]) {
+      self.hand.clear();
+      self.hand.extend_from_slice(new_hand);
+   }
+
+   fn assume_position(
+      &mut self,
+      game_start_event: GameStartEvent,
+      state: &PublicGameState,
+      pile: &[Card],
+      _cleared: &[Card],
+   ) {
+      self.on_game_start(game_start_event);
+      self.on_game_state_update(state);
+      self.cur_pile.clear();
+      self.cur_pile.extend_from_slice(pile);
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::game::CardSuit;
+
+   #[test]
+   fn plays_the_best_card_that_goes_on_the_pile() {
+      let card = |value| Card {
+         value,
+         suit: CardSuit::Spades,
+         id: None,
+      };
+      let mut scores = [0.0; NUM_VALUES];
+      scores[CardValue::Three as usize] = 1.0;
+      scores[CardValue::Nine as usize] = 0.5;
+      let hand = [card(CardValue::Three), card(CardValue::Nine), card(CardValue::Queen)];
+      let pile = [card(CardValue::Eight)];
+      assert_eq!(&*choose_play(&hand, &pile, &scores), &[card(CardValue::Nine)]);
+      // with nothing playable, the pile has to be picked up with the best scoring card
+      let pile = [card(CardValue::Ace)];
+      assert_eq!(&*choose_play(&hand, &pile, &scores), &[card(CardValue::Three)]);
+   }
+}
@@ -3,12 +3,14 @@ use super::PalaceAi;
 use crate::data::GameStartEvent;
 use crate::game::{Card, PublicGameState};
 use rand::seq::SliceRandom;
-use rand::{self, thread_rng};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 
 pub struct RandomAi {
    hand: Vec<Card>,
    faceup_cards: Vec<Card>,
    turn_number: u8,
+   rng: ChaChaRng,
 }
 
 pub fn new() -> RandomAi {
@@ -16,6 +18,7 @@ pub fn new() -> RandomAi {
       hand: vec![],
       faceup_cards: vec![],
       turn_number: 0,
+      rng: super::unseeded_rng(),
    }
 }
 
@@ -30,12 +33,16 @@ impl PalaceAi for RandomAi {
 
    fn make_play(&mut self) -> Box<[Card]> {
       if !self.hand.is_empty() {
-         vec![*self.hand.choose(&mut thread_rng()).unwrap()].into_boxed_slice()
+         vec![*self.hand.choose(&mut self.rng).unwrap()].into_boxed_slice()
       } else {
-         vec![*self.faceup_cards.choose(&mut thread_rng()).unwrap()].into_boxed_slice()
+         vec![*self.faceup_cards.choose(&mut self.rng).unwrap()].into_boxed_slice()
       }
    }
 
+   fn reseed(&mut self, seed: u64) {
+      self.rng = ChaChaRng::seed_from_u64(seed);
+   }
+
    fn on_game_state_update(&mut self, new_state: &PublicGameState) {
       self.faceup_cards.clear();
       self
@@ -0,0 +1,114 @@
+// This AI makes a fixed series of plays, then hands over to another strategy. Scenarios use it to set up situations
+use super::{CandidateMove, PalaceAi};
+use crate::data::GameStartEvent;
+use crate::game::{Card, CardZone, PublicGameState};
+
+pub struct ScriptedAi {
+   script: Vec<Box<[Card]>>,
+   next: usize,
+   then: Box<dyn PalaceAi + Send + Sync>,
+}
+
+/// `script` is played in order, one entry per turn after setup
+pub fn new(script: Vec<Box<[Card]>>, then: Box<dyn PalaceAi + Send + Sync>) -> ScriptedAi {
+   ScriptedAi { script, next: 0, then }
+}
+
+impl PalaceAi for ScriptedAi {
+   fn strategy_name(&self) -> &'static str {
+      "Scripted"
+   }
+
+   fn choose_three_faceup(&mut self) -> Box<[Card]> {
+      self.then.choose_three_faceup()
+   }
+
+   fn make_play(&mut self) -> Box<[Card]> {
+      match self.script.get(self.next) {
+         Some(play) => {
+            self.next += 1;
+            play.clone()
+         }
+         None => self.then.make_play(),
+      }
+   }
+
+   fn on_game_state_update(&mut self, new_state: &PublicGameState) {
+      self.then.on_game_state_update(new_state);
+   }
+
+   fn on_game_start(&mut self, game_start_event: GameStartEvent) {
+      self.next = 0;
+      self.then.on_game_start(game_start_event);
+   }
+
+   fn on_hand_update(&mut self, new_hand: &[Card]) {
+      self.then.on_hand_update(new_hand);
+   }
+
+   fn on_opponent_play(&mut self, slot: u8, cards: &[Card], zone: CardZone, picked_up: bool) {
+      self.then.on_opponent_play(slot, cards, zone, picked_up);
+   }
+
+   fn on_your_turn(&mut self) {
+      self.then.on_your_turn();
+   }
+
+   fn on_player_conceded(&mut self, slot: u8) {
+      self.then.on_player_conceded(slot);
+   }
+
+   fn should_concede(&self) -> bool {
+      self.next >= self.script.len() && self.then.should_concede()
+   }
+
+   fn set_strength(&mut self, strength: f64) {
+      self.then.set_strength(strength);
+   }
+
+   /// The script is written for the start of the game, so taking over partway only follows `then`
+   fn assume_position(
+      &mut self,
+      game_start_event: GameStartEvent,
+      state: &PublicGameState,
+      pile: &[Card],
+      cleared: &[Card],
+   ) {
+      self.next = self.script.len();
+      self.then.assume_position(game_start_event, state, pile, cleared);
+   }
+
+   fn decision_explanation(&self) -> Option<&[CandidateMove]> {
+      self.then.decision_explanation()
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::ai::random;
+   #[cfg(test)]
+   use crate::game::{CardSuit, CardValue};
+   #[cfg(test)]
+   use std::collections::HashMap;
+
+   #[test]
+   fn plays_the_script_then_hands_over() {
+      let card = |value| Card {
+         value,
+         suit: CardSuit::Spades,
+         id: None,
+      };
+      let script = vec![vec![card(CardValue::Seven)].into_boxed_slice()];
+      let mut ai = new(script, Box::new(random::new()));
+      ai.on_game_start(GameStartEvent {
+         hand: &[card(CardValue::Seven), card(CardValue::Jack)],
+         turn_number: 1,
+         players: &HashMap::new(),
+      });
+      assert_eq!(&*ai.make_play(), &[card(CardValue::Seven)]);
+      ai.on_hand_update(&[card(CardValue::Jack)]);
+      assert_eq!(&*ai.make_play(), &[card(CardValue::Jack)]);
+   }
+}
@@ -0,0 +1,165 @@
+//! Reviews of finished games, comparing a player's plays with what Monty would have done in their seat
+
+use crate::ai::monty::{self, RolloutPolicy};
+use crate::ai::{self, PalaceAi};
+use crate::data::{GameAnalysis, MoveAnnotation};
+use crate::game::{Card, GameState, Phase, Turn};
+use crate::PlayerId;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashMap;
+
+/// Simulations for each analyzed play. Nobody is waiting on a turn, so this is more than Monty uses in games
+const ANALYSIS_SIMULATIONS: usize = 2000;
+
+/// A finished game, kept so that its players can have it analyzed
+#[derive(Clone)]
+pub(crate) struct GameRecording {
+   /// The game as dealt
+   pub opening: GameState,
+   pub history: Vec<Turn>,
+   /// The seat each player had
+   pub seats: HashMap<PlayerId, u8>,
+}
+
+impl GameRecording {
+   /// The positions where `slot` had a choice of cards to play, with what they played
+   fn decisions(&self, slot: u8) -> Vec<(GameState, &[Card])> {
+      let mut gs = self.opening.clone();
+      let mut decisions = Vec::new();
+      for turn in &self.history {
+         match turn {
+            Turn::Play(cards) => {
+               let p = gs.active_player as usize;
+               let face_down = gs.hands[p].is_empty() && gs.face_up_three[p].is_empty();
+               if gs.active_player == slot && gs.cur_phase == Phase::Play && !face_down {
+                  decisions.push((gs.clone(), &cards[..]));
+               }
+               // Only plays that were accepted are recorded, so they're accepted again
+               let _ = gs.take_turn(cards);
+            }
+            Turn::Concede(player) => {
+               gs.concede(*player);
+            }
+         }
+      }
+      decisions
+   }
+}
+
+/// Suits matter here, unlike in play, since the AI is only asked about the cards it holds
+fn same_cards(a: &[Card], b: &[Card]) -> bool {
+   let sorted = |cards: &[Card]| {
+      let mut cards: Vec<_> = cards.iter().map(|x| (x.value, x.suit)).collect();
+      cards.sort_unstable();
+      cards
+   };
+   sorted(a) == sorted(b)
+}
+
+/// None if the play was forced
+fn annotate(gs: &GameState, played: &[Card]) -> Option<MoveAnnotation> {
+   let mut ai = monty::with_parameters(
+      monty::DEFAULT_EXPLORATION_VAL,
+      ANALYSIS_SIMULATIONS,
+      1,
+      RolloutPolicy::Random,
+   );
+   ai::assume_seat(&mut ai, gs, gs.active_player);
+   ai.make_play();
+   let candidates = ai.decision_explanation()?;
+   if candidates.len() < 2 {
+      return None;
+   }
+   let preferred = &candidates[0];
+   let played_win_rate = candidates
+      .iter()
+      .find(|x| same_cards(&x.cards, played))
+      .map(|x| x.win_rate);
+   Some(MoveAnnotation {
+      turn: gs.turns_taken + 1,
+      played: played.into(),
+      preferred: preferred.cards.clone(),
+      preferred_win_rate: preferred.win_rate,
+      played_win_rate,
+      win_rate_swing: played_win_rate.map(|x| x - preferred.win_rate),
+   })
+}
+
+/// Replays the game, having Monty look at each of the plays made from `slot`. Plays are analyzed in parallel
+pub(crate) fn analyze(recording: &GameRecording, slot: u8) -> GameAnalysis {
+   let moves = recording
+      .decisions(slot)
+      .into_par_iter()
+      .filter_map(|(gs, played)| annotate(&gs, played))
+      .collect();
+   GameAnalysis { slot, moves }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::ai::random;
+   #[cfg(test)]
+   use crate::data::{GameStartEvent, PlayerProfile};
+
+   /// Plays a two player game out with random AI, recording it
+   #[cfg(test)]
+   fn random_game() -> GameRecording {
+      let mut gs = GameState::new(2);
+      let opening = gs.clone();
+      let players: HashMap<u8, PlayerProfile> = (0..2).map(|x| (x, PlayerProfile::new(String::new(), true))).collect();
+      let mut ais: Vec<_> = (0..2)
+         .map(|x| {
+            let mut ai = random::new();
+            ai.on_game_start(GameStartEvent {
+               hand: gs.get_hand(x),
+               turn_number: x,
+               players: &players,
+            });
+            ai
+         })
+         .collect();
+      loop {
+         let slot = gs.active_player as usize;
+         ais[slot].on_hand_update(gs.get_hand(slot as u8));
+         ais[slot].on_game_state_update(&gs.public_state());
+         let play = ai::get_turn(&gs, &mut ais[slot]);
+         if gs.take_turn(&play).unwrap().game_over {
+            break;
+         }
+      }
+      GameRecording {
+         opening,
+         history: gs.history,
+         seats: HashMap::new(),
+      }
+   }
+
+   #[test]
+   fn replays_only_the_seats_own_choices() {
+      let recording = random_game();
+      let decisions = recording.decisions(1);
+      assert!(!decisions.is_empty());
+      for (gs, played) in decisions {
+         assert_eq!(gs.active_player, 1);
+         assert_eq!(gs.cur_phase, Phase::Play);
+         assert!(gs.clone().take_turn(played).is_ok());
+      }
+   }
+
+   #[test]
+   fn matches_moves_regardless_of_order() {
+      use crate::game::{CardSuit, CardValue};
+      let card = |suit| Card {
+         value: CardValue::Nine,
+         suit,
+         id: None,
+      };
+      assert!(same_cards(
+         &[card(CardSuit::Clubs), card(CardSuit::Hearts)],
+         &[card(CardSuit::Hearts), card(CardSuit::Clubs)]
+      ));
+      assert!(!same_cards(&[card(CardSuit::Clubs)], &[card(CardSuit::Hearts)]));
+   }
+}
@@ -0,0 +1,419 @@
+use crate::commentary::Commentary;
+use crate::data::{StatsSummaries, StatsSummary};
+use crate::game::Highlight;
+use log::{debug, error};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 86_400;
+/// The unix epoch fell on a Thursday, so shifting by three days makes weeks begin on Monday
+const EPOCH_WEEK_OFFSET_DAYS: u64 = 3;
+const SUMMARY_DAYS: u64 = 7;
+const SUMMARY_WEEKS: u64 = 4;
+/// The oldest of the weeks summarized can't have started more than this many days ago
+const KEPT_DAYS: u64 = SUMMARY_WEEKS * 7;
+
+/// Appends a JSON line per completed game, so operators can analyze games offline
+pub struct GameArchive {
+   /// Lines for the writer thread to append, so that games are recorded without waiting on the disk
+   lines: Option<mpsc::Sender<Vec<u8>>>,
+   writer: Option<JoinHandle<()>>,
+   /// Totals for the days that summaries still cover
+   days: Mutex<Days>,
+}
+
+#[derive(Serialize)]
+pub struct ArchivedPlayer<'a> {
+   pub name: &'a str,
+   pub turn_number: u8,
+   /// None for humans
+   pub ai_strategy: Option<&'static str>,
+   pub clandestine: bool,
+}
+
+#[derive(Serialize)]
+pub struct GameRecord<'a> {
+   pub lobby_name: &'a str,
+   pub players: Vec<ArchivedPlayer<'a>>,
+   /// Turn numbers, first place first
+   pub finish_order: &'a [u8],
+   pub turns: u32,
+   pub duration_secs: u64,
+   pub turn_timer_secs: u64,
+   pub max_players: u8,
+   /// Deals the game again with `GameState::with_seed`, and seeds its AIs
+   pub seed: u64,
+   pub highlights: &'a [Highlight],
+   pub commentary: &'a [Commentary],
+}
+
+#[derive(Serialize)]
+struct ArchiveLine<'a> {
+   completed_at: u64,
+   #[serde(flatten)]
+   record: &'a GameRecord<'a>,
+}
+
+/// The parts of an archived game that summaries are built from
+#[derive(Deserialize)]
+struct ArchivedGame {
+   completed_at: u64,
+   players: Vec<SummaryPlayer>,
+   finish_order: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct SummaryPlayer {
+   name: String,
+   turn_number: u8,
+   ai_strategy: Option<String>,
+}
+
+fn write_lines(mut file: File, lines: mpsc::Receiver<Vec<u8>>) {
+   // One write per line, so lines from concurrent games never interleave
+   for line in lines {
+      if let Err(e) = file.write_all(&line) {
+         error!("Failed to append to game archive: {:?}", e);
+      }
+   }
+}
+
+fn unix_now() -> u64 {
+   SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|x| x.as_secs())
+      .unwrap_or(0)
+}
+
+/// Running totals for the games completed on one day
+#[derive(Default)]
+struct DayTotals {
+   games_played: u64,
+   ai_seats: u64,
+   ai_wins: u64,
+   human_seats: u64,
+   human_wins: u64,
+   human_names: HashSet<String>,
+}
+
+/// By days since the unix epoch
+type Days = BTreeMap<u64, DayTotals>;
+
+/// Adds a game to the totals for the day it was completed, and forgets days that summaries no longer cover.
+/// `players` are each player's name, turn number, and whether they're an AI
+fn add_game<'a>(
+   days: &mut Days,
+   completed_at: u64,
+   now: u64,
+   players: impl Iterator<Item = (&'a str, u8, bool)>,
+   winner: Option<u8>,
+) {
+   let oldest_day = (now / SECS_PER_DAY + 1).saturating_sub(KEPT_DAYS);
+   *days = days.split_off(&oldest_day);
+   let day = completed_at / SECS_PER_DAY;
+   if day < oldest_day {
+      return;
+   }
+   let totals = days.entry(day).or_default();
+   totals.games_played += 1;
+   for (name, turn_number, is_ai) in players {
+      let won = winner == Some(turn_number);
+      if is_ai {
+         totals.ai_seats += 1;
+         totals.ai_wins += won as u64;
+      } else {
+         totals.human_seats += 1;
+         totals.human_wins += won as u64;
+         if !totals.human_names.contains(name) {
+            totals.human_names.insert(name.to_string());
+         }
+      }
+   }
+}
+
+impl ArchivedGame {
+   fn add_to(&self, days: &mut Days, now: u64) {
+      let players = self
+         .players
+         .iter()
+         .map(|x| (x.name.as_str(), x.turn_number, x.ai_strategy.is_some()));
+      add_game(
+         days,
+         self.completed_at,
+         now,
+         players,
+         self.finish_order.first().copied(),
+      );
+   }
+}
+
+/// Reads through an existing archive a line at a time, keeping only the totals for recent days
+fn load_days(path: &Path, now: u64) -> Days {
+   let mut days = Days::new();
+   let file = match File::open(path) {
+      Ok(file) => file,
+      Err(e) => {
+         error!("Failed to read game archive at {:?}: {:?}", path, e);
+         return days;
+      }
+   };
+   for line in BufReader::new(file).lines() {
+      let line = match line {
+         Ok(line) => line,
+         Err(_) => break,
+      };
+      match serde_json::from_str::<ArchivedGame>(&line) {
+         Ok(game) => game.add_to(&mut days, now),
+         Err(e) => debug!("Skipping malformed archive line: {:?}", e),
+      }
+   }
+   days
+}
+
+/// `start_day` inclusive, `end_day` exclusive
+fn summarize_period(days: &Days, start_day: u64, end_day: u64) -> StatsSummary {
+   let mut summary = StatsSummary {
+      period_start: start_day * SECS_PER_DAY,
+      ..StatsSummary::default()
+   };
+   let mut names = HashSet::new();
+   let (mut ai_seats, mut ai_wins, mut human_seats, mut human_wins) = (0u64, 0u64, 0u64, 0u64);
+   for totals in days.range(start_day..end_day).map(|(_, x)| x) {
+      summary.games_played += totals.games_played;
+      ai_seats += totals.ai_seats;
+      ai_wins += totals.ai_wins;
+      human_seats += totals.human_seats;
+      human_wins += totals.human_wins;
+      names.extend(totals.human_names.iter());
+   }
+   summary.unique_names = names.len() as u64;
+   summary.ai_win_rate = if ai_seats > 0 {
+      Some(ai_wins as f64 / ai_seats as f64)
+   } else {
+      None
+   };
+   summary.human_win_rate = if human_seats > 0 {
+      Some(human_wins as f64 / human_seats as f64)
+   } else {
+      None
+   };
+   summary
+}
+
+fn summarize(days: &Days, now: u64) -> StatsSummaries {
+   let today = now / SECS_PER_DAY;
+   let daily = (0..SUMMARY_DAYS)
+      .filter_map(|i| today.checked_sub(i))
+      .map(|day| summarize_period(days, day, day + 1))
+      .collect();
+   let this_week = (today + EPOCH_WEEK_OFFSET_DAYS) / 7;
+   let weekly = (0..SUMMARY_WEEKS)
+      .filter_map(|i| this_week.checked_sub(i))
+      .map(|week| {
+         let first_day = (week * 7).saturating_sub(EPOCH_WEEK_OFFSET_DAYS);
+         let end_day = (week * 7 + 7).saturating_sub(EPOCH_WEEK_OFFSET_DAYS);
+         summarize_period(days, first_day, end_day)
+      })
+      .collect();
+   StatsSummaries { daily, weekly }
+}
+
+impl GameArchive {
+   /// With no path, or a path that can't be opened, games are not archived.
+   /// Games already in the archive count towards the summaries
+   pub fn open(path: Option<&Path>) -> GameArchive {
+      let file = path.and_then(|path| match OpenOptions::new().create(true).append(true).open(path) {
+         Ok(file) => Some(file),
+         Err(e) => {
+            error!("Failed to open game archive at {:?}: {:?}", path, e);
+            None
+         }
+      });
+      let days = match (path, &file) {
+         (Some(path), Some(_)) => load_days(path, unix_now()),
+         _ => Days::new(),
+      };
+      let (lines, writer) = match file {
+         Some(file) => {
+            let (lines, incoming) = mpsc::channel();
+            (
+               Some(lines),
+               Some(std::thread::spawn(move || write_lines(file, incoming))),
+            )
+         }
+         None => (None, None),
+      };
+      GameArchive {
+         lines,
+         writer,
+         days: Mutex::new(days),
+      }
+   }
+
+   pub fn is_enabled(&self) -> bool {
+      self.lines.is_some()
+   }
+
+   /// Only serializes the record, leaving the write to the writer thread
+   pub fn record(&self, record: &GameRecord) {
+      let lines = match self.lines {
+         Some(ref lines) => lines,
+         None => return,
+      };
+      let now = unix_now();
+      let players = record
+         .players
+         .iter()
+         .map(|x| (x.name, x.turn_number, x.ai_strategy.is_some()));
+      add_game(
+         &mut self.days.lock().unwrap(),
+         now,
+         now,
+         players,
+         record.finish_order.first().copied(),
+      );
+      let line = ArchiveLine {
+         completed_at: now,
+         record,
+      };
+      let mut line = match serde_json::to_vec(&line) {
+         Ok(line) => line,
+         Err(e) => {
+            error!("Failed to serialize game record: {:?}", e);
+            return;
+         }
+      };
+      line.push(b'\n');
+      let _ = lines.send(line);
+   }
+
+   pub fn summaries(&self) -> StatsSummaries {
+      summarize(&self.days.lock().unwrap(), unix_now())
+   }
+}
+
+/// Waits for every game recorded so far to be written
+impl Drop for GameArchive {
+   fn drop(&mut self) {
+      self.lines = None;
+      if let Some(writer) = self.writer.take() {
+         let _ = writer.join();
+      }
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn appends_one_line_per_game() {
+      let path = std::env::temp_dir().join(format!("palace_archive_test_{}.jsonl", std::process::id()));
+      let _ = std::fs::remove_file(&path);
+      let archive = GameArchive::open(Some(&path));
+      let record = GameRecord {
+         lobby_name: "Lobby",
+         players: vec![
+            ArchivedPlayer {
+               name: "Alice",
+               turn_number: 0,
+               ai_strategy: None,
+               clandestine: false,
+            },
+            ArchivedPlayer {
+               name: "BOT Bob",
+               turn_number: 1,
+               ai_strategy: Some("Random"),
+               clandestine: false,
+            },
+         ],
+         finish_order: &[1, 0],
+         turns: 40,
+         duration_secs: 120,
+         turn_timer_secs: 50,
+         max_players: 4,
+         seed: 7,
+         highlights: &[],
+         commentary: &[],
+      };
+      archive.record(&record);
+      archive.record(&record);
+      assert_eq!(archive.summaries().daily[0].games_played, 2);
+      drop(archive);
+      // games already archived are summarized without being kept around
+      let reopened = GameArchive::open(Some(&path));
+      assert_eq!(reopened.summaries().daily[0].games_played, 2);
+      drop(reopened);
+      let contents = std::fs::read_to_string(&path).unwrap();
+      let _ = std::fs::remove_file(&path);
+      let lines: Vec<serde_json::Value> = contents.lines().map(|x| serde_json::from_str(x).unwrap()).collect();
+      assert_eq!(lines.len(), 2);
+      assert_eq!(lines[0]["lobby_name"], "Lobby");
+      assert_eq!(lines[0]["players"][1]["ai_strategy"], "Random");
+      assert!(lines[0]["completed_at"].is_u64());
+   }
+
+   #[cfg(test)]
+   fn game(completed_at: u64, players: &[(&str, bool)], winner: u8) -> ArchivedGame {
+      ArchivedGame {
+         completed_at,
+         players: players
+            .iter()
+            .enumerate()
+            .map(|(i, (name, is_ai))| SummaryPlayer {
+               name: name.to_string(),
+               turn_number: i as u8,
+               ai_strategy: if *is_ai { Some("Monty".into()) } else { None },
+            })
+            .collect(),
+         finish_order: vec![winner],
+      }
+   }
+
+   #[test]
+   fn summaries_split_by_day_and_week() {
+      // Monday, 12:00 UTC
+      let monday = 19_996 * SECS_PER_DAY + SECS_PER_DAY / 2;
+      let games = [
+         game(monday, &[("Alice", false), ("BOT Bob", true)], 0),
+         game(monday - SECS_PER_DAY, &[("Alice", false), ("Carol", false)], 1),
+         game(monday - SECS_PER_DAY, &[("Dave", false), ("BOT Eve", true)], 1),
+      ];
+      let mut days = Days::new();
+      for game in games.iter() {
+         game.add_to(&mut days, monday);
+      }
+      let summaries = summarize(&days, monday);
+      assert_eq!(summaries.daily.len() as u64, SUMMARY_DAYS);
+      assert_eq!(summaries.daily[0].games_played, 1);
+      assert_eq!(summaries.daily[0].ai_win_rate, Some(0.0));
+      assert_eq!(summaries.daily[1].games_played, 2);
+      assert_eq!(summaries.daily[1].unique_names, 3);
+      assert_eq!(summaries.daily[1].ai_win_rate, Some(1.0));
+      assert_eq!(summaries.daily[1].human_win_rate, Some(1.0 / 3.0));
+      assert_eq!(summaries.daily[2].ai_win_rate, None);
+      // Sunday's games belong to the previous week
+      assert_eq!(summaries.weekly[0].period_start, monday - SECS_PER_DAY / 2);
+      assert_eq!(summaries.weekly[0].games_played, 1);
+      assert_eq!(summaries.weekly[1].games_played, 2);
+   }
+
+   #[test]
+   fn days_too_old_to_summarize_are_dropped() {
+      let now = 19_996 * SECS_PER_DAY + SECS_PER_DAY / 2;
+      let mut days = Days::new();
+      game(now - KEPT_DAYS * SECS_PER_DAY, &[("Alice", false)], 0).add_to(&mut days, now);
+      assert!(days.is_empty());
+      game(now - (KEPT_DAYS - 1) * SECS_PER_DAY, &[("Alice", false)], 0).add_to(&mut days, now);
+      assert_eq!(days.len(), 1);
+      let later = now + SECS_PER_DAY;
+      game(later, &[("Alice", false)], 0).add_to(&mut days, later);
+      assert_eq!(days.keys().copied().collect::<Vec<_>>(), vec![later / SECS_PER_DAY]);
+   }
+}
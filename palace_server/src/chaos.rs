@@ -0,0 +1,48 @@
+//! Test-only fault injection for outgoing messages, so that tests can check clients recover from a bad connection
+
+use lazy_static::lazy_static;
+use rand::Rng;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Chances are per outgoing message, and are checked in the order drop, delay, disconnect
+#[derive(Clone, Debug)]
+pub struct ChaosConfig {
+   pub drop_chance: f64,
+   pub delay_chance: f64,
+   pub max_delay_millis: u64,
+   /// Closes the connection instead of sending the message
+   pub disconnect_chance: f64,
+}
+
+pub(crate) enum Fault {
+   Drop,
+   /// Later messages may overtake a delayed one
+   Delay(Duration),
+   Disconnect,
+}
+
+lazy_static! {
+   static ref CHAOS: RwLock<Option<ChaosConfig>> = RwLock::new(None);
+}
+
+/// Starts (or with None, stops) interfering with every connection on the server
+pub fn set_chaos(config: Option<ChaosConfig>) {
+   *CHAOS.write().unwrap() = config;
+}
+
+pub(crate) fn roll() -> Option<Fault> {
+   let chaos = CHAOS.read().unwrap();
+   let config = chaos.as_ref()?;
+   let mut rng = rand::thread_rng();
+   if rng.gen_bool(config.drop_chance) {
+      Some(Fault::Drop)
+   } else if rng.gen_bool(config.delay_chance) {
+      let delay_millis = rng.gen_range(0, config.max_delay_millis + 1);
+      Some(Fault::Delay(Duration::from_millis(delay_millis)))
+   } else if rng.gen_bool(config.disconnect_chance) {
+      Some(Fault::Disconnect)
+   } else {
+      None
+   }
+}
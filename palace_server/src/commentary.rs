@@ -0,0 +1,199 @@
+//! Human-readable remarks on plays, for spectators and for archived games
+
+use crate::game::{Card, CardValue, CardZone, GameState, Phase, PlayOutcome};
+use schemars::JsonSchema;
+use serde_derive::Serialize;
+
+/// A play as the commentator sees it
+pub struct Play<'a> {
+   pub player: u8,
+   pub player_name: &'a str,
+   /// The cards that went onto the pile, including a face down card once flipped. Empty during setup
+   pub cards: &'a [Card],
+   pub outcome: PlayOutcome,
+}
+
+/// Run by the server after each successful play
+pub trait Commentator: Send + Sync {
+   /// None if the play isn't worth remarking on
+   fn on_turn(&self, gs_before: &GameState, play: &Play, gs_after: &GameState) -> Option<String>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Commentary {
+   /// Counts turns the same way as `GameState::turns_taken`, starting from 1
+   pub turn: u32,
+   pub player: u8,
+   pub text: String,
+}
+
+/// Remarks on pickups, burns and players going out, in English
+pub struct EnglishCommentator;
+
+fn value_name(value: CardValue) -> &'static str {
+   match value {
+      CardValue::Two => "2",
+      CardValue::Three => "3",
+      CardValue::Four => "4",
+      CardValue::Five => "5",
+      CardValue::Six => "6",
+      CardValue::Seven => "7",
+      CardValue::Eight => "8",
+      CardValue::Nine => "9",
+      CardValue::Ten => "10",
+      CardValue::Jack => "Jack",
+      CardValue::Queen => "Queen",
+      CardValue::King => "King",
+      CardValue::Ace => "Ace",
+   }
+}
+
+fn with_article(value: CardValue) -> String {
+   match value {
+      CardValue::Eight | CardValue::Ace => format!("an {}", value_name(value)),
+      _ => format!("a {}", value_name(value)),
+   }
+}
+
+/// Places count from 0, as in `GameState::out_players`
+fn ordinal(place: usize) -> String {
+   let place = place + 1;
+   let suffix = match (place % 10, place % 100) {
+      (_, 11..=13) => "th",
+      (1, _) => "st",
+      (2, _) => "nd",
+      (3, _) => "rd",
+      _ => "th",
+   };
+   format!("{}{}", place, suffix)
+}
+
+impl Commentator for EnglishCommentator {
+   fn on_turn(&self, gs_before: &GameState, play: &Play, gs_after: &GameState) -> Option<String> {
+      if gs_before.cur_phase == Phase::Setup {
+         return None;
+      }
+      let value = play.cards.first()?.value;
+      let name = play.player_name;
+      if play.outcome.picked_up {
+         let picked_up = gs_before.pile_cards.len() + play.cards.len();
+         return Some(if gs_after.last_played_zone == Some(CardZone::FaceDownThree) {
+            format!(
+               "{} flipped {} from their face down cards and had to pick up {} cards",
+               name,
+               with_article(value),
+               picked_up
+            )
+         } else {
+            format!("{} picked up the pile of {} cards", name, picked_up)
+         });
+      }
+      if play.outcome.player_out {
+         if let Some(place) = gs_after.out_players.iter().position(|x| *x == play.player) {
+            return Some(if place == 0 {
+               format!("{} played their last card and won", name)
+            } else {
+               format!("{} played their last card, finishing {}", name, ordinal(place))
+            });
+         }
+      }
+      if play.outcome.cleared {
+         return Some(if value == CardValue::Ten {
+            format!("{} burned the pile with a 10", name)
+         } else {
+            format!("{} burned the pile by completing a set of {}s", name, value_name(value))
+         });
+      }
+      None
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::game::CardSuit;
+
+   #[cfg(test)]
+   fn card(value: CardValue) -> Card {
+      Card {
+         value,
+         suit: CardSuit::Clubs,
+         id: None,
+      }
+   }
+
+   /// Has player 0 of a two player game play `cards` from `hand` onto `pile`, returning what was said
+   #[cfg(test)]
+   fn comment(hand: Vec<Card>, pile: Vec<Card>, cards: &[Card]) -> Option<String> {
+      let mut gs = GameState::new(2);
+      gs.cur_phase = Phase::Play;
+      gs.hands[0] = hand;
+      gs.pile_cards = pile;
+      let gs_before = gs.clone();
+      let outcome = gs.take_turn(cards).unwrap();
+      let play = Play {
+         player: 0,
+         player_name: "Alice",
+         cards: &gs.last_cards_played,
+         outcome,
+      };
+      EnglishCommentator.on_turn(&gs_before, &play, &gs)
+   }
+
+   #[test]
+   fn remarks_on_burns_and_pickups() {
+      let burned = comment(
+         vec![card(CardValue::Ten), card(CardValue::Three)],
+         vec![card(CardValue::Five)],
+         &[card(CardValue::Ten)],
+      );
+      assert_eq!(burned.as_deref(), Some("Alice burned the pile with a 10"));
+      let picked_up = comment(
+         vec![card(CardValue::Three), card(CardValue::Six)],
+         vec![card(CardValue::Five), card(CardValue::King)],
+         &[card(CardValue::Three)],
+      );
+      assert_eq!(picked_up.as_deref(), Some("Alice picked up the pile of 3 cards"));
+   }
+
+   #[test]
+   fn remarks_on_winning() {
+      let mut gs = GameState::new(2);
+      gs.cur_phase = Phase::Play;
+      gs.hands[0] = vec![card(CardValue::Ace)];
+      gs.face_up_three[0].clear();
+      gs.face_down_three[0].clear();
+      gs.pile_cards.clear();
+      let gs_before = gs.clone();
+      let outcome = gs.take_turn(&[card(CardValue::Ace)]).unwrap();
+      assert!(outcome.game_over);
+      let play = Play {
+         player: 0,
+         player_name: "Alice",
+         cards: &gs.last_cards_played,
+         outcome,
+      };
+      assert_eq!(
+         EnglishCommentator.on_turn(&gs_before, &play, &gs).as_deref(),
+         Some("Alice played their last card and won")
+      );
+   }
+
+   #[test]
+   fn ordinary_plays_go_unremarked() {
+      let said = comment(
+         vec![card(CardValue::Six), card(CardValue::Three)],
+         vec![card(CardValue::Five)],
+         &[card(CardValue::Six)],
+      );
+      assert_eq!(said, None);
+   }
+
+   #[test]
+   fn ordinals() {
+      assert_eq!(ordinal(1), "2nd");
+      assert_eq!(ordinal(10), "11th");
+      assert_eq!(ordinal(20), "21st");
+   }
+}
@@ -1,31 +1,77 @@
-use crate::game::{Card, PublicGameState};
-use crate::{LobbyDisplay, LobbyId, PlayerId};
+use crate::ai::{AiStrategy, CandidateMove, MontyParameters};
+use crate::commentary::Commentary;
+use crate::game::{Card, CardValue, DrawReason, DrawRules, Highlight, PublicGameState};
+use crate::{JoinToken, LobbyDisplay, LobbyId, PlayerId};
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 
-pub fn default_turn_timer_secs() -> u8 {
+pub fn default_turn_timer_secs() -> u16 {
    50
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct NewLobbyMessage {
    pub max_players: u8,
    pub password: String,
    pub lobby_name: String,
    pub player_name: String,
+   /// Seconds each player has to take their turn, or 0 for no limit. Otherwise at least 5
    #[serde(default = "default_turn_timer_secs")]
-   pub turn_timer: u8,
+   pub turn_timer: u16,
+   /// Seconds humans have to take their turn when that's longer than `turn_timer`, so that AI can be held to less.
+   /// Has no effect without a turn timer
+   #[serde(default)]
+   pub min_human_turn_timer: u16,
+   #[serde(default)]
+   pub description: String,
+   #[serde(default)]
+   pub after_game: AfterGamePolicy,
+   #[serde(default)]
+   pub hints: HintPolicy,
+   /// A lobby for one human against AI, who can rewind their turns. Nobody else can join
+   #[serde(default)]
+   pub practice: bool,
+   /// The name of a tutorial scenario, which decides the seats, the cards and how the AI plays
+   #[serde(default)]
+   pub scenario: Option<String>,
+   /// When games in the lobby that aren't getting anywhere are called a draw
+   #[serde(default)]
+   pub draw_rules: DrawRules,
 }
 
-#[derive(Serialize)]
+/// Whether players in the lobby can ask the server to suggest a play
+#[derive(Copy, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub enum HintPolicy {
+   #[default]
+   Disabled,
+   /// Each player can have this many hints per game
+   PerGame(u16),
+}
+
+/// What happens to the lobby once a game in it is completed
+#[derive(Copy, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub enum AfterGamePolicy {
+   #[default]
+   Persist,
+   /// The lobby closes this long after the game ends, unless another game is started first
+   CloseAfterSecs(u16),
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LobbyClosingSoonEvent {
+   pub secs_remaining: u16,
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct NewLobbyResponse {
    pub player_id: PlayerId,
    pub lobby_id: LobbyId,
    pub max_players: u8,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub enum NewLobbyError {
    LessThanTwoMaxPlayers,
    EmptyLobbyName,
@@ -33,25 +79,42 @@ pub enum NewLobbyError {
    LobbyNameTooLong,
    PlayerNameTooLong,
    PasswordTooLong,
+   DescriptionTooLong,
+   ServerDraining,
+   /// The server has as many lobbies open as it allows
+   ServerFull,
+   /// Too many lobbies have been created from this address recently
+   TooManyLobbies,
+   UnknownScenario,
+   /// Turn timers under 5 seconds aren't allowed
+   TurnTimerTooShort,
+   /// A position has to be allowed to come up at least twice before the game is drawn
+   TooFewRepetitions,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct JoinLobbyMessage {
    pub lobby_id: LobbyId,
    pub player_name: String,
    pub password: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct JoinLobbyResponse<'a> {
    pub player_id: PlayerId,
-   pub lobby_players: Vec<&'a str>,
+   /// The seat we were given. Names needn't be unique, so other players refer to us by slot
+   pub slot: u8,
+   /// By slot
+   pub lobby_players: Vec<LobbySeat<'a>>,
    pub max_players: u8,
-   pub num_spectators: u8,
-   pub turn_timer: u8,
+   pub num_spectators: u16,
+   pub turn_timer: u16,
+   pub min_human_turn_timer: u16,
+   /// The lobby's latest journal seq, for catching up from with `CatchUp`
+   pub journal_seq: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum JoinLobbyError {
    LobbyNotFound,
    LobbyFull,
@@ -59,121 +122,261 @@ pub enum JoinLobbyError {
    GameInProgress,
    EmptyPlayerName,
    PlayerNameTooLong,
+   ServerDraining,
+   PracticeLobby,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct SpectateLobbyResponse<'a> {
-   pub lobby_players: Vec<&'a str>,
+   /// By slot
+   pub lobby_players: Vec<LobbySeat<'a>>,
    pub max_players: u8,
-   pub num_spectators: u8,
-   pub turn_timer: u8,
+   pub num_spectators: u16,
+   pub turn_timer: u16,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum SpectateLobbyError {
    LobbyNotFound,
    SpectateLobbyFull,
+   /// We have a seat in the lobby, so we already see everything
+   AlreadyInLobby,
+   AlreadySpectating,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum StopSpectatingError {
+   NotSpectating,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+/// Claim an open seat in a lobby we're spectating, before its game starts
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct TakeSeatMessage {
+   pub lobby_id: LobbyId,
+   pub player_name: String,
+   pub password: String,
+}
+
+/// Join with a token from the lobby owner instead of the password
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct JoinWithTokenMessage {
+   pub lobby_id: LobbyId,
+   pub token: JoinToken,
+   pub player_name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum JoinWithTokenError {
+   /// The token is unknown, expired, or used up
+   BadToken,
+   CantJoin(JoinLobbyError),
+}
+
+/// Hand out a token that lets people join without the password
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct CreateJoinTokenMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub expires_in_secs: u32,
+   /// How many players can join with the token
+   pub max_uses: u16,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum CreateJoinTokenError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   /// Tokens must expire, within a week
+   InvalidExpiry,
+   InvalidMaxUses,
+   /// The lobby already has as many unexpired tokens as it can
+   TooManyTokens,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum TakeSeatError {
+   NotSpectating,
+   CantJoin(JoinLobbyError),
+}
+
+/// Wait for a seat in a full lobby. Queueing gives up any seat held elsewhere
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct QueueForSeatMessage {
+   pub lobby_id: LobbyId,
+   pub player_name: String,
+   pub password: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum QueueForSeatError {
+   LobbyNotFound,
+   LobbyNotFull,
+   GameInProgress,
+   BadPassword,
+   AlreadyInLobby,
+   QueueFull,
+   EmptyPlayerName,
+   PlayerNameTooLong,
+   ServerDraining,
+   PracticeLobby,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct QueuePositionEvent {
+   /// 1 is next in line
+   pub position: u16,
+}
+
+#[derive(Copy, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct StartGameMessage {
    pub lobby_id: LobbyId,
    pub player_id: PlayerId,
+   /// Fill every remaining open seat with this strategy before starting
+   #[serde(default)]
+   pub fill_with_ai: Option<AiStrategy>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct TakeTurnMessage {
    pub lobby_id: LobbyId,
    pub player_id: PlayerId,
    pub cards: Box<[Card]>,
+   /// Increases with every play this game. Resending a play with the same number is acknowledged without
+   /// playing it twice
+   #[serde(default)]
+   pub play_seq: Option<u32>,
 }
 
-#[derive(Serialize)]
+/// What everyone at the table knows about a player
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct PlayerProfile {
+   pub name: String,
+   pub is_ai: bool,
+   /// Always None until the server tracks ratings
+   pub rating: Option<u32>,
+   /// Always None until the server has accounts
+   pub account_id: Option<String>,
+}
+
+impl PlayerProfile {
+   pub fn new(name: String, is_ai: bool) -> PlayerProfile {
+      PlayerProfile {
+         name,
+         is_ai,
+         rating: None,
+         account_id: None,
+      }
+   }
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct GameStartEvent<'a> {
    pub hand: &'a [Card],
    pub turn_number: u8,
-   pub players: &'a HashMap<u8, String>,
+   /// Keyed by turn number
+   pub players: &'a HashMap<u8, PlayerProfile>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct SpectateGameStartEvent<'a> {
-   pub players: &'a HashMap<u8, String>,
+   pub players: &'a HashMap<u8, PlayerProfile>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum StartGameError {
    LobbyNotFound,
    NotLobbyOwner,
    LessThanTwoPlayers,
    GameInProgress,
+   StrategyUnavailable,
+   /// Scenarios are only played with every seat filled
+   ScenarioSeatsEmpty,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct ReconnectMessage {
    pub player_id: PlayerId,
    pub lobby_id: LobbyId,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ReconnectResponse {
    pub max_players: u8,
-   pub num_spectators: u8,
-   pub turn_timer: u8,
+   pub num_spectators: u16,
+   pub turn_timer: u16,
+   /// The lobby's latest journal seq, for catching up from with `CatchUp`
+   pub journal_seq: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct KickPlayerMessage {
    pub player_id: PlayerId,
    pub lobby_id: LobbyId,
    pub slot: u8,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum TakeTurnError {
    LobbyNotFound,
    GameNotStarted,
    PlayerNotFound,
    NotYourTurn,
+   /// The play's sequence number is lower than one already accepted
+   StalePlaySeq,
    GameError(&'static str),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum ReconnectError {
    LobbyNotFound,
    PlayerNotFound,
    PlayerKicked,
+   TooManyAttempts,
+   ReplacedByAi,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct PlayerJoinEvent<'a> {
    pub total_num_players: u8,
    pub new_player_name: &'a str,
    pub slot: u8,
+   /// Where this is in the lobby's journal, for catching up from with `CatchUp`
+   pub seq: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct PlayerLeaveEvent {
    pub total_num_players: u8,
    pub slot: u8,
+   /// Where this is in the lobby's journal, for catching up from with `CatchUp`
+   pub seq: u64,
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct RequestAiMessage {
    pub lobby_id: LobbyId,
    pub player_id: PlayerId,
    pub num_ai: u8,
+   #[serde(default)]
+   pub strategy: AiStrategy,
+   /// Only used when `strategy` is Monty
+   #[serde(default)]
+   pub monty: Option<MontyParameters>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum RequestAiError {
    NotLobbyOwner,
    LessThanOneAiRequested,
    LobbyNotFound,
    LobbyTooSmall,
    GameInProgress,
+   StrategyUnavailable,
+   NotEnoughVacantSeats,
+   InvalidMontyParameters,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum KickPlayerError {
    NotLobbyOwner,
    LobbyNotFound,
@@ -182,56 +385,1035 @@ pub enum KickPlayerError {
    CantKickAiDuringGame,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SwapAiStrategyMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+   pub slot: u8,
+   pub strategy: AiStrategy,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum SwapAiStrategyError {
+   NotLobbyOwner,
+   LobbyNotFound,
+   TargetPlayerNotFound,
+   TargetNotAi,
+   StrategyUnavailable,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SetSeatClosedMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+   pub slot: u8,
+   pub closed: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum SetSeatClosedError {
+   NotLobbyOwner,
+   LobbyNotFound,
+   GameInProgress,
+   SeatNotFound,
+   SeatOccupied,
+   LessThanTwoOpenSeats,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct TransferOwnershipMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+   pub slot: u8,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum TransferOwnershipError {
+   NotLobbyOwner,
+   LobbyNotFound,
+   TargetPlayerNotFound,
+   TargetIsOwner,
+   TargetNotHuman,
+   TargetDisconnected,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct OwnerChangedEvent {
+   pub slot: u8,
+}
+
+/// Sent only to the player whose turn it now is
+#[derive(Serialize, JsonSchema)]
+pub struct YourTurnEvent {
+   /// Seconds left to take the turn, or None if the lobby has no turn timer
+   pub deadline: Option<u16>,
+}
+
+/// Sent only to the active player, shortly before a random play is made for them
+#[derive(Serialize, JsonSchema)]
+pub struct TurnTimeWarningEvent {
+   pub secs_remaining: u16,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct AbortGameMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum AbortGameError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ConcedeMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum ConcedeError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   /// Everyone has to finish choosing their face up cards first
+   GameInSetup,
+   /// The player already finished or conceded
+   AlreadyOut,
+}
+
+/// A vote to have the turn in progress played for the player on turn, who keeps their seat
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct VoteSkipTurnMessage {
+   pub player_id: PlayerId,
+   pub lobby_id: LobbyId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum VoteSkipTurnError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   /// The turn in progress is ours
+   OwnTurn,
+   /// Only the turns of connected humans can be skipped. The server already plays everyone else's
+   ActivePlayerNotConnected,
+}
+
+/// A player gave up and is out of the game, placed behind everyone still playing
+#[derive(Serialize, JsonSchema)]
+pub struct PlayerConcededEvent {
+   pub slot: u8,
+}
+
+#[derive(Copy, Clone, Serialize, JsonSchema)]
+pub enum GameAbortReason {
+   Owner,
+   Vote,
+}
+
+/// The game was called a draw. Like an aborted game, nobody wins
+#[derive(Serialize, JsonSchema)]
+pub struct GameDrawEvent {
+   pub reason: DrawReason,
+   pub turns: u32,
+}
+
+/// Sent as each human votes to abort, until the vote is unanimous
+#[derive(Serialize, JsonSchema)]
+pub struct AbortVoteEvent {
+   pub slot: u8,
+   pub votes: u8,
+   pub needed: u8,
+}
+
+/// Sent as each human votes to skip the turn in progress, until everyone but the player on turn has
+#[derive(Serialize, JsonSchema)]
+pub struct SkipTurnVoteEvent {
+   pub slot: u8,
+   pub votes: u8,
+   pub needed: u8,
+}
+
+/// The player on turn was voted past, so their turn is played for them as if it had run out
+#[derive(Serialize, JsonSchema)]
+pub struct TurnSkippedEvent {
+   pub slot: u8,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LobbySettingsEvent<'a> {
+   pub max_players: u8,
+   pub closed_seats: &'a [u8],
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SetLobbyDescriptionMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub description: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum SetLobbyDescriptionError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   DescriptionTooLong,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LobbyInfoEvent<'a> {
+   pub description: &'a str,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, JsonSchema)]
+pub enum ConnectionStatus {
+   Connected,
+   Disconnected,
+   TimedOut,
+   Kicked,
+   /// The seat was taken over by an AI, mid-game
+   ReplacedByAi,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PlayerConnectionStatusEvent {
+   pub slot: u8,
+   pub status: ConnectionStatus,
+}
+
+/// Whether a player seems to be at the keyboard
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, JsonSchema)]
+pub enum Presence {
+   Active,
+   /// Nothing has been heard from the player for a while
+   Idle,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PlayerPresenceEvent {
+   pub slot: u8,
+   pub presence: Presence,
+}
+
+/// Names are only for display, since two players can share one. The slot is what identifies the player
+#[derive(Serialize, JsonSchema)]
+pub struct LobbySeat<'a> {
+   pub slot: u8,
+   pub name: &'a str,
+   pub is_ai: bool,
+   pub is_owner: bool,
+   pub status: ConnectionStatus,
+   pub presence: Presence,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum GetLobbyPlayersError {
+   LobbyNotFound,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub enum AnnouncementSeverity {
+   Info,
+   Warning,
+   Critical,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct AnnounceMessage {
+   pub admin_key: String,
+   /// When absent, the announcement goes to every connection on the server
+   pub lobby_id: Option<LobbyId>,
+   pub severity: AnnouncementSeverity,
+   pub text: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum AnnounceError {
+   NotAdmin,
+   LobbyNotFound,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ServerAnnouncementEvent<'a> {
+   pub severity: AnnouncementSeverity,
+   pub text: &'a str,
+}
+
+impl<'a> ServerAnnouncementEvent<'a> {
+   pub fn info(text: &'a str) -> ServerAnnouncementEvent<'a> {
+      ServerAnnouncementEvent {
+         severity: AnnouncementSeverity::Info,
+         text,
+      }
+   }
+
+   pub fn warning(text: &'a str) -> ServerAnnouncementEvent<'a> {
+      ServerAnnouncementEvent {
+         severity: AnnouncementSeverity::Warning,
+         text,
+      }
+   }
+
+   pub fn critical(text: &'a str) -> ServerAnnouncementEvent<'a> {
+      ServerAnnouncementEvent {
+         severity: AnnouncementSeverity::Critical,
+         text,
+      }
+   }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SetDrainModeMessage {
+   pub admin_key: String,
+   pub draining: bool,
+   /// When the operator intends to shut the server down, counted from now
+   pub shutdown_in_secs: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum SetDrainModeError {
+   NotAdmin,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ServerDrainingEvent {
+   pub draining: bool,
+   pub shutdown_in_secs: Option<u64>,
+}
+
+/// Why the server is closing a connection
+#[derive(Copy, Clone, Debug, Serialize, JsonSchema)]
+pub enum DisconnectCode {
+   /// A message couldn't be decoded. Reconnecting won't help until the client is fixed
+   ProtocolError,
+   /// Too many attempts to reconnect to a seat. Wait a while before trying again
+   RateLimited,
+   /// The lobby owner removed this player
+   Kicked,
+   /// The player was removed for not taking their turns
+   Afk,
+   /// The server is shutting down. Reconnecting to another server may work
+   ServerDraining,
+   /// Messages to this connection were backing up, so the server gave up on it
+   Unreachable,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DisconnectEvent {
+   pub code: DisconnectCode,
+   /// Human readable detail, meant for logs rather than for players
+   pub reason: String,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SubscribeServerStatsMessage {
+   pub admin_key: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum SubscribeServerStatsError {
+   NotAdmin,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct GetStatsSummariesMessage {
+   pub admin_key: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum GetStatsSummariesError {
+   NotAdmin,
+   ArchiveDisabled,
+}
+
+/// Aggregated from the game archive, over one UTC day or week
+#[derive(Clone, Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct StatsSummary {
+   /// Unix time at which the period began. Weeks begin on Monday
+   pub period_start: u64,
+   pub games_played: u64,
+   /// Distinct human player names
+   pub unique_names: u64,
+   /// Wins per seat played. None if no AI played in the period
+   pub ai_win_rate: Option<f64>,
+   pub human_win_rate: Option<f64>,
+}
+
+/// Most recent period first
+#[derive(Clone, Default, Serialize, JsonSchema)]
+pub struct StatsSummaries {
+   pub daily: Vec<StatsSummary>,
+   pub weekly: Vec<StatsSummary>,
+}
+
+/// A periodic snapshot of server health, sent to subscribed admins
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct ServerStatsEvent {
+   pub lobbies: u64,
+   pub games_in_progress: u64,
+   pub players: u64,
+   pub ai_players: u64,
+   pub spectators: u64,
+   pub messages_per_sec: f64,
+   /// Games currently waiting on an AI to take its turn
+   pub ai_queue_depth: u64,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct GetLobbyStatsMessage {
+   pub lobby_id: LobbyId,
+   /// Either the lobby owner's id or an admin key must be provided
+   pub player_id: Option<PlayerId>,
+   pub admin_key: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SeatPlacements<'a> {
+   pub slot: u8,
+   pub name: &'a str,
+   /// Finishing position (0 is first place) in each completed game this player took part in
+   pub placements: &'a [u8],
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LobbyStatsResponse<'a> {
+   pub games_completed: u64,
+   pub total_players_seen: u64,
+   pub average_game_secs: u64,
+   pub seat_placements: Vec<SeatPlacements<'a>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum GetLobbyStatsError {
+   LobbyNotFound,
+   NotLobbyOwner,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct RequestHintMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+}
+
+/// Sent only to the player who asked
+#[derive(Serialize, JsonSchema)]
+pub struct Hint {
+   /// Empty when the only play is a face down card
+   pub cards: Box<[Card]>,
+   /// Hints the player has left this game
+   pub hints_left: u16,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum RequestHintError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   NotYourTurn,
+   /// The lobby owner didn't allow hints
+   HintsDisabled,
+   NoHintsLeft,
+   /// Hints are rate-limited, even while the player has some left
+   TooSoon,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct RewindTurnMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   /// How many of the player's own turns to take back
+   pub turns: u16,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum RewindTurnError {
+   LobbyNotFound,
+   PlayerNotFound,
+   NotPracticeLobby,
+   GameNotStarted,
+   ZeroTurns,
+   /// The player hasn't taken that many turns this game
+   NotEnoughTurns,
+}
+
+/// The game went back to before one of the human's turns. Everyone's hand and the public state follow
+#[derive(Serialize, JsonSchema)]
+pub struct GameRewoundEvent {
+   pub turns_taken: u32,
+}
+
+/// A spectator's guess at a play, by what it puts on the pile rather than exactly which cards
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, JsonSchema)]
+pub enum PredictedPlay {
+   /// This many cards of one value, from the hand or face up cards
+   Cards(CardValue, u8),
+   /// Flipping a face down card
+   FaceDown,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct PredictPlayMessage {
+   pub lobby_id: LobbyId,
+   pub play: PredictedPlay,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum PredictPlayError {
+   LobbyNotFound,
+   NotSpectating,
+   GameNotStarted,
+   /// Choosing face up cards can't be guessed, since the cards are hidden from spectators
+   NotDuringSetup,
+   /// Guesses of cards have to be for at least one
+   NoCards,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PredictionTally {
+   pub play: PredictedPlay,
+   pub guesses: u16,
+}
+
+/// Sent to everyone in the lobby after a play that spectators guessed at
+#[derive(Serialize, JsonSchema)]
+pub struct PredictionResultsEvent {
+   /// Counts turns the same way as `GameState::turns_taken`, starting from 1
+   pub turn: u32,
+   pub played: PredictedPlay,
+   /// Most guessed first
+   pub tallies: Vec<PredictionTally>,
+   pub correct: u16,
+}
+
+/// Sent to a spectator after a play they guessed at
+#[derive(Serialize, JsonSchema)]
+pub struct PredictionScoreEvent {
+   pub correct: bool,
+   /// Correct guesses this game
+   pub score: u32,
+}
+
+/// Asks the owner of `target_lobby_id` to take in everyone seated in `lobby_id`
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct MergeLobbyMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub target_lobby_id: LobbyId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum MergeLobbyError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   TargetNotFound,
+   SameLobby,
+   /// In either lobby
+   GameInProgress,
+   /// Practice lobbies and tutorial scenarios can't be merged in either direction
+   NotMergeable,
+   /// The target doesn't have an open seat for everyone
+   NotEnoughSeats,
+   AlreadyRequested,
+}
+
+/// Sent to the owner of the lobby being asked to take another in
+#[derive(Serialize, JsonSchema)]
+pub struct MergeRequestEvent<'a> {
+   pub from_lobby_id: LobbyId,
+   pub lobby_name: &'a str,
+   pub player_names: Vec<&'a str>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct AnswerMergeMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub from_lobby_id: LobbyId,
+   pub accept: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum AnswerMergeError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   /// Nothing was asked, or the asking lobby has since closed
+   NoSuchRequest,
+   GameInProgress,
+   NotEnoughSeats,
+}
+
+/// Sent to the lobby that asked to merge
+#[derive(Serialize, JsonSchema)]
+pub struct MergeDeclinedEvent {
+   pub target_lobby_id: LobbyId,
+}
+
+/// Sent to each player moved by a merge, in place of a `JoinLobbyResponse`. They keep their player id,
+/// but take whatever seat was open
+#[derive(Serialize, JsonSchema)]
+pub struct LobbyMergedEvent<'a> {
+   pub lobby_id: LobbyId,
+   pub slot: u8,
+   pub lobby: JoinLobbyResponse<'a>,
+}
+
+/// Issues a new player id for the same seat, for when the old one may have been seen by others.
+/// Only from a connection playing as the player
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct RotateIdMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RotateIdResponse {
+   pub player_id: PlayerId,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum RotateIdError {
+   LobbyNotFound,
+   PlayerNotFound,
+   /// This connection isn't playing as the player, so has to reconnect first
+   NotConnectedAsPlayer,
+}
+
+/// Asks for the lobby's events after `since_seq`, for a client that may have missed some
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct CatchUpMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub since_seq: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CatchUpResponse {
+   pub latest_seq: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum CatchUpError {
+   LobbyNotFound,
+   PlayerNotFound,
+   /// Some of the events have been forgotten, so the client has to reconnect for a full snapshot instead
+   TooFarBehind,
+}
+
+/// The events kept in a lobby's journal
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub enum LobbyEventKind {
+   Join,
+   Leave,
+   Play,
+}
+
+/// Follows a lobby's public events alongside any other lobbies being observed, for moderators and tournament
+/// directors. Observing a lobby again replaces which kinds of event are passed on
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ObserveLobbyMessage {
+   pub admin_key: String,
+   pub lobby_id: LobbyId,
+   /// Every kind of event is passed on if this is empty
+   #[serde(default)]
+   pub kinds: Vec<LobbyEventKind>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum ObserveLobbyError {
+   LobbyNotFound,
+   NotAdmin,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum StopObservingLobbyError {
+   NotObserving,
+}
+
+/// An event from one of the lobbies being observed, as its players and spectators were sent it
+#[derive(Serialize, JsonSchema)]
+pub struct ObservedLobbyEvent<'a> {
+   pub lobby_id: LobbyId,
+   pub event: &'a PalaceOutMessage<'a>,
+}
+
+/// A lobby the server opens by itself at the same time every day, such as a nightly tournament
+#[derive(Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ScheduledEvent {
+   /// Also the name of the lobby it opens
+   pub name: String,
+   /// As "HH:MM" or "HH:MM:SS"
+   pub opens_at_utc: String,
+   pub max_players: u8,
+   #[serde(default = "default_turn_timer_secs")]
+   pub turn_timer: u16,
+   #[serde(default)]
+   pub description: String,
+   /// Every connection is told this long before the event opens, as well as when it opens
+   #[serde(default)]
+   pub announce_mins_before: u16,
+}
+
+/// Adds an event to the schedule, or replaces the one with the same name, keeping who has registered for it
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ScheduleEventMessage {
+   pub admin_key: String,
+   pub event: ScheduledEvent,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub enum ScheduleEventError {
+   NotAdmin,
+   BadOpeningTime,
+   LessThanTwoMaxPlayers,
+   EmptyName,
+   NameTooLong,
+   DescriptionTooLong,
+   TurnTimerTooShort,
+   /// Announcements can be at most a day ahead
+   AnnouncementTooEarly,
+}
+
+/// Those registered for the event are told it was cancelled
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct UnscheduleEventMessage {
+   pub admin_key: String,
+   pub name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum UnscheduleEventError {
+   NotAdmin,
+   EventNotFound,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct EventListing {
+   pub name: String,
+   pub description: String,
+   pub max_players: u8,
+   pub opens_in_secs: u32,
+   pub num_registered: u16,
+}
+
+/// Registers for the event's next opening. A connection can be registered for one event at a time, so
+/// registering again replaces the earlier registration
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct RegisterForEventMessage {
+   pub event: String,
+   pub player_name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RegisterForEventResponse {
+   pub opens_in_secs: u32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum RegisterForEventError {
+   EventNotFound,
+   EmptyPlayerName,
+   PlayerNameTooLong,
+   /// Enough players have registered to fill the lobby and its seat queue
+   EventFull,
+   ServerDraining,
+}
+
+/// The event we registered for has opened. It's followed by a `SeatGrantedEvent`, or a `QueuePositionEvent`
+/// if the lobby filled up first
+#[derive(Serialize, JsonSchema)]
+pub struct EventOpenedEvent<'a> {
+   pub event: &'a str,
+   pub lobby_id: LobbyId,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct AnalyzeGameMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+}
+
+/// How one of the player's plays compares to what the AI would have done, as the AI saw it from that player's seat
+#[derive(Serialize, JsonSchema)]
+pub struct MoveAnnotation {
+   /// Counts turns the same way as `GameState::turns_taken`, starting from 1
+   pub turn: u32,
+   pub played: Box<[Card]>,
+   pub preferred: Box<[Card]>,
+   /// The estimated chance of finishing first after the preferred move, from 0 to 1
+   pub preferred_win_rate: f64,
+   /// None if the AI didn't look at the played move closely enough to say
+   pub played_win_rate: Option<f64>,
+   /// `played_win_rate` less `preferred_win_rate`, so negative for a worse move than the AI's
+   pub win_rate_swing: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GameAnalysis {
+   pub slot: u8,
+   /// Plays where there was a choice to make, oldest first
+   pub moves: Vec<MoveAnnotation>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub enum AnalyzeGameError {
+   LobbyNotFound,
+   /// Only the most recently completed game in the lobby can be analyzed
+   NoCompletedGame,
+   /// The player didn't take part in the most recently completed game
+   NotInGame,
+   /// Too many games are being analyzed already
+   ServerBusy,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AiDecisionDebugEvent<'a> {
+   pub slot: u8,
+   pub strategy: &'static str,
+   pub candidates: &'a [CandidateMove],
+}
+
+#[derive(Copy, Clone, Serialize, JsonSchema)]
 pub enum LobbyCloseEvent {
    Kicked,
    OwnerLeft,
    Afk,
+   GameOver,
+   /// The lobby was open for longer than the server allows
+   Expired,
+   /// Everyone seated moved to another lobby, and there's no seat there for those left
+   Merged,
+   /// The player's id was rotated from another connection, so this one no longer plays as them
+   IdRotated,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct ListLobbiesMessage {
    pub page: u64,
+   /// Defaults to 50, and is capped at 100
+   #[serde(default)]
+   pub page_size: Option<u64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub enum PalaceInMessage {
    NewLobby(NewLobbyMessage),
    JoinLobby(JoinLobbyMessage),
+   JoinWithToken(JoinWithTokenMessage),
+   CreateJoinToken(CreateJoinTokenMessage),
    ListLobbies(ListLobbiesMessage),
    StartGame(StartGameMessage),
    TakeTurn(TakeTurnMessage),
    Reconnect(ReconnectMessage),
+   AttachToPlayer(ReconnectMessage),
    RequestAi(RequestAiMessage),
    KickPlayer(KickPlayerMessage),
+   SwapAiStrategy(SwapAiStrategyMessage),
+   SetSeatClosed(SetSeatClosedMessage),
+   AbortGame(AbortGameMessage),
+   Concede(ConcedeMessage),
+   VoteSkipTurn(VoteSkipTurnMessage),
+   TransferOwnership(TransferOwnershipMessage),
    SpectateLobby(LobbyId),
+   StopSpectating(LobbyId),
+   TakeSeat(TakeSeatMessage),
+   QueueForSeat(QueueForSeatMessage),
+   SetLobbyDescription(SetLobbyDescriptionMessage),
+   GetLobbyPlayers(LobbyId),
+   Announce(AnnounceMessage),
+   SetDrainMode(SetDrainModeMessage),
+   SubscribeServerStats(SubscribeServerStatsMessage),
+   GetStatsSummaries(GetStatsSummariesMessage),
+   GetLobbyStats(GetLobbyStatsMessage),
+   /// Answered once the analysis is done, which can take a while
+   AnalyzeGame(AnalyzeGameMessage),
+   /// Answered once the hint has been searched for, which can take a moment
+   RequestHint(RequestHintMessage),
+   /// Only in practice lobbies
+   RewindTurn(RewindTurnMessage),
+   /// Only from spectators. A new guess replaces any earlier one for the same play
+   PredictPlay(PredictPlayMessage),
+   /// Only from the lobby's owner. Answered once the request is sent; the target's owner decides
+   MergeLobby(MergeLobbyMessage),
+   AnswerMerge(AnswerMergeMessage),
+   /// The old id stops working, and any other connections playing as the player are sent a `LobbyCloseEvent`
+   RotateId(RotateIdMessage),
+   /// The missed events are sent again first, in order, followed by the response
+   CatchUp(CatchUpMessage),
+   /// Only with the admin key. Any number of lobbies can be observed from one connection
+   ObserveLobby(ObserveLobbyMessage),
+   StopObservingLobby(LobbyId),
+   /// Only with the admin key
+   ScheduleEvent(ScheduleEventMessage),
+   /// Only with the admin key
+   UnscheduleEvent(UnscheduleEventMessage),
+   ListEvents(()),
+   /// Once the event opens, the first registrant still free to play owns its lobby, and everyone else who is
+   /// gets a seat or a place in the seat queue, in the order they registered
+   RegisterForEvent(RegisterForEventMessage),
+}
+
+impl PalaceInMessage {
+   /// The message's type, as sent over the wire
+   pub fn name(&self) -> &'static str {
+      match self {
+         PalaceInMessage::NewLobby(_) => "NewLobby",
+         PalaceInMessage::JoinLobby(_) => "JoinLobby",
+         PalaceInMessage::JoinWithToken(_) => "JoinWithToken",
+         PalaceInMessage::CreateJoinToken(_) => "CreateJoinToken",
+         PalaceInMessage::ListLobbies(_) => "ListLobbies",
+         PalaceInMessage::StartGame(_) => "StartGame",
+         PalaceInMessage::TakeTurn(_) => "TakeTurn",
+         PalaceInMessage::Reconnect(_) => "Reconnect",
+         PalaceInMessage::AttachToPlayer(_) => "AttachToPlayer",
+         PalaceInMessage::RequestAi(_) => "RequestAi",
+         PalaceInMessage::KickPlayer(_) => "KickPlayer",
+         PalaceInMessage::SwapAiStrategy(_) => "SwapAiStrategy",
+         PalaceInMessage::SetSeatClosed(_) => "SetSeatClosed",
+         PalaceInMessage::AbortGame(_) => "AbortGame",
+         PalaceInMessage::Concede(_) => "Concede",
+         PalaceInMessage::VoteSkipTurn(_) => "VoteSkipTurn",
+         PalaceInMessage::TransferOwnership(_) => "TransferOwnership",
+         PalaceInMessage::SpectateLobby(_) => "SpectateLobby",
+         PalaceInMessage::StopSpectating(_) => "StopSpectating",
+         PalaceInMessage::TakeSeat(_) => "TakeSeat",
+         PalaceInMessage::QueueForSeat(_) => "QueueForSeat",
+         PalaceInMessage::SetLobbyDescription(_) => "SetLobbyDescription",
+         PalaceInMessage::GetLobbyPlayers(_) => "GetLobbyPlayers",
+         PalaceInMessage::Announce(_) => "Announce",
+         PalaceInMessage::SetDrainMode(_) => "SetDrainMode",
+         PalaceInMessage::SubscribeServerStats(_) => "SubscribeServerStats",
+         PalaceInMessage::GetStatsSummaries(_) => "GetStatsSummaries",
+         PalaceInMessage::GetLobbyStats(_) => "GetLobbyStats",
+         PalaceInMessage::AnalyzeGame(_) => "AnalyzeGame",
+         PalaceInMessage::RequestHint(_) => "RequestHint",
+         PalaceInMessage::RewindTurn(_) => "RewindTurn",
+         PalaceInMessage::PredictPlay(_) => "PredictPlay",
+         PalaceInMessage::MergeLobby(_) => "MergeLobby",
+         PalaceInMessage::AnswerMerge(_) => "AnswerMerge",
+         PalaceInMessage::RotateId(_) => "RotateId",
+         PalaceInMessage::CatchUp(_) => "CatchUp",
+         PalaceInMessage::ObserveLobby(_) => "ObserveLobby",
+         PalaceInMessage::StopObservingLobby(_) => "StopObservingLobby",
+         PalaceInMessage::ScheduleEvent(_) => "ScheduleEvent",
+         PalaceInMessage::UnscheduleEvent(_) => "UnscheduleEvent",
+         PalaceInMessage::ListEvents(_) => "ListEvents",
+         PalaceInMessage::RegisterForEvent(_) => "RegisterForEvent",
+      }
+   }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ListLobbyResponse<'a> {
-   pub lobbies: &'a [LobbyDisplay<'a>],
+   pub lobbies: &'a [LobbyDisplay],
    pub has_next_page: bool,
+   pub total_lobbies: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum PalaceOutMessage<'a> {
    NewLobbyResponse(Result<NewLobbyResponse, NewLobbyError>),
    JoinLobbyResponse(Result<JoinLobbyResponse<'a>, JoinLobbyError>),
+   JoinWithTokenResponse(Result<JoinLobbyResponse<'a>, JoinWithTokenError>),
+   CreateJoinTokenResponse(Result<JoinToken, CreateJoinTokenError>),
    ListLobbiesResponse(ListLobbyResponse<'a>),
    StartGameResponse(Result<(), StartGameError>),
    TakeTurnResponse(Result<(), TakeTurnError>),
    ReconnectResponse(Result<ReconnectResponse, ReconnectError>),
+   AttachToPlayerResponse(Result<ReconnectResponse, ReconnectError>),
    RequestAiResponse(Result<(), RequestAiError>),
    KickPlayerResponse(Result<(), KickPlayerError>),
+   SwapAiStrategyResponse(Result<(), SwapAiStrategyError>),
+   SetSeatClosedResponse(Result<(), SetSeatClosedError>),
+   AbortGameResponse(Result<(), AbortGameError>),
+   ConcedeResponse(Result<(), ConcedeError>),
+   VoteSkipTurnResponse(Result<(), VoteSkipTurnError>),
+   TransferOwnershipResponse(Result<(), TransferOwnershipError>),
    SpectateLobbyResponse(Result<SpectateLobbyResponse<'a>, SpectateLobbyError>),
+   StopSpectatingResponse(Result<(), StopSpectatingError>),
+   TakeSeatResponse(Result<JoinLobbyResponse<'a>, TakeSeatError>),
+   /// The queue position we were given
+   QueueForSeatResponse(Result<u16, QueueForSeatError>),
+   SetLobbyDescriptionResponse(Result<(), SetLobbyDescriptionError>),
+   GetLobbyPlayersResponse(Result<Vec<LobbySeat<'a>>, GetLobbyPlayersError>),
+   AnnounceResponse(Result<(), AnnounceError>),
+   SetDrainModeResponse(Result<(), SetDrainModeError>),
+   SubscribeServerStatsResponse(Result<(), SubscribeServerStatsError>),
+   GetStatsSummariesResponse(Result<StatsSummaries, GetStatsSummariesError>),
+   GetLobbyStatsResponse(Result<LobbyStatsResponse<'a>, GetLobbyStatsError>),
+   AnalyzeGameResponse(Result<GameAnalysis, AnalyzeGameError>),
+   RequestHintResponse(Result<Hint, RequestHintError>),
+   RewindTurnResponse(Result<(), RewindTurnError>),
+   PredictPlayResponse(Result<(), PredictPlayError>),
+   MergeLobbyResponse(Result<(), MergeLobbyError>),
+   AnswerMergeResponse(Result<(), AnswerMergeError>),
+   RotateIdResponse(Result<RotateIdResponse, RotateIdError>),
+   CatchUpResponse(Result<CatchUpResponse, CatchUpError>),
+   ObserveLobbyResponse(Result<(), ObserveLobbyError>),
+   StopObservingLobbyResponse(Result<(), StopObservingLobbyError>),
+   ObservedLobbyEvent(ObservedLobbyEvent<'a>),
+   ScheduleEventResponse(Result<(), ScheduleEventError>),
+   UnscheduleEventResponse(Result<(), UnscheduleEventError>),
+   ListEventsResponse(Vec<EventListing>),
+   RegisterForEventResponse(Result<RegisterForEventResponse, RegisterForEventError>),
+   EventOpenedEvent(EventOpenedEvent<'a>),
+   /// Sent instead of closing the connection when a message's type isn't recognized
+   UnknownMessageType(&'a str),
+   /// Sent instead of handling a message when the server is strict and the message has fields it doesn't know
+   UnknownFields(&'a [String]),
    PublicGameStateEvent(&'a PublicGameState<'a>),
    HandEvent(&'a [Card]),
    GameStartEvent(GameStartEvent<'a>),
    SpectateGameStartEvent(SpectateGameStartEvent<'a>),
    PlayerJoinEvent(PlayerJoinEvent<'a>),
    PlayerLeaveEvent(PlayerLeaveEvent),
+   LobbyInfoEvent(LobbyInfoEvent<'a>),
+   LobbySettingsEvent(LobbySettingsEvent<'a>),
+   PlayerConnectionStatusEvent(PlayerConnectionStatusEvent),
+   PlayerPresenceEvent(PlayerPresenceEvent),
+   ServerAnnouncementEvent(ServerAnnouncementEvent<'a>),
+   ServerDrainingEvent(ServerDrainingEvent),
+   ServerStatsEvent(ServerStatsEvent),
+   AiDecisionDebugEvent(AiDecisionDebugEvent<'a>),
    LobbyCloseEvent(LobbyCloseEvent),
+   /// The last message before the server closes the connection
+   DisconnectEvent(DisconnectEvent),
    SpectatorJoinEvent(()),
    SpectatorLeaveEvent(()),
    GameCompleteEvent(&'a [u8]),
+   AbortVoteEvent(AbortVoteEvent),
+   SkipTurnVoteEvent(SkipTurnVoteEvent),
+   TurnSkippedEvent(TurnSkippedEvent),
+   /// The game ended without a winner and the lobby is back to waiting for a start
+   GameAbortedEvent(GameAbortReason),
+   GameDrawEvent(GameDrawEvent),
+   PlayerConcededEvent(PlayerConcededEvent),
+   GameRewoundEvent(GameRewoundEvent),
+   PredictionResultsEvent(PredictionResultsEvent),
+   PredictionScoreEvent(PredictionScoreEvent),
+   MergeRequestEvent(MergeRequestEvent<'a>),
+   MergeDeclinedEvent(MergeDeclinedEvent),
+   LobbyMergedEvent(LobbyMergedEvent<'a>),
+   OwnerChangedEvent(OwnerChangedEvent),
+   LobbyClosingSoonEvent(LobbyClosingSoonEvent),
+   YourTurnEvent(YourTurnEvent),
+   TurnTimeWarningEvent(TurnTimeWarningEvent),
+   /// Sent to spectators after a notable play
+   HighlightEvent(Highlight),
+   /// Sent to spectators when the server's commentator remarks on a play
+   CommentaryEvent(&'a Commentary),
+   QueuePositionEvent(QueuePositionEvent),
+   /// Our turn in the queue came, and we now have a seat in the lobby
+   SeatGrantedEvent(JoinLobbyResponse<'a>),
+}
+
+/// JSON Schema for both directions of the protocol, so clients in other languages can generate bindings
+pub fn protocol_schema() -> serde_json::Value {
+   serde_json::json!({
+      "PalaceInMessage": schemars::schema_for!(PalaceInMessage),
+      "PalaceOutMessage": schemars::schema_for!(PalaceOutMessage<'static>),
+   })
 }
@@ -1,12 +1,19 @@
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::time::Instant;
-use std::usize;
 
 pub const HAND_SIZE: usize = 6;
+/// Picking up a pile at least this big counts as a highlight
+pub const BIG_PICKUP_SIZE: usize = 10;
+pub const DEFAULT_MAX_REPETITIONS: u8 = 3;
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Hash, JsonSchema)]
 pub enum CardSuit {
    Clubs,
    Diamonds,
@@ -14,9 +21,9 @@ pub enum CardSuit {
    Spades,
 }
 
-const SUITS: [CardSuit; 4] = [CardSuit::Clubs, CardSuit::Diamonds, CardSuit::Hearts, CardSuit::Spades];
+pub(crate) const SUITS: [CardSuit; 4] = [CardSuit::Clubs, CardSuit::Diamonds, CardSuit::Hearts, CardSuit::Spades];
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Hash, JsonSchema)]
 pub enum CardValue {
    Two,
    Three,
@@ -33,7 +40,7 @@ pub enum CardValue {
    Ace,
 }
 
-const VALUES: [CardValue; 13] = [
+pub(crate) const VALUES: [CardValue; 13] = [
    CardValue::Two,
    CardValue::Three,
    CardValue::Four,
@@ -49,25 +56,226 @@ const VALUES: [CardValue; 13] = [
    CardValue::Ace,
 ];
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Hash)]
+/// Cards compare by value and suit alone, so the same card from two decks is interchangeable to the rules
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Card {
    pub value: CardValue,
    pub suit: CardSuit,
+   /// Unique within a game, so clients can tell identical cards apart when animating them.
+   /// Assigned when the cards are dealt, and clients can leave it out when playing cards
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub id: Option<u16>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+/// Anything the rules can read a card value from, so that compact cards can share the rules with `Card`
+pub trait HasCardValue {
+   fn card_value(&self) -> CardValue;
+}
+
+impl HasCardValue for Card {
+   fn card_value(&self) -> CardValue {
+      self.value
+   }
+}
+
+impl PartialEq for Card {
+   fn eq(&self, other: &Card) -> bool {
+      (self.value, self.suit) == (other.value, other.suit)
+   }
+}
+
+impl Eq for Card {}
+
+impl PartialOrd for Card {
+   fn partial_cmp(&self, other: &Card) -> Option<std::cmp::Ordering> {
+      Some(self.cmp(other))
+   }
+}
+
+impl Ord for Card {
+   fn cmp(&self, other: &Card) -> std::cmp::Ordering {
+      (self.value, self.suit).cmp(&(other.value, other.suit))
+   }
+}
+
+impl std::hash::Hash for Card {
+   fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+      (self.value, self.suit).hash(state);
+   }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 pub enum Phase {
    Setup,
    Play,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 pub enum CardZone {
    Hand,
    FaceUpThree,
    FaceDownThree,
 }
 
+/// Why a play was rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum PlayError {
+   SetupNeedsThreeCards,
+   SetupCardsNotHeld,
+   TooManyCards,
+   FaceDownChosen,
+   NoCards,
+   MixedValues,
+   CardsNotHeld,
+}
+
+impl PlayError {
+   pub fn message(self) -> &'static str {
+      match self {
+         PlayError::SetupNeedsThreeCards => "During setup, must choose exactly three cards",
+         PlayError::SetupCardsNotHeld => "Chosen three faceup cards are not in hand / already faceup cards",
+         PlayError::TooManyCards => "Can't play more cards than you have",
+         PlayError::FaceDownChosen => "Can't choose any cards when playing from the face down three",
+         PlayError::NoCards => "Have to play at least one card",
+         PlayError::MixedValues => "Can only play multiple cards if each card has the same value",
+         PlayError::CardsNotHeld => "can only play cards that you have",
+      }
+   }
+}
+
+/// A notable moment from a single play
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum HighlightKind {
+   /// The player had to pick up this many cards, at least `BIG_PICKUP_SIZE`
+   BigPickup(u16),
+   /// Four cards of the same value cleared the pile
+   FourOfAKind,
+   /// The player finished first by playing their last face down card
+   WonFromFaceDown,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Highlight {
+   /// Counts turns the same way as `GameState::turns_taken`, starting from 1
+   pub turn: u32,
+   pub player: u8,
+   pub kind: HighlightKind,
+}
+
+/// Something a player did that changed the game, enough to replay the game from the deal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Turn {
+   /// The cards as asked for, so empty for a face down play
+   Play(Box<[Card]>),
+   Concede(u8),
+}
+
+/// Checks a play by the active player, who holds `hand`, against the same rules the server uses.
+/// Only legality is checked: a legal play may still end in picking up the pile
+pub fn validate_play(state: &PublicGameState, hand: &[Card], cards: &[Card]) -> Result<(), PlayError> {
+   let face_up_three = state.face_up_three[state.active_player as usize];
+   check_play(state.cur_phase, hand, face_up_three, cards).map(|_| ())
+}
+
+/// The zone cards are played from, once the active player's hand and face up three are used up in turn
+fn play_zone(hand: &[Card], face_up_three: &[Card]) -> CardZone {
+   if !hand.is_empty() {
+      CardZone::Hand
+   } else if !face_up_three.is_empty() {
+      CardZone::FaceUpThree
+   } else {
+      CardZone::FaceDownThree
+   }
+}
+
+/// Whether every card is in `held`, counting duplicates
+fn holds_all(held: &[Card], cards: &[Card]) -> bool {
+   let mut held = held.to_vec();
+   cards.iter().all(|card| match held.iter().position(|x| x == card) {
+      Some(i) => {
+         held.swap_remove(i);
+         true
+      }
+      None => false,
+   })
+}
+
+/// Returns the zone being played from, which is None during setup
+fn check_play(
+   phase: Phase,
+   hand: &[Card],
+   face_up_three: &[Card],
+   cards: &[Card],
+) -> Result<Option<CardZone>, PlayError> {
+   if phase == Phase::Setup {
+      if cards.len() != 3 {
+         return Err(PlayError::SetupNeedsThreeCards);
+      }
+      let mut all_cards = hand.to_vec();
+      all_cards.extend_from_slice(face_up_three);
+      if !holds_all(&all_cards, cards) {
+         return Err(PlayError::SetupCardsNotHeld);
+      }
+      return Ok(None);
+   }
+
+   let card_zone = play_zone(hand, face_up_three);
+   let held = match card_zone {
+      CardZone::Hand => hand,
+      CardZone::FaceUpThree => face_up_three,
+      CardZone::FaceDownThree => {
+         if !cards.is_empty() {
+            return Err(PlayError::FaceDownChosen);
+         }
+         return Ok(Some(card_zone));
+      }
+   };
+   if cards.len() > held.len() {
+      return Err(PlayError::TooManyCards);
+   }
+   if cards.is_empty() {
+      return Err(PlayError::NoCards);
+   }
+   if cards.iter().any(|x| x.value != cards[0].value) {
+      return Err(PlayError::MixedValues);
+   }
+   if !holds_all(held, cards) {
+      return Err(PlayError::CardsNotHeld);
+   }
+   Ok(Some(card_zone))
+}
+
+/// Where every card is at the start of a game that isn't dealt at random, one entry per seat
+#[derive(Clone, Debug, Deserialize)]
+pub struct Layout {
+   pub hands: Vec<Vec<Card>>,
+   pub face_up_three: Vec<Vec<Card>>,
+   pub face_down_three: Vec<Vec<Card>>,
+   /// Bottom first
+   #[serde(default)]
+   pub pile: Vec<Card>,
+   #[serde(default)]
+   pub cleared: Vec<Card>,
+   /// Setup only ends once play comes back around to seat 0, so layouts in setup start there
+   #[serde(default = "default_layout_phase")]
+   pub phase: Phase,
+   #[serde(default)]
+   pub active_player: u8,
+}
+
+fn default_layout_phase() -> Phase {
+   Phase::Play
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+   TooFewPlayers,
+   /// The hands, face up threes and face down threes aren't all for the same number of seats
+   MismatchedSeats,
+   TooManyTableCards,
+   NoSuchActivePlayer,
+}
+
 #[derive(Clone, Debug)]
 pub struct GameState {
    pub active_player: u8,
@@ -80,8 +288,62 @@ pub struct GameState {
    pub cur_phase: Phase,
    pub last_cards_played: Vec<Card>,
    pub out_players: Vec<u8>,
+   /// Players who gave up, in the order they did so. They are also in `out_players`
+   pub conceded_players: Vec<u8>,
    pub last_turn_start: Instant,
    pub last_played_zone: Option<CardZone>,
+   pub start_time: Instant,
+   /// Turns successfully taken, including setup
+   pub turns_taken: u32,
+   /// Every highlight so far, oldest first
+   pub highlights: Vec<Highlight>,
+   /// Every successful turn and concession so far, oldest first
+   pub history: Vec<Turn>,
+   /// How many times each position has come up after a play, by `position_hash`
+   positions: HashMap<u64, u8>,
+   /// What the deck was shuffled with. Dealing again with `with_seed` and the same seed gives the same cards.
+   /// Games set out with `from_layout` don't come from a shuffle, so theirs is random and deals nothing like them
+   pub seed: u64,
+}
+
+/// When a game that isn't getting anywhere is called a draw
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct DrawRules {
+   /// Draw once the same position comes up this many times. Positions aren't watched if unset
+   pub max_repetitions: Option<u8>,
+   /// Draw after this many turns, counting setup. No limit if unset
+   pub max_turns: Option<u32>,
+}
+
+impl Default for DrawRules {
+   fn default() -> DrawRules {
+      DrawRules {
+         max_repetitions: Some(DEFAULT_MAX_REPETITIONS),
+         max_turns: None,
+      }
+   }
+}
+
+/// What a turn did, so that nobody has to work it out by comparing the game before and after
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlayOutcome {
+   /// The play couldn't go on the pile, so the player picked the pile up
+   pub picked_up: bool,
+   /// The pile was cleared, by a ten or by completing a set
+   pub cleared: bool,
+   /// The player has no cards left
+   pub player_out: bool,
+   pub game_over: bool,
+   /// Who has the turn now. The player who just went, once the game is over
+   pub next_player: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum DrawReason {
+   /// The players kept coming back to the same position
+   Repetition,
+   TurnLimit,
 }
 
 pub fn new_deck(num_players: usize) -> impl Iterator<Item = Card> {
@@ -93,13 +355,22 @@ pub fn new_deck(num_players: usize) -> impl Iterator<Item = Card> {
       .map(|(value, suit)| Card {
          suit: *suit,
          value: *value,
+         id: None,
       })
 }
 
 impl GameState {
    pub fn new(num_players: u8) -> GameState {
+      GameState::with_seed(num_players, rand::random())
+   }
+
+   pub fn with_seed(num_players: u8, seed: u64) -> GameState {
       let mut deck: Vec<Card> = new_deck(num_players as usize).collect();
-      deck.shuffle(&mut thread_rng());
+      deck.shuffle(&mut ChaChaRng::seed_from_u64(seed));
+      // after shuffling, so that an id says nothing about the card
+      for (i, card) in deck.iter_mut().enumerate() {
+         card.id = Some(i as u16);
+      }
       let mut deck = deck.into_iter();
       let mut face_down_three = Vec::with_capacity(num_players as usize);
       let mut face_up_three = Vec::with_capacity(num_players as usize);
@@ -120,12 +391,64 @@ impl GameState {
          cur_phase: Phase::Setup,
          last_cards_played: Vec::new(),
          out_players: Vec::with_capacity(num_players as usize),
+         conceded_players: Vec::new(),
          last_turn_start: Instant::now(),
          last_played_zone: None,
+         start_time: Instant::now(),
+         turns_taken: 0,
+         highlights: Vec::new(),
+         history: Vec::new(),
+         positions: HashMap::new(),
+         seed,
+      }
+   }
+
+   pub fn from_layout(layout: &Layout) -> Result<GameState, LayoutError> {
+      let num_players = layout.hands.len();
+      if num_players < 2 {
+         return Err(LayoutError::TooFewPlayers);
+      }
+      if layout.face_up_three.len() != num_players || layout.face_down_three.len() != num_players {
+         return Err(LayoutError::MismatchedSeats);
+      }
+      if layout
+         .face_up_three
+         .iter()
+         .chain(layout.face_down_three.iter())
+         .any(|x| x.len() > 3)
+      {
+         return Err(LayoutError::TooManyTableCards);
       }
+      let setup_elsewhere = layout.phase == Phase::Setup && layout.active_player != 0;
+      if layout.active_player as usize >= num_players || setup_elsewhere {
+         return Err(LayoutError::NoSuchActivePlayer);
+      }
+
+      let mut gs = GameState::new(num_players as u8);
+      gs.hands = layout.hands.clone().into_boxed_slice();
+      gs.face_up_three = layout.face_up_three.clone().into_boxed_slice();
+      gs.face_down_three = layout.face_down_three.clone().into_boxed_slice();
+      gs.pile_cards = layout.pile.clone();
+      gs.cleared_cards = layout.cleared.clone();
+      gs.cur_phase = layout.phase;
+      gs.active_player = layout.active_player;
+      let zones = gs
+         .hands
+         .iter_mut()
+         .chain(gs.face_up_three.iter_mut())
+         .chain(gs.face_down_three.iter_mut())
+         .chain(std::iter::once(&mut gs.pile_cards))
+         .chain(std::iter::once(&mut gs.cleared_cards));
+      for (i, card) in zones.flatten().enumerate() {
+         card.id = Some(i as u16);
+      }
+      for zone in gs.hands.iter_mut().chain(gs.face_up_three.iter_mut()) {
+         zone.sort_unstable();
+      }
+      Ok(gs)
    }
 
-   pub fn public_state(&self) -> PublicGameState {
+   pub fn public_state(&self) -> PublicGameState<'_> {
       PublicGameState {
          hands: self
             .hands
@@ -152,184 +475,169 @@ impl GameState {
          active_player: self.active_player,
          last_cards_played: &self.last_cards_played,
          last_played_zone: self.last_played_zone,
+         placements: self.placements(),
+         turn: self.turns_taken,
+         elapsed_secs: self.start_time.elapsed().as_secs(),
+         turn_deadline: None,
+         journal_seq: None,
+      }
+   }
+
+   /// Indexed by turn number. Finished players are placed from the front, conceded players from the back
+   fn placements(&self) -> Box<[Option<u8>]> {
+      let mut placements = vec![None; self.num_players as usize];
+      let finished = self.out_players.iter().filter(|x| !self.conceded_players.contains(x));
+      for (place, player) in finished.enumerate() {
+         placements[*player as usize] = Some(place as u8);
       }
+      for (i, player) in self.conceded_players.iter().enumerate() {
+         placements[*player as usize] = Some(self.num_players - 1 - i as u8);
+      }
+      placements.into_boxed_slice()
    }
 
-   /// Return bool = whether or not the game is complete
-   pub fn take_turn(&mut self, cards: &[Card]) -> Result<bool, &'static str> {
-      match self.cur_phase {
-         Phase::Setup => {
-            if cards.len() != 3 {
-               return Err("During setup, must choose exactly three cards");
+   pub fn take_turn(&mut self, cards: &[Card]) -> Result<PlayOutcome, PlayError> {
+      let p = self.active_player as usize;
+      let card_zone = check_play(self.cur_phase, &self.hands[p], &self.face_up_three[p], cards)?;
+      let cleared_before = self.cleared_cards.len();
+      self.turns_taken += 1;
+      self.history.push(Turn::Play(cards.into()));
+      let outcome = match card_zone {
+         None => {
+            self.choose_three_faceup(cards[0], cards[1], cards[2]);
+            PlayOutcome {
+               next_player: self.active_player,
+               ..PlayOutcome::default()
             }
-            self.choose_three_faceup(cards[0], cards[1], cards[2])?;
-            Ok(false)
          }
-         Phase::Play => self.make_play(cards),
-      }
-   }
-
-   fn choose_three_faceup(&mut self, card_one: Card, card_two: Card, card_three: Card) -> Result<(), &'static str> {
-      // Combine hand + face up cards
-      let mut all_cards = self.hands[self.active_player as usize].clone();
-      all_cards.extend_from_slice(&self.face_up_three[self.active_player as usize]);
-
-      // Calculate the new hand after removing new face up cards
-      let mut card_one_removed = false;
-      let mut card_two_removed = false;
-      let mut card_three_removed = false;
-      let mut new_hand = Vec::with_capacity(HAND_SIZE);
-      for card in all_cards {
-         if card == card_one && !card_one_removed {
-            card_one_removed = true;
-         } else if card == card_two && !card_two_removed {
-            card_two_removed = true;
-         } else if card == card_three && !card_three_removed {
-            card_three_removed = true;
-         } else {
-            new_hand.push(card);
+         Some(card_zone) => self.make_play(card_zone, cards),
+      };
+      if !outcome.game_over && self.cur_phase == Phase::Play {
+         // Cleared cards never come back, so neither can any position from before they were cleared
+         if self.cleared_cards.len() != cleared_before {
+            self.positions.clear();
          }
+         let repetitions = self.positions.entry(self.position_hash()).or_default();
+         *repetitions = repetitions.saturating_add(1);
       }
+      Ok(outcome)
+   }
 
-      // Make sure that all three cards put face up were removed from hand
-      if !(card_one_removed && card_two_removed && card_three_removed) {
-         return Err("Chosen three faceup cards are not in hand / already faceup cards");
+   /// Whether the game should be called a draw under `rules`, as of the last turn
+   pub fn draw_reason(&self, rules: DrawRules) -> Option<DrawReason> {
+      if matches!(rules.max_turns, Some(max) if self.turns_taken >= max) {
+         return Some(DrawReason::TurnLimit);
+      }
+      let repetitions = self.positions.get(&self.position_hash()).copied().unwrap_or(0);
+      if matches!(rules.max_repetitions, Some(max) if repetitions >= max) {
+         return Some(DrawReason::Repetition);
+      }
+      None
+   }
+
+   /// Covers everything that decides how the game goes from here, so that the same hash means the same position.
+   /// Cleared cards can never come back, so only how many there are matters
+   fn position_hash(&self) -> u64 {
+      let mut hasher = DefaultHasher::new();
+      self.active_player.hash(&mut hasher);
+      self.hands.hash(&mut hasher);
+      self.face_up_three.hash(&mut hasher);
+      self.face_down_three.hash(&mut hasher);
+      self.pile_cards.hash(&mut hasher);
+      self.cleared_cards.len().hash(&mut hasher);
+      self.out_players.hash(&mut hasher);
+      hasher.finish()
+   }
+
+   /// The cards must have been checked already
+   fn choose_three_faceup(&mut self, card_one: Card, card_two: Card, card_three: Card) {
+      // Combine hand + face up cards, then take the new face up cards back out
+      let p = self.active_player as usize;
+      let mut new_hand = std::mem::take(&mut self.hands[p]);
+      new_hand.append(&mut self.face_up_three[p]);
+      let mut new_face_up = Vec::with_capacity(3);
+      for card in [card_one, card_two, card_three].iter() {
+         let i = new_hand.iter().position(|x| x == card).unwrap();
+         new_face_up.push(new_hand.swap_remove(i));
       }
 
       // Mutate state
-      self.face_up_three[self.active_player as usize] = vec![card_one, card_two, card_three];
-      self.face_up_three[self.active_player as usize].sort_unstable();
-      self.hands[self.active_player as usize] = new_hand;
-      self.hands[self.active_player as usize].sort_unstable();
+      self.face_up_three[p] = new_face_up;
+      self.face_up_three[p].sort_unstable();
+      self.hands[p] = new_hand;
+      self.hands[p].sort_unstable();
 
       self.rotate_play();
 
       if self.active_player == 0 {
          self.cur_phase = Phase::Play;
       }
-
-      Ok(())
    }
 
-   /// Return bool = whether or not the game is complete
-   fn make_play(&mut self, cards: &[Card]) -> Result<bool, &'static str> {
-      // Figure out which zone we are retrieving cards from
-      let hand_len = self.hands[self.active_player as usize].len();
-      let fup3_len = self.face_up_three[self.active_player as usize].len();
-      let a_card;
-      let (card_zone, cards) = if hand_len > 0 {
-         if cards.len() > hand_len {
-            return Err("Can't play more cards than you have");
-         }
-         (CardZone::Hand, cards)
-      } else if fup3_len > 0 {
-         if cards.len() > fup3_len {
-            return Err("Can't play more cards than you have");
-         }
-         (CardZone::FaceUpThree, cards)
-      } else {
-         if !cards.is_empty() {
-            return Err("Can't choose any cards when playing from the face down three");
-         }
-         // In the case of face down cards, we can safely pop now as there's no way this play can fail
-         a_card = [self.face_down_three[self.active_player as usize].pop().unwrap()];
-         (CardZone::FaceDownThree, a_card.as_ref())
-      };
-
-      if cards.is_empty() {
-         return Err("Have to play at least one card");
-      }
-
-      // check that play is valid
-      let play_value = cards[0].value;
-
-      for card in cards {
-         if card.value != play_value {
-            return Err("Can only play multiple cards if each card has the same value");
-         }
-
-         match card_zone {
-            CardZone::Hand => {
-               if self.hands[self.active_player as usize].binary_search(card).is_err() {
-                  return Err("can only play cards that you have");
-               }
-            }
-            CardZone::FaceUpThree => {
-               if self.face_up_three[self.active_player as usize]
-                  .binary_search(card)
-                  .is_err()
-               {
-                  return Err("can only play cards that you have");
-               }
-            }
-            CardZone::FaceDownThree => {
-               // already checked
-            }
-         }
-      }
+   /// The cards must have been checked already
+   fn make_play(&mut self, card_zone: CardZone, cards: &[Card]) -> PlayOutcome {
+      let p = self.active_player as usize;
 
-      // Remove cards from old zone
-      match card_zone {
-         CardZone::Hand => {
-            let backup_hand = self.hands[self.active_player as usize].clone();
-            for card in cards.iter() {
-               let index_to_remove = self.hands[self.active_player as usize].iter().position(|x| x == card);
-               if let Some(i) = index_to_remove {
-                  self.hands[self.active_player as usize].swap_remove(i);
-               } else {
-                  self.hands[self.active_player as usize] = backup_hand;
-                  return Err("can only play cards that you have");
-               }
-            }
-         }
-         CardZone::FaceUpThree => {
-            let backup_three = self.face_up_three[self.active_player as usize].clone();
+      // Move the held cards rather than the ones asked for, so that their ids carry over.
+      // Removing from the old zone keeps it sorted so that later plays can be validated
+      self.last_cards_played.clear();
+      let zone = match card_zone {
+         CardZone::Hand => Some(&mut self.hands[p]),
+         CardZone::FaceUpThree => Some(&mut self.face_up_three[p]),
+         CardZone::FaceDownThree => None,
+      };
+      match zone {
+         Some(zone) => {
             for card in cards.iter() {
-               let index_to_remove = self.face_up_three[self.active_player as usize].iter().position(|x| x == card);
-               if let Some(i) = index_to_remove {
-                  self.face_up_three[self.active_player as usize].swap_remove(i);
-               } else {
-                  self.face_up_three[self.active_player as usize] = backup_three;
-                  return Err("can only play cards that you have");
-               }
+               let i = zone.iter().position(|x| x == card).unwrap();
+               self.last_cards_played.push(zone.remove(i));
             }
          }
-         CardZone::FaceDownThree => {
-            // Already popped
-         }
+         None => self.last_cards_played.push(self.face_down_three[p].pop().unwrap()),
       }
+      let play_value = self.last_cards_played[0].value;
 
       self.last_played_zone = Some(card_zone);
 
-      let is_playable = is_playable_without_pickup(play_value, &self.pile_cards);
+      let picked_up = !is_playable_without_pickup(play_value, &self.pile_cards);
 
       // Put cards in pile
-      self.pile_cards.extend_from_slice(cards);
+      self.pile_cards.extend_from_slice(&self.last_cards_played);
 
-      self.last_cards_played.clear();
-      self.last_cards_played.extend_from_slice(cards);
-
-      let player_out = if !is_playable {
-         self.hands[self.active_player as usize].extend_from_slice(&self.pile_cards);
-         self.hands[self.active_player as usize].sort_unstable();
+      let player_out = if picked_up {
+         if self.pile_cards.len() >= BIG_PICKUP_SIZE {
+            self.add_highlight(HighlightKind::BigPickup(self.pile_cards.len() as u16));
+         }
+         self.hands[p].extend_from_slice(&self.pile_cards);
+         self.hands[p].sort_unstable();
          self.pile_cards.clear();
          false
-      } else if self.hands[self.active_player as usize].is_empty()
-         && self.face_up_three[self.active_player as usize].is_empty()
-         && self.face_down_three[self.active_player as usize].is_empty()
-      {
+      } else if self.hands[p].is_empty() && self.face_up_three[p].is_empty() && self.face_down_three[p].is_empty() {
          self.out_players.push(self.active_player);
+         if card_zone == CardZone::FaceDownThree && self.out_players.len() == self.conceded_players.len() + 1 {
+            self.add_highlight(HighlightKind::WonFromFaceDown);
+         }
          if self.out_players.len() as u8 == self.num_players - 1 {
             self.out_players.push(self.next_player());
-            return Ok(true);
+            self.rank_conceded_last();
+            return PlayOutcome {
+               player_out: true,
+               game_over: true,
+               next_player: self.active_player,
+               ..PlayOutcome::default()
+            };
          }
          true
       } else {
          false
       };
 
-      if (is_playable && play_value == CardValue::Ten) || top_n_cards_same(&self.pile_cards, self.num_players as usize)
-      {
+      let cleared =
+         (!picked_up && play_value == CardValue::Ten) || top_n_cards_same(&self.pile_cards, self.num_players as usize);
+      if cleared {
+         if top_n_cards_same(&self.pile_cards, 4) {
+            self.add_highlight(HighlightKind::FourOfAKind);
+         }
          self.cleared_cards.extend_from_slice(&self.pile_cards);
          self.pile_cards.clear();
          if player_out {
@@ -339,7 +647,55 @@ impl GameState {
          self.rotate_play();
       }
 
-      Ok(false)
+      PlayOutcome {
+         picked_up,
+         cleared,
+         player_out,
+         game_over: false,
+         next_player: self.active_player,
+      }
+   }
+
+   /// Takes a player out of the game, to be placed behind everyone still playing. Returns true if the game is over
+   pub fn concede(&mut self, player: u8) -> bool {
+      self.history.push(Turn::Concede(player));
+      self.out_players.push(player);
+      self.conceded_players.push(player);
+      if self.out_players.len() as u8 == self.num_players - 1 {
+         let last_player = (0..self.num_players).find(|x| !self.out_players.contains(x)).unwrap();
+         self.out_players.push(last_player);
+         self.rank_conceded_last();
+         return true;
+      }
+      if player == self.active_player {
+         self.rotate_play();
+      }
+      false
+   }
+
+   /// Moves conceded players behind everyone who finished, so that the first to concede is last
+   fn rank_conceded_last(&mut self) {
+      let conceded_players = &self.conceded_players;
+      self.out_players.retain(|x| !conceded_players.contains(x));
+      self.out_players.extend(conceded_players.iter().rev());
+   }
+
+   fn add_highlight(&mut self, kind: HighlightKind) {
+      self.highlights.push(Highlight {
+         turn: self.turns_taken,
+         player: self.active_player,
+         kind,
+      });
+   }
+
+   /// The highlights from the most recent turn
+   pub fn last_turn_highlights(&self) -> &[Highlight] {
+      let start = self
+         .highlights
+         .iter()
+         .rposition(|x| x.turn != self.turns_taken)
+         .map_or(0, |i| i + 1);
+      &self.highlights[start..]
    }
 
    pub fn get_hand(&self, player_num: u8) -> &[Card] {
@@ -366,17 +722,17 @@ impl GameState {
    }
 }
 
-pub fn top_n_cards_same(pile: &[Card], n: usize) -> bool {
+pub fn top_n_cards_same<C: HasCardValue>(pile: &[C], n: usize) -> bool {
    let top_value = if let Some(card) = pile.last() {
-      card.value
+      card.card_value()
    } else {
       return false;
    };
    let mut top_n_same = 0;
-   for card in pile.iter().rev() {
-      if card.value == top_value {
+   for value in pile.iter().rev().map(HasCardValue::card_value) {
+      if value == top_value {
          top_n_same += 1;
-      } else if card.value == CardValue::Four {
+      } else if value == CardValue::Four {
          continue;
       } else {
          break;
@@ -385,7 +741,7 @@ pub fn top_n_cards_same(pile: &[Card], n: usize) -> bool {
    top_n_same >= n
 }
 
-pub fn is_playable_without_pickup(card_value: CardValue, pile: &[Card]) -> bool {
+pub fn is_playable_without_pickup<C: HasCardValue>(card_value: CardValue, pile: &[C]) -> bool {
    match (card_value, effective_top_card(pile)) {
       (CardValue::Two, _) => true,
       (CardValue::Four, _) => true,
@@ -395,16 +751,16 @@ pub fn is_playable_without_pickup(card_value: CardValue, pile: &[Card]) -> bool
    }
 }
 
-pub fn effective_top_card(pile: &[Card]) -> CardValue {
+pub fn effective_top_card<C: HasCardValue>(pile: &[C]) -> CardValue {
    pile
       .iter()
       .rev()
-      .map(|x| x.value)
+      .map(HasCardValue::card_value)
       .find(|x| *x != CardValue::Four)
       .unwrap_or(CardValue::Two)
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct PublicGameState<'a> {
    pub hands: Box<[u16]>,
    pub face_up_three: Box<[&'a [Card]]>,
@@ -416,11 +772,24 @@ pub struct PublicGameState<'a> {
    pub active_player: u8,
    pub last_cards_played: &'a [Card],
    pub last_played_zone: Option<CardZone>,
+   /// Each player's place so far, 0 being first, or None while they are still playing
+   pub placements: Box<[Option<u8>]>,
+   /// Turns taken so far, counting the same way as `GameState::turns_taken`
+   pub turn: u32,
+   /// Seconds since the game was dealt
+   pub elapsed_secs: u64,
+   /// Seconds the active player has left, or None if the lobby has no turn timer.
+   /// The game doesn't know about turn timers, so this is filled in by the server
+   pub turn_deadline: Option<u16>,
+   /// Where this is in the lobby's journal, if it was sent to everyone in the lobby. Also filled in by the server
+   pub journal_seq: Option<u64>,
 }
 
 mod test {
    #[cfg(test)]
    use super::*;
+   #[cfg(test)]
+   use rand::thread_rng;
 
    #[cfg(test)]
    impl GameState {
@@ -430,13 +799,14 @@ mod test {
          game
       }
 
-      fn play_card(&mut self, card_val: CardValue) -> Result<bool, &'static str> {
+      fn play_card(&mut self, card_val: CardValue) -> Result<PlayOutcome, PlayError> {
          let card = Card {
             value: card_val,
             suit: *SUITS.choose(&mut thread_rng()).unwrap(),
+            id: None,
          };
          self.hands[self.active_player as usize] = vec![card];
-         self.make_play(&[card])
+         self.take_turn(&[card])
       }
    }
 
@@ -462,12 +832,45 @@ mod test {
       assert_eq!(game.active_player, 3);
    }
 
+   #[test]
+   fn hand_stays_playable_after_removing_a_card() {
+      let mut game = GameState::new_game_skip_setup(2);
+      let card = |value| Card {
+         value,
+         suit: CardSuit::Clubs,
+         id: None,
+      };
+      game.hands[0] = vec![
+         card(CardValue::Three),
+         card(CardValue::Five),
+         card(CardValue::Six),
+         card(CardValue::Nine),
+      ];
+      game.hands[1] = vec![card(CardValue::Four), card(CardValue::Jack)];
+      assert!(game.take_turn(&[card(CardValue::Three)]).is_ok());
+      assert!(game.take_turn(&[card(CardValue::Four)]).is_ok());
+      assert!(game.take_turn(&[card(CardValue::Nine)]).is_ok());
+   }
+
+   #[test]
+   fn records_pickups() {
+      let mut game = GameState::new_game_skip_setup(4);
+      assert!(!game.play_card(CardValue::Nine).unwrap().picked_up);
+      let outcome = game.play_card(CardValue::Three).unwrap();
+      assert!(outcome.picked_up);
+      assert!(!outcome.cleared);
+      assert_eq!(outcome.next_player, 2);
+      assert!(game.pile_cards.is_empty());
+   }
+
    #[test]
    fn ten_clears_no_rotate() {
       let mut game = GameState::new_game_skip_setup(4);
       assert!(game.play_card(CardValue::Three).is_ok());
       assert_eq!(game.pile_cards.len(), 1);
-      assert!(game.play_card(CardValue::Ten).is_ok());
+      let outcome = game.play_card(CardValue::Ten).unwrap();
+      assert!(outcome.cleared);
+      assert_eq!(outcome.next_player, 1);
       assert_eq!(game.pile_cards.len(), 0);
       assert_eq!(game.active_player, 1);
    }
@@ -548,4 +951,345 @@ mod test {
       game.play_card(CardValue::Ten).unwrap();
       assert_eq!(game.active_player, 2);
    }
+
+   #[test]
+   fn validate_play_matches_take_turn() {
+      let mut game = GameState::new(2);
+      let hand = game.hands[0].clone();
+      assert_eq!(
+         validate_play(&game.public_state(), &hand, &hand[..2]),
+         Err(PlayError::SetupNeedsThreeCards)
+      );
+      assert_eq!(game.take_turn(&hand[..2]), Err(PlayError::SetupNeedsThreeCards));
+      // every card in a two player deck is unique
+      assert_eq!(
+         validate_play(&game.public_state(), &hand, &[hand[0], hand[0], hand[1]]),
+         Err(PlayError::SetupCardsNotHeld)
+      );
+      // face up cards can be chosen again
+      let face_up = game.face_up_three[0].clone();
+      assert_eq!(validate_play(&game.public_state(), &hand, &face_up), Ok(()));
+      assert!(game.take_turn(&face_up).is_ok());
+   }
+
+   #[test]
+   fn validate_play_checks_play_zone() {
+      let mut game = GameState::new_game_skip_setup(2);
+      let three = Card {
+         value: CardValue::Three,
+         suit: CardSuit::Clubs,
+         id: None,
+      };
+      let five = Card {
+         value: CardValue::Five,
+         suit: CardSuit::Clubs,
+         id: None,
+      };
+      game.hands[0] = vec![three, five];
+      let state = game.public_state();
+      assert_eq!(validate_play(&state, &game.hands[0], &[]), Err(PlayError::NoCards));
+      assert_eq!(
+         validate_play(&state, &game.hands[0], &[three, five]),
+         Err(PlayError::MixedValues)
+      );
+      assert_eq!(
+         validate_play(&state, &game.hands[0], &[three, three]),
+         Err(PlayError::CardsNotHeld)
+      );
+      assert_eq!(validate_play(&state, &game.hands[0], &[five]), Ok(()));
+
+      // with an empty hand, the face up three are played, then the face down three blind
+      let face_up = game.face_up_three[0].clone();
+      // two player decks have no spades
+      let not_dealt = Card {
+         value: CardValue::Three,
+         suit: CardSuit::Spades,
+         id: None,
+      };
+      assert_eq!(validate_play(&state, &[], &[not_dealt]), Err(PlayError::CardsNotHeld));
+      assert_eq!(validate_play(&state, &[], &face_up[..1]), Ok(()));
+      game.face_up_three[0].clear();
+      let state = game.public_state();
+      assert_eq!(validate_play(&state, &[], &[three]), Err(PlayError::FaceDownChosen));
+      assert_eq!(validate_play(&state, &[], &[]), Ok(()));
+   }
+
+   #[test]
+   fn records_highlights() {
+      let mut game = GameState::new_game_skip_setup(4);
+      for _ in 0..4 {
+         assert!(game.play_card(CardValue::Three).is_ok());
+      }
+      assert_eq!(game.last_turn_highlights().len(), 1);
+      assert_eq!(game.last_turn_highlights()[0].kind, HighlightKind::FourOfAKind);
+      assert_eq!(game.last_turn_highlights()[0].turn, 4);
+
+      use CardValue::*;
+      for &value in &[Five, Six, Eight, Nine, Jack, Queen, King, Ace, Ace] {
+         assert!(game.play_card(value).is_ok());
+         assert!(game.last_turn_highlights().is_empty());
+      }
+      let picking_up = game.active_player;
+      assert!(game.play_card(Three).is_ok());
+      assert_eq!(
+         game.last_turn_highlights(),
+         &[Highlight {
+            turn: 14,
+            player: picking_up,
+            kind: HighlightKind::BigPickup(10),
+         }]
+      );
+      assert_eq!(game.highlights.len(), 2);
+   }
+
+   #[test]
+   fn highlights_winning_from_face_down() {
+      let mut game = GameState::new_game_skip_setup(2);
+      game.hands[0].clear();
+      game.face_up_three[0].clear();
+      game.face_down_three[0] = vec![Card {
+         value: CardValue::Two,
+         suit: CardSuit::Clubs,
+         id: None,
+      }];
+      assert!(game.take_turn(&[]).unwrap().game_over);
+      assert_eq!(game.last_turn_highlights()[0].kind, HighlightKind::WonFromFaceDown);
+   }
+
+   #[test]
+   fn card_ids_are_unique_and_follow_plays() {
+      let mut game = GameState::new_game_skip_setup(4);
+      let mut ids: Vec<u16> = game
+         .hands
+         .iter()
+         .chain(game.face_up_three.iter())
+         .chain(game.face_down_three.iter())
+         .flatten()
+         .map(|x| x.id.unwrap())
+         .collect();
+      ids.sort_unstable();
+      ids.dedup();
+      assert_eq!(ids.len(), 4 * (HAND_SIZE + 6));
+
+      // clients don't have to send ids, and the held card is played either way
+      let held = game.hands[0][0];
+      let asked_for = Card { id: None, ..held };
+      assert!(game.take_turn(&[asked_for]).is_ok());
+      assert_eq!(game.last_cards_played[0].id, held.id);
+   }
+
+   #[test]
+   fn conceding_passes_the_turn() {
+      let mut game = GameState::new_game_skip_setup(3);
+      assert!(!game.concede(0));
+      assert_eq!(game.active_player, 1);
+      assert!(game.play_card(CardValue::Three).is_ok());
+      assert_eq!(game.active_player, 2);
+      assert!(game.play_card(CardValue::Four).is_ok());
+      assert_eq!(game.active_player, 1);
+   }
+
+   #[test]
+   fn conceded_players_finish_last() {
+      let mut game = GameState::new_game_skip_setup(4);
+      assert!(!game.concede(2));
+      assert!(!game.concede(1));
+      game.face_up_three[0].clear();
+      game.face_down_three[0].clear();
+      assert!(game.play_card(CardValue::Three).unwrap().game_over);
+      assert_eq!(game.out_players, vec![0, 3, 1, 2]);
+
+      let mut game = GameState::new_game_skip_setup(3);
+      assert!(!game.concede(1));
+      assert!(game.concede(0));
+      assert_eq!(game.out_players, vec![2, 0, 1]);
+   }
+
+   #[test]
+   fn placements_fill_in_during_the_game() {
+      let mut game = GameState::new_game_skip_setup(4);
+      assert_eq!(&*game.public_state().placements, &[None, None, None, None]);
+      game.face_up_three[0].clear();
+      game.face_down_three[0].clear();
+      let outcome = game.play_card(CardValue::Three).unwrap();
+      assert!(outcome.player_out);
+      assert!(!outcome.game_over);
+      assert!(!game.concede(2));
+      assert_eq!(&*game.public_state().placements, &[Some(0), None, Some(3), None]);
+      assert!(game.concede(1));
+      assert_eq!(&*game.public_state().placements, &[Some(0), Some(2), Some(3), Some(1)]);
+   }
+
+   #[test]
+   fn repeated_positions_are_drawn() {
+      let mut looped = GameState::new_game_skip_setup(2);
+      // Not a ten, since a clear would start the count over
+      let card = Card {
+         value: CardValue::Three,
+         suit: CardSuit::Hearts,
+         id: None,
+      };
+      looped.hands[looped.active_player as usize] = vec![card];
+      let mut game = looped.clone();
+      for _ in 0..DEFAULT_MAX_REPETITIONS {
+         assert_eq!(game.draw_reason(DrawRules::default()), None);
+         // As if the players had found their way back to where they started
+         let positions = std::mem::take(&mut game.positions);
+         game = GameState {
+            positions,
+            ..looped.clone()
+         };
+         assert!(!game.take_turn(&[card]).unwrap().game_over);
+      }
+      assert_eq!(game.draw_reason(DrawRules::default()), Some(DrawReason::Repetition));
+
+      let rules = DrawRules {
+         max_repetitions: None,
+         max_turns: Some(game.turns_taken),
+      };
+      assert_eq!(game.draw_reason(rules), Some(DrawReason::TurnLimit));
+   }
+
+   #[test]
+   fn positions_before_a_clear_are_forgotten() {
+      let mut game = GameState::new_game_skip_setup(2);
+      assert!(!game.play_card(CardValue::Three).unwrap().cleared);
+      let before_clear = game.position_hash();
+      assert_eq!(game.positions.get(&before_clear), Some(&1));
+
+      assert!(game.play_card(CardValue::Ten).unwrap().cleared);
+      assert_eq!(game.positions.len(), 1);
+      assert!(!game.positions.contains_key(&before_clear));
+   }
+
+   #[test]
+   fn repetitions_stop_counting_at_the_most_a_count_can_hold() {
+      let mut game = GameState::new_game_skip_setup(2);
+      let card = Card {
+         value: CardValue::Three,
+         suit: CardSuit::Hearts,
+         id: None,
+      };
+      game.hands[game.active_player as usize] = vec![card];
+      let mut next = game.clone();
+      next.take_turn(&[card]).unwrap();
+      game.positions.insert(next.position_hash(), u8::MAX);
+
+      assert!(!game.take_turn(&[card]).unwrap().game_over);
+      assert_eq!(game.positions[&next.position_hash()], u8::MAX);
+   }
+
+   #[test]
+   fn outcomes_say_what_each_play_did() {
+      let card = |value| Card {
+         value,
+         suit: CardSuit::Hearts,
+         id: None,
+      };
+      let mut game = GameState::from_layout(&Layout {
+         hands: vec![
+            vec![card(CardValue::Five), card(CardValue::Nine)],
+            vec![card(CardValue::Three), card(CardValue::Ten)],
+            vec![card(CardValue::Five)],
+         ],
+         face_up_three: vec![vec![], vec![], vec![]],
+         face_down_three: vec![vec![], vec![], vec![]],
+         pile: vec![card(CardValue::Eight)],
+         cleared: vec![],
+         phase: Phase::Play,
+         active_player: 0,
+      })
+      .unwrap();
+
+      assert_eq!(
+         game.take_turn(&[card(CardValue::Five)]),
+         Ok(PlayOutcome {
+            picked_up: true,
+            next_player: 1,
+            ..PlayOutcome::default()
+         })
+      );
+      // Clearing the pile earns another go
+      assert_eq!(
+         game.take_turn(&[card(CardValue::Ten)]),
+         Ok(PlayOutcome {
+            cleared: true,
+            next_player: 1,
+            ..PlayOutcome::default()
+         })
+      );
+      assert_eq!(
+         game.take_turn(&[card(CardValue::Three)]),
+         Ok(PlayOutcome {
+            player_out: true,
+            next_player: 2,
+            ..PlayOutcome::default()
+         })
+      );
+      assert_eq!(
+         game.take_turn(&[card(CardValue::Five)]),
+         Ok(PlayOutcome {
+            player_out: true,
+            game_over: true,
+            next_player: 2,
+            ..PlayOutcome::default()
+         })
+      );
+   }
+
+   #[test]
+   fn same_seed_deals_the_same_cards() {
+      let game = GameState::with_seed(4, 7);
+      let again = GameState::with_seed(4, 7);
+      assert_eq!(game.hands, again.hands);
+      assert_eq!(game.face_up_three, again.face_up_three);
+      assert_eq!(game.face_down_three, again.face_down_three);
+      assert_ne!(game.hands, GameState::with_seed(4, 8).hands);
+   }
+
+   #[test]
+   fn layouts_place_every_card() {
+      let card = |value| Card {
+         value,
+         suit: CardSuit::Hearts,
+         id: None,
+      };
+      let mut layout = Layout {
+         hands: vec![
+            vec![card(CardValue::Nine), card(CardValue::Three)],
+            vec![card(CardValue::Five)],
+         ],
+         face_up_three: vec![vec![card(CardValue::Ace)], vec![]],
+         face_down_three: vec![vec![card(CardValue::Two)], vec![card(CardValue::King)]],
+         pile: vec![card(CardValue::Six), card(CardValue::Seven)],
+         cleared: vec![],
+         phase: Phase::Play,
+         active_player: 1,
+      };
+      let mut game = GameState::from_layout(&layout).unwrap();
+      assert_eq!(game.hands[0], [card(CardValue::Three), card(CardValue::Nine)]);
+      assert_eq!(game.pile_cards.last(), Some(&card(CardValue::Seven)));
+      let mut ids: Vec<_> = game
+         .hands
+         .iter()
+         .chain(game.face_up_three.iter())
+         .chain(game.face_down_three.iter())
+         .flatten()
+         .chain(game.pile_cards.iter())
+         .map(|x| x.id.unwrap())
+         .collect();
+      ids.sort_unstable();
+      ids.dedup();
+      assert_eq!(ids.len(), 8);
+      // Only a seven or lower can go on the seven
+      let outcome = game.take_turn(&[card(CardValue::Five)]).unwrap();
+      assert!(!outcome.game_over);
+      assert!(!outcome.picked_up);
+
+      layout.face_up_three.pop();
+      assert_eq!(
+         GameState::from_layout(&layout).unwrap_err(),
+         LayoutError::MismatchedSeats
+      );
+   }
 }
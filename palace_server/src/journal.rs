@@ -0,0 +1,143 @@
+//! A short record of a lobby's recent events, so that a client who missed a few can be sent just those
+//! instead of everything about the lobby. Observers overseeing many lobbies are passed each event as it's recorded
+
+use crate::data::{LobbyEventKind, ObservedLobbyEvent, PalaceOutMessage};
+use crate::{send_serialized, serialize_once, LobbyId, Sender};
+use std::collections::VecDeque;
+
+/// Clients further behind than this get a full snapshot instead
+const MAX_JOURNAL_EVENTS: usize = 64;
+
+pub struct Observer {
+   pub sender: Sender,
+   /// Which kinds of event they want, or every kind if empty
+   pub kinds: Vec<LobbyEventKind>,
+}
+
+impl Observer {
+   fn wants(&self, kind: LobbyEventKind) -> bool {
+      self.kinds.is_empty() || self.kinds.contains(&kind)
+   }
+}
+
+pub struct Journal {
+   lobby_id: LobbyId,
+   /// Each event's seq, and the message as it was sent
+   events: VecDeque<(u64, Vec<u8>)>,
+   latest_seq: u64,
+   pub observers: Vec<Observer>,
+}
+
+impl Journal {
+   pub fn new(lobby_id: LobbyId) -> Journal {
+      Journal {
+         lobby_id,
+         events: VecDeque::new(),
+         latest_seq: 0,
+         observers: Vec::new(),
+      }
+   }
+
+   /// The seq the next event has to carry
+   pub fn next_seq(&self) -> u64 {
+      self.latest_seq + 1
+   }
+
+   /// Keeps an event carrying `next_seq`, and passes it on to the observers who want its kind.
+   /// Returns the event encoded, for sending to everyone else
+   pub fn record(&mut self, kind: LobbyEventKind, event: &PalaceOutMessage) -> &[u8] {
+      self.latest_seq += 1;
+      if self.observers.iter().any(|x| x.wants(kind)) {
+         let observed = serialize_once(&PalaceOutMessage::ObservedLobbyEvent(ObservedLobbyEvent {
+            lobby_id: self.lobby_id,
+            event,
+         }));
+         for observer in self.observers.iter_mut().filter(|x| x.wants(kind)) {
+            let _ = send_serialized(&mut observer.sender, observed.clone());
+         }
+      }
+      if self.events.len() == MAX_JOURNAL_EVENTS {
+         self.events.pop_front();
+      }
+      self.events.push_back((self.latest_seq, serialize_once(event)));
+      &self.events.back().unwrap().1
+   }
+
+   /// The seq of the last event recorded, or 0 before there are any
+   pub fn latest_seq(&self) -> u64 {
+      self.latest_seq
+   }
+
+   /// Every event after `seq`, oldest first, or None if some of them have been forgotten
+   pub fn since(&self, seq: u64) -> Option<impl Iterator<Item = &[u8]>> {
+      let forgotten_up_to = self.latest_seq - self.events.len() as u64;
+      if seq < forgotten_up_to {
+         return None;
+      }
+      Some(
+         self
+            .events
+            .iter()
+            .filter(move |(event_seq, _)| *event_seq > seq)
+            .map(|(_, event)| event.as_slice()),
+      )
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::data::PlayerLeaveEvent;
+
+   #[cfg(test)]
+   fn record_leave(journal: &mut Journal) {
+      let event = PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
+         total_num_players: 1,
+         slot: 1,
+         seq: journal.next_seq(),
+      });
+      journal.record(LobbyEventKind::Leave, &event);
+   }
+
+   #[test]
+   fn catch_up_from_any_remembered_seq() {
+      let mut journal = Journal::new(LobbyId(7));
+      assert_eq!(journal.latest_seq(), 0);
+      assert_eq!(journal.since(0).unwrap().count(), 0);
+
+      for _ in 0..MAX_JOURNAL_EVENTS + 2 {
+         record_leave(&mut journal);
+      }
+      assert_eq!(journal.latest_seq(), 66);
+      let missed: Vec<&[u8]> = journal.since(64).unwrap().collect();
+      assert_eq!(missed.len(), 2);
+      assert!(std::str::from_utf8(missed[1]).unwrap().contains(r#""seq":66"#));
+
+      // The first two events were forgotten to make room
+      assert_eq!(journal.since(2).unwrap().count(), MAX_JOURNAL_EVENTS);
+      assert!(journal.since(1).is_none());
+      assert_eq!(journal.since(66).unwrap().count(), 0);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn observers_only_get_the_kinds_they_asked_for() {
+      let mut journal = Journal::new(LobbyId(7));
+      let mut observe = |kinds| {
+         let (outgoing, incoming) = std::sync::mpsc::channel();
+         journal.observers.push(Observer {
+            sender: Sender::new(crate::Transport::Local(outgoing)),
+            kinds,
+         });
+         incoming
+      };
+      let everything = observe(Vec::new());
+      let plays = observe(vec![LobbyEventKind::Play]);
+      record_leave(&mut journal);
+
+      let observed = String::from_utf8(everything.try_recv().unwrap().unwrap()).unwrap();
+      assert!(observed.starts_with(r#"{"ObservedLobbyEvent":{"lobby_id":"7","event":{"PlayerLeaveEvent""#));
+      assert!(plays.try_recv().is_err());
+   }
+}
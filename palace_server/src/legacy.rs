@@ -0,0 +1,104 @@
+//! The message shapes of clients written against the original protocol, so they keep working while they migrate.
+//! Only understood when the server runs with `ServerConfig::legacy_protocol`
+
+use crate::data::{ListLobbiesMessage, PalaceInMessage, TakeTurnError, TakeTurnMessage};
+use crate::game::Card;
+use crate::{LobbyId, PlayerId};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub enum LegacyInMessage {
+   /// Always the first page
+   ListLobbies,
+   ChooseFaceup(ChooseFaceupMessage),
+   MakePlay(MakePlayMessage),
+}
+
+#[derive(Deserialize)]
+pub struct ChooseFaceupMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub card_one: Card,
+   pub card_two: Card,
+   pub card_three: Card,
+}
+
+#[derive(Deserialize)]
+pub struct MakePlayMessage {
+   pub lobby_id: LobbyId,
+   pub player_id: PlayerId,
+   pub cards: Box<[Card]>,
+}
+
+impl LegacyInMessage {
+   /// The same request in the current protocol
+   pub fn upgrade(self) -> PalaceInMessage {
+      match self {
+         LegacyInMessage::ListLobbies => PalaceInMessage::ListLobbies(ListLobbiesMessage {
+            page: 0,
+            page_size: None,
+         }),
+         LegacyInMessage::ChooseFaceup(message) => PalaceInMessage::TakeTurn(TakeTurnMessage {
+            lobby_id: message.lobby_id,
+            player_id: message.player_id,
+            cards: Box::new([message.card_one, message.card_two, message.card_three]),
+            play_seq: None,
+         }),
+         LegacyInMessage::MakePlay(message) => PalaceInMessage::TakeTurn(TakeTurnMessage {
+            lobby_id: message.lobby_id,
+            player_id: message.player_id,
+            cards: message.cards,
+            play_seq: None,
+         }),
+      }
+   }
+}
+
+/// The messages whose shape changed since the original protocol. Everything else is sent as it is today
+#[derive(Serialize)]
+pub enum LegacyOutMessage<'a> {
+   ChooseFaceupResponse(Result<(), TakeTurnError>),
+   MakePlayResponse(Result<(), TakeTurnError>),
+   HandEvent(LegacyHandEvent<'a>),
+}
+
+#[derive(Serialize)]
+pub struct LegacyHandEvent<'a> {
+   pub hand: &'a [Card],
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn documented_legacy_messages_upgrade() {
+      let message: LegacyInMessage = serde_json::from_str(r#""ListLobbies""#).unwrap();
+      match message.upgrade() {
+         PalaceInMessage::ListLobbies(message) => assert_eq!(message.page, 0),
+         _ => panic!("Expected ListLobbies"),
+      }
+
+      let message: LegacyInMessage = serde_json::from_str(
+         r#"{"ChooseFaceup":{
+            "lobby_id":"958bf2645045de4eacd96d8deab7db5a",
+            "player_id":"54c26676175c633b4c904b7369729e10",
+            "card_one":{"value":"Two","suit":"Clubs"},
+            "card_two":{"value":"Three","suit":"Diamonds"},
+            "card_three":{"value":"Four","suit":"Spades"}
+         }}"#,
+      )
+      .unwrap();
+      match message.upgrade() {
+         PalaceInMessage::TakeTurn(message) => assert_eq!(message.cards.len(), 3),
+         _ => panic!("Expected TakeTurn"),
+      }
+   }
+
+   #[test]
+   fn hand_events_are_wrapped() {
+      let hand = [];
+      let event = LegacyOutMessage::HandEvent(LegacyHandEvent { hand: &hand });
+      assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"HandEvent":{"hand":[]}}"#);
+   }
+}
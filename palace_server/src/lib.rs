@@ -1,31 +1,137 @@
+// ws::Error is what ws::Handler returns, so there's no making it smaller
+#![allow(clippy::result_large_err)]
+
 pub mod ai;
+mod analysis;
+mod archive;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod commentary;
 pub mod data;
 pub mod game;
+mod journal;
+mod legacy;
+#[cfg(unix)]
+mod local;
+mod metrics;
 pub mod monte_game;
+mod overlay;
+mod permissions;
+mod predictions;
+mod redact;
+mod round_trip;
+mod scenario;
+pub mod schedule;
+pub mod test_vectors;
+pub mod tournament;
 
-use crate::ai::PalaceAi;
+use crate::ai::{AiResources, PalaceAi};
+use crate::analysis::GameRecording;
+use crate::archive::{ArchivedPlayer, GameArchive, GameRecord};
+use crate::commentary::{Commentary, Commentator, Play};
 use crate::data::*;
-use crate::game::GameState;
+use crate::game::{Card, DrawReason, DrawRules, GameState, Phase, PlayOutcome, PublicGameState};
+use crate::journal::{Journal, Observer};
+use crate::legacy::{LegacyHandEvent, LegacyInMessage, LegacyOutMessage};
+use crate::metrics::Metrics;
+use crate::overlay::{OverlayFeed, OverlayGame, OverlaySeat, Overlays};
+use crate::permissions::{Denial, Permission};
+use crate::predictions::Predictions;
+use crate::round_trip::RoundTrip;
+use crate::scenario::Scenario;
+use crate::schedule::{Due, Registrant, Schedule};
+use arc_swap::ArcSwap;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
 use log::{debug, error, info, trace};
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
-use serde::{Deserialize, Deserializer, Serializer};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use schemars::JsonSchema;
+use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
-use ws::{CloseCode, Handler, Handshake, Message, Sender};
+use ws::{CloseCode, Frame, Handler, Handshake, Message, OpCode};
 
 const EMPTY_LOBBY_PRUNE_THRESHOLD_SECS: u64 = 30;
 const PLAYER_NAME_LIMIT: usize = 20;
 const LOBBY_NAME_LIMIT: usize = 20;
 const PASSWORD_LIMIT: usize = 20;
+const DESCRIPTION_LIMIT: usize = 200;
+const DEFAULT_LOBBY_PAGE_SIZE: u64 = 50;
+const MAX_LOBBY_PAGE_SIZE: u64 = 100;
+const LOBBY_LIST_REFRESH_MILLIS: u64 = 100;
+const LOBBY_LIST_MAX_AGE_MILLIS: u64 = 1000;
+const DRAIN_COUNTDOWN_INTERVAL_SECS: u64 = 30;
+const SCHEDULE_CHECK_INTERVAL_MILLIS: u64 = 500;
+/// Games analyzed at once. Each analysis already spreads over every core
+const MAX_CONCURRENT_ANALYSES: usize = 2;
+/// Simulations behind a hint. Fewer than AI players use, so that hints come back quickly
+const HINT_SIMULATIONS: usize = 200;
+/// Shortest time between hints for one player, however many they have left
+const HINT_INTERVAL_SECS: u64 = 10;
+const SERVER_STATS_INTERVAL_SECS: u64 = 5;
+const PRESENCE_IDLE_SECS: u64 = 60;
+const PRESENCE_CHECK_INTERVAL_MILLIS: u64 = 1000;
+/// Shorter turns leave no time for the round trip to the player, let alone for them to play
+const MIN_TURN_TIMER_SECS: u16 = 5;
+/// How long before their turn is up that a human is warned
+const TURN_WARNING_SECS: u64 = 5;
+/// How long before a lobby is closed for its age that its players are warned
+const LOBBY_EXPIRY_WARNING_SECS: u64 = 60;
+const LOBBY_CREATION_WINDOW_SECS: u64 = 3600;
+/// How long a player who dropped out of a game has to reconnect before their seat is freed
+const RECONNECT_WINDOW_SECS: u64 = 120;
+const MAX_RECONNECT_ATTEMPTS: usize = 5;
+const RECONNECT_ATTEMPT_WINDOW_SECS: u64 = 10;
+/// Failed sends in a row before a connection is given up on mid-game
+const MAX_CONSECUTIVE_SEND_FAILURES: u32 = 3;
+/// Spectator counts are sent as u16
+const MAX_SPECTATORS: usize = u16::MAX as usize;
+const MAX_SEAT_QUEUE: usize = 50;
+const MAX_JOIN_TOKEN_SECS: u32 = 7 * 24 * 60 * 60;
+/// Unexpired join tokens a lobby can have at once
+const MAX_JOIN_TOKENS: usize = 20;
+
+lazy_static! {
+   /// Connections whose last message came in a text frame; everyone else is sent binary frames
+   static ref TEXT_FRAME_CONNECTIONS: RwLock<HashSet<u32>> = RwLock::new(HashSet::new());
+   /// Connections that have sent a message in the original protocol, so are sent its shapes where they differ
+   static ref LEGACY_CONNECTIONS: RwLock<HashSet<u32>> = RwLock::new(HashSet::new());
+   /// Sends that have failed in a row, by connection. Connections whose last send went through aren't listed
+   static ref SEND_FAILURES: RwLock<HashMap<u32, u32>> = RwLock::new(HashMap::new());
+   /// By connection, for those that have been pinged
+   static ref ROUND_TRIPS: RwLock<HashMap<u32, RoundTrip>> = RwLock::new(HashMap::new());
+   /// What `Activity` times are measured from
+   static ref STARTED: Instant = Instant::now();
+}
+
+#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct PlayerId(
+   #[schemars(with = "String")]
+   #[serde(serialize_with = "as_hex_str", deserialize_with = "hex_to_u128")]
+   u128,
+);
 
-#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
-pub struct PlayerId(#[serde(serialize_with = "as_hex_str", deserialize_with = "hex_to_u128")] u128);
+#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct LobbyId(
+   #[schemars(with = "String")]
+   #[serde(serialize_with = "as_hex_str", deserialize_with = "hex_to_u128")]
+   u128,
+);
 
-#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
-pub struct LobbyId(#[serde(serialize_with = "as_hex_str", deserialize_with = "hex_to_u128")] u128);
+/// Lets whoever holds it join a lobby without its password
+#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct JoinToken(
+   #[schemars(with = "String")]
+   #[serde(serialize_with = "as_hex_str", deserialize_with = "hex_to_u128")]
+   u128,
+);
 
 pub fn as_hex_str<T, S>(token: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -40,12 +146,99 @@ where
    D: Deserializer<'de>,
 {
    use serde::de::{Error, Unexpected};
+   use serde::Deserialize;
    String::deserialize(deserializer).and_then(|string| {
       u128::from_str_radix(&string, 16)
          .map_err(|_| Error::invalid_value(Unexpected::Str(&string), &"hex encoded token"))
    })
 }
 
+/// Server-wide settings that are fixed for the lifetime of the process
+#[derive(Default)]
+pub struct ServerConfig {
+   /// Secret that grants access to admin messages. With no key set, admin messages are always rejected
+   pub admin_key: Option<String>,
+   /// Announced to every connection as soon as it opens
+   pub motd: Option<String>,
+   /// Send spectators the moves AI players considered on each turn. Spectators could pass these on
+   /// to players, so this is only meant for developing and debugging AI
+   pub explain_ai_decisions: bool,
+   /// ONNX model used by the Neural strategy. Only loaded when built with the `neural` feature
+   pub policy_model_path: Option<std::path::PathBuf>,
+   /// Where to serve response time metrics over HTTP, in the Prometheus format. Not served if unset
+   pub metrics_address: Option<String>,
+   /// Where to serve a simplified JSON feed of each lobby over HTTP, for stream overlays. Not served if unset
+   pub overlay_address: Option<String>,
+   /// How far behind the game the overlay feed runs, so that a stream can't be watched for players' cards
+   pub overlay_delay_secs: u64,
+   /// File to append a JSON line to for every completed game. Games aren't archived if unset
+   pub game_archive_path: Option<std::path::PathBuf>,
+   /// Reject messages with fields the server doesn't know about, instead of ignoring those fields.
+   /// Meant for catching typos while developing a client
+   pub strict_protocol: bool,
+   /// Also accept messages in the original protocol, answering them in kind, for clients that haven't migrated
+   pub legacy_protocol: bool,
+   /// Close lobbies that have gone this long without starting a game, counting from when the lobby was
+   /// created or its last game ended. Lobbies can wait forever if unset
+   pub max_waiting_lobby_secs: Option<u64>,
+   /// Close lobbies this long after they were created, even in the middle of a game. No limit if unset
+   pub max_lobby_lifetime_secs: Option<u64>,
+   /// Most lobbies that can be open at once. No limit if unset
+   pub max_lobbies: Option<usize>,
+   /// Most lobbies a single address can create in an hour. No limit if unset
+   pub max_lobbies_per_address_per_hour: Option<usize>,
+   /// Proxies whose X-Forwarded-For or Forwarded headers are believed when working out where a client is
+   /// connecting from. Anyone else could claim to be forwarding for any address, so these headers are
+   /// ignored if unset
+   pub trusted_proxies: Vec<IpAddr>,
+   /// Stop adding clandestine bots while at least this many humans are connected, and close the lobbies that
+   /// only bots are waiting in. No limit if unset
+   pub bot_max_humans: Option<usize>,
+   /// Like `bot_max_humans`, but for the one minute load average. Ignored where the load average isn't known
+   pub bot_max_load: Option<f64>,
+   /// How long a pass of the AI loop may take on average before AI thinks less. AI always thinks fully if unset
+   pub ai_latency_budget_millis: Option<u64>,
+   /// How hard AI thinks while over `ai_latency_budget_millis`, from 0 to 1. At 0, Monty plays like LowAndSteady
+   pub degraded_ai_strength: f64,
+   /// More addresses to listen on besides the one the server is run with, sharing the same lobbies.
+   /// Note that on most systems `[::]` already takes IPv4 connections on the same port
+   pub additional_addresses: Vec<String>,
+   /// Unix domain socket to also serve the protocol on, one JSON message per line, for frontends on the same
+   /// machine. Each connection to the socket is one client, though broadcasts such as announcements only reach
+   /// WebSocket clients. Not served if unset
+   pub local_socket_path: Option<std::path::PathBuf>,
+   /// Remarks on plays for spectators, which are also kept with archived games. No commentary if unset
+   pub commentator: Option<Arc<dyn Commentator>>,
+   /// Lobbies to open every day at set times. More can be scheduled by admins while the server runs
+   pub scheduled_events: Vec<ScheduledEvent>,
+   /// Games still going after this many turns, counting setup, are called a draw. Games can go on forever if unset
+   pub max_game_turns: Option<u32>,
+}
+
+/// While draining, no new lobbies can be created or joined, but games in progress carry on
+struct DrainState {
+   shutdown_at: Option<Instant>,
+}
+
+impl DrainState {
+   fn event(&self) -> ServerDrainingEvent {
+      ServerDrainingEvent {
+         draining: true,
+         shutdown_in_secs: self
+            .shutdown_at
+            .map(|x| x.saturating_duration_since(Instant::now()).as_secs()),
+      }
+   }
+}
+
+/// Lifetime statistics of a lobby, across every game played in it
+#[derive(Default)]
+struct LobbyStats {
+   total_players_seen: u64,
+   total_game_time: Duration,
+   placements: HashMap<PlayerId, Vec<u8>>,
+}
+
 struct Lobby {
    players: HashMap<PlayerId, Player>,
    players_by_turn_num: HashMap<u8, PlayerId>,
@@ -55,56 +248,304 @@ struct Lobby {
    game: Option<GameState>,
    owner: PlayerId,
    name: String,
+   description: String,
    creation_time: Instant,
-   turn_timer: Duration,
+   /// When the lobby was created, or its last game ended
+   waiting_since: Instant,
+   /// The age-based closing time that players were last warned about
+   expiry_warned: Option<Instant>,
+   turn_timer: TurnTimer,
    games_completed: u64,
+   stats: LobbyStats,
+   /// Seats the owner has taken out of play, sorted. They count against `max_players`
+   closed_seats: Vec<u8>,
+   /// Humans who have voted to abort the game in progress
+   abort_votes: HashSet<PlayerId>,
+   /// Humans who have voted to skip the turn in progress
+   skip_turn_votes: HashSet<PlayerId>,
+   after_game: AfterGamePolicy,
+   hints: HintPolicy,
+   practice: bool,
+   /// Set if the lobby plays a tutorial scenario instead of dealing at random
+   scenario: Option<&'static Scenario>,
+   /// In practice lobbies, the game as it was before each of the human's turns, oldest first
+   rewind_points: Vec<GameState>,
+   /// Spectators' guesses at the next play, and how well they've guessed this game
+   predictions: Predictions,
+   /// Lobbies waiting for the owner to answer their request to merge into this one, oldest first
+   merge_requests: Vec<LobbyId>,
+   /// When the prune loop should close the lobby, per `after_game`
+   closes_at: Option<Instant>,
+   /// Set while every human in the game is disconnected, so that their turns aren't played out
+   turn_timer_paused_at: Option<Instant>,
+   /// Set once the active player has been warned that their turn is nearly up
+   turn_warning: Option<TurnWarning>,
+   /// Recent reconnect attempts, per player
+   reconnect_attempts: HashMap<PlayerId, Vec<Instant>>,
+   /// Humans waiting for a seat to open up before the game starts, first in line first
+   seat_queue: VecDeque<QueuedPlayer>,
+   /// Tokens the owner has handed out for joining without the password
+   join_tokens: HashMap<JoinToken, JoinTokenGrant>,
+   /// What the commentator has said about the game in progress
+   commentary: Vec<Commentary>,
+   /// The game in progress as it was dealt, to be recorded once it's over
+   opening: Option<GameState>,
+   /// The most recently completed game, which its players can have analyzed
+   last_game: Option<Arc<GameRecording>>,
+   /// Recent joins, leaves and plays, for clients catching up on what they missed, and who's observing them
+   journal: Journal,
+   /// The scheduled event the lobby was opened for, if any
+   event: Option<String>,
+   draw_rules: DrawRules,
+   /// Seats players and seeds each game, so that everything left to chance in a game follows from its seed
+   rng: ChaChaRng,
+}
+
+struct JoinTokenGrant {
+   expires_at: Instant,
+   uses_left: u16,
+}
+
+struct QueuedPlayer {
+   name: String,
+   sender: Sender,
+}
+
+/// How long players have to take their turn
+#[derive(Clone, Copy)]
+struct TurnTimer {
+   /// Zero if turns aren't timed
+   limit: Duration,
+   /// Humans get at least this long, however short `limit` is
+   min_human: Duration,
+}
+
+impl TurnTimer {
+   fn is_enabled(&self) -> bool {
+      self.limit.as_secs() != 0
+   }
+
+   /// Clandestine bots get as long as humans, so that they keep passing as people
+   fn for_slot(&self, players: &HashMap<PlayerId, Player>, slot: u8) -> Duration {
+      if !self.is_enabled() {
+         return self.limit;
+      }
+      if players.values().any(|x| x.turn_number == slot && !x.is_requested_ai()) {
+         self.limit.max(self.min_human)
+      } else {
+         self.limit
+      }
+   }
+}
+
+/// The active player was warned that their turn is nearly up
+struct TurnWarning {
+   /// `GameState::last_turn_start` of the turn they were warned about
+   turn_start: Instant,
+   sent_at: Instant,
 }
 
 impl Lobby {
    pub fn display(&self, lobby_id: &LobbyId) -> LobbyDisplay {
       LobbyDisplay {
-         cur_players: self.players.len() as u8,
-         ai_players: self.players.values().filter(|p| p.is_requested_ai()).count() as u8,
+         cur_players: self.players.len() as u16,
+         ai_players: self.players.values().filter(|p| p.is_requested_ai()).count() as u16,
          max_players: self.max_players,
          started: self.game.is_some(),
          has_password: !self.password.is_empty(),
-         owner: &self.players[&self.owner].name,
-         name: &self.name,
+         owner_slot: self.players[&self.owner].turn_number,
+         owner_name: self.players[&self.owner].name.clone(),
+         name: self.name.clone(),
+         description: self.description.clone(),
          age: self.creation_time.elapsed().as_secs(),
          lobby_id: *lobby_id,
-         cur_spectators: self.spectators.len() as u8,
-         turn_timer: self.turn_timer.as_secs() as u8,
+         cur_spectators: self.spectators.len() as u16,
+         turn_timer: self.turn_timer.limit.as_secs() as u16,
+         games_completed: self.games_completed,
+         closed_seats: self.closed_seats.len() as u8,
+      }
+   }
+
+   /// Who's playing over a connection, if anyone
+   fn player_connected_as(&self, connection_id: u32) -> Option<PlayerId> {
+      self
+         .players
+         .iter()
+         .find(|(_, p)| match &p.connection {
+            Connection::Connected(senders) => senders.iter().any(|x| x.connection_id() == connection_id),
+            _ => false,
+         })
+         .map(|(id, _)| *id)
+   }
+
+   /// Keyed by turn number. Clandestine bots are passing as people, so they aren't marked as AI
+   fn player_profiles(&self) -> HashMap<u8, PlayerProfile> {
+      self
+         .players
+         .values()
+         .map(|x| {
+            let mut profile = PlayerProfile::new(x.name.clone(), x.is_requested_ai());
+            profile.rating = x.rating;
+            (x.turn_number, profile)
+         })
+         .collect()
+   }
+
+   /// The mean rating of the humans seated who have one
+   fn average_rating(&self) -> Option<u32> {
+      let ratings: Vec<u32> = self
+         .players
+         .values()
+         .filter(|x| !x.is_ai())
+         .filter_map(|x| x.rating)
+         .collect();
+      if ratings.is_empty() {
+         return None;
+      }
+      Some(ratings.iter().sum::<u32>() / ratings.len() as u32)
+   }
+
+   /// Whether the game in progress should be called a draw, under the lobby's rules and the server's turn limit
+   fn draw_reason(&self, max_game_turns: Option<u32>) -> Option<DrawReason> {
+      let gs = self.game.as_ref()?;
+      let max_turns = match (self.draw_rules.max_turns, max_game_turns) {
+         (Some(lobby), Some(server)) => Some(lobby.min(server)),
+         (lobby, server) => lobby.or(server),
+      };
+      gs.draw_reason(DrawRules {
+         max_turns,
+         ..self.draw_rules
+      })
+   }
+
+   /// How many players the lobby can currently hold
+   fn open_seats(&self) -> u8 {
+      self.max_players - self.closed_seats.len() as u8
+   }
+
+   fn settings(&self) -> LobbySettingsEvent<'_> {
+      LobbySettingsEvent {
+         max_players: self.max_players,
+         closed_seats: &self.closed_seats,
+      }
+   }
+
+   /// Owner first
+   fn next_open_slot(&self) -> u8 {
+      let mut slot: u8 = 0;
+      while self.players_by_turn_num.contains_key(&slot) || self.closed_seats.contains(&slot) {
+         slot += 1;
+      }
+      slot
+   }
+
+   pub fn stats(&self) -> LobbyStatsResponse<'_> {
+      let mut seat_placements: Vec<SeatPlacements> = self
+         .players
+         .iter()
+         .map(|(id, player)| SeatPlacements {
+            slot: player.turn_number,
+            name: &player.name,
+            placements: self.stats.placements.get(id).map(Vec::as_slice).unwrap_or(&[]),
+         })
+         .collect();
+      seat_placements.sort_unstable_by_key(|seat| seat.slot);
+      LobbyStatsResponse {
          games_completed: self.games_completed,
+         total_players_seen: self.stats.total_players_seen,
+         average_game_secs: self
+            .stats
+            .total_game_time
+            .as_secs()
+            .checked_div(self.games_completed)
+            .unwrap_or(0),
+         seat_placements,
+      }
+   }
+
+   /// No more than spectators can see
+   fn overlay(&self) -> OverlayFeed<'_> {
+      let public_gs = self.game.as_ref().map(GameState::public_state);
+      let mut seats: Vec<OverlaySeat> = self
+         .players
+         .iter()
+         .map(|(id, player)| {
+            let slot = usize::from(player.turn_number);
+            OverlaySeat {
+               slot: player.turn_number,
+               name: &player.name,
+               wins: self
+                  .stats
+                  .placements
+                  .get(id)
+                  .map_or(0, |x| x.iter().filter(|x| **x == 0).count() as u32),
+               cards_left: public_gs
+                  .as_ref()
+                  .map(|gs| gs.hands[slot] + gs.face_up_three[slot].len() as u16 + u16::from(gs.face_down_three[slot])),
+               placement: public_gs.as_ref().and_then(|gs| gs.placements[slot]),
+            }
+         })
+         .collect();
+      seats.sort_unstable_by_key(|seat| seat.slot);
+      let game = match (&self.game, &public_gs) {
+         (Some(gs), Some(public_gs)) => Some(OverlayGame {
+            turns_taken: gs.turns_taken,
+            active_player: gs.active_player,
+            top_card: public_gs.top_card,
+            pile_size: public_gs.pile_size,
+            last_highlight: gs.highlights.last().copied(),
+         }),
+         _ => None,
+      };
+      OverlayFeed {
+         lobby_name: &self.name,
+         seats,
+         game,
       }
    }
+
+   /// Everyone seated in the lobby, ordered by slot
+   pub fn seats(&self) -> Vec<LobbySeat<'_>> {
+      let mut seats: Vec<LobbySeat> = self
+         .players
+         .iter()
+         .map(|(id, player)| LobbySeat {
+            slot: player.turn_number,
+            name: &player.name,
+            is_ai: player.is_requested_ai(),
+            is_owner: *id == self.owner,
+            status: player.connection_status(),
+            presence: player.presence(),
+         })
+         .collect();
+      seats.sort_unstable_by_key(|seat| seat.slot);
+      seats
+   }
 }
 
-#[derive(Serialize)]
-pub struct LobbyDisplay<'a> {
-   pub cur_players: u8,
-   pub ai_players: u8,
+#[derive(Serialize, JsonSchema)]
+pub struct LobbyDisplay {
+   pub cur_players: u16,
+   pub ai_players: u16,
    pub max_players: u8,
    pub started: bool,
    pub has_password: bool,
-   pub owner: &'a str,
-   pub name: &'a str,
+   pub owner_slot: u8,
+   /// Only for display, since names needn't be unique
+   pub owner_name: String,
+   pub name: String,
+   pub description: String,
    pub age: u64,
    pub lobby_id: LobbyId,
-   pub cur_spectators: u8,
-   pub turn_timer: u8,
+   pub cur_spectators: u16,
+   pub turn_timer: u16,
    pub games_completed: u64,
-}
-
-fn next_public_id(players_by_public_id: &HashMap<u8, PlayerId>) -> u8 {
-   let mut id: u8 = 0;
-   while players_by_public_id.contains_key(&id) {
-      id += 1;
-   }
-   id
+   pub closed_seats: u8,
 }
 
 enum Connection {
-   Connected(ws::Sender),
+   /// Every device (socket) currently attached to the player
+   Connected(Vec<Sender>),
    Disconnected(DisconnectedState),
    Ai(AiState),
 }
@@ -130,9 +571,25 @@ struct Player {
    name: String,
    connection: Connection,
    turn_number: u8,
+   idle: bool,
+   /// The sequence number of the last play accepted from this player this game
+   last_play_seq: Option<u32>,
+   /// Hints given this game
+   hints_used: u16,
+   last_hint: Option<Instant>,
+   /// Always None until the server tracks ratings
+   rating: Option<u32>,
 }
 
 impl Player {
+   fn presence(&self) -> Presence {
+      if self.idle {
+         Presence::Idle
+      } else {
+         Presence::Active
+      }
+   }
+
    fn is_requested_ai(&self) -> bool {
       match &self.connection {
          Connection::Ai(ai) => !ai.is_clandestine,
@@ -143,49 +600,557 @@ impl Player {
    fn is_ai(&self) -> bool {
       matches!(self.connection, Connection::Ai(_))
    }
+
+   /// Clandestine bots are reported as connected humans
+   fn connection_status(&self) -> ConnectionStatus {
+      match &self.connection {
+         Connection::Connected(_) => ConnectionStatus::Connected,
+         Connection::Disconnected(ds) => match ds.reason {
+            DisconnectedReason::Left => ConnectionStatus::Disconnected,
+            DisconnectedReason::TimedOut => ConnectionStatus::TimedOut,
+            DisconnectedReason::Kicked => ConnectionStatus::Kicked,
+         },
+         Connection::Ai(_) => ConnectionStatus::Connected,
+      }
+   }
+}
+
+/// Every lobby a connection belongs to: at most one seat or place in a seat queue,
+/// plus any number of lobbies being spectated or observed. A lobby we're seated in is never also spectated
+#[derive(Default)]
+struct Memberships {
+   seat: Option<(LobbyId, PlayerId)>,
+   queued: Option<LobbyId>,
+   spectating: Vec<LobbyId>,
+   observing: Vec<LobbyId>,
+   /// The scheduled event we've registered for, until it opens
+   registered_for: Option<String>,
+}
+
+impl Memberships {
+   /// Leaves the seat held before, if it's a different one
+   fn take_seat(&mut self, lobbies: &mut IndexMap<LobbyId, Lobby>, seat: (LobbyId, PlayerId), our_sender_id: u32) {
+      self.settle_seat(lobbies, our_sender_id);
+      self.settle_queue(lobbies, our_sender_id);
+      if self.seat == Some(seat) {
+         return;
+      }
+      if let Some((old_lobby_id, old_player_id)) = self.seat.take() {
+         leave_seat(&old_lobby_id, &old_player_id, lobbies, our_sender_id);
+      }
+      if let Some(lobby_id) = self.queued.take() {
+         leave_queue(&lobby_id, lobbies, our_sender_id);
+      }
+      self.stop_spectating(lobbies, &seat.0, our_sender_id);
+      self.seat = Some(seat);
+   }
+
+   /// Gives up whatever seat or place in a queue we held before
+   fn queue(&mut self, lobbies: &mut IndexMap<LobbyId, Lobby>, lobby_id: LobbyId, our_sender_id: u32) {
+      self.settle_seat(lobbies, our_sender_id);
+      self.settle_queue(lobbies, our_sender_id);
+      if let Some((old_lobby_id, old_player_id)) = self.seat.take() {
+         leave_seat(&old_lobby_id, &old_player_id, lobbies, our_sender_id);
+      }
+      if let Some(old_lobby_id) = self.queued.take() {
+         leave_queue(&old_lobby_id, lobbies, our_sender_id);
+      }
+      self.queued = Some(lobby_id);
+   }
+
+   /// A merge may have moved our seat to another lobby, under the same player id. Or the player's id may have
+   /// been rotated, which leaves us seated only if we were the connection that rotated it
+   fn settle_seat(&mut self, lobbies: &IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+      let (lobby_id, player_id) = match self.seat {
+         Some(seat) => seat,
+         None => return,
+      };
+      match lobbies.get(&lobby_id) {
+         Some(lobby) if lobby.players.contains_key(&player_id) => return,
+         Some(lobby) => {
+            self.seat = lobby.player_connected_as(our_sender_id).map(|x| (lobby_id, x));
+            return;
+         }
+         None => (),
+      }
+      self.seat = lobbies
+         .iter()
+         .find(|(_, lobby)| lobby.players.contains_key(&player_id))
+         .map(|(lobby_id, _)| (*lobby_id, player_id));
+      if let Some((lobby_id, _)) = self.seat {
+         self.spectating.retain(|x| *x != lobby_id);
+      }
+   }
+
+   /// Someone leaving may have granted us the seat we were queued for
+   fn settle_queue(&mut self, lobbies: &IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+      let lobby_id = match self.queued {
+         Some(lobby_id) => lobby_id,
+         None => return,
+      };
+      let lobby = match lobbies.get(&lobby_id) {
+         Some(lobby) => lobby,
+         None => {
+            self.queued = None;
+            return;
+         }
+      };
+      if lobby
+         .seat_queue
+         .iter()
+         .any(|x| x.sender.connection_id() == our_sender_id)
+      {
+         return;
+      }
+      self.queued = None;
+      self.seat = lobby.player_connected_as(our_sender_id).map(|x| (lobby_id, x));
+   }
+
+   /// The event we registered for may have opened, seating or queueing us in its lobby.
+   /// If we were busy in another lobby at the time, we were left out
+   fn settle_registration(&mut self, lobbies: &IndexMap<LobbyId, Lobby>, still_registered: bool, our_sender_id: u32) {
+      if still_registered {
+         return;
+      }
+      let event = match self.registered_for.take() {
+         Some(event) => event,
+         None => return,
+      };
+      if self.seat.is_some() || self.queued.is_some() {
+         return;
+      }
+      for (lobby_id, lobby) in lobbies.iter().filter(|(_, x)| x.event.as_ref() == Some(&event)) {
+         if let Some(player_id) = lobby.player_connected_as(our_sender_id) {
+            self.seat = Some((*lobby_id, player_id));
+            return;
+         }
+         if lobby
+            .seat_queue
+            .iter()
+            .any(|x| x.sender.connection_id() == our_sender_id)
+         {
+            self.queued = Some(*lobby_id);
+            return;
+         }
+      }
+   }
+
+   fn is_spectating(&self, lobby_id: &LobbyId) -> bool {
+      self.spectating.contains(lobby_id)
+   }
+
+   /// Returns false if we weren't spectating the lobby
+   fn stop_spectating(
+      &mut self,
+      lobbies: &mut IndexMap<LobbyId, Lobby>,
+      lobby_id: &LobbyId,
+      our_sender_id: u32,
+   ) -> bool {
+      let before = self.spectating.len();
+      self.spectating.retain(|x| x != lobby_id);
+      if self.spectating.len() == before {
+         return false;
+      }
+      leave_spectating(lobby_id, lobbies, our_sender_id);
+      true
+   }
+
+   fn leave_all(&mut self, lobbies: &mut IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+      self.settle_seat(lobbies, our_sender_id);
+      self.settle_queue(lobbies, our_sender_id);
+      if let Some((lobby_id, player_id)) = self.seat.take() {
+         leave_seat(&lobby_id, &player_id, lobbies, our_sender_id);
+      }
+      if let Some(lobby_id) = self.queued.take() {
+         leave_queue(&lobby_id, lobbies, our_sender_id);
+      }
+      for lobby_id in self.spectating.drain(..) {
+         leave_spectating(&lobby_id, lobbies, our_sender_id);
+      }
+      for lobby_id in self.observing.drain(..) {
+         stop_observing(&lobby_id, lobbies, our_sender_id);
+      }
+   }
+
+   fn is_empty(&self) -> bool {
+      self.seat.is_none() && self.queued.is_none() && self.spectating.is_empty() && self.observing.is_empty()
+   }
+}
+
+/// What ListLobbies shows, copied out of the lobbies so that listing never takes the lobbies lock
+#[derive(Default)]
+struct LobbyListSnapshot {
+   lobbies: Vec<LobbyDisplay>,
+   /// Serialized pages, keyed by (page, page size), so bursts of listings don't each serialize every lobby.
+   /// Every page past the end is the same empty page, so they share the first one's entry
+   pages: Mutex<HashMap<(u64, u64), Vec<u8>>>,
+}
+
+impl LobbyListSnapshot {
+   fn new(lobbies: &IndexMap<LobbyId, Lobby>) -> LobbyListSnapshot {
+      LobbyListSnapshot {
+         lobbies: lobbies.iter().map(|(k, v)| v.display(k)).collect(),
+         pages: Mutex::default(),
+      }
+   }
+
+   /// The serialized ListLobbiesResponse for a page, built on first request
+   fn page(&self, page: u64, page_size: u64) -> serde_json::Result<Vec<u8>> {
+      let past_the_end = (self.lobbies.len() as u64).div_ceil(page_size);
+      let key = (page.min(past_the_end), page_size);
+      let mut pages = self.pages.lock().unwrap();
+      if let Entry::Vacant(entry) = pages.entry(key) {
+         // Lobbies are kept in creation order, so a page is a slice of the snapshot
+         let lobbies = &self.lobbies;
+         let start = (key.0.saturating_mul(page_size) as usize).min(lobbies.len());
+         let end = start.saturating_add(page_size as usize).min(lobbies.len());
+         let response = PalaceOutMessage::ListLobbiesResponse(ListLobbyResponse {
+            lobbies: &lobbies[start..end],
+            has_next_page: lobbies.len() as u64 > key.0.saturating_add(1).saturating_mul(page_size),
+            total_lobbies: lobbies.len() as u64,
+         });
+         entry.insert(serde_json::to_vec(&response)?);
+      }
+      Ok(pages[&key].clone())
+   }
+}
+
+/// The lobby list is eventually consistent: connections mark it stale, and a background thread rebuilds the
+/// snapshot when it is stale or older than `LOBBY_LIST_MAX_AGE_MILLIS`
+#[derive(Default)]
+struct LobbyList {
+   snapshot: ArcSwap<LobbyListSnapshot>,
+   stale: AtomicBool,
+}
+
+/// The lobbies that the AI and turn timer loops have work in, so they don't have to visit every lobby.
+/// Entries can go stale; the loops drop them when they find nothing to do.
+/// Always locked after `lobbies`
+#[derive(Default)]
+struct ActiveLobbies {
+   /// Lobbies whose turn belongs to an AI, or to a seat nobody is left in
+   ai_turns: HashSet<LobbyId>,
+   in_game: HashSet<LobbyId>,
+}
+
+impl ActiveLobbies {
+   /// Call after a lobby's game starts, its turn moves on, or its seats change hands
+   fn track(&mut self, lobby_id: LobbyId, lobby: &Lobby) {
+      if lobby.game.is_some() {
+         self.in_game.insert(lobby_id);
+      }
+      if ai_on_turn(lobby) {
+         self.ai_turns.insert(lobby_id);
+      }
+   }
+}
+
+/// Watches how long passes of the AI loop take, to tell when AI should think less
+struct AiLoadMonitor {
+   budget: Duration,
+   /// Weighted toward recent passes, so that one slow pass doesn't count for much
+   average: Duration,
+   degraded: bool,
+}
+
+impl AiLoadMonitor {
+   fn new(budget: Duration) -> AiLoadMonitor {
+      AiLoadMonitor {
+         budget,
+         average: Duration::from_secs(0),
+         degraded: false,
+      }
+   }
+
+   /// Returns whether AI should be degraded. It stays degraded until passes are well under budget, so that it
+   /// doesn't flap
+   fn observe(&mut self, elapsed: Duration) -> bool {
+      self.average = (self.average * 9 + elapsed) / 10;
+      if self.average > self.budget {
+         self.degraded = true;
+      } else if self.average < self.budget / 2 {
+         self.degraded = false;
+      }
+      self.degraded
+   }
+}
+
+fn set_ai_strength(lobbies: &mut IndexMap<LobbyId, Lobby>, strength: f64) {
+   for player in lobbies.values_mut().flat_map(|x| x.players.values_mut()) {
+      if let Connection::Ai(ref mut ai) = player.connection {
+         ai.core.set_strength(strength);
+      }
+   }
+}
+
+/// Whether the AI loop should play the current turn
+fn ai_on_turn(lobby: &Lobby) -> bool {
+   match lobby.game {
+      Some(ref gs) => match lobby.players_by_turn_num.get(&gs.active_player) {
+         Some(player_id) => lobby.players[player_id].is_ai(),
+         // Evicted players' seats are played by the AI loop
+         None => true,
+      },
+      None => false,
+   }
+}
+
+/// A connection, numbered uniquely across every address we listen on.
+/// ws-rs numbers connections per listener, so its ids would collide between listeners
+#[derive(Clone)]
+struct Sender {
+   transport: Transport,
+   connection_id: u32,
+   activity: Arc<Activity>,
+}
+
+/// When a connection last sent us a message. Kept outside the lobbies so that hearing from a player doesn't
+/// have to lock them
+struct Activity {
+   /// Milliseconds after `STARTED`, so that it fits in an atomic
+   last_message: AtomicU64,
+   /// Whether the player this connection is seated as has been marked idle. Only the first message after going
+   /// quiet has to lock the lobbies, to let everyone know the player is back
+   player_idle: AtomicBool,
+}
+
+impl Activity {
+   fn new() -> Activity {
+      let activity = Activity {
+         last_message: AtomicU64::new(0),
+         player_idle: AtomicBool::new(false),
+      };
+      activity.note_message();
+      activity
+   }
+
+   fn note_message(&self) {
+      self
+         .last_message
+         .store(STARTED.elapsed().as_millis() as u64, Ordering::Relaxed);
+   }
+
+   fn quiet_for(&self) -> Duration {
+      STARTED
+         .elapsed()
+         .saturating_sub(Duration::from_millis(self.last_message.load(Ordering::Relaxed)))
+   }
+}
+
+#[derive(Clone)]
+enum Transport {
+   WebSocket(ws::Sender),
+   /// Messages for the local socket's writer thread to send. None closes the connection
+   #[cfg(unix)]
+   Local(std::sync::mpsc::Sender<Option<Vec<u8>>>),
 }
 
-enum ConnectedUser {
-   Player((LobbyId, PlayerId)),
-   Spectator(LobbyId),
+static NEXT_CONNECTION_ID: AtomicU32 = AtomicU32::new(0);
+static ANALYSES_IN_PROGRESS: AtomicUsize = AtomicUsize::new(0);
+
+impl Sender {
+   fn new(transport: Transport) -> Sender {
+      Sender {
+         transport,
+         connection_id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+         activity: Arc::new(Activity::new()),
+      }
+   }
+
+   fn connection_id(&self) -> u32 {
+      self.connection_id
+   }
+
+   fn send<M: Into<Message>>(&self, message: M) -> ws::Result<()> {
+      match self.transport {
+         Transport::WebSocket(ref inner) => inner.send(message),
+         #[cfg(unix)]
+         Transport::Local(ref outgoing) => outgoing
+            .send(Some(message.into().into_data()))
+            .map_err(|_| ws::Error::new(ws::ErrorKind::Internal, "local connection closed")),
+      }
+   }
+
+   /// Local connections have no network to measure the round trip over, so they're never pinged
+   fn ping(&self) -> ws::Result<()> {
+      match self.transport {
+         Transport::WebSocket(ref inner) => inner.ping(Vec::new()),
+         #[cfg(unix)]
+         Transport::Local(_) => Ok(()),
+      }
+   }
+
+   fn close(&self, code: CloseCode) -> ws::Result<()> {
+      match self.transport {
+         Transport::WebSocket(ref inner) => inner.close(code),
+         #[cfg(unix)]
+         Transport::Local(ref outgoing) => outgoing
+            .send(None)
+            .map_err(|_| ws::Error::new(ws::ErrorKind::Internal, "local connection closed")),
+      }
+   }
 }
 
 struct Server {
    out: Sender,
-   lobbies: Arc<RwLock<HashMap<LobbyId, Lobby>>>,
-   connected_user: Option<ConnectedUser>,
+   lobbies: Arc<RwLock<IndexMap<LobbyId, Lobby>>>,
+   memberships: Memberships,
+   config: Arc<ServerConfig>,
+   drain_state: Arc<RwLock<Option<DrainState>>>,
+   ai_resources: Arc<AiResources>,
+   text_frames: bool,
+   /// Whether this connection has spoken the original protocol
+   legacy_client: bool,
+   lobby_list: Arc<LobbyList>,
+   active_lobbies: Arc<Mutex<ActiveLobbies>>,
+   metrics: Arc<Metrics>,
+   archive: Arc<GameArchive>,
+   stats_subscribers: Arc<RwLock<Vec<Sender>>>,
+   /// Where the client is connecting from, preferring what a trusted proxy in front of us says
+   remote_addr: Option<String>,
+   /// When lobbies were created, per remote address
+   lobby_creations: Arc<Mutex<HashMap<String, Vec<Instant>>>>,
+   /// One per address we listen on
+   broadcasters: Arc<RwLock<Vec<ws::Sender>>>,
+   schedule: Arc<Mutex<Schedule>>,
+}
+
+/// Records a lobby being created, unless the address has already created `limit` lobbies recently
+fn note_lobby_creation(creations: &mut HashMap<String, Vec<Instant>>, remote_addr: &str, limit: usize) -> bool {
+   let recent = creations.entry(remote_addr.to_string()).or_default();
+   recent.retain(|x| x.elapsed() < Duration::from_secs(LOBBY_CREATION_WINDOW_SECS));
+   if recent.len() >= limit {
+      return false;
+   }
+   recent.push(Instant::now());
+   true
+}
+
+/// The addresses that proxies say they've forwarded a request for, from the client to the last proxy
+fn forwarded_for(request: &ws::Request) -> Vec<String> {
+   let header = |name| request.header(name).map(|x| String::from_utf8_lossy(x).into_owned());
+   if let Some(x_forwarded_for) = header("X-Forwarded-For") {
+      return x_forwarded_for.split(',').map(str::to_owned).collect();
+   }
+   match header("Forwarded") {
+      Some(forwarded) => forwarded
+         .split(',')
+         .filter_map(|element| {
+            element
+               .split(';')
+               .map(str::trim)
+               .find(|x| x.len() > 4 && x[..4].eq_ignore_ascii_case("for="))
+               .map(|x| x[4..].to_owned())
+         })
+         .collect(),
+      None => Vec::new(),
+   }
+}
+
+/// Follows the forwarded addresses back from the peer for as long as they were forwarded by a trusted proxy.
+/// Addresses further back could have been made up by the client
+fn client_address(peer: IpAddr, forwarded_for: &[String], trusted_proxies: &[IpAddr]) -> IpAddr {
+   let mut client = peer;
+   for hop in forwarded_for.iter().rev() {
+      if !trusted_proxies.contains(&client) {
+         break;
+      }
+      match parse_hop(hop) {
+         Some(hop) => client = hop,
+         None => break,
+      }
+   }
+   client
+}
+
+/// Proxies write addresses with or without a port, and Forwarded quotes and brackets IPv6 addresses
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+   let hop = hop.trim().trim_matches('"');
+   hop.parse()
+      .ok()
+      .or_else(|| hop.parse::<std::net::SocketAddr>().ok().map(|x| x.ip()))
+      .or_else(|| hop.strip_prefix('[')?.split(']').next()?.parse().ok())
 }
 
-enum OnMessageError {
-   WebsocketError(ws::Error),
-   SerdeError(serde_json::error::Error),
+/// If a message failed to decode only because its type isn't one we know, returns that type.
+/// Newer clients can then probe for optional features without losing their connection.
+fn unknown_message_type(binary: &[u8], error: &serde_json::Error) -> Option<String> {
+   if !error.to_string().starts_with("unknown variant") {
+      return None;
+   }
+   let message_type = match serde_json::from_slice::<serde_json::Value>(binary).ok()? {
+      serde_json::Value::String(message_type) => message_type,
+      serde_json::Value::Object(map) if map.len() == 1 => map.into_iter().next()?.0,
+      _ => return None,
+   };
+   // The unknown variant may instead be in some enum inside a message we do know
+   if is_message_type(&message_type) {
+      return None;
+   }
+   Some(message_type)
 }
 
-impl From<ws::Error> for OnMessageError {
-   fn from(e: ws::Error) -> OnMessageError {
-      OnMessageError::WebsocketError(e)
+fn is_message_type(name: &str) -> bool {
+   match serde_json::from_value::<PalaceInMessage>(serde_json::Value::String(name.to_owned())) {
+      Ok(_) => true,
+      Err(e) => !e.to_string().starts_with("unknown variant"),
    }
 }
 
-impl From<serde_json::error::Error> for OnMessageError {
-   fn from(e: serde_json::error::Error) -> OnMessageError {
-      OnMessageError::SerdeError(e)
+/// Like `serde_json::from_slice`, but also collects the path of every field that was ignored
+fn decode_noting_unknown_fields(
+   binary: &[u8],
+   unknown_fields: &mut Vec<String>,
+) -> Result<PalaceInMessage, serde_json::Error> {
+   let mut deserializer = serde_json::Deserializer::from_slice(binary);
+   let message: PalaceInMessage =
+      serde_ignored::deserialize(&mut deserializer, |path| unknown_fields.push(path.to_string()))?;
+   deserializer.end()?;
+   // The message type is the one part of the path that isn't known while decoding
+   for field in unknown_fields.iter_mut() {
+      if field.starts_with('?') {
+         field.replace_range(..1, message.name());
+      }
    }
+   Ok(message)
 }
 
-fn ai_play(lobbies: &mut HashMap<LobbyId, Lobby>) {
-   for lobby in &mut lobbies.values_mut() {
+fn ai_play(
+   lobbies: &mut IndexMap<LobbyId, Lobby>,
+   ai_turns: &mut HashSet<LobbyId>,
+   explain_decisions: bool,
+   commentator: Option<&dyn Commentator>,
+   archive: &GameArchive,
+   max_game_turns: Option<u32>,
+) {
+   ai_turns.retain(|lobby_id| {
+      let lobby = match lobbies.get_mut(lobby_id) {
+         Some(lobby) if ai_on_turn(lobby) => lobby,
+         _ => return false,
+      };
       if let Some(ref mut gs) = lobby.game {
-         if let Some(player_id) = lobby.players_by_turn_num.get(&gs.active_player) {
+         let slot = gs.active_player;
+         if let Some(player_id) = lobby.players_by_turn_num.get(&slot) {
+            let conceding = match lobby.players[player_id].connection {
+               Connection::Ai(ref ai) => gs.cur_phase == Phase::Play && ai.core.should_concede(),
+               // ai_on_turn already checked, but if the seat isn't an AI's, leave it to the turn timer rather than panic
+               _ => return false,
+            };
+            if conceding {
+               concede(lobby, slot, archive);
+               return ai_on_turn(lobby);
+            }
             let play = match lobby.players.get_mut(player_id).unwrap().connection {
                Connection::Ai(ref mut ai) => ai::get_turn(gs, &mut *ai.core),
-               _ => continue,
+               _ => return false,
             };
+            let gs_before = commentator.map(|_| gs.clone());
             match gs.take_turn(&play) {
-               Ok(game_finished) => {
-                  report_take_turn(gs, &mut lobby.players, &mut lobby.spectators, *player_id);
-                  if game_finished {
-                     end_game(lobby);
+               Ok(outcome) => {
+                  let player_id = *player_id;
+                  after_play(lobby, slot, outcome, commentator, gs_before, archive, max_game_turns);
+                  if explain_decisions {
+                     report_ai_decision(&lobby.players[&player_id], &mut lobby.spectators);
                   }
                }
                Err(_) => {
@@ -196,6 +1161,7 @@ fn ai_play(lobbies: &mut HashMap<LobbyId, Lobby>) {
                         if ai.core.strategy_name() != "Random" {
                            info!("Falling back to Random");
                            ai.core = Box::new(ai::random::new());
+                           ai.core.reseed(ai::seat_seed(gs, player.turn_number));
                            ai.core.on_game_start(GameStartEvent {
                               hand: gs.get_hand(player.turn_number),
                               turn_number: player.turn_number,
@@ -204,13 +1170,108 @@ fn ai_play(lobbies: &mut HashMap<LobbyId, Lobby>) {
                            ai.core.on_game_state_update(&gs.public_state());
                         }
                      }
-                     _ => unreachable!(),
+                     _ => return false,
                   }
                }
             }
+         } else {
+            // Nobody is left in this seat to time out, so play it right away
+            let gs_before = commentator.map(|_| gs.clone());
+            let outcome = take_random_turn(gs);
+            after_play(lobby, slot, outcome, commentator, gs_before, archive, max_game_turns);
+         }
+      }
+      // Keep going for as long as AIs have the turn
+      ai_on_turn(lobby)
+   });
+}
+
+/// Puts a practice game back to `gs`, bringing everyone's hands and the AIs' knowledge of the game along
+fn rewind_game(lobby: &mut Lobby, mut gs: GameState) {
+   gs.last_turn_start = Instant::now();
+   lobby.commentary.retain(|x| x.turn <= gs.turns_taken);
+   lobby.predictions.discard_guesses();
+   lobby.skip_turn_votes.clear();
+   broadcast(
+      &mut lobby.players,
+      &mut lobby.spectators,
+      &PalaceOutMessage::GameRewoundEvent(GameRewoundEvent {
+         turns_taken: gs.turns_taken,
+      }),
+   );
+   let players = lobby.player_profiles();
+   let public_gs = journaled_public_state(&gs, &lobby.players, lobby.turn_timer, &lobby.journal);
+   let public_gs_bytes = lobby.journal.record(
+      LobbyEventKind::Play,
+      &PalaceOutMessage::PublicGameStateEvent(&public_gs),
+   );
+   for player in lobby.players.values_mut() {
+      match player.connection {
+         Connection::Connected(ref mut senders) => {
+            let _ = send_hand(senders, gs.get_hand(player.turn_number));
+            let _ = send_serialized_all(senders, public_gs_bytes);
+         }
+         Connection::Disconnected(_) => (),
+         Connection::Ai(ref mut ai) => {
+            ai.core.reseed(ai::seat_seed(&gs, player.turn_number));
+            ai.core.assume_position(
+               GameStartEvent {
+                  hand: gs.get_hand(player.turn_number),
+                  turn_number: player.turn_number,
+                  players: &players,
+               },
+               &public_gs,
+               &gs.pile_cards,
+               &gs.cleared_cards,
+            );
+            for slot in gs.conceded_players.iter() {
+               ai.core.on_player_conceded(*slot);
+            }
+         }
+      }
+   }
+   for sender in &mut lobby.spectators {
+      let _ = send_serialized(sender, public_gs_bytes.to_vec());
+   }
+   notify_active_player(&gs, &mut lobby.players, lobby.turn_timer);
+   lobby.game = Some(gs);
+}
+
+/// The handler's result, unless the message was turned away before it could run
+fn unless_denied<T, E: From<Denial>>(denial: Option<Denial>, handler: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+   match denial {
+      Some(denial) => Err(denial.into()),
+      None => handler(),
+   }
+}
+
+/// Plays a random move for the active player
+fn take_random_turn(gs: &mut GameState) -> PlayOutcome {
+   let mut ai = Box::new(ai::random::new());
+   ai.reseed(ai::seat_seed(gs, gs.active_player));
+   ai.on_game_start(GameStartEvent {
+      hand: gs.get_hand(gs.active_player),
+      turn_number: gs.active_player,
+      players: &HashMap::new(), // Random doesn't need players
+   });
+   ai.on_game_state_update(&gs.public_state());
+   let play = ai::get_turn(gs, &mut *ai);
+   gs.take_turn(&play).unwrap()
+}
+
+fn report_ai_decision(player: &Player, spectators: &mut [Sender]) {
+   if let Connection::Ai(ref ai) = player.connection {
+      if let Some(candidates) = ai.core.decision_explanation() {
+         for sender in spectators {
+            let _ = serialize_and_send(
+               sender,
+               &PalaceOutMessage::AiDecisionDebugEvent(AiDecisionDebugEvent {
+                  slot: player.turn_number,
+                  strategy: ai.core.strategy_name(),
+                  candidates,
+               }),
+            );
          }
-      } else {
-         continue;
       }
    }
 }
@@ -218,30 +1279,64 @@ fn ai_play(lobbies: &mut HashMap<LobbyId, Lobby>) {
 impl Handler for Server {
    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
       let recv_time = Instant::now();
-      let result = match msg {
-         Message::Text(_) => {
-            debug!("Received text; closing connection");
-            self.out.close(CloseCode::Unsupported)
+      self.metrics.count_message();
+      let binary = match msg {
+         Message::Text(text) => {
+            self.set_text_frames(true);
+            text.into_bytes()
          }
          Message::Binary(binary) => {
-            debug!("Received bytes (as string): {}", String::from_utf8_lossy(&binary));
-            match serde_json::from_slice::<PalaceInMessage>(&binary) {
-               Ok(message) => {
-                  // We don't log an error here because that is done
-                  // in `serialize_and_send`
-                  // an error here would just be an error sending
-                  // ISE which we can't handle sanely
-                  self.handle_message(message)
-               }
-               Err(e) => {
-                  debug!(
-                     "Received a binary message but could not decode it into an object; Error: {:?}",
-                     e
-                  );
-                  self.out.close(CloseCode::Invalid)
-               }
-            }
+            self.set_text_frames(false);
+            binary
+         }
+      };
+      debug!("Received message: {}", redact::redacted_string(&binary));
+      let mut unknown_fields = Vec::new();
+      let decoded = if self.config.strict_protocol {
+         decode_noting_unknown_fields(&binary, &mut unknown_fields)
+      } else {
+         serde_json::from_slice::<PalaceInMessage>(&binary)
+      };
+      let legacy = match decoded {
+         Err(_) if self.config.legacy_protocol => serde_json::from_slice::<LegacyInMessage>(&binary).ok(),
+         _ => None,
+      };
+      let result = match (decoded, legacy) {
+         (Ok(_), _) if !unknown_fields.is_empty() => {
+            debug!("Rejected a message with unknown fields {:?}", unknown_fields);
+            serialize_and_send(&mut self.out, &PalaceOutMessage::UnknownFields(&unknown_fields))
+         }
+         (Ok(message), _) => {
+            // We don't log an error here because that is done
+            // in `serialize_and_send`
+            // an error here would just be an error sending
+            // ISE which we can't handle sanely
+            let message_type = message.name();
+            let result = self.handle_message(message);
+            self.note_activity();
+            self
+               .metrics
+               .observe_response_time(message_type, self.lobby_size_bucket(), recv_time.elapsed());
+            result
          }
+         (Err(_), Some(message)) => {
+            let result = self.handle_legacy_message(message);
+            self.note_activity();
+            result
+         }
+         (Err(e), None) => match unknown_message_type(&binary, &e) {
+            Some(message_type) => {
+               debug!("Received a message of unknown type {}", message_type);
+               serialize_and_send(&mut self.out, &PalaceOutMessage::UnknownMessageType(&message_type))
+            }
+            None => {
+               debug!(
+                  "Received a message but could not decode it into an object; Error: {:?}",
+                  e
+               );
+               self.disconnect(DisconnectCode::ProtocolError, e.to_string())
+            }
+         },
       };
       trace!("Response time: {:?}", recv_time.elapsed());
       result
@@ -249,28 +1344,192 @@ impl Handler for Server {
 
    fn on_close(&mut self, _code: CloseCode, _reason: &str) {
       debug!("A connection closed");
+      self.set_text_frames(false);
+      if self.legacy_client {
+         LEGACY_CONNECTIONS.write().unwrap().remove(&self.out.connection_id());
+      }
+      SEND_FAILURES.write().unwrap().remove(&self.out.connection_id());
+      ROUND_TRIPS.write().unwrap().remove(&self.out.connection_id());
+      self
+         .stats_subscribers
+         .write()
+         .unwrap()
+         .retain(|x| x.connection_id() != self.out.connection_id());
       let mut lobbies = self.lobbies.write().unwrap();
-      if let Some(ref connected_user_details) = self.connected_user {
-         disconnect_old_player(connected_user_details, &mut lobbies, self.out.connection_id());
+      if self.memberships.registered_for.is_some() {
+         let mut schedule = self.schedule.lock().unwrap();
+         let still_registered = schedule.is_registered(self.out.connection_id());
+         schedule.unregister(self.out.connection_id());
+         self
+            .memberships
+            .settle_registration(&lobbies, still_registered, self.out.connection_id());
+      }
+      if !self.memberships.is_empty() {
+         self.memberships.leave_all(&mut lobbies, self.out.connection_id());
+         self.lobby_list.stale.store(true, Ordering::Relaxed);
       }
    }
 
-   fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+   fn on_open(&mut self, shake: Handshake) -> ws::Result<()> {
       debug!("A connection opened");
-      Ok(())
+      self.remote_addr = shake.peer_addr.map(|peer| {
+         let forwarded_for = forwarded_for(&shake.request);
+         client_address(peer.ip(), &forwarded_for, &self.config.trusted_proxies).to_string()
+      });
+      self.greet()
+   }
+
+   fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+      if frame.opcode() == OpCode::Pong {
+         if let Some(round_trip) = ROUND_TRIPS.write().unwrap().get_mut(&self.out.connection_id()) {
+            round_trip.pong(Instant::now());
+         }
+      }
+      // As the default handler does
+      if frame.has_rsv1() || frame.has_rsv2() || frame.has_rsv3() {
+         Err(ws::Error::new(
+            ws::ErrorKind::Protocol,
+            "Encountered frame with reserved bits set.",
+         ))
+      } else {
+         Ok(Some(frame))
+      }
+   }
+}
+
+impl Server {
+   /// What every connection is sent as soon as it opens
+   fn greet(&mut self) -> ws::Result<()> {
+      if let Some(ref drain_state) = *self.drain_state.read().unwrap() {
+         serialize_and_send(
+            &mut self.out,
+            &PalaceOutMessage::ServerDrainingEvent(drain_state.event()),
+         )?;
+      }
+      if let Some(ref motd) = self.config.motd {
+         serialize_and_send(
+            &mut self.out,
+            &PalaceOutMessage::ServerAnnouncementEvent(ServerAnnouncementEvent::info(motd)),
+         )
+      } else {
+         Ok(())
+      }
    }
 }
 
 impl Server {
+   /// The player behind this connection is at the keyboard
+   fn note_activity(&self) {
+      self.out.activity.note_message();
+      if !self.out.activity.player_idle.swap(false, Ordering::Relaxed) {
+         return;
+      }
+      if let Some((lobby_id, player_id)) = self.memberships.seat {
+         let mut lobbies = self.lobbies.write().unwrap();
+         if let Some(lobby) = lobbies.get_mut(&lobby_id) {
+            if let Some(player) = lobby.players.get_mut(&player_id) {
+               if player.idle {
+                  player.idle = false;
+                  let slot = player.turn_number;
+                  broadcast_presence(&mut lobby.players, &mut lobby.spectators, slot, Presence::Active);
+               }
+            }
+         }
+      }
+   }
+
+   fn lobby_size_bucket(&self) -> &'static str {
+      let lobby_id = match self.memberships.seat {
+         Some((lobby_id, _)) => lobby_id,
+         None => match self.memberships.spectating.first() {
+            Some(lobby_id) => *lobby_id,
+            None => return metrics::lobby_size_bucket(0),
+         },
+      };
+      let num_players = self
+         .lobbies
+         .read()
+         .unwrap()
+         .get(&lobby_id)
+         .map(|x| x.players.len())
+         .unwrap_or(0);
+      metrics::lobby_size_bucket(num_players)
+   }
+
+   fn disconnect(&mut self, code: DisconnectCode, reason: String) -> ws::Result<()> {
+      disconnect(&mut self.out, code, reason)
+   }
+
+   /// Replies are sent in the same kind of frame as the last message we received
+   fn set_text_frames(&mut self, text_frames: bool) {
+      if self.text_frames == text_frames {
+         return;
+      }
+      self.text_frames = text_frames;
+      let mut text_frame_connections = TEXT_FRAME_CONNECTIONS.write().unwrap();
+      if text_frames {
+         text_frame_connections.insert(self.out.connection_id());
+      } else {
+         text_frame_connections.remove(&self.out.connection_id());
+      }
+   }
+
+   /// Handles a message in the original protocol like its current counterpart, but answers in the original shape
+   fn handle_legacy_message(&mut self, message: LegacyInMessage) -> ws::Result<()> {
+      if !self.legacy_client {
+         self.legacy_client = true;
+         LEGACY_CONNECTIONS.write().unwrap().insert(self.out.connection_id());
+      }
+      let is_setup = matches!(message, LegacyInMessage::ChooseFaceup(_));
+      let message = message.upgrade();
+      let denial = self.check_permission(message.permission()).err();
+      match message {
+         PalaceInMessage::TakeTurn(message) => {
+            let result = unless_denied(denial, || self.do_take_turn(message));
+            let response = if is_setup {
+               LegacyOutMessage::ChooseFaceupResponse(result)
+            } else {
+               LegacyOutMessage::MakePlayResponse(result)
+            };
+            send_serialized(&mut self.out, serde_json::to_vec(&response).unwrap())
+         }
+         message => self.handle_message(message),
+      }
+   }
+
    fn handle_message(&mut self, message: PalaceInMessage) -> ws::Result<()> {
+      // Anything but a listing may change what the listing shows.
+      // Changes made by the background threads are picked up once the snapshot gets old.
+      if !matches!(message, PalaceInMessage::ListLobbies(_)) {
+         self.lobby_list.stale.store(true, Ordering::Relaxed);
+      }
+      if self.memberships.seat.is_some() || self.memberships.queued.is_some() {
+         let lobbies = self.lobbies.read().unwrap();
+         self.memberships.settle_seat(&lobbies, self.out.connection_id());
+         self.memberships.settle_queue(&lobbies, self.out.connection_id());
+      }
+      if self.memberships.registered_for.is_some() {
+         let still_registered = self.schedule.lock().unwrap().is_registered(self.out.connection_id());
+         let lobbies = self.lobbies.read().unwrap();
+         self
+            .memberships
+            .settle_registration(&lobbies, still_registered, self.out.connection_id());
+      }
+      let denial = self.check_permission(message.permission()).err();
       match message {
          PalaceInMessage::RequestAi(message) => {
-            let response = PalaceOutMessage::RequestAiResponse(self.do_request_ai(message));
-            serialize_and_send(&mut self.out, &response)
+            let response = unless_denied(denial, || self.do_request_ai(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::RequestAiResponse(response))
          }
          PalaceInMessage::NewLobby(message) => {
-            let response = PalaceOutMessage::NewLobbyResponse(self.do_new_lobby(message));
-            serialize_and_send(&mut self.out, &response)
+            let response = self.do_new_lobby(message);
+            let new_lobby_id = response.as_ref().ok().map(|x| x.lobby_id);
+            serialize_and_send(&mut self.out, &PalaceOutMessage::NewLobbyResponse(response))?;
+            // Seated after the response, so that the owner knows the lobby by the time they hear the AI join
+            if let Some(lobby_id) = new_lobby_id {
+               self.seat_scenario_opponents(&lobby_id);
+            }
+            Ok(())
          }
          PalaceInMessage::JoinLobby(message) => {
             // This is an unfortunate special case,
@@ -281,37 +1540,83 @@ impl Server {
                Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::JoinLobbyResponse(Err(e))),
             }
          }
+         PalaceInMessage::JoinWithToken(message) => {
+            // See note on JoinLobby above
+            match self.do_join_with_token(message) {
+               Ok(()) => Ok(()),
+               Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::JoinWithTokenResponse(Err(e))),
+            }
+         }
+         PalaceInMessage::CreateJoinToken(message) => {
+            let response = unless_denied(denial, || self.do_create_join_token(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::CreateJoinTokenResponse(response))
+         }
          PalaceInMessage::ListLobbies(message) => {
-            let lobbies = self.lobbies.read().unwrap();
-            // @Performance we should be able to serialize with Serializer::collect_seq
-            // and avoid collecting into a vector
-            serialize_and_send(
-               &mut self.out,
-               &PalaceOutMessage::ListLobbiesResponse(ListLobbyResponse {
-                  lobbies: &lobbies
-                     .iter()
-                     .skip(message.page as usize * 50)
-                     .map(|(k, v)| v.display(k))
-                     .collect::<Vec<_>>(),
-                  has_next_page: lobbies.len() as u64 > (message.page + 1) * 50,
-               }),
-            )
+            let page_size = message
+               .page_size
+               .unwrap_or(DEFAULT_LOBBY_PAGE_SIZE)
+               .clamp(1, MAX_LOBBY_PAGE_SIZE);
+            match self.lobby_list.snapshot.load().page(message.page, page_size) {
+               Ok(bytes) => send_serialized(&mut self.out, bytes),
+               Err(e) => {
+                  error!("Failed to serialize a message: {:?}", e);
+                  send_serialized(&mut self.out, Vec::from("\"InternalServerError\""))
+               }
+            }
          }
          PalaceInMessage::StartGame(message) => {
-            let response = PalaceOutMessage::StartGameResponse(self.do_start_game(message));
-            serialize_and_send(&mut self.out, &response)
+            let response = unless_denied(denial, || self.do_start_game(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::StartGameResponse(response))
          }
          PalaceInMessage::TakeTurn(message) => {
-            let response = PalaceOutMessage::TakeTurnResponse(self.do_take_turn(message));
-            serialize_and_send(&mut self.out, &response)
+            let response = unless_denied(denial, || self.do_take_turn(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::TakeTurnResponse(response))
          }
          PalaceInMessage::Reconnect(message) => {
-            let response = PalaceOutMessage::ReconnectResponse(self.do_reconnect(&message));
-            serialize_and_send(&mut self.out, &response)
+            let result = self.do_reconnect(&message, false);
+            let rate_limited = matches!(result, Err(ReconnectError::TooManyAttempts));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::ReconnectResponse(result))?;
+            if rate_limited {
+               self.disconnect(DisconnectCode::RateLimited, "Too many reconnect attempts".into())?;
+            }
+            Ok(())
+         }
+         PalaceInMessage::AttachToPlayer(message) => {
+            let result = self.do_reconnect(&message, true);
+            let rate_limited = matches!(result, Err(ReconnectError::TooManyAttempts));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::AttachToPlayerResponse(result))?;
+            if rate_limited {
+               self.disconnect(DisconnectCode::RateLimited, "Too many reconnect attempts".into())?;
+            }
+            Ok(())
          }
          PalaceInMessage::KickPlayer(message) => {
-            let response = PalaceOutMessage::KickPlayerResponse(self.do_kick_player(&message));
-            serialize_and_send(&mut self.out, &response)
+            let response = unless_denied(denial, || self.do_kick_player(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::KickPlayerResponse(response))
+         }
+         PalaceInMessage::SwapAiStrategy(message) => {
+            let response = unless_denied(denial, || self.do_swap_ai_strategy(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::SwapAiStrategyResponse(response))
+         }
+         PalaceInMessage::SetSeatClosed(message) => {
+            let response = unless_denied(denial, || self.do_set_seat_closed(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::SetSeatClosedResponse(response))
+         }
+         PalaceInMessage::TransferOwnership(message) => {
+            let response = unless_denied(denial, || self.do_transfer_ownership(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::TransferOwnershipResponse(response))
+         }
+         PalaceInMessage::AbortGame(message) => {
+            let response = unless_denied(denial, || self.do_abort_game(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::AbortGameResponse(response))
+         }
+         PalaceInMessage::Concede(message) => {
+            let response = unless_denied(denial, || self.do_concede(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::ConcedeResponse(response))
+         }
+         PalaceInMessage::VoteSkipTurn(message) => {
+            let response = unless_denied(denial, || self.do_vote_skip_turn(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::VoteSkipTurnResponse(response))
          }
          PalaceInMessage::SpectateLobby(message) => {
             // See note on JoinLobby above
@@ -320,38 +1625,284 @@ impl Server {
                Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::SpectateLobbyResponse(Err(e))),
             }
          }
+         PalaceInMessage::TakeSeat(message) => {
+            // See note on JoinLobby above
+            match self.do_take_seat(message) {
+               Ok(()) => Ok(()),
+               Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::TakeSeatResponse(Err(e))),
+            }
+         }
+         PalaceInMessage::QueueForSeat(message) => {
+            let response = PalaceOutMessage::QueueForSeatResponse(self.do_queue_for_seat(message));
+            serialize_and_send(&mut self.out, &response)
+         }
+         PalaceInMessage::StopSpectating(message) => {
+            let response = PalaceOutMessage::StopSpectatingResponse(self.do_stop_spectating(message));
+            serialize_and_send(&mut self.out, &response)
+         }
+         PalaceInMessage::SetLobbyDescription(message) => {
+            let response = unless_denied(denial, || self.do_set_lobby_description(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::SetLobbyDescriptionResponse(response))
+         }
+         PalaceInMessage::Announce(message) => {
+            let response = unless_denied(denial, || self.do_announce(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::AnnounceResponse(response))
+         }
+         PalaceInMessage::SetDrainMode(message) => {
+            let response = unless_denied(denial, || self.do_set_drain_mode(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::SetDrainModeResponse(response))
+         }
+         PalaceInMessage::GetStatsSummaries(_) => {
+            let response = unless_denied(denial, || self.do_get_stats_summaries());
+            serialize_and_send(&mut self.out, &PalaceOutMessage::GetStatsSummariesResponse(response))
+         }
+         PalaceInMessage::SubscribeServerStats(_) => {
+            let response = unless_denied(denial, || self.do_subscribe_server_stats());
+            serialize_and_send(&mut self.out, &PalaceOutMessage::SubscribeServerStatsResponse(response))
+         }
+         PalaceInMessage::GetLobbyStats(message) => {
+            let lobbies = self.lobbies.read().unwrap();
+            let response = unless_denied(denial, || {
+               let lobby = lobbies
+                  .get(&message.lobby_id)
+                  .ok_or(GetLobbyStatsError::LobbyNotFound)?;
+               Ok(lobby.stats())
+            });
+            serialize_and_send(&mut self.out, &PalaceOutMessage::GetLobbyStatsResponse(response))
+         }
+         PalaceInMessage::RequestHint(message) => {
+            // Success is answered from the search thread once it's done
+            match unless_denied(denial, || self.do_request_hint(&message)) {
+               Ok(()) => Ok(()),
+               Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::RequestHintResponse(Err(e))),
+            }
+         }
+         PalaceInMessage::RewindTurn(message) => {
+            let response = unless_denied(denial, || self.do_rewind_turn(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::RewindTurnResponse(response))
+         }
+         PalaceInMessage::PredictPlay(message) => {
+            let response = unless_denied(denial, || self.do_predict_play(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::PredictPlayResponse(response))
+         }
+         PalaceInMessage::MergeLobby(message) => {
+            let response = unless_denied(denial, || self.do_merge_lobby(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::MergeLobbyResponse(response))
+         }
+         PalaceInMessage::AnswerMerge(message) => {
+            let response = unless_denied(denial, || self.do_answer_merge(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::AnswerMergeResponse(response))
+         }
+         PalaceInMessage::RotateId(message) => {
+            let response = unless_denied(denial, || self.do_rotate_id(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::RotateIdResponse(response))
+         }
+         PalaceInMessage::CatchUp(message) => {
+            let response = unless_denied(denial, || self.do_catch_up(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::CatchUpResponse(response))
+         }
+         PalaceInMessage::ObserveLobby(message) => {
+            let response = unless_denied(denial, || self.do_observe_lobby(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::ObserveLobbyResponse(response))
+         }
+         PalaceInMessage::StopObservingLobby(message) => {
+            let response = self.do_stop_observing_lobby(message);
+            serialize_and_send(&mut self.out, &PalaceOutMessage::StopObservingLobbyResponse(response))
+         }
+         PalaceInMessage::ScheduleEvent(message) => {
+            let response = unless_denied(denial, || self.do_schedule_event(message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::ScheduleEventResponse(response))
+         }
+         PalaceInMessage::UnscheduleEvent(message) => {
+            let response = unless_denied(denial, || self.do_unschedule_event(&message));
+            serialize_and_send(&mut self.out, &PalaceOutMessage::UnscheduleEventResponse(response))
+         }
+         PalaceInMessage::ListEvents(()) => {
+            let listings = self.schedule.lock().unwrap().listings(schedule::now_secs());
+            serialize_and_send(&mut self.out, &PalaceOutMessage::ListEventsResponse(listings))
+         }
+         PalaceInMessage::RegisterForEvent(message) => {
+            let response = self.do_register_for_event(message);
+            serialize_and_send(&mut self.out, &PalaceOutMessage::RegisterForEventResponse(response))
+         }
+         PalaceInMessage::AnalyzeGame(message) => {
+            // Success is answered from the analysis thread once it's done
+            match self.do_analyze_game(&message) {
+               Ok(()) => Ok(()),
+               Err(e) => serialize_and_send(&mut self.out, &PalaceOutMessage::AnalyzeGameResponse(Err(e))),
+            }
+         }
+         PalaceInMessage::GetLobbyPlayers(message) => {
+            let lobbies = self.lobbies.read().unwrap();
+            let response = PalaceOutMessage::GetLobbyPlayersResponse(match lobbies.get(&message) {
+               Some(lobby) => Ok(lobby.seats()),
+               None => Err(GetLobbyPlayersError::LobbyNotFound),
+            });
+            serialize_and_send(&mut self.out, &response)
+         }
+      }
+   }
+
+   fn is_admin(&self, admin_key: &str) -> bool {
+      self.config.admin_key.as_ref().map(|x| x == admin_key).unwrap_or(false)
+   }
+
+   fn check_permission(&self, permission: Permission) -> Result<(), Denial> {
+      // Most messages, which needn't wait on the lock
+      if let Permission::Anyone = permission {
+         return Ok(());
+      }
+      let lobbies = self.lobbies.read().unwrap();
+      permissions::check(
+         permission,
+         &lobbies,
+         |x| self.is_admin(x),
+         |x| self.memberships.is_spectating(x),
+      )
+   }
+
+   fn do_announce(&mut self, message: AnnounceMessage) -> Result<(), AnnounceError> {
+      let event = PalaceOutMessage::ServerAnnouncementEvent(ServerAnnouncementEvent {
+         severity: message.severity,
+         text: &message.text,
+      });
+
+      if let Some(lobby_id) = message.lobby_id {
+         let mut lobbies = self.lobbies.write().unwrap();
+         let lobby = lobbies.get_mut(&lobby_id).ok_or(AnnounceError::LobbyNotFound)?;
+         broadcast(&mut lobby.players, &mut lobby.spectators, &event);
+      } else {
+         let _ = serialize_and_broadcast(&self.broadcasters.read().unwrap(), &event);
+      }
+
+      Ok(())
+   }
+
+   fn do_get_stats_summaries(&mut self) -> Result<StatsSummaries, GetStatsSummariesError> {
+      if !self.archive.is_enabled() {
+         return Err(GetStatsSummariesError::ArchiveDisabled);
+      }
+
+      Ok(self.archive.summaries())
+   }
+
+   fn do_subscribe_server_stats(&mut self) -> Result<(), SubscribeServerStatsError> {
+      let mut subscribers = self.stats_subscribers.write().unwrap();
+      if subscribers
+         .iter()
+         .all(|x| x.connection_id() != self.out.connection_id())
+      {
+         subscribers.push(self.out.clone());
       }
+
+      Ok(())
+   }
+
+   fn do_set_drain_mode(&mut self, message: SetDrainModeMessage) -> Result<(), SetDrainModeError> {
+      let mut drain_state = self.drain_state.write().unwrap();
+      let event = if message.draining {
+         info!("Server is draining");
+         let new_state = DrainState {
+            shutdown_at: message
+               .shutdown_in_secs
+               .map(|x| Instant::now() + Duration::from_secs(x)),
+         };
+         let event = new_state.event();
+         *drain_state = Some(new_state);
+         event
+      } else {
+         info!("Server is no longer draining");
+         *drain_state = None;
+         ServerDrainingEvent {
+            draining: false,
+            shutdown_in_secs: None,
+         }
+      };
+      let _ = serialize_and_broadcast(
+         &self.broadcasters.read().unwrap(),
+         &PalaceOutMessage::ServerDrainingEvent(event),
+      );
+
+      Ok(())
+   }
+
+   fn is_draining(&self) -> bool {
+      self.drain_state.read().unwrap().is_some()
    }
 
    fn do_request_ai(&mut self, message: RequestAiMessage) -> Result<(), RequestAiError> {
       if message.num_ai == 0 {
          return Err(RequestAiError::LessThanOneAiRequested);
       }
+      if matches!(message.monty, Some(monty) if !monty.is_valid()) {
+         return Err(RequestAiError::InvalidMontyParameters);
+      }
 
       let mut lobbies = self.lobbies.write().unwrap();
       if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
-         if lobby.owner != message.player_id {
-            Err(RequestAiError::NotLobbyOwner)
-         } else if lobby.players.len() + message.num_ai as usize > lobby.max_players as usize {
+         if let Some(ref gs) = lobby.game {
+            // Mid-game, AI can only take over the seats of players that are gone
+            let mut vacant_slots: Vec<u8> = lobby
+               .players
+               .values()
+               .filter(|x| matches!(x.connection, Connection::Disconnected(_)))
+               .map(|x| x.turn_number)
+               .chain((0..gs.num_players).filter(|x| !lobby.players_by_turn_num.contains_key(x)))
+               .collect();
+            if vacant_slots.len() < message.num_ai as usize {
+               return Err(RequestAiError::NotEnoughVacantSeats);
+            }
+            vacant_slots.sort_unstable();
+            let players = lobby.player_profiles();
+            let mut seated = Vec::with_capacity(message.num_ai as usize);
+            for slot in vacant_slots.into_iter().take(message.num_ai as usize) {
+               let mut ai = message
+                  .strategy
+                  .instantiate_tuned(&self.ai_resources, message.monty)
+                  .ok_or(RequestAiError::StrategyUnavailable)?;
+               ai.reseed(ai::seat_seed(gs, slot));
+               ai.assume_position(
+                  GameStartEvent {
+                     hand: gs.get_hand(slot),
+                     turn_number: slot,
+                     players: &players,
+                  },
+                  &gs.public_state(),
+                  &gs.pile_cards,
+                  &gs.cleared_cards,
+               );
+               seated.push((slot, ai));
+            }
+            for (slot, ai) in seated {
+               match lobby.players_by_turn_num.get(&slot) {
+                  Some(player_id) => {
+                     lobby.players.get_mut(player_id).unwrap().connection = Connection::Ai(AiState {
+                        core: ai,
+                        is_clandestine: false,
+                     });
+                     broadcast_connection_status(
+                        &mut lobby.players,
+                        &mut lobby.spectators,
+                        slot,
+                        ConnectionStatus::ReplacedByAi,
+                     );
+                  }
+                  // The player was evicted
+                  None => add_requested_ai(lobby, ai, slot),
+               }
+            }
+            self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
+            Ok(())
+         } else if lobby.players.len() + message.num_ai as usize > lobby.open_seats() as usize {
             Err(RequestAiError::LobbyTooSmall)
-         } else if lobby.game.is_some() {
-            Err(RequestAiError::GameInProgress)
          } else {
             for _ in 0..message.num_ai {
-               let player_id = PlayerId(rand::random());
-               let ai: Box<dyn PalaceAi + Send + Sync> = Box::new(ai::monty::new());
-               add_player(
-                  Player {
-                     name: ai::get_bot_name(),
-                     connection: Connection::Ai(AiState {
-                        core: ai,
-                        is_clandestine: false,
-                     }),
-                     turn_number: next_public_id(&lobby.players_by_turn_num),
-                  },
-                  player_id,
-                  lobby,
-               );
+               let ai = message
+                  .strategy
+                  .instantiate_tuned(&self.ai_resources, message.monty)
+                  .ok_or(RequestAiError::StrategyUnavailable)?;
+               let slot = lobby.next_open_slot();
+               add_requested_ai(lobby, ai, slot);
             }
             Ok(())
          }
@@ -360,7 +1911,7 @@ impl Server {
       }
    }
 
-   fn do_new_lobby(&mut self, message: NewLobbyMessage) -> Result<NewLobbyResponse, NewLobbyError> {
+   fn do_new_lobby(&mut self, mut message: NewLobbyMessage) -> Result<NewLobbyResponse, NewLobbyError> {
       if message.max_players < 2 {
          return Err(NewLobbyError::LessThanTwoMaxPlayers);
       }
@@ -385,152 +1936,298 @@ impl Server {
          return Err(NewLobbyError::PasswordTooLong);
       }
 
-      let mut lobbies = self.lobbies.write().unwrap();
-      let (lobby_id, player_id) = create_lobby(
-         &mut lobbies,
-         Connection::Connected(self.out.clone()),
-         message.lobby_name,
-         message.player_name,
-         message.password,
-         message.max_players,
-         message.turn_timer,
-      );
+      if message.description.len() > DESCRIPTION_LIMIT {
+         return Err(NewLobbyError::DescriptionTooLong);
+      }
 
-      update_connected_player_info(
-         &mut self.connected_user,
-         &mut lobbies,
-         ConnectedUser::Player((lobby_id, player_id)),
-         self.out.connection_id(),
-      );
+      if message.turn_timer != 0 && message.turn_timer < MIN_TURN_TIMER_SECS {
+         return Err(NewLobbyError::TurnTimerTooShort);
+      }
 
-      Ok(NewLobbyResponse {
-         player_id,
-         lobby_id,
-         max_players: message.max_players,
-      })
-   }
+      if matches!(message.draw_rules.max_repetitions, Some(x) if x < 2) {
+         return Err(NewLobbyError::TooFewRepetitions);
+      }
 
-   fn do_join_lobby(&mut self, message: JoinLobbyMessage) -> Result<(), JoinLobbyError> {
-      if message.player_name.is_empty() {
-         return Err(JoinLobbyError::EmptyPlayerName);
+      if self.is_draining() {
+         return Err(NewLobbyError::ServerDraining);
       }
 
-      if message.player_name.len() > PLAYER_NAME_LIMIT {
-         return Err(JoinLobbyError::PlayerNameTooLong);
+      let scenario = match message.scenario {
+         Some(ref name) => Some(scenario::find(name).ok_or(NewLobbyError::UnknownScenario)?),
+         None => None,
+      };
+      if let Some(scenario) = scenario {
+         message.max_players = scenario.num_players();
+         if message.description.is_empty() {
+            message.description = scenario.description.clone();
+         }
       }
 
+      let max_players = message.max_players;
       let mut lobbies = self.lobbies.write().unwrap();
-      let new_player_id = if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
-         if lobby.game.is_some() {
-            return Err(JoinLobbyError::GameInProgress);
+      if let Some(max_lobbies) = self.config.max_lobbies {
+         if lobbies.len() >= max_lobbies {
+            return Err(NewLobbyError::ServerFull);
          }
-
-         if lobby.password != message.password {
-            return Err(JoinLobbyError::BadPassword);
+      }
+      if let (Some(limit), Some(remote_addr)) = (self.config.max_lobbies_per_address_per_hour, &self.remote_addr) {
+         if !note_lobby_creation(&mut self.lobby_creations.lock().unwrap(), remote_addr, limit) {
+            return Err(NewLobbyError::TooManyLobbies);
          }
+      }
+      let (lobby_id, player_id) = create_lobby(&mut lobbies, Connection::Connected(vec![self.out.clone()]), message);
+      lobbies.get_mut(&lobby_id).unwrap().scenario = scenario;
 
-         if lobby.players.len() as u8 >= lobby.max_players {
-            return Err(JoinLobbyError::LobbyFull);
-         }
+      self
+         .memberships
+         .take_seat(&mut lobbies, (lobby_id, player_id), self.out.connection_id());
 
-         let player_id = PlayerId(rand::random());
+      Ok(NewLobbyResponse {
+         player_id,
+         lobby_id,
+         max_players,
+      })
+   }
 
-         let lobby_players = {
-            let mut lobby_players: Vec<&str> = vec![lobby.players[&lobby.owner].name.as_ref()];
-            lobby_players.extend(
-               lobby
-                  .players
-                  .iter()
-                  .filter(|(id, _)| **id != lobby.owner)
-                  .map(|(_, p)| p.name.as_str()),
-            );
-            lobby_players
-         };
+   /// Fills a new scenario lobby's other seats with the scenario's AI
+   fn seat_scenario_opponents(&mut self, lobby_id: &LobbyId) {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = match lobbies.get_mut(lobby_id) {
+         Some(lobby) => lobby,
+         None => return,
+      };
+      if let Some(scenario) = lobby.scenario {
+         for core in scenario.opponents(&self.ai_resources) {
+            // Someone may have joined in the meantime
+            if lobby.players.len() as u8 >= lobby.open_seats() {
+               break;
+            }
+            let slot = lobby.next_open_slot();
+            add_requested_ai(lobby, core, slot);
+         }
+      }
+   }
 
-         let _ = serialize_and_send(
-            &mut self.out,
-            &PalaceOutMessage::JoinLobbyResponse(Ok(JoinLobbyResponse {
-               player_id,
-               lobby_players,
-               max_players: lobby.max_players,
-               num_spectators: lobby.spectators.len() as u8,
-               turn_timer: lobby.turn_timer.as_secs() as u8,
-            })),
-         );
+   fn do_join_lobby(&mut self, message: JoinLobbyMessage) -> Result<(), JoinLobbyError> {
+      check_player_name(&message.player_name)?;
 
-         add_player(
-            Player {
-               name: message.player_name,
-               connection: Connection::Connected(self.out.clone()),
-               turn_number: next_public_id(&lobby.players_by_turn_num),
-            },
-            player_id,
-            lobby,
-         );
+      if self.is_draining() {
+         return Err(JoinLobbyError::ServerDraining);
+      }
 
-         player_id
+      let mut lobbies = self.lobbies.write().unwrap();
+      let new_player_id = if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
+         check_can_join(lobby, &message.password)?;
+         seat_new_player(&mut self.out, lobby, message.player_name, |r| {
+            PalaceOutMessage::JoinLobbyResponse(Ok(r))
+         })
       } else {
          return Err(JoinLobbyError::LobbyNotFound);
       };
 
-      update_connected_player_info(
-         &mut self.connected_user,
+      self.memberships.take_seat(
          &mut lobbies,
-         ConnectedUser::Player((message.lobby_id, new_player_id)),
+         (message.lobby_id, new_player_id),
          self.out.connection_id(),
       );
 
       Ok(())
    }
 
-   fn do_spectate_lobby(&mut self, message: LobbyId) -> Result<(), SpectateLobbyError> {
+   fn do_join_with_token(&mut self, message: JoinWithTokenMessage) -> Result<(), JoinWithTokenError> {
+      check_player_name(&message.player_name).map_err(JoinWithTokenError::CantJoin)?;
+
+      if self.is_draining() {
+         return Err(JoinWithTokenError::CantJoin(JoinLobbyError::ServerDraining));
+      }
+
       let mut lobbies = self.lobbies.write().unwrap();
-      if let Some(lobby) = lobbies.get_mut(&message) {
-         if lobby.spectators.len() as u8 == std::u8::MAX {
-            return Err(SpectateLobbyError::SpectateLobbyFull);
-         }
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(JoinWithTokenError::CantJoin(JoinLobbyError::LobbyNotFound))?;
+      if !matches!(lobby.join_tokens.get(&message.token), Some(grant) if grant.expires_at > Instant::now()) {
+         return Err(JoinWithTokenError::BadToken);
+      }
+      // The token stands in for the password
+      check_can_join(lobby, &lobby.password).map_err(JoinWithTokenError::CantJoin)?;
 
-         let lobby_players = {
-            let mut lobby_players: Vec<&str> = vec![lobby.players[&lobby.owner].name.as_ref()];
-            lobby_players.extend(
-               lobby
-                  .players
-                  .iter()
-                  .filter(|(id, _)| **id != lobby.owner)
-                  .map(|(_, p)| p.name.as_str()),
-            );
-            lobby_players
-         };
+      let grant = lobby.join_tokens.get_mut(&message.token).unwrap();
+      grant.uses_left -= 1;
+      if grant.uses_left == 0 {
+         lobby.join_tokens.remove(&message.token);
+      }
+      let new_player_id = seat_new_player(&mut self.out, lobby, message.player_name, |r| {
+         PalaceOutMessage::JoinWithTokenResponse(Ok(r))
+      });
+      self.memberships.take_seat(
+         &mut lobbies,
+         (message.lobby_id, new_player_id),
+         self.out.connection_id(),
+      );
 
-         let _ = serialize_and_send(
-            &mut self.out,
-            &PalaceOutMessage::SpectateLobbyResponse(Ok(SpectateLobbyResponse {
-               lobby_players,
-               max_players: lobby.max_players,
-               num_spectators: lobby.spectators.len() as u8 + 1,
-               turn_timer: lobby.turn_timer.as_secs() as u8,
-            })),
-         );
+      Ok(())
+   }
 
-         if let Some(ref gs) = lobby.game {
-            let mut players = HashMap::new();
-            for player in lobby.players.values() {
-               players.insert(player.turn_number, player.name.clone());
-            }
-            let _ = serialize_and_send(
-               &mut self.out,
-               &PalaceOutMessage::SpectateGameStartEvent(SpectateGameStartEvent { players: &players }),
-            );
-            let _ = serialize_and_send(
-               &mut self.out,
-               &PalaceOutMessage::PublicGameStateEvent(&gs.public_state()),
-            );
-         }
+   fn do_create_join_token(&mut self, message: &CreateJoinTokenMessage) -> Result<JoinToken, CreateJoinTokenError> {
+      if message.expires_in_secs == 0 || message.expires_in_secs > MAX_JOIN_TOKEN_SECS {
+         return Err(CreateJoinTokenError::InvalidExpiry);
+      }
+      if message.max_uses == 0 {
+         return Err(CreateJoinTokenError::InvalidMaxUses);
+      }
+
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(CreateJoinTokenError::LobbyNotFound)?;
+
+      let now = Instant::now();
+      lobby.join_tokens.retain(|_, x| x.expires_at > now);
+      if lobby.join_tokens.len() >= MAX_JOIN_TOKENS {
+         return Err(CreateJoinTokenError::TooManyTokens);
+      }
+
+      let token = JoinToken(rand::random());
+      lobby.join_tokens.insert(
+         token,
+         JoinTokenGrant {
+            expires_at: now + Duration::from_secs(u64::from(message.expires_in_secs)),
+            uses_left: message.max_uses,
+         },
+      );
+      Ok(token)
+   }
+
+   fn do_take_seat(&mut self, message: TakeSeatMessage) -> Result<(), TakeSeatError> {
+      check_player_name(&message.player_name).map_err(TakeSeatError::CantJoin)?;
+
+      if self.is_draining() {
+         return Err(TakeSeatError::CantJoin(JoinLobbyError::ServerDraining));
+      }
+
+      let mut lobbies = self.lobbies.write().unwrap();
+      if !self.memberships.is_spectating(&message.lobby_id) {
+         return Err(TakeSeatError::NotSpectating);
+      }
+      let lobby = lobbies
+         .get(&message.lobby_id)
+         .ok_or(TakeSeatError::CantJoin(JoinLobbyError::LobbyNotFound))?;
+      check_can_join(lobby, &message.password).map_err(TakeSeatError::CantJoin)?;
+
+      self
+         .memberships
+         .stop_spectating(&mut lobbies, &message.lobby_id, self.out.connection_id());
+      let lobby = lobbies.get_mut(&message.lobby_id).unwrap();
+      let new_player_id = seat_new_player(&mut self.out, lobby, message.player_name, |r| {
+         PalaceOutMessage::TakeSeatResponse(Ok(r))
+      });
+      self.memberships.take_seat(
+         &mut lobbies,
+         (message.lobby_id, new_player_id),
+         self.out.connection_id(),
+      );
+
+      Ok(())
+   }
+
+   fn do_queue_for_seat(&mut self, message: QueueForSeatMessage) -> Result<u16, QueueForSeatError> {
+      if message.player_name.is_empty() {
+         return Err(QueueForSeatError::EmptyPlayerName);
+      }
+
+      if message.player_name.len() > PLAYER_NAME_LIMIT {
+         return Err(QueueForSeatError::PlayerNameTooLong);
+      }
+
+      if self.is_draining() {
+         return Err(QueueForSeatError::ServerDraining);
+      }
+
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies.get(&message.lobby_id).ok_or(QueueForSeatError::LobbyNotFound)?;
+      if lobby.practice {
+         return Err(QueueForSeatError::PracticeLobby);
+      }
+      if lobby.game.is_some() {
+         return Err(QueueForSeatError::GameInProgress);
+      }
+      if lobby.password != message.password {
+         return Err(QueueForSeatError::BadPassword);
+      }
+      if self.memberships.seat.map(|(lobby_id, _)| lobby_id) == Some(message.lobby_id) {
+         return Err(QueueForSeatError::AlreadyInLobby);
+      }
+      let our_sender_id = self.out.connection_id();
+      if let Some(i) = lobby
+         .seat_queue
+         .iter()
+         .position(|x| x.sender.connection_id() == our_sender_id)
+      {
+         return Ok(i as u16 + 1);
+      }
+      if (lobby.players.len() as u8) < lobby.open_seats() {
+         return Err(QueueForSeatError::LobbyNotFull);
+      }
+      if lobby.seat_queue.len() >= MAX_SEAT_QUEUE {
+         return Err(QueueForSeatError::QueueFull);
+      }
+
+      self.memberships.queue(&mut lobbies, message.lobby_id, our_sender_id);
+      let lobby = lobbies.get_mut(&message.lobby_id).unwrap();
+      lobby.seat_queue.push_back(QueuedPlayer {
+         name: message.player_name,
+         sender: self.out.clone(),
+      });
+      Ok(lobby.seat_queue.len() as u16)
+   }
+
+   fn do_spectate_lobby(&mut self, message: LobbyId) -> Result<(), SpectateLobbyError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      // Forget lobbies that have closed since we started spectating them
+      self.memberships.spectating.retain(|x| lobbies.contains_key(x));
+      if let Some(lobby) = lobbies.get_mut(&message) {
+         if self.memberships.seat.map(|(lobby_id, _)| lobby_id) == Some(message) {
+            return Err(SpectateLobbyError::AlreadyInLobby);
+         }
+         if self.memberships.is_spectating(&message) {
+            return Err(SpectateLobbyError::AlreadySpectating);
+         }
+         if lobby.spectators.len() >= MAX_SPECTATORS {
+            return Err(SpectateLobbyError::SpectateLobbyFull);
+         }
+
+         let _ = serialize_and_send(
+            &mut self.out,
+            &PalaceOutMessage::SpectateLobbyResponse(Ok(SpectateLobbyResponse {
+               lobby_players: lobby.seats(),
+               max_players: lobby.max_players,
+               num_spectators: lobby.spectators.len() as u16 + 1,
+               turn_timer: lobby.turn_timer.limit.as_secs() as u16,
+            })),
+         );
+
+         send_lobby_info(&mut self.out, lobby);
+
+         if let Some(ref gs) = lobby.game {
+            let players = lobby.player_profiles();
+            let _ = serialize_and_send(
+               &mut self.out,
+               &PalaceOutMessage::SpectateGameStartEvent(SpectateGameStartEvent { players: &players }),
+            );
+            let _ = serialize_and_send(
+               &mut self.out,
+               &PalaceOutMessage::PublicGameStateEvent(&public_state_with_deadline(
+                  gs,
+                  &lobby.players,
+                  lobby.turn_timer,
+               )),
+            );
+         }
 
          for player in lobby.players.values_mut() {
             match player.connection {
-               Connection::Connected(ref mut sender) => {
-                  let _ = serialize_and_send(sender, &PalaceOutMessage::SpectatorJoinEvent(()));
+               Connection::Connected(ref mut senders) => {
+                  let _ = serialize_and_send_all(senders, &PalaceOutMessage::SpectatorJoinEvent(()));
                }
                Connection::Disconnected(_) => (),
                Connection::Ai(_) => (),
@@ -541,13 +2238,7 @@ impl Server {
          }
 
          lobby.spectators.push(self.out.clone());
-
-         update_connected_player_info(
-            &mut self.connected_user,
-            &mut lobbies,
-            ConnectedUser::Spectator(message),
-            self.out.connection_id(),
-         );
+         self.memberships.spectating.push(message);
 
          Ok(())
       } else {
@@ -555,6 +2246,18 @@ impl Server {
       }
    }
 
+   fn do_stop_spectating(&mut self, message: LobbyId) -> Result<(), StopSpectatingError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      if self
+         .memberships
+         .stop_spectating(&mut lobbies, &message, self.out.connection_id())
+      {
+         Ok(())
+      } else {
+         Err(StopSpectatingError::NotSpectating)
+      }
+   }
+
    fn do_start_game(&mut self, message: StartGameMessage) -> Result<(), StartGameError> {
       let mut lobbies = self.lobbies.write().unwrap();
       if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
@@ -562,15 +2265,28 @@ impl Server {
             return Err(StartGameError::GameInProgress);
          }
 
-         if message.player_id != lobby.owner {
-            return Err(StartGameError::NotLobbyOwner);
+         if let Some(strategy) = message.fill_with_ai {
+            let num_missing = (lobby.open_seats() as usize).saturating_sub(lobby.players.len());
+            let cores = (0..num_missing)
+               .map(|_| strategy.instantiate(&self.ai_resources))
+               .collect::<Option<Vec<_>>>()
+               .ok_or(StartGameError::StrategyUnavailable)?;
+            for core in cores {
+               let slot = lobby.next_open_slot();
+               add_requested_ai(lobby, core, slot);
+            }
          }
 
          if lobby.players.len() < 2 {
             return Err(StartGameError::LessThanTwoPlayers);
          }
 
+         if matches!(lobby.scenario, Some(scenario) if lobby.players.len() != scenario.num_players() as usize) {
+            return Err(StartGameError::ScenarioSeatsEmpty);
+         }
+
          start_game(lobby);
+         self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
 
          Ok(())
       } else {
@@ -578,29 +2294,351 @@ impl Server {
       }
    }
 
+   /// Charges the player for the hint up front, so the search can run without holding the lobbies
+   fn do_request_hint(&mut self, message: &RequestHintMessage) -> Result<(), RequestHintError> {
+      let (gs, slot, hints_left) = {
+         let mut lobbies = self.lobbies.write().unwrap();
+         let lobby = lobbies
+            .get_mut(&message.lobby_id)
+            .ok_or(RequestHintError::LobbyNotFound)?;
+         let max_hints = match lobby.hints {
+            HintPolicy::Disabled => return Err(RequestHintError::HintsDisabled),
+            HintPolicy::PerGame(max_hints) => max_hints,
+         };
+         let player = lobby
+            .players
+            .get_mut(&message.player_id)
+            .ok_or(RequestHintError::PlayerNotFound)?;
+         let gs = lobby.game.as_ref().ok_or(RequestHintError::GameNotStarted)?;
+         if gs.active_player != player.turn_number {
+            return Err(RequestHintError::NotYourTurn);
+         }
+         if player.hints_used >= max_hints {
+            return Err(RequestHintError::NoHintsLeft);
+         }
+         if matches!(player.last_hint, Some(x) if x.elapsed() < Duration::from_secs(HINT_INTERVAL_SECS)) {
+            return Err(RequestHintError::TooSoon);
+         }
+         player.hints_used += 1;
+         player.last_hint = Some(Instant::now());
+         (gs.clone(), player.turn_number, max_hints - player.hints_used)
+      };
+      let mut out = self.out.clone();
+      std::thread::spawn(move || {
+         let mut ai = ai::monty::with_parameters(
+            ai::monty::DEFAULT_EXPLORATION_VAL,
+            HINT_SIMULATIONS,
+            1,
+            ai::monty::RolloutPolicy::Random,
+         );
+         ai::assume_seat(&mut ai, &gs, slot);
+         let cards = ai::get_turn(&gs, &mut ai);
+         let hint = Hint { cards, hints_left };
+         let _ = serialize_and_send(&mut out, &PalaceOutMessage::RequestHintResponse(Ok(hint)));
+      });
+      Ok(())
+   }
+
+   fn do_rewind_turn(&mut self, message: &RewindTurnMessage) -> Result<(), RewindTurnError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(RewindTurnError::LobbyNotFound)?;
+      if !lobby.practice {
+         return Err(RewindTurnError::NotPracticeLobby);
+      }
+      if lobby.game.is_none() {
+         return Err(RewindTurnError::GameNotStarted);
+      }
+      if message.turns == 0 {
+         return Err(RewindTurnError::ZeroTurns);
+      }
+      let turns = usize::from(message.turns);
+      if turns > lobby.rewind_points.len() {
+         return Err(RewindTurnError::NotEnoughTurns);
+      }
+
+      // Rewinding and then playing differently branches the game, so the turns after are forgotten
+      let keep = lobby.rewind_points.len() - turns;
+      let gs = lobby.rewind_points.drain(keep..).next().unwrap();
+      rewind_game(lobby, gs);
+      self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
+      Ok(())
+   }
+
+   fn do_rotate_id(&mut self, message: &RotateIdMessage) -> Result<RotateIdResponse, RotateIdError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies.get_mut(&message.lobby_id).ok_or(RotateIdError::LobbyNotFound)?;
+      let our_sender_id = self.out.connection_id();
+      if lobby.player_connected_as(our_sender_id) != Some(message.player_id) {
+         return Err(RotateIdError::NotConnectedAsPlayer);
+      }
+
+      let player_id = PlayerId(rand::random());
+      rotate_player_id(lobby, message.player_id, player_id, our_sender_id);
+      self.memberships.seat = Some((message.lobby_id, player_id));
+      Ok(RotateIdResponse { player_id })
+   }
+
+   fn do_catch_up(&mut self, message: &CatchUpMessage) -> Result<CatchUpResponse, CatchUpError> {
+      let lobbies = self.lobbies.read().unwrap();
+      let lobby = lobbies.get(&message.lobby_id).ok_or(CatchUpError::LobbyNotFound)?;
+      let missed = lobby
+         .journal
+         .since(message.since_seq)
+         .ok_or(CatchUpError::TooFarBehind)?;
+      for event in missed {
+         let _ = send_serialized(&mut self.out, event.to_vec());
+      }
+      Ok(CatchUpResponse {
+         latest_seq: lobby.journal.latest_seq(),
+      })
+   }
+
+   fn do_observe_lobby(&mut self, message: ObserveLobbyMessage) -> Result<(), ObserveLobbyError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      // Forget lobbies that have closed since we started observing them
+      self.memberships.observing.retain(|x| lobbies.contains_key(x));
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(ObserveLobbyError::LobbyNotFound)?;
+      let our_sender_id = self.out.connection_id();
+      lobby
+         .journal
+         .observers
+         .retain(|x| x.sender.connection_id() != our_sender_id);
+      lobby.journal.observers.push(Observer {
+         sender: self.out.clone(),
+         kinds: message.kinds,
+      });
+      if !self.memberships.observing.contains(&message.lobby_id) {
+         self.memberships.observing.push(message.lobby_id);
+      }
+      Ok(())
+   }
+
+   fn do_stop_observing_lobby(&mut self, message: LobbyId) -> Result<(), StopObservingLobbyError> {
+      let before = self.memberships.observing.len();
+      self.memberships.observing.retain(|x| *x != message);
+      if self.memberships.observing.len() == before {
+         return Err(StopObservingLobbyError::NotObserving);
+      }
+      stop_observing(&message, &mut self.lobbies.write().unwrap(), self.out.connection_id());
+      Ok(())
+   }
+
+   fn do_schedule_event(&mut self, message: ScheduleEventMessage) -> Result<(), ScheduleEventError> {
+      let event = &message.event;
+      info!("Scheduling {} daily at {} UTC", event.name, event.opens_at_utc);
+      self.schedule.lock().unwrap().add(message.event)
+   }
+
+   fn do_unschedule_event(&mut self, message: &UnscheduleEventMessage) -> Result<(), UnscheduleEventError> {
+      let registrants = self
+         .schedule
+         .lock()
+         .unwrap()
+         .remove(&message.name)
+         .ok_or(UnscheduleEventError::EventNotFound)?;
+      let text = format!("{} has been cancelled", message.name);
+      let event = PalaceOutMessage::ServerAnnouncementEvent(ServerAnnouncementEvent::warning(&text));
+      for mut registrant in registrants {
+         let _ = serialize_and_send(&mut registrant.sender, &event);
+      }
+      Ok(())
+   }
+
+   fn do_register_for_event(
+      &mut self,
+      message: RegisterForEventMessage,
+   ) -> Result<RegisterForEventResponse, RegisterForEventError> {
+      if message.player_name.is_empty() {
+         return Err(RegisterForEventError::EmptyPlayerName);
+      }
+      if message.player_name.len() > PLAYER_NAME_LIMIT {
+         return Err(RegisterForEventError::PlayerNameTooLong);
+      }
+      if self.is_draining() {
+         return Err(RegisterForEventError::ServerDraining);
+      }
+      let registrant = Registrant {
+         name: message.player_name,
+         sender: self.out.clone(),
+      };
+      let opens_in_secs = self
+         .schedule
+         .lock()
+         .unwrap()
+         .register(&message.event, registrant, schedule::now_secs())?;
+      self.memberships.registered_for = Some(message.event);
+      Ok(RegisterForEventResponse { opens_in_secs })
+   }
+
+   fn do_predict_play(&mut self, message: &PredictPlayMessage) -> Result<(), PredictPlayError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(PredictPlayError::LobbyNotFound)?;
+      let gs = lobby.game.as_ref().ok_or(PredictPlayError::GameNotStarted)?;
+      if gs.cur_phase == Phase::Setup {
+         return Err(PredictPlayError::NotDuringSetup);
+      }
+      if let PredictedPlay::Cards(_, 0) = message.play {
+         return Err(PredictPlayError::NoCards);
+      }
+
+      lobby
+         .predictions
+         .guess(self.out.connection_id(), gs.turns_taken, message.play);
+      Ok(())
+   }
+
+   fn do_merge_lobby(&mut self, message: &MergeLobbyMessage) -> Result<(), MergeLobbyError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies.get(&message.lobby_id).ok_or(MergeLobbyError::LobbyNotFound)?;
+      if message.target_lobby_id == message.lobby_id {
+         return Err(MergeLobbyError::SameLobby);
+      }
+      let target = lobbies
+         .get(&message.target_lobby_id)
+         .ok_or(MergeLobbyError::TargetNotFound)?;
+      check_can_merge(lobby, target)?;
+      if target.merge_requests.contains(&message.lobby_id) {
+         return Err(MergeLobbyError::AlreadyRequested);
+      }
+
+      let lobby_name = lobby.name.clone();
+      let player_names: Vec<String> = lobby
+         .players
+         .values()
+         .filter(|x| moves_in_merge(x))
+         .map(|x| x.name.clone())
+         .collect();
+      let target = lobbies.get_mut(&message.target_lobby_id).unwrap();
+      target.merge_requests.push(message.lobby_id);
+      let owner = target.owner;
+      if let Connection::Connected(ref mut senders) = target.players.get_mut(&owner).unwrap().connection {
+         let _ = serialize_and_send_all(
+            senders,
+            &PalaceOutMessage::MergeRequestEvent(MergeRequestEvent {
+               from_lobby_id: message.lobby_id,
+               lobby_name: &lobby_name,
+               player_names: player_names.iter().map(String::as_str).collect(),
+            }),
+         );
+      }
+      Ok(())
+   }
+
+   fn do_answer_merge(&mut self, message: &AnswerMergeMessage) -> Result<(), AnswerMergeError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let target = lobbies.get(&message.lobby_id).ok_or(AnswerMergeError::LobbyNotFound)?;
+      if !target.merge_requests.contains(&message.from_lobby_id) {
+         return Err(AnswerMergeError::NoSuchRequest);
+      }
+      if !lobbies.contains_key(&message.from_lobby_id) {
+         let target = lobbies.get_mut(&message.lobby_id).unwrap();
+         target.merge_requests.retain(|x| *x != message.from_lobby_id);
+         return Err(AnswerMergeError::NoSuchRequest);
+      }
+      if message.accept {
+         match check_can_merge(&lobbies[&message.from_lobby_id], &lobbies[&message.lobby_id]) {
+            Err(MergeLobbyError::NotEnoughSeats) => return Err(AnswerMergeError::NotEnoughSeats),
+            Err(_) => return Err(AnswerMergeError::GameInProgress),
+            Ok(()) => (),
+         }
+      }
+
+      let target = lobbies.get_mut(&message.lobby_id).unwrap();
+      target.merge_requests.retain(|x| *x != message.from_lobby_id);
+      if message.accept {
+         let from = lobbies.shift_remove(&message.from_lobby_id).unwrap();
+         let target = lobbies.get_mut(&message.lobby_id).unwrap();
+         merge_lobbies(from, message.lobby_id, target);
+      } else {
+         let from = lobbies.get_mut(&message.from_lobby_id).unwrap();
+         broadcast(
+            &mut from.players,
+            &mut from.spectators,
+            &PalaceOutMessage::MergeDeclinedEvent(MergeDeclinedEvent {
+               target_lobby_id: message.lobby_id,
+            }),
+         );
+      }
+      Ok(())
+   }
+
+   fn do_analyze_game(&mut self, message: &AnalyzeGameMessage) -> Result<(), AnalyzeGameError> {
+      let (recording, slot) = {
+         let lobbies = self.lobbies.read().unwrap();
+         let lobby = lobbies.get(&message.lobby_id).ok_or(AnalyzeGameError::LobbyNotFound)?;
+         let recording = lobby.last_game.clone().ok_or(AnalyzeGameError::NoCompletedGame)?;
+         let slot = *recording
+            .seats
+            .get(&message.player_id)
+            .ok_or(AnalyzeGameError::NotInGame)?;
+         (recording, slot)
+      };
+      if ANALYSES_IN_PROGRESS.fetch_add(1, Ordering::SeqCst) >= MAX_CONCURRENT_ANALYSES {
+         ANALYSES_IN_PROGRESS.fetch_sub(1, Ordering::SeqCst);
+         return Err(AnalyzeGameError::ServerBusy);
+      }
+      let mut out = self.out.clone();
+      std::thread::spawn(move || {
+         let analysis = analysis::analyze(&recording, slot);
+         ANALYSES_IN_PROGRESS.fetch_sub(1, Ordering::SeqCst);
+         let _ = serialize_and_send(&mut out, &PalaceOutMessage::AnalyzeGameResponse(Ok(analysis)));
+      });
+      Ok(())
+   }
+
    fn do_take_turn(&mut self, message: TakeTurnMessage) -> Result<(), TakeTurnError> {
       let mut lobbies = self.lobbies.write().unwrap();
       if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
          if let Some(ref mut gs) = lobby.game {
-            let result = if let Some(player) = lobby.players.get(&message.player_id) {
-               if player.turn_number != gs.active_player {
+            let slot = gs.active_player;
+            let commentator = self.config.commentator.as_deref();
+            let gs_before = commentator.map(|_| gs.clone());
+            let rewind_point = if lobby.practice { Some(gs.clone()) } else { None };
+            let result = if let Some(player) = lobby.players.get_mut(&message.player_id) {
+               if let (Some(play_seq), Some(last_play_seq)) = (message.play_seq, player.last_play_seq) {
+                  // A resend of a play we already made is acknowledged without playing it again
+                  if play_seq == last_play_seq {
+                     return Ok(());
+                  } else if play_seq < last_play_seq {
+                     return Err(TakeTurnError::StalePlaySeq);
+                  }
+               }
+
+               if player.turn_number != slot {
                   return Err(TakeTurnError::NotYourTurn);
                }
 
-               gs.take_turn(&message.cards)
+               let result = gs.take_turn(&message.cards);
+               if result.is_ok() && message.play_seq.is_some() {
+                  player.last_play_seq = message.play_seq;
+               }
+               result
             } else {
                return Err(TakeTurnError::PlayerNotFound);
             };
 
             match result {
-               Ok(game_finished) => {
-                  report_take_turn(gs, &mut lobby.players, &mut lobby.spectators, message.player_id);
-                  if game_finished {
-                     end_game(lobby);
-                  }
+               Ok(outcome) => {
+                  lobby.rewind_points.extend(rewind_point);
+                  after_play(
+                     lobby,
+                     slot,
+                     outcome,
+                     commentator,
+                     gs_before,
+                     &self.archive,
+                     self.config.max_game_turns,
+                  );
+                  self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
                   Ok(())
                }
-               Err(e) => Err(TakeTurnError::GameError(e)),
+               Err(e) => Err(TakeTurnError::GameError(e.message())),
             }
          } else {
             Err(TakeTurnError::GameNotStarted)
@@ -610,68 +2648,148 @@ impl Server {
       }
    }
 
-   fn do_reconnect(&mut self, message: &ReconnectMessage) -> Result<ReconnectResponse, ReconnectError> {
+   /// Connects this socket to an existing player. With `keep_other_devices`, any sockets already attached
+   /// to the player stay attached and all of them receive the player's events
+   fn do_reconnect(
+      &mut self,
+      message: &ReconnectMessage,
+      keep_other_devices: bool,
+   ) -> Result<ReconnectResponse, ReconnectError> {
       let mut lobbies = self.lobbies.write().unwrap();
       if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
-         // @Performance we construct this but throw it away if the user can't reconnect
-         // (was kicked)
-         let mut players = HashMap::new();
-         for player in lobby.players.values() {
-            players.insert(player.turn_number, player.name.clone());
-         }
-         if let Some(player) = lobby.players.get_mut(&message.player_id) {
-            if let Connection::Disconnected(ds) = &player.connection {
-               if ds.reason == DisconnectedReason::Kicked {
-                  return Err(ReconnectError::PlayerKicked);
-               }
+         if let Some(player) = lobby.players.get(&message.player_id) {
+            let attempts = lobby.reconnect_attempts.entry(message.player_id).or_default();
+            attempts.retain(|x| x.elapsed() < Duration::from_secs(RECONNECT_ATTEMPT_WINDOW_SECS));
+            if attempts.len() >= MAX_RECONNECT_ATTEMPTS {
+               return Err(ReconnectError::TooManyAttempts);
             }
+            attempts.push(Instant::now());
 
-            player.connection = Connection::Connected(self.out.clone());
-            if let Some(ref gs) = lobby.game {
-               let _ = serialize_and_send(
-                  &mut self.out,
-                  &PalaceOutMessage::GameStartEvent(GameStartEvent {
-                     hand: gs.get_hand(player.turn_number),
-                     turn_number: player.turn_number,
-                     players: &players,
-                  }),
-               );
-               let _ = serialize_and_send(
-                  &mut self.out,
-                  &PalaceOutMessage::PublicGameStateEvent(&gs.public_state()),
-               );
+            match &player.connection {
+               Connection::Disconnected(ds) if ds.reason == DisconnectedReason::Kicked => {
+                  return Err(ReconnectError::PlayerKicked);
+               }
+               Connection::Ai(ai) if !ai.is_clandestine => return Err(ReconnectError::ReplacedByAi),
+               _ => (),
             }
-
-            Ok(ReconnectResponse {
-               max_players: lobby.max_players,
-               num_spectators: lobby.spectators.len() as u8,
-               turn_timer: lobby.turn_timer.as_secs() as u8,
-            })
          } else {
-            Err(ReconnectError::PlayerNotFound)
+            return Err(ReconnectError::PlayerNotFound);
          }
       } else {
-         Err(ReconnectError::LobbyNotFound)
+         return Err(ReconnectError::LobbyNotFound);
+      }
+
+      self.memberships.take_seat(
+         &mut lobbies,
+         (message.lobby_id, message.player_id),
+         self.out.connection_id(),
+      );
+
+      // Leaving our old lobby may have closed this one, if we were its owner
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(ReconnectError::LobbyNotFound)?;
+      let players = lobby.player_profiles();
+      let player = lobby
+         .players
+         .get_mut(&message.player_id)
+         .ok_or(ReconnectError::PlayerNotFound)?;
+
+      let was_connected = match player.connection {
+         Connection::Connected(ref mut senders) => {
+            if keep_other_devices {
+               if senders.iter().all(|x| x.connection_id() != self.out.connection_id()) {
+                  senders.push(self.out.clone());
+               }
+            } else {
+               *senders = vec![self.out.clone()];
+            }
+            true
+         }
+         _ => {
+            player.connection = Connection::Connected(vec![self.out.clone()]);
+            false
+         }
+      };
+      let slot = player.turn_number;
+      if let Some(ref gs) = lobby.game {
+         let _ = serialize_and_send(
+            &mut self.out,
+            &PalaceOutMessage::GameStartEvent(GameStartEvent {
+               hand: gs.get_hand(slot),
+               turn_number: slot,
+               players: &players,
+            }),
+         );
+         let _ = serialize_and_send(
+            &mut self.out,
+            &PalaceOutMessage::PublicGameStateEvent(&public_state_with_deadline(gs, &lobby.players, lobby.turn_timer)),
+         );
+         if gs.active_player == slot {
+            let _ = serialize_and_send(
+               &mut self.out,
+               &PalaceOutMessage::YourTurnEvent(YourTurnEvent {
+                  deadline: turn_deadline(gs, &lobby.players, lobby.turn_timer),
+               }),
+            );
+         }
+      }
+
+      if !was_connected {
+         broadcast_connection_status(
+            &mut lobby.players,
+            &mut lobby.spectators,
+            slot,
+            ConnectionStatus::Connected,
+         );
       }
+
+      Ok(ReconnectResponse {
+         max_players: lobby.max_players,
+         num_spectators: lobby.spectators.len() as u16,
+         turn_timer: lobby.turn_timer.limit.as_secs() as u16,
+         journal_seq: lobby.journal.latest_seq(),
+      })
    }
 
-   fn do_kick_player(&mut self, message: &KickPlayerMessage) -> Result<(), KickPlayerError> {
-      if message.slot == 0 {
-         return Err(KickPlayerError::CantKickLobbyOwner);
+   fn do_set_lobby_description(&mut self, message: SetLobbyDescriptionMessage) -> Result<(), SetLobbyDescriptionError> {
+      if message.description.len() > DESCRIPTION_LIMIT {
+         return Err(SetLobbyDescriptionError::DescriptionTooLong);
+      }
+
+      let mut lobbies = self.lobbies.write().unwrap();
+      if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
+         lobby.description = message.description;
+
+         broadcast(
+            &mut lobby.players,
+            &mut lobby.spectators,
+            &PalaceOutMessage::LobbyInfoEvent(LobbyInfoEvent {
+               description: &lobby.description,
+            }),
+         );
+
+         Ok(())
+      } else {
+         Err(SetLobbyDescriptionError::LobbyNotFound)
       }
+   }
 
+   fn do_kick_player(&mut self, message: &KickPlayerMessage) -> Result<(), KickPlayerError> {
       let mut lobbies = self.lobbies.write().unwrap();
 
       if let Some(lobby) = lobbies.get_mut(&message.lobby_id) {
-         if lobby.owner != message.player_id {
-            Err(KickPlayerError::NotLobbyOwner)
+         if lobby.players_by_turn_num.get(&message.slot) == Some(&lobby.owner) {
+            Err(KickPlayerError::CantKickLobbyOwner)
          } else if let Some(player_id) = lobby.players_by_turn_num.get(&message.slot) {
             match lobby.game {
                Some(_) => {
                   let player = lobby.players.get_mut(player_id).unwrap();
-                  match &mut player.connection {
-                     Connection::Connected(ref mut sender) => {
-                        let _ = serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Kicked));
+                  let result = match &mut player.connection {
+                     Connection::Connected(ref mut senders) => {
+                        let _ =
+                           serialize_and_send_all(senders, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Kicked));
+                        disconnect_all(senders, DisconnectCode::Kicked, "Kicked by the lobby owner");
                         player.connection = Connection::Disconnected(DisconnectedState {
                            time: Instant::now(),
                            reason: DisconnectedReason::Kicked,
@@ -693,7 +2811,16 @@ impl Server {
                            Err(KickPlayerError::CantKickAiDuringGame)
                         }
                      }
+                  };
+                  if result.is_ok() {
+                     broadcast_connection_status(
+                        &mut lobby.players,
+                        &mut lobby.spectators,
+                        message.slot,
+                        ConnectionStatus::Kicked,
+                     );
                   }
+                  result
                }
                None => {
                   remove_player(*player_id, lobby, Some(LobbyCloseEvent::Kicked));
@@ -707,44 +2834,350 @@ impl Server {
          Err(KickPlayerError::LobbyNotFound)
       }
    }
-}
 
-fn create_lobby(
-   lobbies: &mut HashMap<LobbyId, Lobby>,
-   connection: Connection,
-   lobby_name: String,
-   player_name: String,
-   password: String,
-   max_players: u8,
-   turn_timer: u8,
-) -> (LobbyId, PlayerId) {
-   let lobby_id = LobbyId(rand::random());
-   let player_id = PlayerId(rand::random());
-   let mut players = HashMap::new();
-   let mut players_by_public_id = HashMap::new();
-   players.insert(
-      player_id,
-      Player {
-         name: player_name,
-         connection,
-         turn_number: 0,
-      },
-   );
-   players_by_public_id.insert(0, player_id);
-   lobbies.insert(
-      lobby_id,
-      Lobby {
-         players,
-         players_by_turn_num: players_by_public_id,
-         game: None,
-         password,
-         name: lobby_name,
-         owner: player_id,
-         max_players,
+   fn do_transfer_ownership(&mut self, message: &TransferOwnershipMessage) -> Result<(), TransferOwnershipError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(TransferOwnershipError::LobbyNotFound)?;
+
+      let target_id = *lobby
+         .players_by_turn_num
+         .get(&message.slot)
+         .ok_or(TransferOwnershipError::TargetPlayerNotFound)?;
+
+      if target_id == lobby.owner {
+         return Err(TransferOwnershipError::TargetIsOwner);
+      }
+
+      match lobby.players[&target_id].connection {
+         Connection::Connected(_) => (),
+         Connection::Disconnected(_) => return Err(TransferOwnershipError::TargetDisconnected),
+         Connection::Ai(_) => return Err(TransferOwnershipError::TargetNotHuman),
+      }
+
+      lobby.owner = target_id;
+      broadcast(
+         &mut lobby.players,
+         &mut lobby.spectators,
+         &PalaceOutMessage::OwnerChangedEvent(OwnerChangedEvent { slot: message.slot }),
+      );
+
+      Ok(())
+   }
+
+   /// The owner aborts the game outright; anyone else casts a vote, and the game is aborted
+   /// once every connected human has voted
+   fn do_abort_game(&mut self, message: &AbortGameMessage) -> Result<(), AbortGameError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(AbortGameError::LobbyNotFound)?;
+
+      let slot = lobby
+         .players
+         .get(&message.player_id)
+         .ok_or(AbortGameError::PlayerNotFound)?
+         .turn_number;
+
+      if lobby.game.is_none() {
+         return Err(AbortGameError::GameNotStarted);
+      }
+
+      if lobby.owner == message.player_id {
+         abort_game(lobby, GameAbortReason::Owner);
+         return Ok(());
+      }
+
+      lobby.abort_votes.insert(message.player_id);
+      let humans: Vec<PlayerId> = lobby
+         .players
+         .iter()
+         .filter(|(_, p)| matches!(p.connection, Connection::Connected(_)))
+         .map(|(id, _)| *id)
+         .collect();
+      let votes = humans.iter().filter(|id| lobby.abort_votes.contains(id)).count();
+      if votes == humans.len() {
+         abort_game(lobby, GameAbortReason::Vote);
+      } else {
+         let event = PalaceOutMessage::AbortVoteEvent(AbortVoteEvent {
+            slot,
+            votes: votes as u8,
+            needed: humans.len() as u8,
+         });
+         broadcast(&mut lobby.players, &mut lobby.spectators, &event);
+      }
+
+      Ok(())
+   }
+
+   fn do_concede(&mut self, message: &ConcedeMessage) -> Result<(), ConcedeError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies.get_mut(&message.lobby_id).ok_or(ConcedeError::LobbyNotFound)?;
+
+      let slot = lobby
+         .players
+         .get(&message.player_id)
+         .ok_or(ConcedeError::PlayerNotFound)?
+         .turn_number;
+
+      let gs = lobby.game.as_ref().ok_or(ConcedeError::GameNotStarted)?;
+      if gs.cur_phase == Phase::Setup {
+         return Err(ConcedeError::GameInSetup);
+      }
+      if gs.out_players.contains(&slot) {
+         return Err(ConcedeError::AlreadyOut);
+      }
+
+      concede(lobby, slot, &self.archive);
+      self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
+      Ok(())
+   }
+
+   /// Everyone but the player on turn votes, and once every other connected human has, the turn is played for
+   /// them as if it had run out. They keep their seat
+   fn do_vote_skip_turn(&mut self, message: &VoteSkipTurnMessage) -> Result<(), VoteSkipTurnError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(VoteSkipTurnError::LobbyNotFound)?;
+
+      let slot = lobby
+         .players
+         .get(&message.player_id)
+         .ok_or(VoteSkipTurnError::PlayerNotFound)?
+         .turn_number;
+
+      let active_player = lobby
+         .game
+         .as_ref()
+         .ok_or(VoteSkipTurnError::GameNotStarted)?
+         .active_player;
+      if slot == active_player {
+         return Err(VoteSkipTurnError::OwnTurn);
+      }
+      let active_connection = lobby
+         .players_by_turn_num
+         .get(&active_player)
+         .map(|id| &lobby.players[id].connection);
+      if !matches!(active_connection, Some(Connection::Connected(_))) {
+         return Err(VoteSkipTurnError::ActivePlayerNotConnected);
+      }
+
+      lobby.skip_turn_votes.insert(message.player_id);
+      let humans: Vec<PlayerId> = lobby
+         .players
+         .iter()
+         .filter(|(_, p)| p.turn_number != active_player && matches!(p.connection, Connection::Connected(_)))
+         .map(|(id, _)| *id)
+         .collect();
+      let votes = humans.iter().filter(|id| lobby.skip_turn_votes.contains(id)).count();
+      if votes < humans.len() {
+         let event = PalaceOutMessage::SkipTurnVoteEvent(SkipTurnVoteEvent {
+            slot,
+            votes: votes as u8,
+            needed: humans.len() as u8,
+         });
+         broadcast(&mut lobby.players, &mut lobby.spectators, &event);
+         return Ok(());
+      }
+
+      broadcast(
+         &mut lobby.players,
+         &mut lobby.spectators,
+         &PalaceOutMessage::TurnSkippedEvent(TurnSkippedEvent { slot: active_player }),
+      );
+      let gs = lobby.game.as_mut().unwrap();
+      let commentator = self.config.commentator.as_deref();
+      let gs_before = commentator.map(|_| gs.clone());
+      let outcome = take_random_turn(gs);
+      after_play(
+         lobby,
+         active_player,
+         outcome,
+         commentator,
+         gs_before,
+         &self.archive,
+         self.config.max_game_turns,
+      );
+      self.active_lobbies.lock().unwrap().track(message.lobby_id, lobby);
+      Ok(())
+   }
+
+   fn do_set_seat_closed(&mut self, message: &SetSeatClosedMessage) -> Result<(), SetSeatClosedError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(SetSeatClosedError::LobbyNotFound)?;
+
+      if lobby.game.is_some() {
+         return Err(SetSeatClosedError::GameInProgress);
+      }
+
+      if message.slot >= lobby.max_players {
+         return Err(SetSeatClosedError::SeatNotFound);
+      }
+
+      match lobby.closed_seats.binary_search(&message.slot) {
+         Ok(i) => {
+            if !message.closed {
+               lobby.closed_seats.remove(i);
+               grant_queued_seats(lobby);
+            }
+         }
+         Err(i) => {
+            if message.closed {
+               if lobby.players_by_turn_num.contains_key(&message.slot) {
+                  return Err(SetSeatClosedError::SeatOccupied);
+               }
+               if lobby.open_seats() <= 2 {
+                  return Err(SetSeatClosedError::LessThanTwoOpenSeats);
+               }
+               lobby.closed_seats.insert(i, message.slot);
+            }
+         }
+      }
+
+      let event = PalaceOutMessage::LobbySettingsEvent(LobbySettingsEvent {
+         max_players: lobby.max_players,
+         closed_seats: &lobby.closed_seats,
+      });
+      broadcast(&mut lobby.players, &mut lobby.spectators, &event);
+
+      Ok(())
+   }
+
+   /// Replaces the brain of a requested AI, even mid-game
+   fn do_swap_ai_strategy(&mut self, message: &SwapAiStrategyMessage) -> Result<(), SwapAiStrategyError> {
+      let mut lobbies = self.lobbies.write().unwrap();
+      let lobby = lobbies
+         .get_mut(&message.lobby_id)
+         .ok_or(SwapAiStrategyError::LobbyNotFound)?;
+
+      let target_id = *lobby
+         .players_by_turn_num
+         .get(&message.slot)
+         .ok_or(SwapAiStrategyError::TargetPlayerNotFound)?;
+
+      match lobby.players[&target_id].connection {
+         // Clandestine bots are passing as people, so they don't get special treatment
+         Connection::Ai(ref ai) if !ai.is_clandestine => (),
+         _ => return Err(SwapAiStrategyError::TargetNotAi),
+      }
+
+      let mut core = message
+         .strategy
+         .instantiate(&self.ai_resources)
+         .ok_or(SwapAiStrategyError::StrategyUnavailable)?;
+
+      if let Some(ref gs) = lobby.game {
+         let players = lobby.player_profiles();
+         core.reseed(ai::seat_seed(gs, message.slot));
+         core.assume_position(
+            GameStartEvent {
+               hand: gs.get_hand(message.slot),
+               turn_number: message.slot,
+               players: &players,
+            },
+            &gs.public_state(),
+            &gs.pile_cards,
+            &gs.cleared_cards,
+         );
+      }
+
+      if let Connection::Ai(ref mut ai) = lobby.players.get_mut(&target_id).unwrap().connection {
+         ai.core = core;
+      }
+
+      Ok(())
+   }
+}
+
+/// Lets a new member know about the lobby description and closed seats, if the owner has set either
+fn send_lobby_info(sender: &mut Sender, lobby: &Lobby) {
+   if !lobby.description.is_empty() {
+      let _ = serialize_and_send(
+         sender,
+         &PalaceOutMessage::LobbyInfoEvent(LobbyInfoEvent {
+            description: &lobby.description,
+         }),
+      );
+   }
+   if !lobby.closed_seats.is_empty() {
+      let _ = serialize_and_send(sender, &PalaceOutMessage::LobbySettingsEvent(lobby.settings()));
+   }
+}
+
+fn create_lobby(
+   lobbies: &mut IndexMap<LobbyId, Lobby>,
+   connection: Connection,
+   message: NewLobbyMessage,
+) -> (LobbyId, PlayerId) {
+   let lobby_id = LobbyId(rand::random());
+   let player_id = PlayerId(rand::random());
+   let mut players = HashMap::new();
+   let mut players_by_public_id = HashMap::new();
+   players.insert(
+      player_id,
+      Player {
+         name: message.player_name,
+         connection,
+         turn_number: 0,
+         idle: false,
+         last_play_seq: None,
+         hints_used: 0,
+         last_hint: None,
+         rating: None,
+      },
+   );
+   players_by_public_id.insert(0, player_id);
+   lobbies.insert(
+      lobby_id,
+      Lobby {
+         players,
+         players_by_turn_num: players_by_public_id,
+         game: None,
+         password: message.password,
+         name: message.lobby_name,
+         description: message.description,
+         owner: player_id,
+         max_players: message.max_players,
          creation_time: Instant::now(),
+         waiting_since: Instant::now(),
+         expiry_warned: None,
          spectators: Vec::new(),
-         turn_timer: Duration::from_secs(u64::from(turn_timer)),
+         turn_timer: TurnTimer {
+            limit: Duration::from_secs(u64::from(message.turn_timer)),
+            min_human: Duration::from_secs(u64::from(message.min_human_turn_timer)),
+         },
          games_completed: 0,
+         stats: LobbyStats {
+            total_players_seen: 1,
+            ..LobbyStats::default()
+         },
+         closed_seats: Vec::new(),
+         abort_votes: HashSet::new(),
+         skip_turn_votes: HashSet::new(),
+         after_game: message.after_game,
+         hints: message.hints,
+         practice: message.practice,
+         scenario: None,
+         rewind_points: Vec::new(),
+         predictions: Predictions::default(),
+         merge_requests: Vec::new(),
+         closes_at: None,
+         turn_timer_paused_at: None,
+         turn_warning: None,
+         reconnect_attempts: HashMap::new(),
+         seat_queue: VecDeque::new(),
+         join_tokens: HashMap::new(),
+         commentary: Vec::new(),
+         opening: None,
+         last_game: None,
+         journal: Journal::new(lobby_id),
+         event: None,
+         draw_rules: message.draw_rules,
+         rng: ChaChaRng::seed_from_u64(rand::random()),
       },
    );
 
@@ -753,44 +3186,75 @@ fn create_lobby(
 
 fn start_game(lobby: &mut Lobby) {
    let num_players = lobby.players.len() as u8;
-   let gs = GameState::new(num_players);
+   let gs = match lobby.scenario {
+      Some(scenario) => scenario.deal(),
+      None => GameState::with_seed(num_players, lobby.rng.gen()),
+   };
+   debug!("Dealing a game with seed {}", gs.seed);
+   lobby.opening = Some(gs.clone());
    lobby.game = Some(gs);
-
-   let public_gs = lobby.game.as_ref().unwrap().public_state();
+   lobby.commentary.clear();
+   lobby.rewind_points.clear();
+   lobby.predictions.reset();
+   lobby.closes_at = None;
+   lobby.turn_timer_paused_at = None;
 
    let mut turn_numbers: Vec<u8> = (0..num_players).collect();
-   turn_numbers.shuffle(&mut thread_rng());
+   turn_numbers.shuffle(&mut lobby.rng);
    let mut turn_numbers = turn_numbers.into_iter();
 
-   let mut players = HashMap::new();
-   // Assign everyone turn numbers
-   for (id, player) in &mut lobby.players {
-      player.turn_number = turn_numbers.next().unwrap();
+   // Assign everyone turn numbers. Scenarios keep the seats they were set up with, since their cards are per seat
+   lobby.players_by_turn_num.clear();
+   // In slot order, so that the same shuffle always seats everyone the same way
+   let mut seated: Vec<(&PlayerId, &mut Player)> = lobby.players.iter_mut().collect();
+   seated.sort_unstable_by_key(|(_, player)| player.turn_number);
+   for (id, player) in seated {
+      if lobby.scenario.is_none() {
+         player.turn_number = turn_numbers.next().unwrap();
+      }
+      player.last_play_seq = None;
+      player.hints_used = 0;
       lobby.players_by_turn_num.insert(player.turn_number, *id);
-      // @Performance: we can avoid cloning here
-      // because we don't modify the hashmap before we send the data.
-      // the problem is convincing that to the rust compiler
-      // which sees us mutably borrowing the hashmap
-      // (to send data out). So, use unsafe?
-      players.insert(player.turn_number, player.name.clone());
    }
+   // @Performance: we can avoid cloning here
+   // because we don't modify the hashmap before we send the data.
+   // the problem is convincing that to the rust compiler
+   // which sees us mutably borrowing the hashmap
+   // (to send data out). So, use unsafe?
+   let players = lobby.player_profiles();
+   // Turn numbers are contiguous now, so move any closed seats out of their way
+   let num_closed = lobby.closed_seats.len() as u8;
+   lobby.closed_seats = (lobby.max_players - num_closed..lobby.max_players).collect();
+   // Only now that everyone has their seat does the deadline go by the right player's turn timer
+   let public_gs = journaled_public_state(
+      lobby.game.as_ref().unwrap(),
+      &lobby.players,
+      lobby.turn_timer,
+      &lobby.journal,
+   );
+   let public_gs_bytes = lobby.journal.record(
+      LobbyEventKind::Play,
+      &PalaceOutMessage::PublicGameStateEvent(&public_gs),
+   );
 
    // Send out game start events
    for player in lobby.players.values_mut() {
       match player.connection {
-         Connection::Connected(ref mut sender) => {
-            let _ = serialize_and_send(
-               sender,
+         Connection::Connected(ref mut senders) => {
+            let _ = serialize_and_send_all(
+               senders,
                &PalaceOutMessage::GameStartEvent(GameStartEvent {
                   hand: lobby.game.as_ref().unwrap().get_hand(player.turn_number),
                   turn_number: player.turn_number,
                   players: &players,
                }),
             );
-            let _ = serialize_and_send(sender, &PalaceOutMessage::PublicGameStateEvent(&public_gs));
+            let _ = send_serialized_all(senders, public_gs_bytes);
          }
          Connection::Disconnected(_) => (),
          Connection::Ai(ref mut ai) => {
+            ai.core
+               .reseed(ai::seat_seed(lobby.game.as_ref().unwrap(), player.turn_number));
             ai.core.on_game_start(GameStartEvent {
                hand: lobby.game.as_ref().unwrap().get_hand(player.turn_number),
                turn_number: player.turn_number,
@@ -806,247 +3270,1393 @@ fn start_game(lobby: &mut Lobby) {
          &PalaceOutMessage::SpectateGameStartEvent(SpectateGameStartEvent { players: &players }),
       );
    }
+   notify_active_player(lobby.game.as_ref().unwrap(), &mut lobby.players, lobby.turn_timer);
 }
 
-fn update_connected_player_info(
-   connected_user: &mut Option<ConnectedUser>,
-   lobbies: &mut HashMap<LobbyId, Lobby>,
-   new_connection: ConnectedUser,
+/// Mid-game the seat is kept for a reconnect. Otherwise the player leaves, closing the lobby if they owned it
+fn leave_seat(
+   old_lobby_id: &LobbyId,
+   old_player_id: &PlayerId,
+   lobbies: &mut IndexMap<LobbyId, Lobby>,
    our_sender_id: u32,
 ) {
-   if let Some(ref connected_user_details) = connected_user {
-      disconnect_old_player(connected_user_details, lobbies, our_sender_id);
-   }
-
-   *connected_user = Some(new_connection);
-}
-
-fn disconnect_old_player(connected_user: &ConnectedUser, lobbies: &mut HashMap<LobbyId, Lobby>, our_sender_id: u32) {
-   match connected_user {
-      ConnectedUser::Player((old_lobby_id, old_player_id)) => {
-         if let Some(old_lobby) = lobbies.get_mut(old_lobby_id) {
-            if old_lobby.game.is_none() {
-               if old_lobby.owner == *old_player_id {
-                  for (_, old_player) in old_lobby.players.iter_mut().filter(|(id, _)| *id != old_player_id) {
-                     match old_player.connection {
-                        Connection::Connected(ref mut sender) => {
-                           let _ = serialize_and_send(
-                              sender,
-                              &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::OwnerLeft),
-                           );
-                        }
-                        Connection::Disconnected(_) => (),
-                        Connection::Ai(_) => (),
-                     }
-                  }
-                  for sender in &mut old_lobby.spectators {
-                     let _ = serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::OwnerLeft));
-                  }
-                  lobbies.remove(old_lobby_id);
-               } else {
-                  remove_player(*old_player_id, old_lobby, None);
-               }
-            } else if let Some(old_player) = old_lobby.players.get_mut(old_player_id) {
-               old_player.connection = Connection::Disconnected(DisconnectedState {
-                  time: Instant::now(),
-                  reason: DisconnectedReason::Left,
-               });
+   if let Some(old_lobby) = lobbies.get_mut(old_lobby_id) {
+      if let Some(old_player) = old_lobby.players.get_mut(old_player_id) {
+         if let Connection::Connected(ref mut senders) = old_player.connection {
+            senders.retain(|x| x.connection_id() != our_sender_id);
+            if !senders.is_empty() {
+               // Another device is still attached to this player, so they haven't left
+               return;
             }
          }
       }
-      ConnectedUser::Spectator(old_lobby_id) => {
-         if let Some(old_lobby) = lobbies.get_mut(old_lobby_id) {
-            old_lobby.spectators.retain(|x| x.connection_id() != our_sender_id);
 
-            for player in old_lobby.players.values_mut() {
-               match player.connection {
-                  Connection::Connected(ref mut sender) => {
-                     let _ = serialize_and_send(sender, &PalaceOutMessage::SpectatorLeaveEvent(()));
+      if old_lobby.game.is_none() {
+         if old_lobby.owner == *old_player_id {
+            for (_, old_player) in old_lobby.players.iter_mut().filter(|(id, _)| *id != old_player_id) {
+               match old_player.connection {
+                  Connection::Connected(ref mut senders) => {
+                     let _ =
+                        serialize_and_send_all(senders, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::OwnerLeft));
                   }
                   Connection::Disconnected(_) => (),
                   Connection::Ai(_) => (),
                }
             }
             for sender in &mut old_lobby.spectators {
-               let _ = serialize_and_send(sender, &PalaceOutMessage::SpectatorLeaveEvent(()));
+               let _ = serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::OwnerLeft));
+            }
+            lobbies.shift_remove(old_lobby_id);
+         } else {
+            remove_player(*old_player_id, old_lobby, None);
+         }
+      } else if let Some(old_player) = old_lobby.players.get_mut(old_player_id) {
+         // Already let go, perhaps for being unreachable, or kicked
+         if let Connection::Disconnected(_) = old_player.connection {
+            return;
+         }
+         old_player.connection = Connection::Disconnected(DisconnectedState {
+            time: Instant::now(),
+            reason: DisconnectedReason::Left,
+         });
+         let slot = old_player.turn_number;
+         broadcast_connection_status(
+            &mut old_lobby.players,
+            &mut old_lobby.spectators,
+            slot,
+            ConnectionStatus::Disconnected,
+         );
+      }
+   }
+}
+
+fn stop_observing(lobby_id: &LobbyId, lobbies: &mut IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+   if let Some(lobby) = lobbies.get_mut(lobby_id) {
+      lobby
+         .journal
+         .observers
+         .retain(|x| x.sender.connection_id() != our_sender_id);
+   }
+}
+
+fn leave_spectating(old_lobby_id: &LobbyId, lobbies: &mut IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+   if let Some(old_lobby) = lobbies.get_mut(old_lobby_id) {
+      old_lobby.spectators.retain(|x| x.connection_id() != our_sender_id);
+
+      for player in old_lobby.players.values_mut() {
+         match player.connection {
+            Connection::Connected(ref mut senders) => {
+               let _ = serialize_and_send_all(senders, &PalaceOutMessage::SpectatorLeaveEvent(()));
             }
+            Connection::Disconnected(_) => (),
+            Connection::Ai(_) => (),
          }
       }
+      for sender in &mut old_lobby.spectators {
+         let _ = serialize_and_send(sender, &PalaceOutMessage::SpectatorLeaveEvent(()));
+      }
+   }
+}
+
+fn leave_queue(lobby_id: &LobbyId, lobbies: &mut IndexMap<LobbyId, Lobby>, our_sender_id: u32) {
+   if let Some(lobby) = lobbies.get_mut(lobby_id) {
+      let before = lobby.seat_queue.len();
+      lobby.seat_queue.retain(|x| x.sender.connection_id() != our_sender_id);
+      if lobby.seat_queue.len() != before {
+         send_queue_positions(lobby);
+      }
+   }
+}
+
+fn send_queue_positions(lobby: &mut Lobby) {
+   for (i, queued) in lobby.seat_queue.iter_mut().enumerate() {
+      let _ = serialize_and_send(
+         &mut queued.sender,
+         &PalaceOutMessage::QueuePositionEvent(QueuePositionEvent { position: i as u16 + 1 }),
+      );
+   }
+}
+
+/// Seats whoever is waiting, for as long as there are open seats and the game hasn't started
+fn grant_queued_seats(lobby: &mut Lobby) {
+   let mut granted = false;
+   while lobby.game.is_none() && (lobby.players.len() as u8) < lobby.open_seats() {
+      let mut queued = match lobby.seat_queue.pop_front() {
+         Some(queued) => queued,
+         None => break,
+      };
+      // the variant on its own isn't general enough over the response's lifetime
+      #[allow(clippy::redundant_closure)]
+      seat_new_player(&mut queued.sender, lobby, queued.name, |r| {
+         PalaceOutMessage::SeatGrantedEvent(r)
+      });
+      granted = true;
+   }
+   if granted {
+      send_queue_positions(lobby);
+   }
+}
+
+/// Opens an event's lobby, owned by the first registrant who isn't busy in another lobby. Those after them are
+/// seated or queued in the order they registered
+fn open_event(
+   lobbies: &mut IndexMap<LobbyId, Lobby>,
+   event: &ScheduledEvent,
+   registrants: Vec<Registrant>,
+) -> Option<LobbyId> {
+   let busy: HashSet<u32> = lobbies
+      .values()
+      .flat_map(|lobby| {
+         let seated = lobby.players.values().filter_map(|x| match x.connection {
+            Connection::Connected(ref senders) => Some(senders.iter().map(Sender::connection_id)),
+            _ => None,
+         });
+         let queued = lobby.seat_queue.iter().map(|x| x.sender.connection_id());
+         seated.flatten().chain(queued)
+      })
+      .collect();
+   let mut registrants = registrants
+      .into_iter()
+      .filter(|x| !busy.contains(&x.sender.connection_id()));
+   let mut owner = registrants.next()?;
+   let (lobby_id, player_id) = create_lobby(
+      lobbies,
+      Connection::Connected(vec![owner.sender.clone()]),
+      NewLobbyMessage {
+         max_players: event.max_players,
+         password: "".into(),
+         lobby_name: event.name.clone(),
+         player_name: owner.name,
+         turn_timer: event.turn_timer,
+         min_human_turn_timer: 0,
+         description: event.description.clone(),
+         after_game: AfterGamePolicy::Persist,
+         hints: HintPolicy::Disabled,
+         practice: false,
+         scenario: None,
+         draw_rules: DrawRules::default(),
+      },
+   );
+   let lobby = lobbies.get_mut(&lobby_id).unwrap();
+   lobby.event = Some(event.name.clone());
+
+   let opened = PalaceOutMessage::EventOpenedEvent(EventOpenedEvent {
+      event: &event.name,
+      lobby_id,
+   });
+   let _ = serialize_and_send(&mut owner.sender, &opened);
+   let _ = serialize_and_send(
+      &mut owner.sender,
+      &PalaceOutMessage::SeatGrantedEvent(JoinLobbyResponse {
+         player_id,
+         slot: lobby.players[&player_id].turn_number,
+         lobby_players: lobby.seats(),
+         max_players: lobby.max_players,
+         num_spectators: 0,
+         turn_timer: lobby.turn_timer.limit.as_secs() as u16,
+         min_human_turn_timer: 0,
+         journal_seq: lobby.journal.latest_seq(),
+      }),
+   );
+   send_lobby_info(&mut owner.sender, lobby);
+   for mut registrant in registrants {
+      let _ = serialize_and_send(&mut registrant.sender, &opened);
+      lobby.seat_queue.push_back(QueuedPlayer {
+         name: registrant.name,
+         sender: registrant.sender,
+      });
+   }
+   grant_queued_seats(lobby);
+   Some(lobby_id)
+}
+
+/// Lets everyone waiting for a seat know they won't get one
+fn close_seat_queue(lobby: &mut Lobby, event: LobbyCloseEvent) {
+   for mut queued in lobby.seat_queue.drain(..) {
+      let _ = serialize_and_send(&mut queued.sender, &PalaceOutMessage::LobbyCloseEvent(event));
+   }
+}
+
+/// Settles everything a play by `slot` changes in the lobby, and only then tells everyone about it,
+/// so that a connection failing partway through the telling can't leave the lobby half updated.
+/// `gs_before` is the state before the play, which is only kept when there's a commentator
+fn after_play(
+   lobby: &mut Lobby,
+   slot: u8,
+   outcome: PlayOutcome,
+   commentator: Option<&dyn Commentator>,
+   gs_before: Option<GameState>,
+   archive: &GameArchive,
+   max_game_turns: Option<u32>,
+) {
+   lobby.skip_turn_votes.clear();
+   let commentary = comment_on_turn(lobby, commentator, gs_before, outcome);
+   let predictions = match lobby.game {
+      Some(ref gs) => lobby.predictions.score(gs),
+      None => None,
+   };
+   let finished = if outcome.game_over {
+      Some(finish_game(lobby, archive))
+   } else {
+      None
+   };
+
+   let gs = match (&finished, &lobby.game) {
+      (Some(finished), _) => &finished.gs,
+      (None, Some(gs)) => gs,
+      (None, None) => return,
+   };
+   report_take_turn(
+      gs,
+      &mut lobby.players,
+      &mut lobby.spectators,
+      &mut lobby.journal,
+      slot,
+      outcome,
+      lobby.turn_timer,
+   );
+   if let Some(commentary) = commentary {
+      for sender in &mut lobby.spectators {
+         let _ = serialize_and_send(sender, &PalaceOutMessage::CommentaryEvent(&commentary));
+      }
+   }
+   if let Some((results, scores)) = predictions {
+      report_predictions(lobby, results, scores);
+   }
+   if let Some(finished) = finished {
+      report_game_end(lobby, &finished);
+   } else if let Some(reason) = lobby.draw_reason(max_game_turns) {
+      draw_game(lobby, reason);
+   }
+}
+
+/// Tells everyone how the spectators guessed at the play just made, and each guesser how they did
+fn report_predictions(lobby: &mut Lobby, results: PredictionResultsEvent, scores: Vec<(u32, PredictionScoreEvent)>) {
+   broadcast(
+      &mut lobby.players,
+      &mut lobby.spectators,
+      &PalaceOutMessage::PredictionResultsEvent(results),
+   );
+   for (connection_id, score) in scores {
+      // Spectators who left since guessing have nobody to tell
+      if let Some(sender) = lobby.spectators.iter_mut().find(|x| x.connection_id() == connection_id) {
+         let _ = serialize_and_send(sender, &PalaceOutMessage::PredictionScoreEvent(score));
+      }
    }
 }
 
+/// Has the commentator remark on the play just taken, keeping anything it says for spectators to be told
+fn comment_on_turn(
+   lobby: &mut Lobby,
+   commentator: Option<&dyn Commentator>,
+   gs_before: Option<GameState>,
+   outcome: PlayOutcome,
+) -> Option<Commentary> {
+   let (commentator, gs_before, gs) = match (commentator, gs_before, &lobby.game) {
+      (Some(commentator), Some(gs_before), Some(gs)) => (commentator, gs_before, gs),
+      _ => return None,
+   };
+   let player = gs_before.active_player;
+   let player_name = match lobby.players_by_turn_num.get(&player) {
+      Some(id) => lobby.players[id].name.clone(),
+      None => format!("Seat {}", player + 1),
+   };
+   // Setup doesn't put anything on the pile, so the last cards played are from before
+   let cards: &[Card] = match gs_before.cur_phase {
+      Phase::Setup => &[],
+      Phase::Play => &gs.last_cards_played,
+   };
+   let play = Play {
+      player,
+      player_name: &player_name,
+      cards,
+      outcome,
+   };
+   let commentary = Commentary {
+      turn: gs.turns_taken,
+      player,
+      text: commentator.on_turn(&gs_before, &play, gs)?,
+   };
+   lobby.commentary.push(commentary.clone());
+   Some(commentary)
+}
+
 fn report_take_turn(
    gs: &GameState,
    players: &mut HashMap<PlayerId, Player>,
    spectators: &mut [Sender],
-   id_of_last_player: PlayerId,
+   journal: &mut Journal,
+   last_turn_number: u8,
+   outcome: PlayOutcome,
+   turn_timer: TurnTimer,
 ) {
-   let public_gs = gs.public_state();
-   for (id, player) in players {
+   let public_gs = journaled_public_state(gs, players, turn_timer, journal);
+   // Everyone sees the same state, so it only needs encoding once per play
+   let public_gs_bytes = journal.record(
+      LobbyEventKind::Play,
+      &PalaceOutMessage::PublicGameStateEvent(&public_gs),
+   );
+   for player in players.values_mut() {
       match player.connection {
-         Connection::Connected(ref mut sender) => {
-            if *id == id_of_last_player {
-               let _ = serialize_and_send(sender, &PalaceOutMessage::HandEvent(gs.get_hand(player.turn_number)));
+         Connection::Connected(ref mut senders) => {
+            if player.turn_number == last_turn_number {
+               let _ = send_hand(senders, gs.get_hand(player.turn_number));
             }
-            let _ = serialize_and_send(sender, &PalaceOutMessage::PublicGameStateEvent(&public_gs));
+            let _ = send_serialized_all(senders, public_gs_bytes);
          }
          Connection::Disconnected(_) => (),
          Connection::Ai(ref mut ai) => {
-            if *id == id_of_last_player {
+            if player.turn_number == last_turn_number {
                ai.core.on_hand_update(gs.get_hand(player.turn_number));
+            } else if let Some(zone) = gs.last_played_zone {
+               ai.core
+                  .on_opponent_play(last_turn_number, &gs.last_cards_played, zone, outcome.picked_up);
             }
             ai.core.on_game_state_update(&public_gs);
          }
       }
    }
+   let highlights_bytes: Vec<Vec<u8>> = gs
+      .last_turn_highlights()
+      .iter()
+      .map(|x| serialize_once(&PalaceOutMessage::HighlightEvent(*x)))
+      .collect();
    for sender in spectators {
-      let _ = serialize_and_send(sender, &PalaceOutMessage::PublicGameStateEvent(&public_gs));
+      let _ = send_serialized(sender, public_gs_bytes.to_vec());
+      for bytes in &highlights_bytes {
+         let _ = send_serialized(sender, bytes.clone());
+      }
    }
+   notify_active_player(gs, players, turn_timer);
 }
 
-fn add_player(new_player: Player, player_id: PlayerId, lobby: &mut Lobby) {
-   let new_player_name = new_player.name.clone();
+/// Sends a player their hand, in whichever shape each of their connections expects
+fn send_hand(senders: &mut [Sender], hand: &[Card]) -> ws::Result<()> {
+   let legacy_connections = LEGACY_CONNECTIONS.read().unwrap();
+   let mut result = Ok(());
+   for sender in senders {
+      let sent = if legacy_connections.contains(&sender.connection_id()) {
+         let event = LegacyOutMessage::HandEvent(LegacyHandEvent { hand });
+         send_serialized(sender, serde_json::to_vec(&event).unwrap())
+      } else {
+         serialize_and_send(sender, &PalaceOutMessage::HandEvent(hand))
+      };
+      if let Err(e) = sent {
+         result = Err(e);
+      }
+   }
+   result
+}
 
-   let turn_number = new_player.turn_number;
-   lobby.players_by_turn_num.insert(turn_number, player_id);
-   lobby.players.insert(player_id, new_player);
+/// Seconds left for the active player to take their turn, if the lobby has a turn timer
+fn turn_deadline(gs: &GameState, players: &HashMap<PlayerId, Player>, turn_timer: TurnTimer) -> Option<u16> {
+   if !turn_timer.is_enabled() {
+      return None;
+   }
+   let limit = turn_timer.for_slot(players, gs.active_player);
+   let remaining = limit.checked_sub(gs.last_turn_start.elapsed()).unwrap_or_default();
+   Some(remaining.as_secs() as u16)
+}
 
-   let new_num_players = lobby.players.len() as u8;
-   for (_, player) in lobby.players.iter_mut().filter(|(id, _)| **id != player_id) {
+/// The public state as clients see it, with the countdown for the active player
+fn public_state_with_deadline<'a>(
+   gs: &'a GameState,
+   players: &HashMap<PlayerId, Player>,
+   turn_timer: TurnTimer,
+) -> PublicGameState<'a> {
+   PublicGameState {
+      turn_deadline: turn_deadline(gs, players, turn_timer),
+      ..gs.public_state()
+   }
+}
+
+/// The public state to send everyone in the lobby, carrying the seq it's about to be journaled with
+fn journaled_public_state<'a>(
+   gs: &'a GameState,
+   players: &HashMap<PlayerId, Player>,
+   turn_timer: TurnTimer,
+   journal: &Journal,
+) -> PublicGameState<'a> {
+   PublicGameState {
+      journal_seq: Some(journal.next_seq()),
+      ..public_state_with_deadline(gs, players, turn_timer)
+   }
+}
+
+/// Tells whoever has to play next that it's their turn, unless the game is over
+fn notify_active_player(gs: &GameState, players: &mut HashMap<PlayerId, Player>, turn_timer: TurnTimer) {
+   if gs.out_players.len() as u8 == gs.num_players {
+      return;
+   }
+   let event = PalaceOutMessage::YourTurnEvent(YourTurnEvent {
+      deadline: turn_deadline(gs, players, turn_timer),
+   });
+   for player in players.values_mut().filter(|x| x.turn_number == gs.active_player) {
       match player.connection {
-         Connection::Connected(ref mut sender) => {
-            let _ = serialize_and_send(
-               sender,
-               &PalaceOutMessage::PlayerJoinEvent(PlayerJoinEvent {
-                  total_num_players: new_num_players,
-                  new_player_name: &new_player_name,
-                  slot: turn_number,
-               }),
-            );
+         Connection::Connected(ref mut senders) => {
+            let _ = serialize_and_send_all(senders, &event);
+            // So there's a fresh estimate of their round trip by the time they might be warned
+            ping_all(senders);
          }
          Connection::Disconnected(_) => (),
-         Connection::Ai(_) => (),
+         Connection::Ai(ref mut ai) => ai.core.on_your_turn(),
       }
    }
-   for sender in &mut lobby.spectators {
-      let _ = serialize_and_send(
-         sender,
-         &PalaceOutMessage::PlayerJoinEvent(PlayerJoinEvent {
-            total_num_players: new_num_players,
-            new_player_name: &new_player_name,
-            slot: turn_number,
-         }),
-      );
-   }
 }
 
-/// This REMOVES players (from the turn order.) Not meant for game in progress
-fn remove_player(old_player_id: PlayerId, lobby: &mut Lobby, opt_event: Option<LobbyCloseEvent>) {
-   let old_player_opt = lobby.players.remove(&old_player_id);
-
-   if let Some(mut old_player) = old_player_opt {
-      lobby.players_by_turn_num.remove(&old_player.turn_number);
+/// Sends a message to every connected player and spectator
+fn broadcast(players: &mut HashMap<PlayerId, Player>, spectators: &mut [Sender], message: &PalaceOutMessage) {
+   broadcast_serialized(players, spectators, &serialize_once(message));
+}
 
-      if let Some(event) = opt_event {
-         match old_player.connection {
-            Connection::Connected(ref mut sender) => {
-               let _ = serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(event));
-            }
-            Connection::Disconnected(_) => (),
-            Connection::Ai(_) => (),
+fn broadcast_serialized(players: &mut HashMap<PlayerId, Player>, spectators: &mut [Sender], bytes: &[u8]) {
+   for player in players.values_mut() {
+      match player.connection {
+         Connection::Connected(ref mut senders) => {
+            let _ = send_serialized_all(senders, bytes);
          }
+         Connection::Disconnected(_) => (),
+         Connection::Ai(_) => (),
       }
+   }
+   for sender in spectators {
+      let _ = send_serialized(sender, bytes.to_vec());
+   }
+}
 
-      let new_num_players = lobby.players.len() as u8;
-      for player in lobby.players.values_mut() {
-         match player.connection {
-            Connection::Connected(ref mut sender) => {
-               let _ = serialize_and_send(
-                  sender,
-                  &PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
-                     total_num_players: new_num_players,
-                     slot: old_player.turn_number,
-                  }),
-               );
-            }
-            Connection::Disconnected(_) => (),
-            Connection::Ai(_) => (),
-         }
+fn broadcast_connection_status(
+   players: &mut HashMap<PlayerId, Player>,
+   spectators: &mut [Sender],
+   slot: u8,
+   status: ConnectionStatus,
+) {
+   broadcast(
+      players,
+      spectators,
+      &PalaceOutMessage::PlayerConnectionStatusEvent(PlayerConnectionStatusEvent { slot, status }),
+   );
+}
+
+fn broadcast_presence(
+   players: &mut HashMap<PlayerId, Player>,
+   spectators: &mut [Sender],
+   slot: u8,
+   presence: Presence,
+) {
+   broadcast(
+      players,
+      spectators,
+      &PalaceOutMessage::PlayerPresenceEvent(PlayerPresenceEvent { slot, presence }),
+   );
+}
+
+/// Lets the lobby know about connected players that have gone quiet, or that have since been heard from on a
+/// device that wasn't told they were idle
+fn mark_idle_players(lobby: &mut Lobby, idle_after: Duration) {
+   let mut changed = Vec::new();
+   for player in lobby.players.values_mut() {
+      let senders = match player.connection {
+         Connection::Connected(ref senders) => senders,
+         _ => continue,
+      };
+      let quiet_for = senders.iter().map(|x| x.activity.quiet_for()).min().unwrap_or_default();
+      let idle = quiet_for >= idle_after;
+      if idle != player.idle {
+         player.idle = idle;
+         changed.push((player.turn_number, player.presence()));
       }
-      for sender in &mut lobby.spectators {
-         let _ = serialize_and_send(
-            sender,
-            &PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
-               total_num_players: new_num_players,
-               slot: old_player.turn_number,
+      for sender in senders {
+         sender.activity.player_idle.store(idle, Ordering::Relaxed);
+      }
+   }
+   for (slot, presence) in changed {
+      broadcast_presence(&mut lobby.players, &mut lobby.spectators, slot, presence);
+   }
+}
+
+/// Mid-game, stops sending to connections that keep failing to take messages. Players left without a connection
+/// are disconnected, so everyone else hears about it and the player can reconnect as usual
+fn disconnect_unreachable_players(lobby: &mut Lobby) {
+   if lobby.game.is_none() {
+      return;
+   }
+   let mut newly_disconnected = Vec::new();
+   for player in lobby.players.values_mut() {
+      if let Connection::Connected(ref mut senders) = player.connection {
+         let (mut unreachable, reachable) = senders.drain(..).partition(|x| is_unreachable(x.connection_id()));
+         *senders = reachable;
+         disconnect_all(
+            &mut unreachable,
+            DisconnectCode::Unreachable,
+            "Too many messages failed to send",
+         );
+         if senders.is_empty() {
+            player.connection = Connection::Disconnected(DisconnectedState {
+               time: Instant::now(),
+               reason: DisconnectedReason::Left,
+            });
+            newly_disconnected.push(player.turn_number);
+         }
+      }
+   }
+   for slot in newly_disconnected {
+      broadcast_connection_status(
+         &mut lobby.players,
+         &mut lobby.spectators,
+         slot,
+         ConnectionStatus::Disconnected,
+      );
+   }
+}
+
+/// Frees the seats of players who didn't reconnect in time. The seats are then played by the AI loop,
+/// until the owner requests AI for them. The owner is never evicted, since the lobby needs one
+fn evict_disconnected_players(lobby: &mut Lobby) {
+   if lobby.game.is_none() {
+      return;
+   }
+   let evicted: Vec<PlayerId> = lobby
+      .players
+      .iter()
+      .filter(|(id, p)| match &p.connection {
+         Connection::Disconnected(ds) => {
+            **id != lobby.owner && ds.time.elapsed() >= Duration::from_secs(RECONNECT_WINDOW_SECS)
+         }
+         _ => false,
+      })
+      .map(|(id, _)| *id)
+      .collect();
+   for id in evicted {
+      remove_player(id, lobby, None);
+   }
+}
+
+/// True if the game has humans, and none of them are connected. Kicked players don't count as humans
+fn all_humans_disconnected(players: &HashMap<PlayerId, Player>) -> bool {
+   let mut humans = players
+      .values()
+      .filter(|p| match &p.connection {
+         Connection::Connected(_) => true,
+         Connection::Disconnected(ds) => ds.reason != DisconnectedReason::Kicked,
+         Connection::Ai(_) => false,
+      })
+      .peekable();
+   humans.peek().is_some() && humans.all(|p| !matches!(p.connection, Connection::Connected(_)))
+}
+
+/// Holds the turn timer while every human is disconnected, which is most likely a network blip on our end, so
+/// that their turns aren't played out for them. The time spent paused isn't counted against the turn. True while
+/// paused
+fn pause_turn_timer_while_abandoned(
+   players: &HashMap<PlayerId, Player>,
+   paused_at: &mut Option<Instant>,
+   gs: &mut GameState,
+) -> bool {
+   if all_humans_disconnected(players) {
+      paused_at.get_or_insert_with(Instant::now);
+      return true;
+   }
+   if let Some(paused_at) = paused_at.take() {
+      gs.last_turn_start += paused_at.elapsed();
+   }
+   false
+}
+
+/// Seats an AI the owner asked for
+fn add_requested_ai(lobby: &mut Lobby, core: Box<dyn PalaceAi + Send + Sync>, turn_number: u8) {
+   let player_id = PlayerId(rand::random());
+   add_player(
+      Player {
+         name: ai::get_bot_name(),
+         connection: Connection::Ai(AiState {
+            core,
+            is_clandestine: false,
+         }),
+         turn_number,
+         idle: false,
+         last_play_seq: None,
+         hints_used: 0,
+         last_hint: None,
+         rating: None,
+      },
+      player_id,
+      lobby,
+   );
+}
+
+fn check_player_name(player_name: &str) -> Result<(), JoinLobbyError> {
+   if player_name.is_empty() {
+      return Err(JoinLobbyError::EmptyPlayerName);
+   }
+
+   if player_name.len() > PLAYER_NAME_LIMIT {
+      return Err(JoinLobbyError::PlayerNameTooLong);
+   }
+
+   Ok(())
+}
+
+fn check_can_join(lobby: &Lobby, password: &str) -> Result<(), JoinLobbyError> {
+   if lobby.practice {
+      return Err(JoinLobbyError::PracticeLobby);
+   }
+
+   if lobby.game.is_some() {
+      return Err(JoinLobbyError::GameInProgress);
+   }
+
+   if lobby.password != password {
+      return Err(JoinLobbyError::BadPassword);
+   }
+
+   if lobby.players.len() as u8 >= lobby.open_seats() {
+      return Err(JoinLobbyError::LobbyFull);
+   }
+
+   Ok(())
+}
+
+/// Disconnected players stay behind, since they'd come back to the lobby they left
+fn moves_in_merge(player: &Player) -> bool {
+   !matches!(player.connection, Connection::Disconnected(_))
+}
+
+fn check_can_merge(from: &Lobby, into: &Lobby) -> Result<(), MergeLobbyError> {
+   if from.practice || into.practice || from.scenario.is_some() || into.scenario.is_some() {
+      return Err(MergeLobbyError::NotMergeable);
+   }
+   if from.game.is_some() || into.game.is_some() {
+      return Err(MergeLobbyError::GameInProgress);
+   }
+   let moving = from.players.values().filter(|x| moves_in_merge(x)).count();
+   if into.players.len() + moving > usize::from(into.open_seats()) {
+      return Err(MergeLobbyError::NotEnoughSeats);
+   }
+   Ok(())
+}
+
+/// Seats everyone who moves from `from` in `into`, which passed `check_can_merge`. Anyone else in `from` hears
+/// that it has closed
+fn merge_lobbies(mut from: Lobby, into_id: LobbyId, into: &mut Lobby) {
+   let moving: Vec<PlayerId> = from
+      .players
+      .iter()
+      .filter(|(_, x)| moves_in_merge(x))
+      .map(|(id, _)| *id)
+      .collect();
+   for player_id in moving {
+      let mut player = from.players.remove(&player_id).unwrap();
+      from.players_by_turn_num.remove(&player.turn_number);
+      player.turn_number = into.next_open_slot();
+      player.last_play_seq = None;
+      if let Connection::Connected(ref mut senders) = player.connection {
+         // A lobby we're seated in is never also spectated
+         into
+            .spectators
+            .retain(|x| senders.iter().all(|y| y.connection_id() != x.connection_id()));
+         let _ = serialize_and_send_all(
+            senders,
+            &PalaceOutMessage::LobbyMergedEvent(LobbyMergedEvent {
+               lobby_id: into_id,
+               slot: player.turn_number,
+               lobby: JoinLobbyResponse {
+                  player_id,
+                  slot: player.turn_number,
+                  lobby_players: into.seats(),
+                  max_players: into.max_players,
+                  num_spectators: into.spectators.len() as u16,
+                  turn_timer: into.turn_timer.limit.as_secs() as u16,
+                  min_human_turn_timer: into.turn_timer.min_human.as_secs() as u16,
+                  journal_seq: into.journal.latest_seq(),
+               },
             }),
          );
+         for sender in senders.iter_mut() {
+            send_lobby_info(sender, into);
+         }
       }
+      add_player(player, player_id, into);
    }
+   broadcast(
+      &mut from.players,
+      &mut from.spectators,
+      &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Merged),
+   );
+   close_seat_queue(&mut from, LobbyCloseEvent::Merged);
 }
 
-fn serialize_and_send(s: &mut Sender, message: &PalaceOutMessage) -> ws::Result<()> {
+/// Adds a human on this connection to a lobby that passed `check_can_join`.
+/// They get `respond`'s message and the lobby info first, and everyone else then hears about the join
+fn seat_new_player<F>(out: &mut Sender, lobby: &mut Lobby, player_name: String, respond: F) -> PlayerId
+where
+   F: FnOnce(JoinLobbyResponse) -> PalaceOutMessage,
+{
+   let player_id = PlayerId(rand::random());
+   let slot = lobby.next_open_slot();
+
+   let _ = serialize_and_send(
+      out,
+      &respond(JoinLobbyResponse {
+         player_id,
+         slot,
+         lobby_players: lobby.seats(),
+         max_players: lobby.max_players,
+         num_spectators: lobby.spectators.len() as u16,
+         turn_timer: lobby.turn_timer.limit.as_secs() as u16,
+         min_human_turn_timer: lobby.turn_timer.min_human.as_secs() as u16,
+         journal_seq: lobby.journal.latest_seq(),
+      }),
+   );
+
+   send_lobby_info(out, lobby);
+
+   add_player(
+      Player {
+         name: player_name,
+         connection: Connection::Connected(vec![out.clone()]),
+         turn_number: slot,
+         idle: false,
+         last_play_seq: None,
+         hints_used: 0,
+         last_hint: None,
+         rating: None,
+      },
+      player_id,
+      lobby,
+   );
+
+   player_id
+}
+
+/// Moves a player to a new id. Their other connections only know the old one, so they're detached
+fn rotate_player_id(lobby: &mut Lobby, old_id: PlayerId, new_id: PlayerId, our_sender_id: u32) {
+   let mut player = lobby.players.remove(&old_id).unwrap();
+   if let Connection::Connected(ref mut senders) = player.connection {
+      for sender in senders.iter_mut().filter(|x| x.connection_id() != our_sender_id) {
+         let _ = serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::IdRotated));
+      }
+      senders.retain(|x| x.connection_id() == our_sender_id);
+   }
+   lobby.players_by_turn_num.insert(player.turn_number, new_id);
+   lobby.players.insert(new_id, player);
+   if lobby.owner == old_id {
+      lobby.owner = new_id;
+   }
+   if lobby.abort_votes.remove(&old_id) {
+      lobby.abort_votes.insert(new_id);
+   }
+   if lobby.skip_turn_votes.remove(&old_id) {
+      lobby.skip_turn_votes.insert(new_id);
+   }
+   if let Some(attempts) = lobby.reconnect_attempts.remove(&old_id) {
+      lobby.reconnect_attempts.insert(new_id, attempts);
+   }
+   if let Some(ref mut recording) = lobby.last_game {
+      let recording = Arc::make_mut(recording);
+      if let Some(slot) = recording.seats.remove(&old_id) {
+         recording.seats.insert(new_id, slot);
+      }
+   }
+}
+
+fn add_player(new_player: Player, player_id: PlayerId, lobby: &mut Lobby) {
+   let new_player_name = new_player.name.clone();
+
+   let turn_number = new_player.turn_number;
+   lobby.players_by_turn_num.insert(turn_number, player_id);
+   lobby.players.insert(player_id, new_player);
+   lobby.stats.total_players_seen += 1;
+
+   let new_num_players = lobby.players.len() as u8;
+   let event = PalaceOutMessage::PlayerJoinEvent(PlayerJoinEvent {
+      total_num_players: new_num_players,
+      new_player_name: &new_player_name,
+      slot: turn_number,
+      seq: lobby.journal.next_seq(),
+   });
+   let bytes = lobby.journal.record(LobbyEventKind::Join, &event);
+   for (_, player) in lobby.players.iter_mut().filter(|(id, _)| **id != player_id) {
+      match player.connection {
+         Connection::Connected(ref mut senders) => {
+            let _ = send_serialized_all(senders, bytes);
+         }
+         Connection::Disconnected(_) => (),
+         Connection::Ai(_) => (),
+      }
+   }
+   for sender in &mut lobby.spectators {
+      let _ = send_serialized(sender, bytes.to_vec());
+   }
+}
+
+/// This REMOVES players (from the turn order.) Not meant for game in progress
+fn remove_player(old_player_id: PlayerId, lobby: &mut Lobby, opt_event: Option<LobbyCloseEvent>) {
+   let old_player_opt = lobby.players.remove(&old_player_id);
+
+   if let Some(mut old_player) = old_player_opt {
+      lobby.players_by_turn_num.remove(&old_player.turn_number);
+
+      if let Some(event) = opt_event {
+         match old_player.connection {
+            Connection::Connected(ref mut senders) => {
+               let _ = serialize_and_send_all(senders, &PalaceOutMessage::LobbyCloseEvent(event));
+               if matches!(event, LobbyCloseEvent::Kicked) {
+                  disconnect_all(senders, DisconnectCode::Kicked, "Kicked by the lobby owner");
+               }
+            }
+            Connection::Disconnected(_) => (),
+            Connection::Ai(_) => (),
+         }
+      }
+
+      let total_num_players = lobby.players.len() as u8;
+      report_player_leave(lobby, old_player.turn_number, total_num_players);
+
+      grant_queued_seats(lobby);
+   }
+}
+
+fn report_player_leave(lobby: &mut Lobby, slot: u8, total_num_players: u8) {
+   let event = PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
+      total_num_players,
+      slot,
+      seq: lobby.journal.next_seq(),
+   });
+   let bytes = lobby.journal.record(LobbyEventKind::Leave, &event);
+   for player in lobby.players.values_mut() {
+      match player.connection {
+         Connection::Connected(ref mut senders) => {
+            let _ = send_serialized_all(senders, bytes);
+         }
+         Connection::Disconnected(_) => (),
+         Connection::Ai(_) => (),
+      }
+   }
+   for sender in &mut lobby.spectators {
+      let _ = send_serialized(sender, bytes.to_vec());
+   }
+}
+
+/// Tells the client why, then closes the connection
+fn disconnect(sender: &mut Sender, code: DisconnectCode, reason: String) -> ws::Result<()> {
+   let _ = serialize_and_send(
+      sender,
+      &PalaceOutMessage::DisconnectEvent(DisconnectEvent { code, reason }),
+   );
+   sender.close(close_code(code))
+}
+
+fn disconnect_all(senders: &mut [Sender], code: DisconnectCode, reason: &str) {
+   for sender in senders {
+      let _ = disconnect(sender, code, reason.into());
+   }
+}
+
+fn close_code(code: DisconnectCode) -> CloseCode {
+   match code {
+      DisconnectCode::ProtocolError => CloseCode::Invalid,
+      DisconnectCode::RateLimited => CloseCode::Again,
+      DisconnectCode::Kicked | DisconnectCode::Afk => CloseCode::Policy,
+      DisconnectCode::ServerDraining => CloseCode::Restart,
+      DisconnectCode::Unreachable => CloseCode::Error,
+   }
+}
+
+/// Sends a message to every device attached to a player
+fn serialize_and_send_all(senders: &mut [Sender], message: &PalaceOutMessage) -> ws::Result<()> {
+   let mut result = Ok(());
+   for sender in senders {
+      if let Err(e) = serialize_and_send(sender, message) {
+         result = Err(e);
+      }
+   }
+   result
+}
+
+/// Encodes a message that's about to go to many connections, so it isn't serialized again for each of them
+fn serialize_once(message: &PalaceOutMessage) -> Vec<u8> {
+   serde_json::to_vec(message).unwrap_or_else(|e| {
+      error!("Failed to serialize a message: {:?}", e);
+      Vec::from("\"InternalServerError\"")
+   })
+}
+
+/// Each connection is still handed its own copy of the bytes, as messages are queued per connection
+fn send_serialized_all(senders: &mut [Sender], bytes: &[u8]) -> ws::Result<()> {
+   let mut result = Ok(());
+   for sender in senders {
+      if let Err(e) = send_serialized(sender, bytes.to_vec()) {
+         result = Err(e);
+      }
+   }
+   result
+}
+
+/// Sends a message to every connection on the server, regardless of lobby or the address they connected to.
+/// This is always a binary frame, as the frame type can't vary per connection.
+fn serialize_and_broadcast(broadcasters: &[ws::Sender], message: &PalaceOutMessage) -> ws::Result<()> {
    match serde_json::to_vec(message) {
       Ok(bytes) => {
-         debug!("Sending bytes (as string) {:?}", String::from_utf8_lossy(&bytes));
-         if let Err(e) = s.send(bytes) {
-            error!("Failed to send a message: {:?}", e);
-            s.send(ws::Message::binary("\"InternalServerError\""))
-         } else {
-            Ok(())
+         debug!("Broadcasting message: {}", redact::redacted_string(&bytes));
+         let mut result = Ok(());
+         for broadcaster in broadcasters {
+            if let Err(e) = broadcaster.broadcast(bytes.clone()) {
+               result = Err(e);
+            }
          }
+         result
       }
       Err(e) => {
          error!("Failed to serialize a message: {:?}", e);
-         s.send(ws::Message::binary("\"InternalServerError\""))
+         Ok(())
       }
    }
 }
 
+/// Wraps an encoded message in whichever kind of frame the connection prefers
+fn frame(s: &Sender, bytes: Vec<u8>) -> Message {
+   if TEXT_FRAME_CONNECTIONS.read().unwrap().contains(&s.connection_id()) {
+      // serde_json only produces valid UTF-8
+      Message::Text(String::from_utf8(bytes).unwrap())
+   } else {
+      Message::Binary(bytes)
+   }
+}
+
+fn send_serialized(s: &mut Sender, bytes: Vec<u8>) -> ws::Result<()> {
+   debug!("Sending message: {}", redact::redacted_string(&bytes));
+   #[cfg(feature = "chaos")]
+   match chaos::roll() {
+      Some(chaos::Fault::Drop) => return Ok(()),
+      Some(chaos::Fault::Delay(delay)) => {
+         let s = s.clone();
+         let message = frame(&s, bytes);
+         std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let _ = s.send(message);
+         });
+         return Ok(());
+      }
+      Some(chaos::Fault::Disconnect) => return s.close(CloseCode::Away),
+      None => (),
+   }
+   let result = if let Err(e) = s.send(frame(s, bytes)) {
+      error!("Failed to send a message: {:?}", e);
+      s.send(frame(s, Vec::from("\"InternalServerError\"")))
+   } else {
+      Ok(())
+   };
+   note_send_result(s.connection_id(), result.is_ok());
+   result
+}
+
+/// Counts failed sends in a row, so that connections that stopped taking messages can be given up on
+fn note_send_result(connection_id: u32, sent: bool) {
+   if sent {
+      if SEND_FAILURES.read().unwrap().contains_key(&connection_id) {
+         SEND_FAILURES.write().unwrap().remove(&connection_id);
+      }
+   } else {
+      *SEND_FAILURES.write().unwrap().entry(connection_id).or_default() += 1;
+   }
+}
+
+fn is_unreachable(connection_id: u32) -> bool {
+   matches!(SEND_FAILURES.read().unwrap().get(&connection_id), Some(n) if *n >= MAX_CONSECUTIVE_SEND_FAILURES)
+}
+
+/// Pings connections that aren't already waiting on one, to keep their round trip estimates fresh
+fn ping_all(senders: &[Sender]) {
+   let now = Instant::now();
+   let mut round_trips = ROUND_TRIPS.write().unwrap();
+   for sender in senders {
+      if round_trips.entry(sender.connection_id()).or_default().ping(now) {
+         let _ = sender.ping();
+      }
+   }
+}
+
+/// The longest estimated round trip to any of a player's connections
+fn round_trip(senders: &[Sender]) -> Duration {
+   let round_trips = ROUND_TRIPS.read().unwrap();
+   senders
+      .iter()
+      .map(|x| round_trips.get(&x.connection_id()).map(|x| x.estimate()))
+      .map(|x| x.unwrap_or_else(|| RoundTrip::default().estimate()))
+      .max()
+      .unwrap_or_default()
+}
+
+fn serialize_and_send(s: &mut Sender, message: &PalaceOutMessage) -> ws::Result<()> {
+   match serde_json::to_vec(message) {
+      Ok(bytes) => send_serialized(s, bytes),
+      Err(e) => {
+         error!("Failed to serialize a message: {:?}", e);
+         s.send(frame(s, Vec::from("\"InternalServerError\"")))
+      }
+   }
+}
+
+fn server_stats(lobbies: &IndexMap<LobbyId, Lobby>, messages_per_sec: f64) -> ServerStatsEvent {
+   let mut stats = ServerStatsEvent {
+      lobbies: lobbies.len() as u64,
+      games_in_progress: 0,
+      players: 0,
+      ai_players: 0,
+      spectators: 0,
+      messages_per_sec,
+      ai_queue_depth: 0,
+   };
+   for lobby in lobbies.values() {
+      stats.players += lobby.players.len() as u64;
+      stats.ai_players += lobby.players.values().filter(|x| x.is_ai()).count() as u64;
+      stats.spectators += lobby.spectators.len() as u64;
+      if let Some(ref gs) = lobby.game {
+         stats.games_in_progress += 1;
+         let ai_to_play = lobby
+            .players_by_turn_num
+            .get(&gs.active_player)
+            .map(|x| lobby.players[x].is_ai())
+            .unwrap_or(false);
+         if ai_to_play {
+            stats.ai_queue_depth += 1;
+         }
+      }
+   }
+   stats
+}
+
+/// A game taken out of its lobby, for telling everyone how it ended
+struct FinishedGame {
+   gs: GameState,
+   /// Players who had disconnected, and so were let go once it ended
+   departed_slots: Vec<u8>,
+   /// When the lobby closes now the game is over, if it's set to
+   closing_secs: Option<u16>,
+}
+
+/// Takes the game out of the lobby and settles everything that depends on its outcome, without telling anyone.
 /// Panics if game is not in progress
-fn end_game(lobby: &mut Lobby) {
+fn finish_game(lobby: &mut Lobby, archive: &GameArchive) -> FinishedGame {
+   let gs = lobby.game.take().unwrap();
+   if archive.is_enabled() {
+      archive.record(&game_record(lobby, &gs));
+   }
+   for (placement, turn_number) in gs.out_players.iter().enumerate() {
+      if let Some(id) = lobby.players_by_turn_num.get(turn_number) {
+         lobby.stats.placements.entry(*id).or_default().push(placement as u8);
+      }
+   }
+   lobby.stats.total_game_time += gs.start_time.elapsed();
+   lobby.last_game = lobby.opening.take().map(|opening| {
+      Arc::new(GameRecording {
+         opening,
+         history: gs.history.clone(),
+         seats: lobby
+            .players_by_turn_num
+            .iter()
+            .map(|(slot, id)| (*id, *slot))
+            .collect(),
+      })
+   });
+   lobby.rewind_points.clear();
+   lobby.waiting_since = Instant::now();
+   lobby.games_completed += 1;
+   lobby.abort_votes.clear();
+   lobby.skip_turn_votes.clear();
+   // Nobody is left to tell about their own removal, so everyone else is told along with the game's end
+   let mut departed_slots = Vec::new();
+   lobby.players.retain(|_, player| {
+      let departed = matches!(player.connection, Connection::Disconnected(_));
+      if departed {
+         departed_slots.push(player.turn_number);
+      }
+      !departed
+   });
+   for slot in &departed_slots {
+      lobby.players_by_turn_num.remove(slot);
+   }
+   let closing_secs = match lobby.after_game {
+      AfterGamePolicy::CloseAfterSecs(secs) => {
+         lobby.closes_at = Some(Instant::now() + Duration::from_secs(u64::from(secs)));
+         Some(secs)
+      }
+      _ => None,
+   };
+   FinishedGame {
+      gs,
+      departed_slots,
+      closing_secs,
+   }
+}
+
+fn report_game_end(lobby: &mut Lobby, finished: &FinishedGame) {
+   broadcast(
+      &mut lobby.players,
+      &mut lobby.spectators,
+      &PalaceOutMessage::GameCompleteEvent(&finished.gs.out_players),
+   );
+   for (i, slot) in finished.departed_slots.iter().enumerate() {
+      let total_num_players = lobby.players.len() + finished.departed_slots.len() - i - 1;
+      report_player_leave(lobby, *slot, total_num_players as u8);
+   }
+   grant_queued_seats(lobby);
+   if let Some(secs) = finished.closing_secs {
+      broadcast(
+         &mut lobby.players,
+         &mut lobby.spectators,
+         &PalaceOutMessage::LobbyClosingSoonEvent(LobbyClosingSoonEvent { secs_remaining: secs }),
+      );
+   }
+}
+
+/// Takes a player out of the game, placed behind everyone still playing. Panics if game is not in progress
+fn concede(lobby: &mut Lobby, slot: u8, archive: &GameArchive) {
+   let gs = lobby.game.as_mut().unwrap();
+   if slot == gs.active_player {
+      lobby.predictions.discard_guesses();
+      lobby.skip_turn_votes.clear();
+   }
+   let finished = if gs.concede(slot) {
+      Some(finish_game(lobby, archive))
+   } else {
+      None
+   };
+   broadcast(
+      &mut lobby.players,
+      &mut lobby.spectators,
+      &PalaceOutMessage::PlayerConcededEvent(PlayerConcededEvent { slot }),
+   );
+   if let Some(finished) = finished {
+      report_game_end(lobby, &finished);
+      return;
+   }
    let gs = lobby.game.as_ref().unwrap();
-   let mut players_to_remove = Vec::new();
-   for (id, player) in &mut lobby.players {
-      match player.connection {
-         Connection::Disconnected(_) => {
-            players_to_remove.push(*id);
-         }
-         Connection::Connected(ref mut sender) => {
-            let _ = serialize_and_send(sender, &PalaceOutMessage::GameCompleteEvent(&gs.out_players));
-         }
-         Connection::Ai(_) => (),
+   let public_gs = journaled_public_state(gs, &lobby.players, lobby.turn_timer, &lobby.journal);
+   for player in lobby.players.values_mut() {
+      if let Connection::Ai(ref mut ai) = player.connection {
+         ai.core.on_player_conceded(slot);
+         ai.core.on_game_state_update(&public_gs);
       }
    }
-   for sender in &mut lobby.spectators {
-      let _ = serialize_and_send(sender, &PalaceOutMessage::GameCompleteEvent(&gs.out_players));
+   let public_gs_bytes = lobby.journal.record(
+      LobbyEventKind::Play,
+      &PalaceOutMessage::PublicGameStateEvent(&public_gs),
+   );
+   broadcast_serialized(&mut lobby.players, &mut lobby.spectators, public_gs_bytes);
+   notify_active_player(gs, &mut lobby.players, lobby.turn_timer);
+}
+
+/// Whether there are more humans connected or more load than the server's limits allow alongside clandestine bots
+fn too_busy_for_bots(lobbies: &IndexMap<LobbyId, Lobby>, config: &ServerConfig, load: Option<f64>) -> bool {
+   let humans: usize = lobbies
+      .values()
+      .map(|lobby| {
+         let players = lobby
+            .players
+            .values()
+            .filter(|x| matches!(x.connection, Connection::Connected(_)))
+            .count();
+         players + lobby.spectators.len()
+      })
+      .sum();
+   matches!(config.bot_max_humans, Some(max) if humans >= max)
+      || matches!((config.bot_max_load, load), (Some(max), Some(load)) if load >= max)
+}
+
+/// Closes lobbies where only clandestine bots are waiting. Games already underway are left to finish
+fn close_bot_lobbies(lobbies: &mut IndexMap<LobbyId, Lobby>) {
+   lobbies.retain(|_, lobby| {
+      let bots_only = lobby
+         .players
+         .values()
+         .all(|x| matches!(x.connection, Connection::Ai(ref ai) if ai.is_clandestine));
+      !bots_only || lobby.game.is_some() || !lobby.spectators.is_empty() || !lobby.seat_queue.is_empty()
+   });
+}
+
+/// The one minute load average, on platforms that report it in /proc/loadavg
+fn load_average() -> Option<f64> {
+   let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+   loadavg.split_whitespace().next()?.parse().ok()
+}
+
+/// When the lobby will be closed for its age, per the server's limits
+fn lobby_expiry(lobby: &Lobby, config: &ServerConfig) -> Option<Instant> {
+   let waiting_expiry = config
+      .max_waiting_lobby_secs
+      .filter(|_| lobby.game.is_none())
+      .map(|secs| lobby.waiting_since + Duration::from_secs(secs));
+   let lifetime_expiry = config
+      .max_lobby_lifetime_secs
+      .map(|secs| lobby.creation_time + Duration::from_secs(secs));
+   waiting_expiry.into_iter().chain(lifetime_expiry).min()
+}
+
+fn game_record<'a>(lobby: &'a Lobby, gs: &'a GameState) -> GameRecord<'a> {
+   let mut players: Vec<ArchivedPlayer> = lobby
+      .players
+      .values()
+      .map(|p| {
+         let (ai_strategy, clandestine) = match &p.connection {
+            Connection::Ai(ai) => (Some(ai.core.strategy_name()), ai.is_clandestine),
+            _ => (None, false),
+         };
+         ArchivedPlayer {
+            name: &p.name,
+            turn_number: p.turn_number,
+            ai_strategy,
+            clandestine,
+         }
+      })
+      .collect();
+   players.sort_by_key(|p| p.turn_number);
+   GameRecord {
+      lobby_name: &lobby.name,
+      players,
+      finish_order: &gs.out_players,
+      turns: gs.turns_taken,
+      duration_secs: gs.start_time.elapsed().as_secs(),
+      turn_timer_secs: lobby.turn_timer.limit.as_secs(),
+      max_players: lobby.max_players,
+      seed: gs.seed,
+      highlights: &gs.highlights,
+      commentary: &lobby.commentary,
    }
+}
+
+/// Like `end_game`, but nobody wins and the game doesn't count toward the lobby's stats
+fn abort_game(lobby: &mut Lobby, reason: GameAbortReason) {
+   call_off_game(lobby, &PalaceOutMessage::GameAbortedEvent(reason));
+}
+
+/// Like `abort_game`, for games that weren't getting anywhere
+fn draw_game(lobby: &mut Lobby, reason: DrawReason) {
+   let turns = lobby.game.as_ref().map_or(0, |gs| gs.turns_taken);
+   call_off_game(lobby, &PalaceOutMessage::GameDrawEvent(GameDrawEvent { reason, turns }));
+}
+
+/// Ends the game without a winner, telling everyone with `event`
+fn call_off_game(lobby: &mut Lobby, event: &PalaceOutMessage) {
+   let players_to_remove: Vec<PlayerId> = lobby
+      .players
+      .iter()
+      .filter(|(_, p)| matches!(p.connection, Connection::Disconnected(_)))
+      .map(|(id, _)| *id)
+      .collect();
+   broadcast(&mut lobby.players, &mut lobby.spectators, event);
    lobby.game = None;
-   lobby.games_completed += 1;
+   lobby.opening = None;
+   lobby.rewind_points.clear();
+   lobby.waiting_since = Instant::now();
+   lobby.abort_votes.clear();
+   lobby.skip_turn_votes.clear();
    players_to_remove
       .into_iter()
       .for_each(|id| remove_player(id, lobby, None));
 }
 
+#[cfg(feature = "neural")]
+fn load_ai_resources(config: &ServerConfig) -> AiResources {
+   AiResources {
+      policy_model: config
+         .policy_model_path
+         .as_ref()
+         .and_then(|path| match ai::neural::PolicyModel::load(path) {
+            Ok(model) => Some(Arc::new(model)),
+            Err(e) => {
+               error!("Failed to load policy model from {:?}: {:?}", path, e);
+               None
+            }
+         }),
+   }
+}
+
+#[cfg(not(feature = "neural"))]
+fn load_ai_resources(config: &ServerConfig) -> AiResources {
+   if config.policy_model_path.is_some() {
+      error!("A policy model was configured, but the server was built without the `neural` feature");
+   }
+   AiResources::default()
+}
+
 pub fn run_server(address: &'static str) {
+   run_server_with_config(address, ServerConfig::default())
+}
+
+pub fn run_server_with_config(address: &'static str, config: ServerConfig) {
+   let config = Arc::new(config);
+   let drain_state: Arc<RwLock<Option<DrainState>>> = Arc::new(RwLock::new(None));
+   let ai_resources = Arc::new(load_ai_resources(&config));
+
    // @Performance this could be a concurrent hashmap
-   let lobbies: Arc<RwLock<HashMap<LobbyId, Lobby>>> = Arc::new(RwLock::new(HashMap::new()));
+   let lobbies: Arc<RwLock<IndexMap<LobbyId, Lobby>>> = Arc::new(RwLock::new(IndexMap::new()));
+   let lobby_list = Arc::new(LobbyList::default());
+   let active_lobbies = Arc::new(Mutex::new(ActiveLobbies::default()));
+   let metrics = Arc::new(Metrics::default());
+   let archive = Arc::new(GameArchive::open(config.game_archive_path.as_deref()));
+   let stats_subscribers: Arc<RwLock<Vec<Sender>>> = Arc::new(RwLock::new(Vec::new()));
+   let lobby_creations: Arc<Mutex<HashMap<String, Vec<Instant>>>> = Arc::new(Mutex::new(HashMap::new()));
+   let schedule = Arc::new(Mutex::new(Schedule::new(&config.scheduled_events)));
+   if let Some(ref address) = config.metrics_address {
+      metrics::serve(address, metrics.clone());
+   }
+   if let Some(ref address) = config.overlay_address {
+      let overlays = Arc::new(Overlays::new(Duration::from_secs(config.overlay_delay_secs)));
+      overlay::serve(address, overlays.clone());
+      let thread_lobbies = lobbies.clone();
+      std::thread::spawn(move || loop {
+         std::thread::sleep(Duration::from_millis(overlay::SAMPLE_INTERVAL_MILLIS));
+         let lobbies = thread_lobbies.read().unwrap();
+         for (lobby_id, lobby) in lobbies.iter() {
+            overlays.record(*lobby_id, &lobby.overlay());
+         }
+         overlays.retain(|lobby_id| lobbies.contains_key(lobby_id));
+      });
+   }
 
    // Kick / take turns for idle players
    {
       let thread_lobbies = lobbies.clone();
+      let thread_active_lobbies = active_lobbies.clone();
+      let thread_archive = archive.clone();
+      let thread_commentator = config.commentator.clone();
+      let max_game_turns = config.max_game_turns;
+      let mut last_presence_check = Instant::now();
       std::thread::spawn(move || loop {
          std::thread::sleep(Duration::from_millis(100));
          //let kick_idle_start = Instant::now();
          {
             let mut lobbies = thread_lobbies.write().unwrap();
-            for lobby in lobbies.values_mut() {
+            if last_presence_check.elapsed() >= Duration::from_millis(PRESENCE_CHECK_INTERVAL_MILLIS) {
+               last_presence_check = Instant::now();
+               for lobby in lobbies.values_mut() {
+                  mark_idle_players(lobby, Duration::from_secs(PRESENCE_IDLE_SECS));
+                  disconnect_unreachable_players(lobby);
+               }
+            }
+            let mut active_lobbies = thread_active_lobbies.lock().unwrap();
+            let ActiveLobbies {
+               ref mut ai_turns,
+               ref mut in_game,
+            } = *active_lobbies;
+            in_game.retain(|lobby_id| matches!(lobbies.get(lobby_id), Some(lobby) if lobby.game.is_some()));
+            for lobby_id in in_game.iter() {
+               let lobby = lobbies.get_mut(lobby_id).unwrap();
                if let Some(ref mut gs) = lobby.game {
-                  if lobby.turn_timer.as_secs() == 0 {
+                  if lobby.turn_timer.limit.as_secs() == 0 {
                      continue;
                   }
 
-                  let player_id = lobby.players_by_turn_num[&gs.active_player];
+                  if pause_turn_timer_while_abandoned(&lobby.players, &mut lobby.turn_timer_paused_at, gs) {
+                     continue;
+                  }
+
+                  let player_id = match lobby.players_by_turn_num.get(&gs.active_player) {
+                     Some(player_id) => *player_id,
+                     // Evicted players' seats are played by the AI loop
+                     None => continue,
+                  };
                   let timed_out_or_kicked = match &lobby.players[&player_id].connection {
                      Connection::Disconnected(ds) => {
                         ds.reason == DisconnectedReason::Kicked || ds.reason == DisconnectedReason::TimedOut
@@ -1054,18 +4664,50 @@ pub fn run_server(address: &'static str) {
                      _ => false,
                   };
 
-                  if gs.last_turn_start.elapsed() >= lobby.turn_timer || timed_out_or_kicked {
+                  // Humans are warned shortly before their turn is up, and no play is made for them until the
+                  // warning has had time to reach them
+                  let turn_limit = lobby.turn_timer.for_slot(&lobby.players, gs.active_player);
+                  let elapsed = gs.last_turn_start.elapsed();
+                  let mut awaiting_warning = false;
+                  let player = lobby.players.get_mut(&player_id).unwrap();
+                  if let Connection::Connected(ref mut senders) = player.connection {
+                     match lobby.turn_warning {
+                        Some(ref warning) if warning.turn_start == gs.last_turn_start => {
+                           awaiting_warning = warning.sent_at.elapsed() < round_trip(senders);
+                        }
+                        _ if elapsed + Duration::from_secs(TURN_WARNING_SECS) >= turn_limit => {
+                           let event = TurnTimeWarningEvent {
+                              secs_remaining: turn_limit.saturating_sub(elapsed).as_secs() as u16,
+                           };
+                           let _ = serialize_and_send_all(senders, &PalaceOutMessage::TurnTimeWarningEvent(event));
+                           ping_all(senders);
+                           lobby.turn_warning = Some(TurnWarning {
+                              turn_start: gs.last_turn_start,
+                              sent_at: Instant::now(),
+                           });
+                           awaiting_warning = true;
+                        }
+                        _ => (),
+                     }
+                  }
+
+                  if (elapsed >= turn_limit && !awaiting_warning) || timed_out_or_kicked {
                      // Update connection, if needed
                      {
                         let player = lobby.players.get_mut(&player_id).unwrap();
-                        match player.connection {
-                           Connection::Connected(ref mut sender) => {
-                              let _ =
-                                 serialize_and_send(sender, &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Afk));
+                        let slot = player.turn_number;
+                        let timed_out = match player.connection {
+                           Connection::Connected(ref mut senders) => {
+                              let _ = serialize_and_send_all(
+                                 senders,
+                                 &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Afk),
+                              );
+                              disconnect_all(senders, DisconnectCode::Afk, "Took too long to take a turn");
                               player.connection = Connection::Disconnected(DisconnectedState {
                                  time: Instant::now(),
                                  reason: DisconnectedReason::TimedOut,
                               });
+                              true
                            }
                            Connection::Disconnected(ref mut dc) => {
                               // Elevate their disconnected status to timed out
@@ -1073,6 +4715,9 @@ pub fn run_server(address: &'static str) {
                               // being taken instantaneously
                               if dc.reason == DisconnectedReason::Left {
                                  dc.reason = DisconnectedReason::TimedOut;
+                                 true
+                              } else {
+                                 false
                               }
                            }
                            Connection::Ai(ref mut ai) => {
@@ -1083,6 +4728,7 @@ pub fn run_server(address: &'static str) {
                               if ai.core.strategy_name() != "Random" {
                                  info!("Falling back to Random");
                                  ai.core = Box::new(ai::random::new());
+                                 ai.core.reseed(ai::seat_seed(gs, player.turn_number));
                                  ai.core.on_game_start(GameStartEvent {
                                     hand: gs.get_hand(player.turn_number),
                                     turn_number: player.turn_number,
@@ -1090,7 +4736,16 @@ pub fn run_server(address: &'static str) {
                                  });
                                  ai.core.on_game_state_update(&gs.public_state());
                               }
+                              false
                            }
+                        };
+                        if timed_out {
+                           broadcast_connection_status(
+                              &mut lobby.players,
+                              &mut lobby.spectators,
+                              slot,
+                              ConnectionStatus::TimedOut,
+                           );
                         }
                      }
 
@@ -1099,18 +4754,20 @@ pub fn run_server(address: &'static str) {
                      // AI play loop pick it up is because we want to avoid the scenario
                      // in which a user gets kicked and immediately reconnects, before the AI
                      // play loop kicks in, therefore circumventing the turn timer
-                     let mut ai = Box::new(ai::random::new());
-                     ai.on_game_start(GameStartEvent {
-                        hand: gs.get_hand(gs.active_player),
-                        turn_number: gs.active_player,
-                        players: &HashMap::new(), // Random doesn't need players
-                     });
-                     ai.on_game_state_update(&gs.public_state());
-                     let play = ai::get_turn(gs, &mut *ai);
-                     let must_end_game = gs.take_turn(&play).unwrap();
-                     report_take_turn(gs, &mut lobby.players, &mut lobby.spectators, player_id);
-                     if must_end_game {
-                        end_game(lobby);
+                     let slot = gs.active_player;
+                     let gs_before = thread_commentator.as_ref().map(|_| gs.clone());
+                     let outcome = take_random_turn(gs);
+                     after_play(
+                        lobby,
+                        slot,
+                        outcome,
+                        thread_commentator.as_deref(),
+                        gs_before,
+                        &thread_archive,
+                        max_game_turns,
+                     );
+                     if ai_on_turn(lobby) {
+                        ai_turns.insert(*lobby_id);
                      }
                   }
                }
@@ -1120,15 +4777,64 @@ pub fn run_server(address: &'static str) {
       });
    }
 
-   // Prune empty lobbies
+   // Prune empty lobbies, and close lobbies whose game is over if they asked to be, or that are too old
    {
       let thread_lobbies = lobbies.clone();
+      let thread_active_lobbies = active_lobbies.clone();
+      let thread_config = config.clone();
+      let thread_lobby_creations = lobby_creations.clone();
       std::thread::spawn(move || loop {
          std::thread::sleep(Duration::from_secs(30));
          let lobby_clean_start = Instant::now();
+         thread_lobby_creations.lock().unwrap().retain(|_, recent| {
+            recent.retain(|x| x.elapsed() < Duration::from_secs(LOBBY_CREATION_WINDOW_SECS));
+            !recent.is_empty()
+         });
          {
             let mut lobbies = thread_lobbies.write().unwrap();
+            let mut active_lobbies = thread_active_lobbies.lock().unwrap();
+            for (lobby_id, lobby) in lobbies.iter_mut() {
+               evict_disconnected_players(lobby);
+               active_lobbies.track(*lobby_id, lobby);
+               lobby.reconnect_attempts.retain(|_, attempts| {
+                  attempts
+                     .iter()
+                     .any(|x| x.elapsed() < Duration::from_secs(RECONNECT_ATTEMPT_WINDOW_SECS))
+               });
+            }
             lobbies.retain(|_, lobby| {
+               if let Some(expiry) = lobby_expiry(lobby, &thread_config) {
+                  let remaining = expiry.saturating_duration_since(Instant::now());
+                  if remaining == Duration::from_secs(0) {
+                     broadcast(
+                        &mut lobby.players,
+                        &mut lobby.spectators,
+                        &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::Expired),
+                     );
+                     close_seat_queue(lobby, LobbyCloseEvent::Expired);
+                     return false;
+                  }
+                  if remaining <= Duration::from_secs(LOBBY_EXPIRY_WARNING_SECS) && lobby.expiry_warned != Some(expiry)
+                  {
+                     lobby.expiry_warned = Some(expiry);
+                     broadcast(
+                        &mut lobby.players,
+                        &mut lobby.spectators,
+                        &PalaceOutMessage::LobbyClosingSoonEvent(LobbyClosingSoonEvent {
+                           secs_remaining: remaining.as_secs() as u16,
+                        }),
+                     );
+                  }
+               }
+               if lobby.closes_at.is_some_and(|t| t <= Instant::now()) {
+                  broadcast(
+                     &mut lobby.players,
+                     &mut lobby.spectators,
+                     &PalaceOutMessage::LobbyCloseEvent(LobbyCloseEvent::GameOver),
+                  );
+                  close_seat_queue(lobby, LobbyCloseEvent::GameOver);
+                  return false;
+               }
                if !lobby.spectators.is_empty() {
                   return true;
                }
@@ -1159,11 +4865,42 @@ pub fn run_server(address: &'static str) {
    // Update AI
    {
       let thread_lobbies = lobbies.clone();
+      let thread_active_lobbies = active_lobbies.clone();
+      let thread_archive = archive.clone();
+      let max_game_turns = config.max_game_turns;
+      let explain_ai_decisions = config.explain_ai_decisions;
+      let thread_commentator = config.commentator.clone();
+      let mut load_monitor = config
+         .ai_latency_budget_millis
+         .map(|x| AiLoadMonitor::new(Duration::from_millis(x)));
+      let degraded_ai_strength = config.degraded_ai_strength;
       std::thread::spawn(move || loop {
          std::thread::sleep(Duration::from_millis(100));
-         //let ai_loop_start = Instant::now();
-         ai_play(&mut thread_lobbies.write().unwrap());
+         let ai_loop_start = Instant::now();
+         let mut lobbies = thread_lobbies.write().unwrap();
+         ai_play(
+            &mut lobbies,
+            &mut thread_active_lobbies.lock().unwrap().ai_turns,
+            explain_ai_decisions,
+            thread_commentator.as_deref(),
+            &thread_archive,
+            max_game_turns,
+         );
          //trace!("AI play runtime: {:?}", ai_loop_start.elapsed());
+         if let Some(ref mut monitor) = load_monitor {
+            let was_degraded = monitor.degraded;
+            let degraded = monitor.observe(ai_loop_start.elapsed());
+            if degraded != was_degraded {
+               let change = if degraded { "degrading" } else { "restoring" };
+               info!("AI loop averaging {:?}, {} AI", monitor.average, change);
+            }
+            // AI that joined while degraded has to be caught up, so keep setting it
+            if degraded {
+               set_ai_strength(&mut lobbies, degraded_ai_strength);
+            } else if was_degraded {
+               set_ai_strength(&mut lobbies, 1.0);
+            }
+         }
       });
    }
 
@@ -1172,20 +4909,36 @@ pub fn run_server(address: &'static str) {
    // @TODO probably pending an async rewrite with tokio-tungstenite
    {
       let thread_lobbies = lobbies.clone();
+      let thread_active_lobbies = active_lobbies.clone();
+      let thread_drain_state = drain_state.clone();
+      let thread_config = config.clone();
+      let mut was_busy = false;
       std::thread::spawn(move || loop {
          std::thread::sleep(Duration::from_millis(rand::thread_rng().gen_range(100, 10000)));
 
          let mut lobbies = thread_lobbies.write().unwrap();
 
+         // Real players come first, so bots make way when the server is busy
+         let busy = too_busy_for_bots(&lobbies, &thread_config, load_average());
+         if busy != was_busy {
+            info!("{} clandestine bots", if busy { "Winding down" } else { "Resuming" });
+            was_busy = busy;
+         }
+         if busy {
+            close_bot_lobbies(&mut lobbies);
+            continue;
+         }
+
          // Fill empty slots
          for lobby in lobbies.values_mut().filter(|l| {
             l.game.is_none()
                && l.creation_time.elapsed() > Duration::from_secs(10)
-               && (l.players.len() as u8) < l.max_players
+               && (l.players.len() as u8) < l.open_seats()
                && l.password.is_empty()
          }) {
             let player_id = PlayerId(rand::random());
-            let ai: Box<dyn PalaceAi + Send + Sync> = Box::new(ai::random::new());
+            // Matched to the lobby, so that hidden bots neither stomp beginners nor hand wins to experts
+            let ai = ai::clandestine_bot_for(lobby.average_rating());
             add_player(
                Player {
                   name: ai::get_bot_name_clandestine(),
@@ -1193,7 +4946,12 @@ pub fn run_server(address: &'static str) {
                      core: ai,
                      is_clandestine: true,
                   }),
-                  turn_number: next_public_id(&lobby.players_by_turn_num),
+                  turn_number: lobby.next_open_slot(),
+                  idle: false,
+                  last_play_seq: None,
+                  hints_used: 0,
+                  last_hint: None,
+                  rating: None,
                },
                player_id,
                lobby,
@@ -1201,36 +4959,1126 @@ pub fn run_server(address: &'static str) {
          }
 
          // Create new lobbies
-         if lobbies.len() < 5 {
+         if lobbies.len() < 5 && thread_drain_state.read().unwrap().is_none() {
             create_lobby(
                &mut lobbies,
                Connection::Ai(AiState {
                   core: Box::new(ai::random::new()),
                   is_clandestine: true,
                }),
-               "botto grotto".into(),
-               ai::get_bot_name_clandestine(),
-               "".into(),
-               4,
-               data::default_turn_timer_secs(),
+               NewLobbyMessage {
+                  max_players: 4,
+                  password: "".into(),
+                  lobby_name: "botto grotto".into(),
+                  player_name: ai::get_bot_name_clandestine(),
+                  turn_timer: data::default_turn_timer_secs(),
+                  min_human_turn_timer: 0,
+                  description: "".into(),
+                  after_game: AfterGamePolicy::Persist,
+                  hints: HintPolicy::Disabled,
+                  practice: false,
+                  scenario: None,
+                  draw_rules: DrawRules::default(),
+               },
             );
          }
 
          // Start full lobbies that are owned by bots
-         for lobby in lobbies
-            .values_mut()
+         for (lobby_id, lobby) in lobbies
+            .iter_mut()
             // this handles the case where the lobby owner has left -- TODO: are we cleaning up old lobbies?
-            .filter(|l| l.game.is_none() && l.players.len() as u8 == l.max_players && l.players.get(&l.owner).map(|x| x.is_ai()).unwrap_or(false))
+            .filter(|(_, l)| {
+               l.game.is_none()
+                  && l.players.len() as u8 == l.open_seats()
+                  && l.players.get(&l.owner).map(|x| x.is_ai()).unwrap_or(false)
+            })
          {
             start_game(lobby);
+            thread_active_lobbies.lock().unwrap().track(*lobby_id, lobby);
          }
       });
    }
 
-   ws::listen(address, |out| Server {
-      out,
-      lobbies: lobbies.clone(),
-      connected_user: None,
-   })
-   .unwrap()
+   // Rebuild the lobby list
+   {
+      let thread_lobbies = lobbies.clone();
+      let thread_lobby_list = lobby_list.clone();
+      std::thread::spawn(move || {
+         let mut built_at = Instant::now();
+         loop {
+            std::thread::sleep(Duration::from_millis(LOBBY_LIST_REFRESH_MILLIS));
+            let stale = thread_lobby_list.stale.swap(false, Ordering::Relaxed);
+            if !stale && built_at.elapsed() < Duration::from_millis(LOBBY_LIST_MAX_AGE_MILLIS) {
+               continue;
+            }
+            let snapshot = LobbyListSnapshot::new(&thread_lobbies.read().unwrap());
+            thread_lobby_list.snapshot.store(Arc::new(snapshot));
+            built_at = Instant::now();
+         }
+      });
+   }
+
+   // Every address gets its own listener, and they all share the same lobbies
+   let broadcasters: Arc<RwLock<Vec<ws::Sender>>> = Arc::new(RwLock::new(Vec::new()));
+   let new_server = {
+      let lobbies = lobbies.clone();
+      let config = config.clone();
+      let drain_state = drain_state.clone();
+      let ai_resources = ai_resources.clone();
+      let lobby_list = lobby_list.clone();
+      let active_lobbies = active_lobbies.clone();
+      let metrics = metrics.clone();
+      let archive = archive.clone();
+      let stats_subscribers = stats_subscribers.clone();
+      let lobby_creations = lobby_creations.clone();
+      let broadcasters = broadcasters.clone();
+      let schedule = schedule.clone();
+      move |out| Server {
+         out,
+         lobbies: lobbies.clone(),
+         memberships: Memberships::default(),
+         config: config.clone(),
+         drain_state: drain_state.clone(),
+         ai_resources: ai_resources.clone(),
+         text_frames: false,
+         legacy_client: false,
+         lobby_list: lobby_list.clone(),
+         active_lobbies: active_lobbies.clone(),
+         metrics: metrics.clone(),
+         archive: archive.clone(),
+         stats_subscribers: stats_subscribers.clone(),
+         remote_addr: None,
+         lobby_creations: lobby_creations.clone(),
+         broadcasters: broadcasters.clone(),
+         schedule: schedule.clone(),
+      }
+   };
+   let sockets: Vec<_> = std::iter::once(address)
+      .chain(config.additional_addresses.iter().map(String::as_str))
+      .map(|address| {
+         let new_server = new_server.clone();
+         ws::WebSocket::new(move |out| new_server(Sender::new(Transport::WebSocket(out))))
+            .unwrap()
+            .bind(address)
+            .unwrap()
+      })
+      .collect();
+   if let Some(ref path) = config.local_socket_path {
+      #[cfg(unix)]
+      local::serve(path, new_server);
+      #[cfg(not(unix))]
+      error!(
+         "Not listening on {}, local sockets are only supported on Unix",
+         path.display()
+      );
+   }
+   broadcasters
+      .write()
+      .unwrap()
+      .extend(sockets.iter().map(ws::WebSocket::broadcaster));
+
+   // Count down to shutdown while draining
+   {
+      let thread_drain_state = drain_state.clone();
+      let thread_broadcasters = broadcasters.clone();
+      std::thread::spawn(move || loop {
+         std::thread::sleep(Duration::from_secs(DRAIN_COUNTDOWN_INTERVAL_SECS));
+         if let Some(ref drain_state) = *thread_drain_state.read().unwrap() {
+            match drain_state.shutdown_at {
+               // Connections opened since shutdown was due are closed on the next count
+               Some(shutdown_at) if shutdown_at <= Instant::now() => {
+                  let broadcasters = thread_broadcasters.read().unwrap();
+                  let _ = serialize_and_broadcast(
+                     &broadcasters,
+                     &PalaceOutMessage::DisconnectEvent(DisconnectEvent {
+                        code: DisconnectCode::ServerDraining,
+                        reason: "The server is shutting down".into(),
+                     }),
+                  );
+                  for broadcaster in broadcasters.iter() {
+                     let _ = broadcaster.close(close_code(DisconnectCode::ServerDraining));
+                  }
+               }
+               Some(_) => {
+                  let _ = serialize_and_broadcast(
+                     &thread_broadcasters.read().unwrap(),
+                     &PalaceOutMessage::ServerDrainingEvent(drain_state.event()),
+                  );
+               }
+               None => (),
+            }
+         }
+      });
+   }
+
+   // Announce and open scheduled events
+   {
+      let thread_lobbies = lobbies.clone();
+      let thread_drain_state = drain_state.clone();
+      let thread_broadcasters = broadcasters.clone();
+      let thread_schedule = schedule.clone();
+      std::thread::spawn(move || {
+         let mut last_check = schedule::now_secs();
+         loop {
+            std::thread::sleep(Duration::from_millis(SCHEDULE_CHECK_INTERVAL_MILLIS));
+            let now = schedule::now_secs();
+            if now == last_check {
+               continue;
+            }
+            let due = thread_schedule.lock().unwrap().due(last_check, now);
+            last_check = now;
+            for due in due {
+               let text = match due {
+                  Due::Announcement(text) => text,
+                  Due::Opening(event, _) if thread_drain_state.read().unwrap().is_some() => {
+                     info!("Not opening {} while draining", event.name);
+                     continue;
+                  }
+                  Due::Opening(event, registrants) => {
+                     match open_event(&mut thread_lobbies.write().unwrap(), &event, registrants) {
+                        Some(lobby_id) => info!("Opened {} as lobby {:x}", event.name, lobby_id.0),
+                        None => {
+                           info!("Not opening {}, as nobody registered is free to play", event.name);
+                           continue;
+                        }
+                     }
+                     format!("{} is open", event.name)
+                  }
+               };
+               let _ = serialize_and_broadcast(
+                  &thread_broadcasters.read().unwrap(),
+                  &PalaceOutMessage::ServerAnnouncementEvent(ServerAnnouncementEvent::info(&text)),
+               );
+            }
+         }
+      });
+   }
+
+   // Feed subscribed dashboards
+   {
+      let thread_lobbies = lobbies.clone();
+      let thread_metrics = metrics.clone();
+      let thread_stats_subscribers = stats_subscribers.clone();
+      std::thread::spawn(move || {
+         let mut last_messages_received = thread_metrics.messages_received();
+         loop {
+            std::thread::sleep(Duration::from_secs(SERVER_STATS_INTERVAL_SECS));
+            let messages_received = thread_metrics.messages_received();
+            let messages_per_sec =
+               (messages_received - last_messages_received) as f64 / SERVER_STATS_INTERVAL_SECS as f64;
+            last_messages_received = messages_received;
+
+            let mut subscribers = thread_stats_subscribers.write().unwrap();
+            if subscribers.is_empty() {
+               continue;
+            }
+            let stats = server_stats(&thread_lobbies.read().unwrap(), messages_per_sec);
+            for sender in subscribers.iter_mut() {
+               let _ = serialize_and_send(sender, &PalaceOutMessage::ServerStatsEvent(stats.clone()));
+            }
+         }
+      });
+   }
+
+   let mut sockets = sockets.into_iter();
+   let first_socket = sockets.next().unwrap();
+   for socket in sockets {
+      std::thread::spawn(move || socket.run().unwrap());
+   }
+   first_socket.run().unwrap();
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   /// A two seat lobby with no turn timer, for tests to change only what they care about
+   #[cfg(test)]
+   pub(crate) fn new_lobby(lobby_name: &str) -> NewLobbyMessage {
+      NewLobbyMessage {
+         max_players: 2,
+         password: String::new(),
+         lobby_name: lobby_name.into(),
+         player_name: "Owner".into(),
+         turn_timer: 0,
+         min_human_turn_timer: 0,
+         description: String::new(),
+         after_game: AfterGamePolicy::Persist,
+         hints: HintPolicy::Disabled,
+         practice: false,
+         scenario: None,
+         draw_rules: DrawRules::default(),
+      }
+   }
+
+   #[test]
+   fn send_failures_count_in_a_row() {
+      // Far above any connection id the tests open
+      const CONNECTION_ID: u32 = u32::MAX;
+      for _ in 0..MAX_CONSECUTIVE_SEND_FAILURES - 1 {
+         note_send_result(CONNECTION_ID, false);
+      }
+      note_send_result(CONNECTION_ID, true);
+      note_send_result(CONNECTION_ID, false);
+      assert!(!is_unreachable(CONNECTION_ID));
+      for _ in 0..MAX_CONSECUTIVE_SEND_FAILURES - 1 {
+         note_send_result(CONNECTION_ID, false);
+      }
+      assert!(is_unreachable(CONNECTION_ID));
+   }
+
+   #[test]
+   fn forwarded_addresses_are_only_believed_from_trusted_proxies() {
+      let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+      let client: IpAddr = "203.0.113.7".parse().unwrap();
+      let hops = |x: &str| x.split(',').map(str::to_owned).collect::<Vec<_>>();
+
+      // Anyone can send the header
+      assert_eq!(client_address(client, &hops("198.51.100.1"), &[]), client);
+      assert_eq!(client_address(client, &hops("198.51.100.1"), &[proxy]), client);
+      // A trusted proxy is believed, but only about the hop it added itself
+      assert_eq!(client_address(proxy, &hops("203.0.113.7"), &[proxy]), client);
+      assert_eq!(
+         client_address(proxy, &hops("198.51.100.1, 203.0.113.7"), &[proxy]),
+         client
+      );
+      assert_eq!(
+         client_address(proxy, &hops("\"[2001:db8::1]:4711\""), &[proxy]),
+         "2001:db8::1".parse::<IpAddr>().unwrap()
+      );
+      assert_eq!(client_address(proxy, &hops("unknown"), &[proxy]), proxy);
+   }
+
+   #[test]
+   fn spoofed_forwarded_addresses_share_a_lobby_limit() {
+      let peer: IpAddr = "203.0.113.7".parse().unwrap();
+      let mut creations = HashMap::new();
+      for spoofed in &["198.51.100.1", "198.51.100.2", "198.51.100.3"] {
+         let address = client_address(peer, &[spoofed.to_string()], &[]).to_string();
+         note_lobby_creation(&mut creations, &address, 2);
+      }
+      let address = client_address(peer, &["198.51.100.4".to_owned()], &[]).to_string();
+      assert!(!note_lobby_creation(&mut creations, &address, 2));
+   }
+
+   #[test]
+   fn only_unknown_message_types_are_reported() {
+      let unknown_type = |binary: &[u8]| {
+         let e = serde_json::from_slice::<PalaceInMessage>(binary).err().unwrap();
+         unknown_message_type(binary, &e)
+      };
+      assert_eq!(
+         unknown_type(br#"{"SomeFutureFeature":{}}"#),
+         Some("SomeFutureFeature".to_owned())
+      );
+      assert_eq!(
+         unknown_type(br#""SomeFutureFeature""#),
+         Some("SomeFutureFeature".to_owned())
+      );
+      // a known message with a value of some enum in it that we don't know is just malformed
+      let new_lobby = br#"{"NewLobby":{"max_players":4,"password":"","lobby_name":"a","player_name":"b",
+         "after_game":"Sometimes"}}"#;
+      assert_eq!(unknown_type(new_lobby), None);
+   }
+
+   #[test]
+   fn lobby_creation_is_limited_per_address() {
+      let mut creations = HashMap::new();
+      assert!(note_lobby_creation(&mut creations, "10.0.0.1", 2));
+      assert!(note_lobby_creation(&mut creations, "10.0.0.1", 2));
+      assert!(!note_lobby_creation(&mut creations, "10.0.0.1", 2));
+      assert!(note_lobby_creation(&mut creations, "10.0.0.2", 2));
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn quiet_players_are_idle_until_heard_from() {
+      let (outgoing, incoming) = std::sync::mpsc::channel();
+      let sender = Sender::new(Transport::Local(outgoing));
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![sender.clone()]),
+         NewLobbyMessage {
+            max_players: 4,
+            ..new_lobby("Quiet")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      let sent = || -> Vec<String> {
+         incoming
+            .try_iter()
+            .flatten()
+            .map(|x| String::from_utf8(x).unwrap())
+            .collect()
+      };
+      let an_hour = Duration::from_secs(3600);
+
+      mark_idle_players(lobby, an_hour);
+      assert!(sent().is_empty());
+
+      mark_idle_players(lobby, Duration::from_secs(0));
+      let idle = sent();
+      assert_eq!(idle.len(), 1);
+      assert!(idle[0].contains("PlayerPresenceEvent") && idle[0].contains("Idle"));
+      assert!(sender.activity.player_idle.load(Ordering::Relaxed));
+      mark_idle_players(lobby, Duration::from_secs(0));
+      assert!(sent().is_empty());
+
+      sender.activity.note_message();
+      mark_idle_players(lobby, an_hour);
+      let active = sent();
+      assert_eq!(active.len(), 1);
+      assert!(active[0].contains("PlayerPresenceEvent") && active[0].contains("Active"));
+      assert!(!sender.activity.player_idle.load(Ordering::Relaxed));
+   }
+
+   #[test]
+   fn bot_lobbies_close_when_busy() {
+      let mut lobbies = IndexMap::new();
+      create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: true,
+         }),
+         NewLobbyMessage {
+            max_players: 4,
+            player_name: "Bot".into(),
+            ..new_lobby("botto grotto")
+         },
+      );
+      let mut config = ServerConfig::default();
+      assert!(!too_busy_for_bots(&lobbies, &config, Some(8.0)));
+
+      config.bot_max_load = Some(4.0);
+      assert!(!too_busy_for_bots(&lobbies, &config, Some(2.0)));
+      assert!(!too_busy_for_bots(&lobbies, &config, None));
+      assert!(too_busy_for_bots(&lobbies, &config, Some(8.0)));
+
+      close_bot_lobbies(&mut lobbies);
+      assert!(lobbies.is_empty());
+   }
+
+   #[test]
+   fn ai_load_monitor_recovers_well_under_budget() {
+      let mut monitor = AiLoadMonitor::new(Duration::from_millis(50));
+      assert!(!monitor.observe(Duration::from_millis(200)));
+      while !monitor.observe(Duration::from_millis(200)) {}
+
+      // back under budget, but not by enough yet
+      let mut passes = 0;
+      while monitor.average > Duration::from_millis(40) {
+         assert!(monitor.observe(Duration::from_millis(10)));
+         passes += 1;
+      }
+      assert!(passes > 1);
+      while monitor.observe(Duration::from_millis(10)) {}
+      assert!(monitor.average < Duration::from_millis(25));
+   }
+
+   #[test]
+   fn lobbies_expire_at_the_earliest_limit() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            max_players: 4,
+            ..new_lobby("Old")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      let mut config = ServerConfig::default();
+      assert_eq!(lobby_expiry(lobby, &config), None);
+
+      config.max_waiting_lobby_secs = Some(60);
+      config.max_lobby_lifetime_secs = Some(600);
+      assert_eq!(
+         lobby_expiry(lobby, &config),
+         Some(lobby.waiting_since + Duration::from_secs(60))
+      );
+
+      // a game in progress doesn't count as waiting
+      lobby.game = Some(GameState::new(2));
+      assert_eq!(
+         lobby_expiry(lobby, &config),
+         Some(lobby.creation_time + Duration::from_secs(600))
+      );
+   }
+
+   #[test]
+   fn ai_loop_only_visits_lobbies_with_an_ai_on_turn() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            // A drawn game wouldn't count as completed
+            draw_rules: DrawRules {
+               max_repetitions: None,
+               max_turns: None,
+            },
+            ..new_lobby("Bots")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      let archive = GameArchive::open(None);
+      let mut active_lobbies = ActiveLobbies::default();
+
+      // Untracked lobbies are left alone
+      start_game(lobby);
+      ai_play(&mut lobbies, &mut active_lobbies.ai_turns, false, None, &archive, None);
+      assert_eq!(lobbies[&lobby_id].game.as_ref().unwrap().turns_taken, 0);
+
+      active_lobbies.track(lobby_id, &lobbies[&lobby_id]);
+      while !active_lobbies.ai_turns.is_empty() {
+         ai_play(&mut lobbies, &mut active_lobbies.ai_turns, false, None, &archive, None);
+      }
+      assert!(lobbies[&lobby_id].game.is_none());
+      assert_eq!(lobbies[&lobby_id].games_completed, 1);
+   }
+
+   #[test]
+   fn games_past_the_turn_limit_are_drawn() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         new_lobby("Stalemate"),
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      start_game(lobby);
+      let archive = GameArchive::open(None);
+      let mut active_lobbies = ActiveLobbies::default();
+      active_lobbies.track(lobby_id, &lobbies[&lobby_id]);
+      let mut passes = 0;
+      while !active_lobbies.ai_turns.is_empty() {
+         ai_play(
+            &mut lobbies,
+            &mut active_lobbies.ai_turns,
+            false,
+            None,
+            &archive,
+            Some(10),
+         );
+         passes += 1;
+      }
+      // No game of two can end within ten turns, two of which are setup
+      assert_eq!(passes, 10);
+      assert!(lobbies[&lobby_id].game.is_none());
+      assert_eq!(lobbies[&lobby_id].games_completed, 0);
+   }
+
+   #[test]
+   fn concessions_can_be_caught_up_on() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            max_players: 3,
+            ..new_lobby("Concession")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 2);
+      start_game(lobby);
+      let started: Vec<&[u8]> = lobby.journal.since(0).unwrap().collect();
+      assert!(std::str::from_utf8(started.last().unwrap())
+         .unwrap()
+         .starts_with(r#"{"PublicGameStateEvent""#));
+
+      let seq = lobby.journal.latest_seq();
+      concede(lobby, 1, &GameArchive::open(None));
+      assert!(lobby.game.is_some());
+      let missed: Vec<&[u8]> = lobby.journal.since(seq).unwrap().collect();
+      assert_eq!(missed.len(), 1);
+      let missed = std::str::from_utf8(missed[0]).unwrap();
+      assert!(missed.starts_with(r#"{"PublicGameStateEvent""#));
+      assert!(missed.contains(&format!(r#""journal_seq":{}"#, seq + 1)));
+   }
+
+   #[test]
+   fn clandestine_ai_have_human_profiles() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![]),
+         NewLobbyMessage {
+            max_players: 3,
+            ..new_lobby("Profiles")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      lobby.players.get_mut(&owner_id).unwrap().rating = Some(1200);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 2);
+      let clandestine_id = lobby.players_by_turn_num[&2];
+      if let Connection::Ai(ref mut ai) = lobby.players.get_mut(&clandestine_id).unwrap().connection {
+         ai.is_clandestine = true;
+      }
+
+      let profiles = lobby.player_profiles();
+      assert_eq!(profiles[&0].name, "Owner");
+      assert!(!profiles[&0].is_ai);
+      assert_eq!(profiles[&0].rating, Some(1200));
+      assert!(profiles[&1].is_ai);
+      assert!(!profiles[&2].is_ai);
+      assert!(profiles.values().all(|x| x.account_id.is_none()));
+   }
+
+   #[test]
+   fn turn_deadlines_count_down_from_the_active_players_limit() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![]),
+         NewLobbyMessage {
+            turn_timer: 30,
+            min_human_turn_timer: 60,
+            ..new_lobby("Timed")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      start_game(lobby);
+      let human_slot = lobby.players[&owner_id].turn_number;
+      let gs = lobby.game.as_mut().unwrap();
+
+      gs.active_player = human_slot;
+      let deadline = turn_deadline(gs, &lobby.players, lobby.turn_timer).unwrap();
+      assert!((59..=60).contains(&deadline));
+      assert_eq!(
+         public_state_with_deadline(gs, &lobby.players, lobby.turn_timer).turn_deadline,
+         Some(deadline)
+      );
+      gs.active_player = 1 - human_slot;
+      let deadline = turn_deadline(gs, &lobby.players, lobby.turn_timer).unwrap();
+      assert!((29..=30).contains(&deadline));
+
+      gs.last_turn_start = Instant::now() - Duration::from_secs(90);
+      assert_eq!(turn_deadline(gs, &lobby.players, lobby.turn_timer), Some(0));
+      let untimed = TurnTimer {
+         limit: Duration::from_secs(0),
+         min_human: Duration::from_secs(0),
+      };
+      assert_eq!(turn_deadline(gs, &lobby.players, untimed), None);
+   }
+
+   #[test]
+   fn first_deadline_goes_by_the_seat_players_end_up_in() {
+      let mut seats_seen = HashSet::new();
+      for seed in 0..16 {
+         let mut lobbies = IndexMap::new();
+         let (lobby_id, owner_id) = create_lobby(
+            &mut lobbies,
+            Connection::Connected(vec![]),
+            NewLobbyMessage {
+               turn_timer: 30,
+               min_human_turn_timer: 60,
+               ..new_lobby("Seating")
+            },
+         );
+         let lobby = lobbies.get_mut(&lobby_id).unwrap();
+         lobby.rng = ChaChaRng::seed_from_u64(seed);
+         add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+         start_game(lobby);
+
+         let started = lobby.journal.since(0).unwrap().last().unwrap();
+         let event = serde_json::from_slice::<serde_json::Value>(started).unwrap();
+         let public_gs = &event["PublicGameStateEvent"];
+         let deadline = public_gs["turn_deadline"].as_u64().unwrap();
+         let human_slot = lobby.players[&owner_id].turn_number;
+         if public_gs["active_player"].as_u64() == Some(u64::from(human_slot)) {
+            assert!((59..=60).contains(&deadline));
+         } else {
+            assert!((29..=30).contains(&deadline));
+         }
+         seats_seen.insert(human_slot);
+      }
+      // Otherwise the shuffle never moved the human and the test proves nothing
+      assert_eq!(seats_seen.len(), 2);
+   }
+
+   #[test]
+   fn players_who_stay_away_are_evicted() {
+      let away_since = |secs| {
+         Connection::Disconnected(DisconnectedState {
+            time: Instant::now() - Duration::from_secs(secs),
+            reason: DisconnectedReason::TimedOut,
+         })
+      };
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         away_since(RECONNECT_WINDOW_SECS),
+         NewLobbyMessage {
+            max_players: 3,
+            ..new_lobby("Abandoned")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 2);
+      let (gone_id, returning_id) = (lobby.players_by_turn_num[&1], lobby.players_by_turn_num[&2]);
+
+      // Seats are only held for players who may come back to a game
+      lobby.players.get_mut(&gone_id).unwrap().connection = away_since(RECONNECT_WINDOW_SECS);
+      evict_disconnected_players(lobby);
+      assert_eq!(lobby.players.len(), 3);
+
+      start_game(lobby);
+      lobby.players.get_mut(&returning_id).unwrap().connection = away_since(RECONNECT_WINDOW_SECS - 10);
+      evict_disconnected_players(lobby);
+      assert!(!lobby.players.contains_key(&gone_id));
+      assert!(lobby.players.contains_key(&returning_id));
+      assert!(lobby.players.contains_key(&owner_id));
+   }
+
+   #[test]
+   fn turn_timers_pause_while_every_human_is_away() {
+      let away = || {
+         Connection::Disconnected(DisconnectedState {
+            time: Instant::now(),
+            reason: DisconnectedReason::TimedOut,
+         })
+      };
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         away(),
+         NewLobbyMessage {
+            turn_timer: 30,
+            ..new_lobby("Blip")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      start_game(lobby);
+      let paused = |lobby: &mut Lobby| {
+         let gs = lobby.game.as_mut().unwrap();
+         pause_turn_timer_while_abandoned(&lobby.players, &mut lobby.turn_timer_paused_at, gs)
+      };
+      let turn_start = lobby.game.as_ref().unwrap().last_turn_start;
+
+      assert!(paused(lobby));
+      assert!(lobby.turn_timer_paused_at.is_some());
+      // As if the blip lasted 20 seconds
+      lobby.turn_timer_paused_at = Some(Instant::now() - Duration::from_secs(20));
+
+      lobby.players.get_mut(&owner_id).unwrap().connection = Connection::Connected(vec![]);
+      assert!(!paused(lobby));
+      assert!(lobby.turn_timer_paused_at.is_none());
+      assert!(lobby.game.as_ref().unwrap().last_turn_start >= turn_start + Duration::from_secs(20));
+
+      // Nobody is coming back for a seat the owner was kicked from
+      lobby.players.get_mut(&owner_id).unwrap().connection = Connection::Disconnected(DisconnectedState {
+         time: Instant::now(),
+         reason: DisconnectedReason::Kicked,
+      });
+      assert!(!paused(lobby));
+      lobby.players.get_mut(&owner_id).unwrap().connection = away();
+      assert!(paused(lobby));
+   }
+
+   #[cfg(test)]
+   fn play_seeded_game(seed: u64) -> Arc<GameRecording> {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            draw_rules: DrawRules {
+               max_repetitions: None,
+               max_turns: None,
+            },
+            ..new_lobby("Replay")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      lobby.rng = ChaChaRng::seed_from_u64(seed);
+      let monty = ai::monty::with_parameters(0.7, 10, 1, ai::monty::RolloutPolicy::Random);
+      add_requested_ai(lobby, Box::new(monty), 1);
+      start_game(lobby);
+      let archive = GameArchive::open(None);
+      let mut active_lobbies = ActiveLobbies::default();
+      active_lobbies.track(lobby_id, &lobbies[&lobby_id]);
+      while !active_lobbies.ai_turns.is_empty() {
+         ai_play(&mut lobbies, &mut active_lobbies.ai_turns, false, None, &archive, None);
+      }
+      lobbies[&lobby_id].last_game.clone().unwrap()
+   }
+
+   #[test]
+   fn seeded_lobbies_play_the_same_game() {
+      let game = play_seeded_game(7);
+      let again = play_seeded_game(7);
+      assert_eq!(game.opening.seed, again.opening.seed);
+      assert_eq!(game.opening.hands, again.opening.hands);
+      assert_eq!(game.history, again.history);
+      assert_ne!(game.opening.seed, play_seeded_game(8).opening.seed);
+   }
+
+   #[test]
+   fn lobby_stats_outlive_the_players_they_count() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            draw_rules: DrawRules {
+               max_repetitions: None,
+               max_turns: None,
+            },
+            ..new_lobby("Series")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      start_game(lobby);
+      let archive = GameArchive::open(None);
+      let mut active_lobbies = ActiveLobbies::default();
+      active_lobbies.track(lobby_id, &lobbies[&lobby_id]);
+      while !active_lobbies.ai_turns.is_empty() {
+         ai_play(&mut lobbies, &mut active_lobbies.ai_turns, false, None, &archive, None);
+      }
+
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      let stats = lobby.stats();
+      assert_eq!(stats.games_completed, 1);
+      assert_eq!(stats.total_players_seen, 2);
+      assert_eq!(stats.seat_placements.len(), 2);
+      let mut placements: Vec<u8> = stats
+         .seat_placements
+         .iter()
+         .map(|seat| {
+            assert_eq!(seat.placements.len(), 1);
+            seat.placements[0]
+         })
+         .collect();
+      placements.sort_unstable();
+      assert_eq!(placements, vec![0, 1]);
+
+      // Whoever takes a seat afterwards is counted, but hasn't placed in anything yet
+      let leaver = lobby.players_by_turn_num[&1];
+      remove_player(leaver, lobby, None);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      let stats = lobby.stats();
+      assert_eq!(stats.total_players_seen, 3);
+      assert_eq!(stats.seat_placements[1].slot, 1);
+      assert!(stats.seat_placements[1].placements.is_empty());
+   }
+
+   /// A lobby of a human, whose connection has gone away without closing, and a player who disconnected
+   #[cfg(all(test, unix))]
+   fn lobby_mid_game_with_dead_connections() -> (IndexMap<LobbyId, Lobby>, LobbyId, u32) {
+      let (outgoing, incoming) = std::sync::mpsc::channel();
+      drop(incoming);
+      let sender = Sender::new(Transport::Local(outgoing));
+      let connection_id = sender.connection_id();
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![sender]),
+         NewLobbyMessage {
+            after_game: AfterGamePolicy::CloseAfterSecs(60),
+            ..new_lobby("Unlucky")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      start_game(lobby);
+      let departed_id = lobby.players_by_turn_num[&1];
+      lobby.players.get_mut(&departed_id).unwrap().connection = Connection::Disconnected(DisconnectedState {
+         time: Instant::now(),
+         reason: DisconnectedReason::TimedOut,
+      });
+      (lobbies, lobby_id, connection_id)
+   }
+
+   #[cfg(all(test, unix))]
+   fn assert_game_settled(lobby: &Lobby, connection_id: u32) {
+      assert!(lobby.game.is_none());
+      assert_eq!(lobby.games_completed, 1);
+      assert!(lobby.closes_at.is_some());
+      assert_eq!(lobby.players.len(), 1);
+      assert_eq!(lobby.players_by_turn_num.keys().collect::<Vec<_>>(), [&0]);
+      // Telling the owner was tried, and failed, only after all that
+      assert!(SEND_FAILURES.read().unwrap()[&connection_id] > 0);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn games_end_cleanly_when_nobody_can_be_told() {
+      let (mut lobbies, lobby_id, connection_id) = lobby_mid_game_with_dead_connections();
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      let outcome = PlayOutcome {
+         game_over: true,
+         ..PlayOutcome::default()
+      };
+      after_play(lobby, 0, outcome, None, None, &GameArchive::open(None), None);
+      assert_game_settled(lobby, connection_id);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn concessions_end_games_cleanly_when_nobody_can_be_told() {
+      let (mut lobbies, lobby_id, connection_id) = lobby_mid_game_with_dead_connections();
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      concede(lobby, 1, &GameArchive::open(None));
+      assert_game_settled(lobby, connection_id);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn plays_settle_the_lobby_when_a_player_cant_be_told() {
+      let dead_sender = || {
+         let (outgoing, incoming) = std::sync::mpsc::channel();
+         drop(incoming);
+         Sender::new(Transport::Local(outgoing))
+      };
+      let owner = dead_sender();
+      let connection_id = owner.connection_id();
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![owner]),
+         NewLobbyMessage {
+            max_players: 3,
+            draw_rules: DrawRules {
+               max_repetitions: None,
+               max_turns: None,
+            },
+            ..new_lobby("Unheard")
+         },
+      );
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      lobby.spectators.push(dead_sender());
+      let (outgoing, incoming) = std::sync::mpsc::channel();
+      lobby.spectators.push(Sender::new(Transport::Local(outgoing)));
+      add_requested_ai(lobby, Box::new(ai::random::new()), 1);
+      add_requested_ai(lobby, Box::new(ai::random::new()), 2);
+      start_game(lobby);
+      let departed_id = *lobby.players.keys().find(|x| **x != owner_id).unwrap();
+      lobby.players.get_mut(&departed_id).unwrap().connection = Connection::Disconnected(DisconnectedState {
+         time: Instant::now(),
+         reason: DisconnectedReason::TimedOut,
+      });
+      let failures = || SEND_FAILURES.read().unwrap().get(&connection_id).copied().unwrap_or(0);
+
+      let archive = GameArchive::open(None);
+      while let Some(gs) = lobby.game.as_mut() {
+         let turns_taken = gs.turns_taken;
+         let failures_before = failures();
+         let seq = lobby.journal.latest_seq();
+         let slot = gs.active_player;
+         let outcome = take_random_turn(gs);
+         after_play(lobby, slot, outcome, None, None, &archive, None);
+
+         // The play is journaled for the players to catch up on, whether or not anyone heard about it
+         let played = lobby.journal.since(seq).unwrap().next().unwrap();
+         let played = serde_json::from_slice::<serde_json::Value>(played).unwrap();
+         assert_eq!(played["PublicGameStateEvent"]["journal_seq"], seq + 1);
+         assert!(failures() > failures_before);
+         match lobby.game {
+            Some(ref gs) => {
+               assert_eq!(gs.turns_taken, turns_taken + 1);
+               assert_eq!(played["PublicGameStateEvent"]["active_player"], gs.active_player);
+               // Nobody is let go until the game is over
+               assert_eq!(lobby.players.len(), 3);
+               assert_eq!(lobby.players_by_turn_num.len(), 3);
+            }
+            None => assert!(outcome.game_over),
+         }
+      }
+      assert_eq!(lobby.games_completed, 1);
+      assert!(!lobby.players.contains_key(&departed_id));
+      assert_eq!(lobby.players.len(), 2);
+      assert!(lobby
+         .players
+         .iter()
+         .all(|(id, player)| lobby.players_by_turn_num[&player.turn_number] == *id));
+      // Those who can still be told are told everything, right up to the end
+      assert!(incoming
+         .try_iter()
+         .flatten()
+         .any(|x| x.starts_with(br#"{"GameCompleteEvent""#)));
+   }
+
+   #[test]
+   fn lobby_pages_past_the_end_are_empty() {
+      let mut lobbies = IndexMap::new();
+      for name in &["First", "Second", "Third"] {
+         create_lobby(&mut lobbies, Connection::Connected(vec![]), new_lobby(name));
+      }
+      let snapshot = LobbyListSnapshot::new(&lobbies);
+      let page = |page, page_size| {
+         serde_json::from_slice::<serde_json::Value>(&snapshot.page(page, page_size).unwrap()).unwrap()
+      };
+
+      let first = page(0, 2);
+      assert_eq!(first["ListLobbiesResponse"]["lobbies"].as_array().unwrap().len(), 2);
+      assert_eq!(first["ListLobbiesResponse"]["has_next_page"], true);
+      let last = page(1, 2);
+      assert_eq!(last["ListLobbiesResponse"]["lobbies"].as_array().unwrap().len(), 1);
+      assert_eq!(last["ListLobbiesResponse"]["has_next_page"], false);
+      for &(page_number, page_size) in &[(u64::MAX, 2), (u64::MAX, MAX_LOBBY_PAGE_SIZE), (u64::MAX / 2, 2)] {
+         let beyond = page(page_number, page_size);
+         assert!(beyond["ListLobbiesResponse"]["lobbies"].as_array().unwrap().is_empty());
+         assert_eq!(beyond["ListLobbiesResponse"]["has_next_page"], false);
+         assert_eq!(beyond["ListLobbiesResponse"]["total_lobbies"], 3);
+      }
+   }
+
+   #[test]
+   fn lobby_pages_are_cached_until_the_next_snapshot() {
+      let mut lobbies = IndexMap::new();
+      create_lobby(&mut lobbies, Connection::Connected(vec![]), new_lobby("First"));
+      let lobby_list = LobbyList::default();
+      lobby_list.snapshot.store(Arc::new(LobbyListSnapshot::new(&lobbies)));
+
+      let snapshot = lobby_list.snapshot.load();
+      let first = snapshot.page(0, 2).unwrap();
+      // A page that was already built is handed out as is
+      snapshot.pages.lock().unwrap().insert((0, 2), b"cached".to_vec());
+      assert_eq!(snapshot.page(0, 2).unwrap(), b"cached");
+      for page in 1..100 {
+         snapshot.page(page, 2).unwrap();
+      }
+      snapshot.page(u64::MAX, 2).unwrap();
+      assert_eq!(snapshot.pages.lock().unwrap().len(), 2);
+
+      create_lobby(&mut lobbies, Connection::Connected(vec![]), new_lobby("Second"));
+      lobby_list.snapshot.store(Arc::new(LobbyListSnapshot::new(&lobbies)));
+      let snapshot = lobby_list.snapshot.load();
+      assert!(snapshot.pages.lock().unwrap().is_empty());
+      let second = snapshot.page(0, 2).unwrap();
+      assert_ne!(second, first);
+      assert!(std::str::from_utf8(&second).unwrap().contains("Second"));
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn lobby_listings_count_past_a_byte() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Connected(vec![]),
+         NewLobbyMessage {
+            turn_timer: 300,
+            ..new_lobby("Popular")
+         },
+      );
+      let (outgoing, _incoming) = std::sync::mpsc::channel();
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      lobby.spectators = (0..300)
+         .map(|_| Sender::new(Transport::Local(outgoing.clone())))
+         .collect();
+      let display = lobby.display(&lobby_id);
+      assert_eq!(display.cur_spectators, 300);
+      assert_eq!(display.turn_timer, 300);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn spectators_are_told_why_monty_played_as_it_did() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, _) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            draw_rules: DrawRules {
+               max_repetitions: None,
+               max_turns: None,
+            },
+            ..new_lobby("Explained")
+         },
+      );
+      let (outgoing, incoming) = std::sync::mpsc::channel();
+      let lobby = lobbies.get_mut(&lobby_id).unwrap();
+      lobby.spectators.push(Sender::new(Transport::Local(outgoing)));
+      let monty = ai::monty::with_parameters(0.7, 10, 1, ai::monty::RolloutPolicy::Random);
+      add_requested_ai(lobby, Box::new(monty), 1);
+      start_game(lobby);
+      // Seats are shuffled when the game starts
+      let monty_slot = lobby
+         .players
+         .values()
+         .find(|x| matches!(x.connection, Connection::Ai(ref ai) if ai.core.strategy_name() == "Monty"))
+         .unwrap()
+         .turn_number;
+      let archive = GameArchive::open(None);
+      let mut active_lobbies = ActiveLobbies::default();
+      active_lobbies.track(lobby_id, &lobbies[&lobby_id]);
+      while !active_lobbies.ai_turns.is_empty() {
+         ai_play(&mut lobbies, &mut active_lobbies.ai_turns, true, None, &archive, None);
+      }
+
+      let explanations: Vec<serde_json::Value> = incoming
+         .try_iter()
+         .flatten()
+         .filter_map(|x| match serde_json::from_slice::<serde_json::Value>(&x) {
+            Ok(serde_json::Value::Object(mut map)) => map.remove("AiDecisionDebugEvent"),
+            _ => None,
+         })
+         .collect();
+      // Random keeps no candidates, so only Monty's decisions are explained
+      let mut considered_moves = false;
+      for explanation in explanations {
+         assert_eq!(explanation["slot"], monty_slot);
+         assert_eq!(explanation["strategy"], "Monty");
+         // Forced moves are played without considering anything
+         let visits: Vec<u64> = explanation["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["visits"].as_u64().unwrap())
+            .collect();
+         considered_moves |= !visits.is_empty();
+         assert!(visits.windows(2).all(|x| x[0] >= x[1]));
+      }
+      assert!(considered_moves);
+   }
+
+   #[test]
+   fn strict_decoding_notes_unknown_fields() {
+      let mut unknown_fields = Vec::new();
+      let message = br#"{"SpectateLobby":"0"}"#;
+      assert!(decode_noting_unknown_fields(message, &mut unknown_fields).is_ok());
+      assert!(unknown_fields.is_empty());
+
+      let message = br#"{"StopSpectating":"0","Extra":1}"#;
+      assert!(decode_noting_unknown_fields(message, &mut unknown_fields).is_err());
+
+      let message = br#"{"ListLobbies":{"page":0,"pagesize":5}}"#;
+      assert!(decode_noting_unknown_fields(message, &mut unknown_fields).is_ok());
+      assert_eq!(unknown_fields, ["ListLobbies.pagesize"]);
+
+      assert!(decode_noting_unknown_fields(br#""ListLobbies" 1"#, &mut unknown_fields).is_err());
+   }
 }
@@ -0,0 +1,75 @@
+//! Serves the protocol over a Unix domain socket, so frontends on the same machine can skip TCP and WebSockets.
+//! Each message is one line of JSON, in both directions
+
+use crate::{Sender, Server, Transport};
+use log::{debug, error, info};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc;
+use ws::{CloseCode, Handler, Message};
+
+pub(crate) fn serve<F>(path: &Path, new_server: F)
+where
+   F: Fn(Sender) -> Server + Send + 'static,
+{
+   // A socket left behind by a previous run would stop us binding
+   let _ = std::fs::remove_file(path);
+   let listener = match UnixListener::bind(path) {
+      Ok(listener) => listener,
+      Err(e) => {
+         error!("Failed to bind local socket to {}: {:?}", path.display(), e);
+         return;
+      }
+   };
+   info!("Serving on local socket {}", path.display());
+   std::thread::spawn(move || {
+      for stream in listener.incoming() {
+         match stream {
+            Ok(stream) => {
+               let (outgoing, incoming) = mpsc::channel();
+               let server = new_server(Sender::new(Transport::Local(outgoing)));
+               std::thread::spawn(move || serve_connection(stream, server, incoming));
+            }
+            Err(e) => debug!("Failed to accept local connection: {:?}", e),
+         }
+      }
+   });
+}
+
+fn serve_connection(stream: UnixStream, mut server: Server, incoming: mpsc::Receiver<Option<Vec<u8>>>) {
+   debug!("A local connection opened");
+   let mut writer = match stream.try_clone() {
+      Ok(writer) => writer,
+      Err(e) => {
+         error!("Failed to split local connection: {:?}", e);
+         return;
+      }
+   };
+   // Sending is done separately so that messages from other players' connections don't wait on our reads
+   std::thread::spawn(move || {
+      while let Ok(Some(mut message)) = incoming.recv() {
+         message.push(b'\n');
+         if writer.write_all(&message).is_err() {
+            break;
+         }
+      }
+      let _ = writer.shutdown(std::net::Shutdown::Both);
+   });
+   if server.greet().is_ok() {
+      for line in BufReader::new(stream).split(b'\n') {
+         let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+         };
+         if line.is_empty() {
+            continue;
+         }
+         if server.on_message(Message::Binary(line)).is_err() {
+            break;
+         }
+      }
+   }
+   server.on_close(CloseCode::Normal, "");
+   let _ = server.out.close(CloseCode::Normal);
+}
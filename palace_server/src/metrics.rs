@@ -0,0 +1,172 @@
+use log::{debug, error, info};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the response time buckets, in seconds
+const BUCKETS_SECS: [f64; 12] = [
+   0.000_05, 0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25,
+];
+
+#[derive(Default)]
+struct Histogram {
+   /// Cumulative, as in the Prometheus format: each bucket counts everything at or below its bound
+   bucket_counts: [u64; BUCKETS_SECS.len()],
+   sum_secs: f64,
+   count: u64,
+}
+
+impl Histogram {
+   fn observe(&mut self, secs: f64) {
+      for (bound, bucket_count) in BUCKETS_SECS.iter().zip(self.bucket_counts.iter_mut()) {
+         if secs <= *bound {
+            *bucket_count += 1;
+         }
+      }
+      self.sum_secs += secs;
+      self.count += 1;
+   }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+   /// Keyed by (message type, lobby size bucket)
+   response_times: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>,
+   messages_received: AtomicU64,
+}
+
+/// Groups lobbies by how many players they hold, so the number of series stays small
+pub fn lobby_size_bucket(num_players: usize) -> &'static str {
+   match num_players {
+      0 => "none",
+      1..=2 => "1-2",
+      3..=4 => "3-4",
+      5..=8 => "5-8",
+      _ => "9+",
+   }
+}
+
+impl Metrics {
+   pub fn count_message(&self) {
+      self.messages_received.fetch_add(1, Ordering::Relaxed);
+   }
+
+   pub fn messages_received(&self) -> u64 {
+      self.messages_received.load(Ordering::Relaxed)
+   }
+
+   pub fn observe_response_time(&self, message_type: &'static str, lobby_size: &'static str, elapsed: Duration) {
+      self
+         .response_times
+         .lock()
+         .unwrap()
+         .entry((message_type, lobby_size))
+         .or_default()
+         .observe(elapsed.as_secs_f64());
+   }
+
+   /// Everything we track, in the Prometheus text exposition format
+   pub fn render(&self) -> String {
+      let mut out = String::new();
+      out.push_str("# HELP palace_messages_received_total Messages received from every connection\n");
+      out.push_str("# TYPE palace_messages_received_total counter\n");
+      out.push_str(&format!(
+         "palace_messages_received_total {}\n",
+         self.messages_received()
+      ));
+      out.push_str("# HELP palace_response_time_seconds Time taken to handle an incoming message\n");
+      out.push_str("# TYPE palace_response_time_seconds histogram\n");
+      for ((message_type, lobby_size), histogram) in self.response_times.lock().unwrap().iter() {
+         let labels = format!("message_type=\"{}\",lobby_size=\"{}\"", message_type, lobby_size);
+         for (bound, bucket_count) in BUCKETS_SECS.iter().zip(histogram.bucket_counts.iter()) {
+            out.push_str(&format!(
+               "palace_response_time_seconds_bucket{{{},le=\"{}\"}} {}\n",
+               labels, bound, bucket_count
+            ));
+         }
+         out.push_str(&format!(
+            "palace_response_time_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+            labels, histogram.count
+         ));
+         out.push_str(&format!(
+            "palace_response_time_seconds_sum{{{}}} {}\n",
+            labels, histogram.sum_secs
+         ));
+         out.push_str(&format!(
+            "palace_response_time_seconds_count{{{}}} {}\n",
+            labels, histogram.count
+         ));
+      }
+      out
+   }
+}
+
+/// Serves the metrics over plain HTTP for scraping. Every request gets the metrics, regardless of path
+pub fn serve(address: &str, metrics: Arc<Metrics>) {
+   let listener = match TcpListener::bind(address) {
+      Ok(listener) => listener,
+      Err(e) => {
+         error!("Failed to bind metrics endpoint to {}: {:?}", address, e);
+         return;
+      }
+   };
+   info!("Serving metrics on {}", address);
+   std::thread::spawn(move || {
+      for stream in listener.incoming() {
+         let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+               debug!("Failed to accept metrics connection: {:?}", e);
+               continue;
+            }
+         };
+         // We don't care what was asked for, but the request has to be read before we respond
+         let mut request = [0; 1024];
+         let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+         let _ = stream.read(&mut request);
+         let body = metrics.render();
+         let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+         );
+         if let Err(e) = stream.write_all(response.as_bytes()) {
+            debug!("Failed to send metrics: {:?}", e);
+         }
+      }
+   });
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn buckets_are_cumulative() {
+      let mut histogram = Histogram::default();
+      histogram.observe(0.003);
+      histogram.observe(0.2);
+      assert_eq!(histogram.bucket_counts[0], 0);
+      // 0.005
+      assert_eq!(histogram.bucket_counts[6], 1);
+      // 0.25
+      assert_eq!(histogram.bucket_counts[11], 2);
+      assert_eq!(histogram.count, 2);
+   }
+
+   #[test]
+   fn render_labels_each_series() {
+      let metrics = Metrics::default();
+      metrics.observe_response_time("ListLobbies", lobby_size_bucket(0), Duration::from_millis(1));
+      metrics.observe_response_time("TakeTurn", lobby_size_bucket(4), Duration::from_millis(1));
+      let rendered = metrics.render();
+      assert!(
+         rendered.contains("palace_response_time_seconds_count{message_type=\"ListLobbies\",lobby_size=\"none\"} 1")
+      );
+      assert!(rendered
+         .contains("palace_response_time_seconds_bucket{message_type=\"TakeTurn\",lobby_size=\"3-4\",le=\"+Inf\"} 1"));
+   }
+}
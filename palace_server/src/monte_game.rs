@@ -1,24 +1,68 @@
 use crate::game::{
-   is_playable_without_pickup, new_deck, top_n_cards_same, Card, CardValue, CardZone, Phase, HAND_SIZE,
+   is_playable_without_pickup, new_deck, top_n_cards_same, Card, CardSuit, CardValue, CardZone, HasCardValue, Phase,
+   HAND_SIZE, SUITS, VALUES,
 };
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use smallvec::SmallVec;
+
+/// A card in a single byte, value first so that packed cards sort the same way as cards.
+/// Simulations copy cards around constantly, so they don't use the full `Card`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PackedCard(u8);
+
+impl PackedCard {
+   pub fn value(self) -> CardValue {
+      VALUES[(self.0 / 4) as usize]
+   }
+
+   pub fn suit(self) -> CardSuit {
+      SUITS[(self.0 % 4) as usize]
+   }
+}
+
+impl HasCardValue for PackedCard {
+   fn card_value(&self) -> CardValue {
+      self.value()
+   }
+}
+
+impl From<Card> for PackedCard {
+   fn from(card: Card) -> PackedCard {
+      PackedCard(card.value as u8 * 4 + card.suit as u8)
+   }
+}
+
+impl From<PackedCard> for Card {
+   fn from(card: PackedCard) -> Card {
+      Card {
+         value: card.value(),
+         suit: card.suit(),
+         id: None,
+      }
+   }
+}
+
+/// Hands only spill onto the heap after picking up a big pile
+pub type Hand = SmallVec<[PackedCard; 16]>;
+pub type ThreeCards = SmallVec<[PackedCard; 3]>;
+pub type Pile = SmallVec<[PackedCard; 32]>;
 
 #[derive(Clone, Debug)]
 pub struct GameState {
    pub active_player: u8,
    pub num_players: u8,
-   pub hands: Box<[Vec<Card>]>,
-   pub face_up_three: Box<[Vec<Card>]>,
-   pub face_down_three: Box<[Vec<Card>]>,
-   pub pile_cards: Vec<Card>,
+   pub hands: Box<[Hand]>,
+   pub face_up_three: Box<[ThreeCards]>,
+   pub face_down_three: Box<[ThreeCards]>,
+   pub pile_cards: Pile,
    pub cur_phase: Phase,
-   pub out_players: Vec<u8>,
+   pub out_players: SmallVec<[u8; 8]>,
 }
 
 impl GameState {
    pub fn new(num_players: u8) -> GameState {
-      let mut deck: Vec<Card> = new_deck(num_players as usize).collect();
+      let mut deck: Vec<PackedCard> = new_deck(num_players as usize).map(PackedCard::from).collect();
       deck.shuffle(&mut thread_rng());
       let mut deck = deck.into_iter();
       let mut face_down_three = Vec::with_capacity(num_players as usize);
@@ -35,14 +79,14 @@ impl GameState {
          hands: hands.into_boxed_slice(),
          face_down_three: face_down_three.into_boxed_slice(),
          face_up_three: face_up_three.into_boxed_slice(),
-         pile_cards: Vec::new(),
+         pile_cards: Pile::new(),
          cur_phase: Phase::Setup,
-         out_players: Vec::with_capacity(num_players as usize),
+         out_players: SmallVec::new(),
       }
    }
 
    /// Return bool = whether or not the game is complete
-   pub fn take_turn(&mut self, cards: &[Card]) -> Result<bool, &'static str> {
+   pub fn take_turn(&mut self, cards: &[PackedCard]) -> Result<bool, &'static str> {
       match self.cur_phase {
          Phase::Setup => {
             self.choose_three_faceup(cards[0], cards[1], cards[2])?;
@@ -52,7 +96,12 @@ impl GameState {
       }
    }
 
-   fn choose_three_faceup(&mut self, card_one: Card, card_two: Card, card_three: Card) -> Result<(), &'static str> {
+   fn choose_three_faceup(
+      &mut self,
+      card_one: PackedCard,
+      card_two: PackedCard,
+      card_three: PackedCard,
+   ) -> Result<(), &'static str> {
       // Combine hand + face up cards
       let mut all_cards = self.hands[self.active_player as usize].clone();
       all_cards.extend_from_slice(&self.face_up_three[self.active_player as usize]);
@@ -61,7 +110,7 @@ impl GameState {
       let mut card_one_removed = false;
       let mut card_two_removed = false;
       let mut card_three_removed = false;
-      let mut new_hand = Vec::with_capacity(HAND_SIZE);
+      let mut new_hand = Hand::new();
       for card in all_cards {
          if card == card_one && !card_one_removed {
             card_one_removed = true;
@@ -75,7 +124,7 @@ impl GameState {
       }
 
       // Mutate state
-      self.face_up_three[self.active_player as usize] = vec![card_one, card_two, card_three];
+      self.face_up_three[self.active_player as usize] = SmallVec::from_buf([card_one, card_two, card_three]);
       self.face_up_three[self.active_player as usize].sort_unstable();
       self.hands[self.active_player as usize] = new_hand;
       self.hands[self.active_player as usize].sort_unstable();
@@ -89,7 +138,7 @@ impl GameState {
    }
 
    /// Return bool = whether or not the game is complete
-   fn make_play(&mut self, cards: &[Card]) -> Result<bool, &'static str> {
+   fn make_play(&mut self, cards: &[PackedCard]) -> Result<bool, &'static str> {
       // Figure out which zone we are retrieving cards from
       let hand_len = self.hands[self.active_player as usize].len();
       let fup3_len = self.face_up_three[self.active_player as usize].len();
@@ -105,7 +154,7 @@ impl GameState {
       };
 
       // check that play is valid
-      let play_value = cards[0].value;
+      let play_value = cards[0].value();
 
       // Remove cards from old zone
       match card_zone {
@@ -165,7 +214,7 @@ impl GameState {
       Ok(false)
    }
 
-   pub fn get_hand(&self, player_num: u8) -> &[Card] {
+   pub fn get_hand(&self, player_num: u8) -> &[PackedCard] {
       &self.hands[player_num as usize]
    }
 
@@ -187,3 +236,19 @@ impl GameState {
       self.active_player = self.next_player();
    }
 }
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn packed_cards_round_trip_and_sort_like_cards() {
+      let mut cards: Vec<Card> = new_deck(4).collect();
+      cards.sort_unstable();
+      let packed: Vec<PackedCard> = cards.iter().copied().map(PackedCard::from).collect();
+      assert!(packed.windows(2).all(|x| x[0] < x[1]));
+      for (card, packed) in cards.iter().zip(packed) {
+         assert_eq!(Card::from(packed), *card);
+      }
+   }
+}
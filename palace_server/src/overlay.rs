@@ -0,0 +1,178 @@
+//! A simplified, delayed view of each lobby over plain HTTP, for stream overlays such as OBS browser sources.
+//! It's read only and needs no connection to the game protocol
+
+use crate::game::{Card, Highlight};
+use crate::LobbyId;
+use log::{debug, error, info};
+use serde_derive::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often lobbies are looked at for changes to their feed
+pub const SAMPLE_INTERVAL_MILLIS: u64 = 500;
+
+#[derive(Serialize)]
+pub struct OverlayFeed<'a> {
+   pub lobby_name: &'a str,
+   /// By slot
+   pub seats: Vec<OverlaySeat<'a>>,
+   /// None between games
+   pub game: Option<OverlayGame>,
+}
+
+#[derive(Serialize)]
+pub struct OverlaySeat<'a> {
+   pub slot: u8,
+   pub name: &'a str,
+   /// Games won in this lobby
+   pub wins: u32,
+   /// Held, face up and face down together. None between games
+   pub cards_left: Option<u16>,
+   pub placement: Option<u8>,
+}
+
+#[derive(Serialize)]
+pub struct OverlayGame {
+   pub turns_taken: u32,
+   pub active_player: u8,
+   pub top_card: Option<Card>,
+   pub pile_size: u16,
+   pub last_highlight: Option<Highlight>,
+}
+
+/// A lobby's feed as it has changed, oldest first. Only kept as far back as the newest version that's at least
+/// `delay` old, since that's the one served
+type FeedHistory = VecDeque<(Instant, Arc<String>)>;
+
+pub struct Overlays {
+   delay: Duration,
+   feeds: Mutex<HashMap<LobbyId, FeedHistory>>,
+}
+
+impl Overlays {
+   pub fn new(delay: Duration) -> Overlays {
+      Overlays {
+         delay,
+         feeds: Mutex::new(HashMap::new()),
+      }
+   }
+
+   pub fn record(&self, lobby_id: LobbyId, feed: &OverlayFeed) {
+      self.record_at(lobby_id, serde_json::to_string(feed).unwrap(), Instant::now());
+   }
+
+   fn record_at(&self, lobby_id: LobbyId, feed: String, now: Instant) {
+      let mut feeds = self.feeds.lock().unwrap();
+      let history = feeds.entry(lobby_id).or_default();
+      if history.back().map(|(_, x)| x.as_str()) != Some(feed.as_str()) {
+         history.push_back((now, Arc::new(feed)));
+      }
+      while history.len() > 1 && now.duration_since(history[1].0) >= self.delay {
+         history.pop_front();
+      }
+   }
+
+   /// None if the lobby doesn't exist, or hasn't for long enough
+   pub fn get(&self, lobby_id: &LobbyId) -> Option<Arc<String>> {
+      self.get_at(lobby_id, Instant::now())
+   }
+
+   fn get_at(&self, lobby_id: &LobbyId, now: Instant) -> Option<Arc<String>> {
+      let feeds = self.feeds.lock().unwrap();
+      feeds
+         .get(lobby_id)?
+         .iter()
+         .rev()
+         .find(|(at, _)| now.duration_since(*at) >= self.delay)
+         .map(|(_, feed)| feed.clone())
+   }
+
+   /// Forgets lobbies that have closed
+   pub fn retain(&self, mut f: impl FnMut(&LobbyId) -> bool) {
+      self.feeds.lock().unwrap().retain(|lobby_id, _| f(lobby_id));
+   }
+}
+
+fn parse_lobby_id(path: &str) -> Option<LobbyId> {
+   // Overlays sometimes add a query string to get around caching
+   let id = path.split('?').next()?.strip_prefix("/lobbies/")?;
+   serde_json::from_value(serde_json::Value::String(id.to_owned())).ok()
+}
+
+/// Serves `GET /lobbies/<lobby id>`. Anyone can fetch it from any page, so it must not show anything spectators
+/// couldn't see
+pub fn serve(address: &str, overlays: Arc<Overlays>) {
+   let listener = match TcpListener::bind(address) {
+      Ok(listener) => listener,
+      Err(e) => {
+         error!("Failed to bind overlay endpoint to {}: {:?}", address, e);
+         return;
+      }
+   };
+   info!("Serving overlays on {}", address);
+   std::thread::spawn(move || {
+      for stream in listener.incoming() {
+         let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+               debug!("Failed to accept overlay connection: {:?}", e);
+               continue;
+            }
+         };
+         // The request line is all we need, but it can arrive in pieces
+         let mut request_line = String::new();
+         let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+         let _ = BufReader::new((&stream).take(1024)).read_line(&mut request_line);
+         let mut request_line = request_line.split(' ');
+         let feed = match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some(path)) => parse_lobby_id(path).and_then(|x| overlays.get(&x)),
+            _ => None,
+         };
+         let (status, body) = match feed {
+            Some(ref feed) => ("200 OK", feed.as_str()),
+            None => ("404 Not Found", "{}"),
+         };
+         let response = format!(
+            concat!(
+               "HTTP/1.1 {}\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\n",
+               "Cache-Control: no-store\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}"
+            ),
+            status,
+            body.len(),
+            body
+         );
+         if let Err(e) = stream.write_all(response.as_bytes()) {
+            debug!("Failed to send overlay: {:?}", e);
+         }
+      }
+   });
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn feeds_are_served_once_old_enough() {
+      let overlays = Overlays::new(Duration::from_secs(30));
+      let lobby_id: LobbyId = parse_lobby_id("/lobbies/1f").unwrap();
+      let start = Instant::now();
+      let at = |secs| start + Duration::from_secs(secs);
+      overlays.record_at(lobby_id, "first".into(), at(0));
+      assert!(overlays.get_at(&lobby_id, at(29)).is_none());
+      overlays.record_at(lobby_id, "second".into(), at(10));
+      overlays.record_at(lobby_id, "second".into(), at(20));
+      assert_eq!(*overlays.get_at(&lobby_id, at(35)).unwrap(), "first");
+      assert_eq!(*overlays.get_at(&lobby_id, at(40)).unwrap(), "second");
+      // Once the second is old enough, the first is forgotten
+      overlays.record_at(lobby_id, "third".into(), at(40));
+      assert_eq!(overlays.feeds.lock().unwrap()[&lobby_id].len(), 2);
+
+      overlays.retain(|_| false);
+      assert!(overlays.get_at(&lobby_id, at(100)).is_none());
+      assert!(parse_lobby_id("/metrics").is_none());
+   }
+}
@@ -0,0 +1,405 @@
+//! Who may send each message, checked before the message's handler runs
+
+use crate::data::*;
+use crate::{Lobby, LobbyId, PlayerId};
+use indexmap::IndexMap;
+
+/// Who a message may come from. Players are known by the player id they send
+#[derive(Clone, Copy)]
+pub enum Permission<'a> {
+   /// Any connection, in a lobby or not. The handler may still turn them away for its own reasons
+   Anyone,
+   /// Whoever has the server's admin key
+   Admin(&'a str),
+   /// Players with a seat in the lobby
+   Seated(&'a LobbyId, &'a PlayerId),
+   /// The lobby's owner
+   Owner(&'a LobbyId, &'a PlayerId),
+   /// The lobby's owner, or whoever has the server's admin key
+   OwnerOrAdmin(&'a LobbyId, Option<&'a PlayerId>, Option<&'a str>),
+   /// Connections spectating the lobby
+   Spectating(&'a LobbyId),
+}
+
+/// Why a message was turned away before its handler ran
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Denial {
+   LobbyNotFound,
+   NotSeated,
+   NotLobbyOwner,
+   NotSpectating,
+   NotAdmin,
+}
+
+impl PalaceInMessage {
+   /// Every message is listed, so that a new one can't be added without deciding who may send it
+   pub fn permission(&self) -> Permission<'_> {
+      match self {
+         PalaceInMessage::NewLobby(_) => Permission::Anyone,
+         PalaceInMessage::JoinLobby(_) => Permission::Anyone,
+         PalaceInMessage::JoinWithToken(_) => Permission::Anyone,
+         PalaceInMessage::CreateJoinToken(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::ListLobbies(_) => Permission::Anyone,
+         PalaceInMessage::StartGame(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::TakeTurn(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         // The player id is what proves who they are, and being turned away has reasons of its own
+         PalaceInMessage::Reconnect(_) => Permission::Anyone,
+         PalaceInMessage::AttachToPlayer(_) => Permission::Anyone,
+         PalaceInMessage::RequestAi(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::KickPlayer(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::SwapAiStrategy(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::SetSeatClosed(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         // The owner aborts outright, while anyone else votes to
+         PalaceInMessage::AbortGame(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::Concede(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::VoteSkipTurn(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::TransferOwnership(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::SpectateLobby(_) => Permission::Anyone,
+         PalaceInMessage::StopSpectating(_) => Permission::Anyone,
+         PalaceInMessage::TakeSeat(_) => Permission::Anyone,
+         PalaceInMessage::QueueForSeat(_) => Permission::Anyone,
+         PalaceInMessage::SetLobbyDescription(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::GetLobbyPlayers(_) => Permission::Anyone,
+         PalaceInMessage::Announce(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::SetDrainMode(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::SubscribeServerStats(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::GetStatsSummaries(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::GetLobbyStats(x) => {
+            Permission::OwnerOrAdmin(&x.lobby_id, x.player_id.as_ref(), x.admin_key.as_deref())
+         }
+         // Anyone who played the lobby's last game, even if they've since left
+         PalaceInMessage::AnalyzeGame(_) => Permission::Anyone,
+         PalaceInMessage::RequestHint(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::RewindTurn(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::PredictPlay(x) => Permission::Spectating(&x.lobby_id),
+         PalaceInMessage::MergeLobby(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::AnswerMerge(x) => Permission::Owner(&x.lobby_id, &x.player_id),
+         PalaceInMessage::RotateId(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::CatchUp(x) => Permission::Seated(&x.lobby_id, &x.player_id),
+         PalaceInMessage::ObserveLobby(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::StopObservingLobby(_) => Permission::Anyone,
+         PalaceInMessage::ScheduleEvent(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::UnscheduleEvent(x) => Permission::Admin(&x.admin_key),
+         PalaceInMessage::ListEvents(_) => Permission::Anyone,
+         PalaceInMessage::RegisterForEvent(_) => Permission::Anyone,
+      }
+   }
+}
+
+/// `is_admin` says whether a key is the server's admin key, and `is_spectating` whether the sender spectates a lobby
+pub fn check(
+   permission: Permission,
+   lobbies: &IndexMap<LobbyId, Lobby>,
+   is_admin: impl Fn(&str) -> bool,
+   is_spectating: impl Fn(&LobbyId) -> bool,
+) -> Result<(), Denial> {
+   let lobby = |lobby_id| lobbies.get(lobby_id).ok_or(Denial::LobbyNotFound);
+   match permission {
+      Permission::Anyone => Ok(()),
+      Permission::Admin(admin_key) => require(is_admin(admin_key), Denial::NotAdmin),
+      Permission::Seated(lobby_id, player_id) => {
+         require(lobby(lobby_id)?.players.contains_key(player_id), Denial::NotSeated)
+      }
+      Permission::Owner(lobby_id, player_id) => require(lobby(lobby_id)?.owner == *player_id, Denial::NotLobbyOwner),
+      Permission::OwnerOrAdmin(lobby_id, player_id, admin_key) => {
+         let owner = lobby(lobby_id)?.owner;
+         let is_admin = matches!(admin_key, Some(x) if is_admin(x));
+         require(is_admin || player_id == Some(&owner), Denial::NotLobbyOwner)
+      }
+      Permission::Spectating(lobby_id) => {
+         lobby(lobby_id)?;
+         require(is_spectating(lobby_id), Denial::NotSpectating)
+      }
+   }
+}
+
+fn require(permitted: bool, denial: Denial) -> Result<(), Denial> {
+   if permitted {
+      Ok(())
+   } else {
+      Err(denial)
+   }
+}
+
+// Denials in the terms of each message's own error. A message's permission only turns it away for some of the
+// reasons, so the rest are never seen
+
+impl From<Denial> for CreateJoinTokenError {
+   fn from(denial: Denial) -> CreateJoinTokenError {
+      match denial {
+         Denial::LobbyNotFound => CreateJoinTokenError::LobbyNotFound,
+         _ => CreateJoinTokenError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for StartGameError {
+   fn from(denial: Denial) -> StartGameError {
+      match denial {
+         Denial::LobbyNotFound => StartGameError::LobbyNotFound,
+         _ => StartGameError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for TakeTurnError {
+   fn from(denial: Denial) -> TakeTurnError {
+      match denial {
+         Denial::LobbyNotFound => TakeTurnError::LobbyNotFound,
+         _ => TakeTurnError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for RequestAiError {
+   fn from(denial: Denial) -> RequestAiError {
+      match denial {
+         Denial::LobbyNotFound => RequestAiError::LobbyNotFound,
+         _ => RequestAiError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for KickPlayerError {
+   fn from(denial: Denial) -> KickPlayerError {
+      match denial {
+         Denial::LobbyNotFound => KickPlayerError::LobbyNotFound,
+         _ => KickPlayerError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for SwapAiStrategyError {
+   fn from(denial: Denial) -> SwapAiStrategyError {
+      match denial {
+         Denial::LobbyNotFound => SwapAiStrategyError::LobbyNotFound,
+         _ => SwapAiStrategyError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for SetSeatClosedError {
+   fn from(denial: Denial) -> SetSeatClosedError {
+      match denial {
+         Denial::LobbyNotFound => SetSeatClosedError::LobbyNotFound,
+         _ => SetSeatClosedError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for AbortGameError {
+   fn from(denial: Denial) -> AbortGameError {
+      match denial {
+         Denial::LobbyNotFound => AbortGameError::LobbyNotFound,
+         _ => AbortGameError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for ConcedeError {
+   fn from(denial: Denial) -> ConcedeError {
+      match denial {
+         Denial::LobbyNotFound => ConcedeError::LobbyNotFound,
+         _ => ConcedeError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for VoteSkipTurnError {
+   fn from(denial: Denial) -> VoteSkipTurnError {
+      match denial {
+         Denial::LobbyNotFound => VoteSkipTurnError::LobbyNotFound,
+         _ => VoteSkipTurnError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for TransferOwnershipError {
+   fn from(denial: Denial) -> TransferOwnershipError {
+      match denial {
+         Denial::LobbyNotFound => TransferOwnershipError::LobbyNotFound,
+         _ => TransferOwnershipError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for SetLobbyDescriptionError {
+   fn from(denial: Denial) -> SetLobbyDescriptionError {
+      match denial {
+         Denial::LobbyNotFound => SetLobbyDescriptionError::LobbyNotFound,
+         _ => SetLobbyDescriptionError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for AnnounceError {
+   fn from(_: Denial) -> AnnounceError {
+      AnnounceError::NotAdmin
+   }
+}
+
+impl From<Denial> for SetDrainModeError {
+   fn from(_: Denial) -> SetDrainModeError {
+      SetDrainModeError::NotAdmin
+   }
+}
+
+impl From<Denial> for SubscribeServerStatsError {
+   fn from(_: Denial) -> SubscribeServerStatsError {
+      SubscribeServerStatsError::NotAdmin
+   }
+}
+
+impl From<Denial> for GetStatsSummariesError {
+   fn from(_: Denial) -> GetStatsSummariesError {
+      GetStatsSummariesError::NotAdmin
+   }
+}
+
+impl From<Denial> for GetLobbyStatsError {
+   fn from(denial: Denial) -> GetLobbyStatsError {
+      match denial {
+         Denial::LobbyNotFound => GetLobbyStatsError::LobbyNotFound,
+         _ => GetLobbyStatsError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for RequestHintError {
+   fn from(denial: Denial) -> RequestHintError {
+      match denial {
+         Denial::LobbyNotFound => RequestHintError::LobbyNotFound,
+         _ => RequestHintError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for RewindTurnError {
+   fn from(denial: Denial) -> RewindTurnError {
+      match denial {
+         Denial::LobbyNotFound => RewindTurnError::LobbyNotFound,
+         _ => RewindTurnError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for PredictPlayError {
+   fn from(denial: Denial) -> PredictPlayError {
+      match denial {
+         Denial::LobbyNotFound => PredictPlayError::LobbyNotFound,
+         _ => PredictPlayError::NotSpectating,
+      }
+   }
+}
+
+impl From<Denial> for MergeLobbyError {
+   fn from(denial: Denial) -> MergeLobbyError {
+      match denial {
+         Denial::LobbyNotFound => MergeLobbyError::LobbyNotFound,
+         _ => MergeLobbyError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for AnswerMergeError {
+   fn from(denial: Denial) -> AnswerMergeError {
+      match denial {
+         Denial::LobbyNotFound => AnswerMergeError::LobbyNotFound,
+         _ => AnswerMergeError::NotLobbyOwner,
+      }
+   }
+}
+
+impl From<Denial> for RotateIdError {
+   fn from(denial: Denial) -> RotateIdError {
+      match denial {
+         Denial::LobbyNotFound => RotateIdError::LobbyNotFound,
+         _ => RotateIdError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for CatchUpError {
+   fn from(denial: Denial) -> CatchUpError {
+      match denial {
+         Denial::LobbyNotFound => CatchUpError::LobbyNotFound,
+         _ => CatchUpError::PlayerNotFound,
+      }
+   }
+}
+
+impl From<Denial> for ObserveLobbyError {
+   fn from(_: Denial) -> ObserveLobbyError {
+      ObserveLobbyError::NotAdmin
+   }
+}
+
+impl From<Denial> for ScheduleEventError {
+   fn from(_: Denial) -> ScheduleEventError {
+      ScheduleEventError::NotAdmin
+   }
+}
+
+impl From<Denial> for UnscheduleEventError {
+   fn from(_: Denial) -> UnscheduleEventError {
+      UnscheduleEventError::NotAdmin
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::{ai, create_lobby, AiState, Connection};
+
+   #[test]
+   fn owners_admins_and_spectators_are_told_apart() {
+      let mut lobbies = IndexMap::new();
+      let (lobby_id, owner_id) = create_lobby(
+         &mut lobbies,
+         Connection::Ai(AiState {
+            core: Box::new(ai::random::new()),
+            is_clandestine: false,
+         }),
+         NewLobbyMessage {
+            max_players: 4,
+            ..crate::test::new_lobby("Lobby")
+         },
+      );
+      let stranger_id = PlayerId(0);
+      let missing_lobby_id = LobbyId(0);
+      let check = |permission| check(permission, &lobbies, |x| x == "secret", |_| false);
+
+      assert_eq!(check(Permission::Owner(&lobby_id, &owner_id)), Ok(()));
+      assert_eq!(
+         check(Permission::Owner(&lobby_id, &stranger_id)),
+         Err(Denial::NotLobbyOwner)
+      );
+      assert_eq!(
+         check(Permission::Owner(&missing_lobby_id, &owner_id)),
+         Err(Denial::LobbyNotFound)
+      );
+      assert_eq!(check(Permission::Seated(&lobby_id, &owner_id)), Ok(()));
+      assert_eq!(
+         check(Permission::Seated(&lobby_id, &stranger_id)),
+         Err(Denial::NotSeated)
+      );
+      assert_eq!(check(Permission::Spectating(&lobby_id)), Err(Denial::NotSpectating));
+      assert_eq!(check(Permission::Admin("guess")), Err(Denial::NotAdmin));
+      assert_eq!(check(Permission::OwnerOrAdmin(&lobby_id, None, Some("secret"))), Ok(()));
+      assert_eq!(
+         check(Permission::OwnerOrAdmin(&lobby_id, Some(&stranger_id), None)),
+         Err(Denial::NotLobbyOwner)
+      );
+
+      // Handlers hear about denials in their own terms
+      assert!(matches!(
+         KickPlayerError::from(Denial::NotLobbyOwner),
+         KickPlayerError::NotLobbyOwner
+      ));
+      assert!(matches!(
+         TakeTurnError::from(Denial::NotSeated),
+         TakeTurnError::PlayerNotFound
+      ));
+   }
+}
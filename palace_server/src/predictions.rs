@@ -0,0 +1,123 @@
+//! Spectators guessing at the active player's next play, scored once it's made
+
+use crate::data::{PredictedPlay, PredictionResultsEvent, PredictionScoreEvent, PredictionTally};
+use crate::game::{GameState, Turn};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+struct Guess {
+   /// `GameState::turns_taken` when the guess was made
+   turns_taken: u32,
+   play: PredictedPlay,
+}
+
+/// Spectators are told apart by their connection
+#[derive(Default)]
+pub(crate) struct Predictions {
+   guesses: HashMap<u32, Guess>,
+   /// Correct guesses this game
+   scores: HashMap<u32, u32>,
+}
+
+impl Predictions {
+   pub fn guess(&mut self, connection_id: u32, turns_taken: u32, play: PredictedPlay) {
+      self.guesses.insert(connection_id, Guess { turns_taken, play });
+   }
+
+   /// For when the play being guessed at won't be made, such as when the player concedes
+   pub fn discard_guesses(&mut self) {
+      self.guesses.clear();
+   }
+
+   pub fn reset(&mut self) {
+      self.guesses.clear();
+      self.scores.clear();
+   }
+
+   /// Scores the guesses at the play `gs` was just left by, with the score for each spectator who guessed.
+   /// None if nobody guessed
+   pub fn score(&mut self, gs: &GameState) -> Option<(PredictionResultsEvent, Vec<(u32, PredictionScoreEvent)>)> {
+      let played = match gs.history.last() {
+         Some(Turn::Play(cards)) => match cards.first() {
+            Some(card) => PredictedPlay::Cards(card.value, cards.len() as u8),
+            None => PredictedPlay::FaceDown,
+         },
+         _ => return None,
+      };
+      let mut tallies: HashMap<PredictedPlay, u16> = HashMap::new();
+      let mut scores = Vec::new();
+      let mut correct = 0;
+      for (connection_id, guess) in self.guesses.drain() {
+         // Guesses at earlier plays are stale
+         if guess.turns_taken + 1 != gs.turns_taken {
+            continue;
+         }
+         *tallies.entry(guess.play).or_default() += 1;
+         let score = self.scores.entry(connection_id).or_default();
+         let is_correct = guess.play == played;
+         if is_correct {
+            *score += 1;
+            correct += 1;
+         }
+         scores.push((
+            connection_id,
+            PredictionScoreEvent {
+               correct: is_correct,
+               score: *score,
+            },
+         ));
+      }
+      if scores.is_empty() {
+         return None;
+      }
+      let mut tallies: Vec<_> = tallies
+         .into_iter()
+         .map(|(play, guesses)| PredictionTally { play, guesses })
+         .collect();
+      tallies.sort_unstable_by_key(|x| (Reverse(x.guesses), x.play));
+      let results = PredictionResultsEvent {
+         turn: gs.turns_taken,
+         played,
+         tallies,
+         correct,
+      };
+      Some((results, scores))
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::game::{Card, CardSuit, CardValue, Phase};
+
+   #[test]
+   fn scores_only_guesses_at_the_play_made() {
+      let mut gs = GameState::new(2);
+      gs.cur_phase = Phase::Play;
+      let card = |suit| Card {
+         value: CardValue::Nine,
+         suit,
+         id: None,
+      };
+      gs.hands[0] = vec![card(CardSuit::Clubs), card(CardSuit::Hearts), card(CardSuit::Spades)];
+      gs.turns_taken = 1;
+      let mut predictions = Predictions::default();
+      // Made before an earlier play, so it has nothing to do with this one
+      predictions.guess(4, 0, PredictedPlay::FaceDown);
+      predictions.guess(1, 1, PredictedPlay::Cards(CardValue::Nine, 2));
+      predictions.guess(2, 1, PredictedPlay::Cards(CardValue::Nine, 1));
+      predictions.guess(3, 1, PredictedPlay::Cards(CardValue::Nine, 2));
+      gs.take_turn(&[card(CardSuit::Clubs), card(CardSuit::Hearts)]).unwrap();
+
+      let (results, mut scores) = predictions.score(&gs).unwrap();
+      assert_eq!(results.played, PredictedPlay::Cards(CardValue::Nine, 2));
+      assert_eq!(results.correct, 2);
+      assert_eq!(results.tallies[0].guesses, 2);
+      assert_eq!(results.tallies.len(), 2);
+      scores.sort_unstable_by_key(|x| x.0);
+      let correct: Vec<_> = scores.iter().map(|(id, x)| (*id, x.correct, x.score)).collect();
+      assert_eq!(correct, [(1, true, 1), (2, false, 0), (3, true, 1)]);
+      assert!(predictions.score(&gs).is_none());
+   }
+}
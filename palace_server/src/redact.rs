@@ -0,0 +1,88 @@
+use serde_json::Value;
+
+const REDACTED: &str = "<redacted>";
+
+/// Object keys whose values must never show up in logs.
+/// Message types and ids are left intact so logs remain useful for debugging.
+const SENSITIVE_KEYS: [&str; 6] = [
+   "password",
+   "admin_key",
+   "hand",
+   "HandEvent",
+   "token",
+   "CreateJoinTokenResponse",
+];
+
+/// Replace the value of every sensitive key (at any depth) with a placeholder
+pub fn redact(value: &mut Value) {
+   match value {
+      Value::Object(map) => {
+         for (key, inner) in map.iter_mut() {
+            if SENSITIVE_KEYS.contains(&key.as_str()) {
+               *inner = Value::String(REDACTED.into());
+            } else {
+               redact(inner);
+            }
+         }
+      }
+      Value::Array(values) => {
+         for inner in values.iter_mut() {
+            redact(inner);
+         }
+      }
+      _ => (),
+   }
+}
+
+/// Render a raw JSON message for logging with all sensitive fields masked.
+/// Anything that can't be parsed is not echoed back at all, as we can't tell what is in it.
+pub fn redacted_string(bytes: &[u8]) -> String {
+   match serde_json::from_slice::<Value>(bytes) {
+      Ok(mut value) => {
+         redact(&mut value);
+         value.to_string()
+      }
+      Err(_) => format!("<{} bytes of undecodable data>", bytes.len()),
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn password_is_masked_but_ids_are_kept() {
+      let message = br#"{"JoinLobby":{"lobby_id":"abc","player_name":"Matt","password":"hunter2"}}"#;
+      let redacted = redacted_string(message);
+      assert!(!redacted.contains("hunter2"));
+      assert!(redacted.contains("JoinLobby"));
+      assert!(redacted.contains("abc"));
+      assert!(redacted.contains("Matt"));
+   }
+
+   #[test]
+   fn hands_are_masked() {
+      let game_start = br#"{"GameStartEvent":{"hand":[{"value":"Ace","suit":"Spades"}],"turn_number":1,"players":{}}}"#;
+      let redacted = redacted_string(game_start);
+      assert!(!redacted.contains("Ace"));
+      assert!(redacted.contains("turn_number"));
+
+      let hand_event = br#"{"HandEvent":[{"value":"Ace","suit":"Spades"}]}"#;
+      assert!(!redacted_string(hand_event).contains("Ace"));
+   }
+
+   #[test]
+   fn join_tokens_are_masked() {
+      let join = br#"{"JoinWithToken":{"lobby_id":"abc","token":"f00d","player_name":"Matt"}}"#;
+      assert!(!redacted_string(join).contains("f00d"));
+
+      let created = br#"{"CreateJoinTokenResponse":{"Ok":"f00d"}}"#;
+      assert!(!redacted_string(created).contains("f00d"));
+   }
+
+   #[test]
+   fn undecodable_data_is_not_echoed() {
+      let redacted = redacted_string(b"password: hunter2");
+      assert!(!redacted.contains("hunter2"));
+   }
+}
@@ -0,0 +1,75 @@
+//! Estimates of how long it takes a message to reach a connection and come back, from websocket pings
+
+use std::time::{Duration, Instant};
+
+/// Assumed until a connection has answered a ping
+const DEFAULT_ROUND_TRIP_MILLIS: u64 = 500;
+/// Connections slower than this aren't waited on any longer, nor are pings that were never answered
+const MAX_ROUND_TRIP_MILLIS: u64 = 5000;
+
+#[derive(Default)]
+pub struct RoundTrip {
+   /// When the ping we're waiting to hear back about was sent
+   pinged_at: Option<Instant>,
+   /// Smoothed like TCP's, so that one slow pong doesn't swing it
+   estimate: Option<Duration>,
+}
+
+impl RoundTrip {
+   /// Returns false if a ping is still waiting on an answer, so there's no need to send another
+   pub fn ping(&mut self, now: Instant) -> bool {
+      let max = Duration::from_millis(MAX_ROUND_TRIP_MILLIS);
+      if matches!(self.pinged_at, Some(pinged_at) if now.duration_since(pinged_at) < max) {
+         return false;
+      }
+      self.pinged_at = Some(now);
+      true
+   }
+
+   /// Pongs that weren't asked for are ignored, since some clients send them as a heartbeat
+   pub fn pong(&mut self, now: Instant) {
+      let sample = match self.pinged_at.take() {
+         Some(pinged_at) => now.duration_since(pinged_at),
+         None => return,
+      };
+      self.estimate = Some(match self.estimate {
+         Some(estimate) => (estimate * 7 + sample) / 8,
+         None => sample,
+      });
+   }
+
+   pub fn estimate(&self) -> Duration {
+      self
+         .estimate
+         .unwrap_or_else(|| Duration::from_millis(DEFAULT_ROUND_TRIP_MILLIS))
+         .min(Duration::from_millis(MAX_ROUND_TRIP_MILLIS))
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn estimates_are_smoothed() {
+      let start = Instant::now();
+      let at = |millis| start + Duration::from_millis(millis);
+      let mut round_trip = RoundTrip::default();
+      assert_eq!(round_trip.estimate(), Duration::from_millis(DEFAULT_ROUND_TRIP_MILLIS));
+      round_trip.pong(at(0));
+      assert_eq!(round_trip.estimate(), Duration::from_millis(DEFAULT_ROUND_TRIP_MILLIS));
+
+      assert!(round_trip.ping(at(0)));
+      assert!(!round_trip.ping(at(50)));
+      round_trip.pong(at(80));
+      assert_eq!(round_trip.estimate(), Duration::from_millis(80));
+
+      assert!(round_trip.ping(at(1000)));
+      round_trip.pong(at(1880));
+      assert_eq!(round_trip.estimate(), Duration::from_millis(180));
+
+      // An unanswered ping is given up on eventually
+      assert!(round_trip.ping(at(2000)));
+      assert!(round_trip.ping(at(2000 + MAX_ROUND_TRIP_MILLIS)));
+   }
+}
@@ -0,0 +1,85 @@
+//! Tutorial games with fixed cards, for teaching particular situations. Each is defined by a file in `scenarios/`.
+//! The human who creates the lobby has seat 0 and the AI opponents fill the rest
+
+use crate::ai::{random, scripted, AiResources, AiStrategy, PalaceAi};
+use crate::game::{Card, GameState, Layout};
+use lazy_static::lazy_static;
+use serde_derive::Deserialize;
+
+lazy_static! {
+   static ref SCENARIOS: Vec<Scenario> = [
+      include_str!("scenarios/sevens.json"),
+      include_str!("scenarios/burning.json"),
+      include_str!("scenarios/face_down_gamble.json"),
+   ]
+   .iter()
+   .map(|x| serde_json::from_str(x).expect("Failed to parse scenario"))
+   .collect();
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Scenario {
+   pub name: String,
+   /// Used for the lobby's description when the owner doesn't give one
+   pub description: String,
+   layout: Layout,
+   /// Seated after the human, in order
+   opponents: Vec<Opponent>,
+}
+
+#[derive(Deserialize)]
+struct Opponent {
+   strategy: AiStrategy,
+   /// Plays to make, in order, before `strategy` takes over
+   #[serde(default)]
+   script: Vec<Box<[Card]>>,
+}
+
+pub(crate) fn find(name: &str) -> Option<&'static Scenario> {
+   SCENARIOS.iter().find(|x| x.name == name)
+}
+
+impl Scenario {
+   pub fn num_players(&self) -> u8 {
+      self.opponents.len() as u8 + 1
+   }
+
+   pub fn deal(&self) -> GameState {
+      GameState::from_layout(&self.layout).expect("Scenario layouts are checked by the tests")
+   }
+
+   /// The AI for each opponent, by seat. Falls back to Random for strategies whose resources aren't loaded
+   pub fn opponents(&self, resources: &AiResources) -> Vec<Box<dyn PalaceAi + Send + Sync>> {
+      self
+         .opponents
+         .iter()
+         .map(|x| {
+            let then = x
+               .strategy
+               .instantiate(resources)
+               .unwrap_or_else(|| Box::new(random::new()));
+            if x.script.is_empty() {
+               then
+            } else {
+               Box::new(scripted::new(x.script.clone(), then))
+            }
+         })
+         .collect()
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn every_scenario_deals() {
+      assert!(!SCENARIOS.is_empty());
+      for scenario in SCENARIOS.iter() {
+         // Names are unique, so every scenario can be found
+         assert!(std::ptr::eq(find(&scenario.name).unwrap(), scenario));
+         let gs = GameState::from_layout(&scenario.layout).unwrap();
+         assert_eq!(gs.num_players, scenario.num_players(), "{}", scenario.name);
+      }
+   }
+}
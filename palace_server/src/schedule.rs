@@ -0,0 +1,280 @@
+//! Lobbies the server opens by itself at the same time every day, such as a nightly tournament.
+//! Players register ahead of time, and are seated in the lobby as soon as it opens
+
+use crate::data::{EventListing, RegisterForEventError, ScheduleEventError, ScheduledEvent};
+use crate::{Sender, DESCRIPTION_LIMIT, LOBBY_NAME_LIMIT, MAX_SEAT_QUEUE, MIN_TURN_TIMER_SECS};
+use indexmap::IndexMap;
+use log::error;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub(crate) struct Registrant {
+   pub name: String,
+   pub sender: Sender,
+}
+
+struct Entry {
+   event: ScheduledEvent,
+   /// Seconds after midnight UTC
+   opens_at: u64,
+   /// In the order they registered
+   registrants: Vec<Registrant>,
+}
+
+/// What a check of the schedule found to do
+pub(crate) enum Due {
+   /// To be announced to every connection
+   Announcement(String),
+   Opening(ScheduledEvent, Vec<Registrant>),
+}
+
+#[derive(Default)]
+pub(crate) struct Schedule {
+   entries: IndexMap<String, Entry>,
+}
+
+impl Schedule {
+   /// Events that can't be scheduled are left out
+   pub fn new(events: &[ScheduledEvent]) -> Schedule {
+      let mut schedule = Schedule::default();
+      for event in events {
+         if let Err(e) = schedule.add(event.clone()) {
+            error!("Not scheduling {}: {:?}", event.name, e);
+         }
+      }
+      schedule
+   }
+
+   /// Replaces any event with the same name, keeping who registered for it
+   pub fn add(&mut self, event: ScheduledEvent) -> Result<(), ScheduleEventError> {
+      let opens_at = check(&event)?;
+      let registrants = self
+         .entries
+         .swap_remove(&event.name)
+         .map(|x| x.registrants)
+         .unwrap_or_default();
+      self.entries.insert(
+         event.name.clone(),
+         Entry {
+            event,
+            opens_at,
+            registrants,
+         },
+      );
+      Ok(())
+   }
+
+   /// Returns who had registered for the event, or None if there's no such event
+   pub fn remove(&mut self, name: &str) -> Option<Vec<Registrant>> {
+      self.entries.shift_remove(name).map(|x| x.registrants)
+   }
+
+   /// Replaces any earlier registration from the same connection. Returns how long until the event opens
+   pub fn register(&mut self, event: &str, registrant: Registrant, now: u64) -> Result<u32, RegisterForEventError> {
+      let entry = self.entries.get(event).ok_or(RegisterForEventError::EventNotFound)?;
+      let already_registered = entry
+         .registrants
+         .iter()
+         .any(|x| x.sender.connection_id() == registrant.sender.connection_id());
+      if !already_registered && entry.registrants.len() >= usize::from(entry.event.max_players) + MAX_SEAT_QUEUE {
+         return Err(RegisterForEventError::EventFull);
+      }
+      self.unregister(registrant.sender.connection_id());
+      let entry = self.entries.get_mut(event).unwrap();
+      entry.registrants.push(registrant);
+      Ok(secs_until(entry.opens_at, now))
+   }
+
+   pub fn unregister(&mut self, connection_id: u32) {
+      for entry in self.entries.values_mut() {
+         entry.registrants.retain(|x| x.sender.connection_id() != connection_id);
+      }
+   }
+
+   pub fn is_registered(&self, connection_id: u32) -> bool {
+      self.entries.values().any(|entry| {
+         entry
+            .registrants
+            .iter()
+            .any(|x| x.sender.connection_id() == connection_id)
+      })
+   }
+
+   /// Soonest first
+   pub fn listings(&self, now: u64) -> Vec<EventListing> {
+      let mut listings: Vec<EventListing> = self
+         .entries
+         .values()
+         .map(|entry| EventListing {
+            name: entry.event.name.clone(),
+            description: entry.event.description.clone(),
+            max_players: entry.event.max_players,
+            opens_in_secs: secs_until(entry.opens_at, now),
+            num_registered: entry.registrants.len() as u16,
+         })
+         .collect();
+      listings.sort_by_key(|x| x.opens_in_secs);
+      listings
+   }
+
+   /// Announcements and openings that came due after `last` and up to `now`, both in seconds since the epoch.
+   /// Opening an event hands over its registrants, leaving it empty for the next day
+   pub fn due(&mut self, last: u64, now: u64) -> Vec<Due> {
+      let mut due = Vec::new();
+      for entry in self.entries.values_mut() {
+         let mins_before = u64::from(entry.event.announce_mins_before);
+         let announce_at = (entry.opens_at + SECS_PER_DAY - mins_before * 60) % SECS_PER_DAY;
+         if mins_before > 0 && passed(announce_at, last, now) {
+            due.push(Due::Announcement(format!(
+               "{} opens in {} minutes. Register now to be seated as soon as it does",
+               entry.event.name, mins_before
+            )));
+         }
+         if passed(entry.opens_at, last, now) {
+            due.push(Due::Opening(
+               entry.event.clone(),
+               std::mem::take(&mut entry.registrants),
+            ));
+         }
+      }
+      due
+   }
+}
+
+/// Seconds since the epoch, which are UTC
+pub fn now_secs() -> u64 {
+   SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Reads a JSON list of events, checking each one as `ScheduleEvent` would
+pub fn read(path: &Path) -> Result<Vec<ScheduledEvent>, String> {
+   let json = std::fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+   let events: Vec<ScheduledEvent> =
+      serde_json::from_slice(&json).map_err(|e| format!("Couldn't parse {}: {}", path.display(), e))?;
+   for event in &events {
+      check(event).map_err(|e| format!("Can't schedule {}: {:?}", event.name, e))?;
+   }
+   Ok(events)
+}
+
+/// The same limits as creating a lobby. Returns when the event opens, in seconds after midnight UTC
+fn check(event: &ScheduledEvent) -> Result<u64, ScheduleEventError> {
+   if event.max_players < 2 {
+      return Err(ScheduleEventError::LessThanTwoMaxPlayers);
+   }
+   if event.name.is_empty() {
+      return Err(ScheduleEventError::EmptyName);
+   }
+   if event.name.len() > LOBBY_NAME_LIMIT {
+      return Err(ScheduleEventError::NameTooLong);
+   }
+   if event.description.len() > DESCRIPTION_LIMIT {
+      return Err(ScheduleEventError::DescriptionTooLong);
+   }
+   if event.turn_timer != 0 && event.turn_timer < MIN_TURN_TIMER_SECS {
+      return Err(ScheduleEventError::TurnTimerTooShort);
+   }
+   if u64::from(event.announce_mins_before) * 60 >= SECS_PER_DAY {
+      return Err(ScheduleEventError::AnnouncementTooEarly);
+   }
+   parse_time_of_day(&event.opens_at_utc).ok_or(ScheduleEventError::BadOpeningTime)
+}
+
+/// Seconds after midnight, from "HH:MM" or "HH:MM:SS"
+fn parse_time_of_day(time: &str) -> Option<u64> {
+   let parts: Vec<u64> = time.split(':').map(|x| x.parse().ok()).collect::<Option<_>>()?;
+   let (hours, mins, secs) = match parts[..] {
+      [hours, mins] => (hours, mins, 0),
+      [hours, mins, secs] => (hours, mins, secs),
+      _ => return None,
+   };
+   if hours >= 24 || mins >= 60 || secs >= 60 {
+      return None;
+   }
+   Some(hours * 60 * 60 + mins * 60 + secs)
+}
+
+/// Whether `at` seconds after some midnight came after `last` and no later than `now`
+fn passed(at: u64, last: u64, now: u64) -> bool {
+   let since_latest = (now + SECS_PER_DAY - at) % SECS_PER_DAY;
+   matches!(now.checked_sub(since_latest), Some(latest) if latest > last)
+}
+
+fn secs_until(at: u64, now: u64) -> u32 {
+   ((at + SECS_PER_DAY - now % SECS_PER_DAY) % SECS_PER_DAY) as u32
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   fn nightly(opens_at_utc: &str, announce_mins_before: u16) -> ScheduledEvent {
+      ScheduledEvent {
+         name: "Nightly".into(),
+         opens_at_utc: opens_at_utc.into(),
+         max_players: 4,
+         turn_timer: 30,
+         description: "".into(),
+         announce_mins_before,
+      }
+   }
+
+   #[test]
+   fn opening_times_are_parsed() {
+      assert_eq!(parse_time_of_day("20:00"), Some(72000));
+      assert_eq!(parse_time_of_day("00:00:59"), Some(59));
+      assert_eq!(parse_time_of_day("24:00"), None);
+      assert_eq!(parse_time_of_day("8pm"), None);
+      assert_eq!(parse_time_of_day("20:00:00:00"), None);
+      assert!(matches!(
+         check(&nightly("20:60", 0)),
+         Err(ScheduleEventError::BadOpeningTime)
+      ));
+      assert!(matches!(
+         check(&nightly("20:00", 24 * 60)),
+         Err(ScheduleEventError::AnnouncementTooEarly)
+      ));
+   }
+
+   #[test]
+   fn events_come_due_once_a_day() {
+      let day = 20_000 * SECS_PER_DAY;
+      let eight_pm = day + 20 * 60 * 60;
+      let mut schedule = Schedule::new(&[nightly("20:00", 15)]);
+      assert_eq!(schedule.listings(eight_pm - 60)[0].opens_in_secs, 60);
+      assert_eq!(
+         schedule.listings(eight_pm + 60)[0].opens_in_secs,
+         SECS_PER_DAY as u32 - 60
+      );
+
+      assert!(schedule.due(day, eight_pm - 15 * 60 - 1).is_empty());
+      match &schedule.due(eight_pm - 15 * 60 - 1, eight_pm - 15 * 60)[..] {
+         [Due::Announcement(text)] => assert!(text.starts_with("Nightly opens in 15 minutes")),
+         _ => panic!("Expected an announcement"),
+      }
+      assert!(schedule.due(eight_pm - 15 * 60, eight_pm - 1).is_empty());
+      assert!(matches!(
+         &schedule.due(eight_pm - 1, eight_pm + 1)[..],
+         [Due::Opening(..)]
+      ));
+      assert!(schedule.due(eight_pm + 1, eight_pm + 2).is_empty());
+
+      // Again the next day, even when checks are far apart
+      assert_eq!(schedule.due(eight_pm + 2, eight_pm + SECS_PER_DAY).len(), 2);
+   }
+
+   #[test]
+   fn midnight_is_no_different() {
+      let midnight = 20_000 * SECS_PER_DAY;
+      let mut schedule = Schedule::new(&[nightly("00:00", 10)]);
+      assert_eq!(schedule.listings(midnight - 1)[0].opens_in_secs, 1);
+      assert!(matches!(
+         &schedule.due(midnight - 10 * 60 - 1, midnight - 1)[..],
+         [Due::Announcement(_)]
+      ));
+      assert!(matches!(&schedule.due(midnight - 1, midnight)[..], [Due::Opening(..)]));
+   }
+}
@@ -0,0 +1,1502 @@
+//! Example messages for both directions of the protocol, encoded exactly as the server encodes them.
+//! They're checked in under `protocol_vectors/`, so that clients in other languages can test their codecs
+//! against them. After changing the protocol, regenerate them with `--dump-test-vectors`
+
+use crate::ai::{AiStrategy, CandidateMove, MontyParameters};
+use crate::commentary::Commentary;
+use crate::data::*;
+use crate::game::{
+   Card, CardSuit, CardValue, CardZone, DrawReason, DrawRules, Highlight, HighlightKind, Phase, PublicGameState,
+};
+use crate::{JoinToken, LobbyDisplay, LobbyId, PlayerId};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// One message, and a name for it that is unique within its direction
+pub struct TestVector {
+   pub name: String,
+   pub json: String,
+}
+
+#[derive(Default)]
+struct Vectors(Vec<TestVector>);
+
+impl Vectors {
+   fn add(&mut self, name: impl Into<String>, message: &impl Serialize) {
+      self.0.push(TestVector {
+         name: name.into(),
+         json: serde_json::to_string(message).unwrap(),
+      });
+   }
+
+   /// Adds `<response>.Err.<variant>` for every error a response can carry
+   fn add_errors<E: Serialize>(
+      &mut self,
+      response: &str,
+      errors: Vec<E>,
+      wrap: impl Fn(E) -> PalaceOutMessage<'static>,
+   ) {
+      for error in errors {
+         let name = format!("{}.Err.{}", response, variant_name(&error));
+         self.add(name, &wrap(error));
+      }
+   }
+}
+
+fn variant_name(value: &impl Serialize) -> String {
+   match serde_json::to_value(value).unwrap() {
+      Value::String(name) => name,
+      Value::Object(map) => map.into_iter().next().unwrap().0,
+      x => panic!("{} is not an enum variant", x),
+   }
+}
+
+const LOBBY_ID: LobbyId = LobbyId(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+/// For messages that name a second lobby
+const OTHER_LOBBY_ID: LobbyId = LobbyId(0x89ab_cdef_0123_4567_89ab_cdef_0123_4567);
+const PLAYER_ID: PlayerId = PlayerId(0xfedc_ba98_7654_3210_fedc_ba98_7654_3210);
+/// The id a player is given in place of `PLAYER_ID`
+const OTHER_PLAYER_ID: PlayerId = PlayerId(0x7654_3210_fedc_ba98_7654_3210_fedc_ba98);
+const JOIN_TOKEN: JoinToken = JoinToken(0x0f1e_2d3c_4b5a_6978_8796_a5b4_c3d2_e1f0);
+
+fn card(value: CardValue, suit: CardSuit, id: u16) -> Card {
+   Card {
+      value,
+      suit,
+      id: Some(id),
+   }
+}
+
+/// Every message a client can send
+pub fn in_vectors() -> Vec<TestVector> {
+   let mut v = Vectors::default();
+   v.add(
+      "NewLobby",
+      &PalaceInMessage::NewLobby(NewLobbyMessage {
+         max_players: 4,
+         password: "hunter2".into(),
+         lobby_name: "Friday night".into(),
+         player_name: "Ada".into(),
+         turn_timer: 50,
+         min_human_turn_timer: 90,
+         description: "Bring snacks".into(),
+         after_game: AfterGamePolicy::CloseAfterSecs(120),
+         hints: HintPolicy::PerGame(3),
+         practice: false,
+         scenario: None,
+         draw_rules: DrawRules {
+            max_repetitions: Some(3),
+            max_turns: Some(500),
+         },
+      }),
+   );
+   v.add(
+      "JoinLobby",
+      &PalaceInMessage::JoinLobby(JoinLobbyMessage {
+         lobby_id: LOBBY_ID,
+         player_name: "Grace".into(),
+         password: "hunter2".into(),
+      }),
+   );
+   v.add(
+      "JoinWithToken",
+      &PalaceInMessage::JoinWithToken(JoinWithTokenMessage {
+         lobby_id: LOBBY_ID,
+         token: JOIN_TOKEN,
+         player_name: "Grace".into(),
+      }),
+   );
+   v.add(
+      "CreateJoinToken",
+      &PalaceInMessage::CreateJoinToken(CreateJoinTokenMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         expires_in_secs: 3600,
+         max_uses: 3,
+      }),
+   );
+   v.add(
+      "ListLobbies",
+      &PalaceInMessage::ListLobbies(ListLobbiesMessage {
+         page: 1,
+         page_size: Some(20),
+      }),
+   );
+   v.add(
+      "StartGame",
+      &PalaceInMessage::StartGame(StartGameMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         fill_with_ai: Some(AiStrategy::Random),
+      }),
+   );
+   v.add(
+      "TakeTurn",
+      &PalaceInMessage::TakeTurn(TakeTurnMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         cards: Box::new([
+            card(CardValue::Seven, CardSuit::Clubs, 20),
+            card(CardValue::Seven, CardSuit::Hearts, 22),
+         ]),
+         play_seq: Some(3),
+      }),
+   );
+   v.add(
+      "Reconnect",
+      &PalaceInMessage::Reconnect(ReconnectMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add(
+      "AttachToPlayer",
+      &PalaceInMessage::AttachToPlayer(ReconnectMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add(
+      "RequestAi",
+      &PalaceInMessage::RequestAi(RequestAiMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         num_ai: 2,
+         strategy: AiStrategy::Monty,
+         monty: Some(MontyParameters {
+            simulations: 1000,
+            simulations_per_determinization: 10,
+         }),
+      }),
+   );
+   v.add(
+      "KickPlayer",
+      &PalaceInMessage::KickPlayer(KickPlayerMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+         slot: 2,
+      }),
+   );
+   v.add(
+      "SwapAiStrategy",
+      &PalaceInMessage::SwapAiStrategy(SwapAiStrategyMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+         slot: 1,
+         strategy: AiStrategy::LowAndSteady,
+      }),
+   );
+   v.add(
+      "SetSeatClosed",
+      &PalaceInMessage::SetSeatClosed(SetSeatClosedMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+         slot: 3,
+         closed: true,
+      }),
+   );
+   v.add(
+      "AbortGame",
+      &PalaceInMessage::AbortGame(AbortGameMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add(
+      "Concede",
+      &PalaceInMessage::Concede(ConcedeMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add(
+      "VoteSkipTurn",
+      &PalaceInMessage::VoteSkipTurn(VoteSkipTurnMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add(
+      "TransferOwnership",
+      &PalaceInMessage::TransferOwnership(TransferOwnershipMessage {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+         slot: 1,
+      }),
+   );
+   v.add("SpectateLobby", &PalaceInMessage::SpectateLobby(LOBBY_ID));
+   v.add("StopSpectating", &PalaceInMessage::StopSpectating(LOBBY_ID));
+   v.add(
+      "TakeSeat",
+      &PalaceInMessage::TakeSeat(TakeSeatMessage {
+         lobby_id: LOBBY_ID,
+         player_name: "Grace".into(),
+         password: "".into(),
+      }),
+   );
+   v.add(
+      "QueueForSeat",
+      &PalaceInMessage::QueueForSeat(QueueForSeatMessage {
+         lobby_id: LOBBY_ID,
+         player_name: "Grace".into(),
+         password: "".into(),
+      }),
+   );
+   v.add(
+      "SetLobbyDescription",
+      &PalaceInMessage::SetLobbyDescription(SetLobbyDescriptionMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         description: "Beginners welcome".into(),
+      }),
+   );
+   v.add("GetLobbyPlayers", &PalaceInMessage::GetLobbyPlayers(LOBBY_ID));
+   v.add(
+      "Announce",
+      &PalaceInMessage::Announce(AnnounceMessage {
+         admin_key: "secret".into(),
+         lobby_id: Some(LOBBY_ID),
+         severity: AnnouncementSeverity::Warning,
+         text: "Restarting in 5 minutes".into(),
+      }),
+   );
+   v.add(
+      "SetDrainMode",
+      &PalaceInMessage::SetDrainMode(SetDrainModeMessage {
+         admin_key: "secret".into(),
+         draining: true,
+         shutdown_in_secs: Some(300),
+      }),
+   );
+   v.add(
+      "SubscribeServerStats",
+      &PalaceInMessage::SubscribeServerStats(SubscribeServerStatsMessage {
+         admin_key: "secret".into(),
+      }),
+   );
+   v.add(
+      "GetStatsSummaries",
+      &PalaceInMessage::GetStatsSummaries(GetStatsSummariesMessage {
+         admin_key: "secret".into(),
+      }),
+   );
+   v.add(
+      "GetLobbyStats",
+      &PalaceInMessage::GetLobbyStats(GetLobbyStatsMessage {
+         lobby_id: LOBBY_ID,
+         player_id: Some(PLAYER_ID),
+         admin_key: None,
+      }),
+   );
+   v.add(
+      "AnalyzeGame",
+      &PalaceInMessage::AnalyzeGame(AnalyzeGameMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+      }),
+   );
+   v.add(
+      "RequestHint",
+      &PalaceInMessage::RequestHint(RequestHintMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+      }),
+   );
+   v.add(
+      "RewindTurn",
+      &PalaceInMessage::RewindTurn(RewindTurnMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         turns: 1,
+      }),
+   );
+   v.add(
+      "PredictPlay",
+      &PalaceInMessage::PredictPlay(PredictPlayMessage {
+         lobby_id: LOBBY_ID,
+         play: PredictedPlay::Cards(CardValue::Seven, 2),
+      }),
+   );
+   v.add(
+      "MergeLobby",
+      &PalaceInMessage::MergeLobby(MergeLobbyMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         target_lobby_id: OTHER_LOBBY_ID,
+      }),
+   );
+   v.add(
+      "AnswerMerge",
+      &PalaceInMessage::AnswerMerge(AnswerMergeMessage {
+         lobby_id: OTHER_LOBBY_ID,
+         player_id: PLAYER_ID,
+         from_lobby_id: LOBBY_ID,
+         accept: true,
+      }),
+   );
+   v.add(
+      "RotateId",
+      &PalaceInMessage::RotateId(RotateIdMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+      }),
+   );
+   v.add(
+      "CatchUp",
+      &PalaceInMessage::CatchUp(CatchUpMessage {
+         lobby_id: LOBBY_ID,
+         player_id: PLAYER_ID,
+         since_seq: 17,
+      }),
+   );
+   v.add(
+      "ObserveLobby",
+      &PalaceInMessage::ObserveLobby(ObserveLobbyMessage {
+         admin_key: "secret".into(),
+         lobby_id: LOBBY_ID,
+         kinds: vec![LobbyEventKind::Join, LobbyEventKind::Play],
+      }),
+   );
+   v.add("StopObservingLobby", &PalaceInMessage::StopObservingLobby(LOBBY_ID));
+   v.add(
+      "ScheduleEvent",
+      &PalaceInMessage::ScheduleEvent(ScheduleEventMessage {
+         admin_key: "secret".into(),
+         event: ScheduledEvent {
+            name: "Nightly".into(),
+            opens_at_utc: "20:00".into(),
+            max_players: 8,
+            turn_timer: 30,
+            description: "Winner takes the crown".into(),
+            announce_mins_before: 15,
+         },
+      }),
+   );
+   v.add(
+      "UnscheduleEvent",
+      &PalaceInMessage::UnscheduleEvent(UnscheduleEventMessage {
+         admin_key: "secret".into(),
+         name: "Nightly".into(),
+      }),
+   );
+   v.add("ListEvents", &PalaceInMessage::ListEvents(()));
+   v.add(
+      "RegisterForEvent",
+      &PalaceInMessage::RegisterForEvent(RegisterForEventMessage {
+         event: "Nightly".into(),
+         player_name: "Alice".into(),
+      }),
+   );
+   v.0
+}
+
+/// Every message the server can send, including each error of every response
+pub fn out_vectors() -> Vec<TestVector> {
+   let mut v = Vectors::default();
+   let hand = [
+      card(CardValue::Three, CardSuit::Spades, 4),
+      card(CardValue::Ten, CardSuit::Diamonds, 31),
+      card(CardValue::Ace, CardSuit::Hearts, 50),
+   ];
+   let mut players = HashMap::new();
+   players.insert(0, PlayerProfile::new("Ada".into(), false));
+   let seats = || {
+      vec![
+         LobbySeat {
+            slot: 0,
+            name: "Ada",
+            is_ai: false,
+            is_owner: true,
+            status: ConnectionStatus::Connected,
+            presence: Presence::Active,
+         },
+         LobbySeat {
+            slot: 1,
+            name: "Grace",
+            is_ai: false,
+            is_owner: false,
+            status: ConnectionStatus::Disconnected,
+            presence: Presence::Idle,
+         },
+      ]
+   };
+   let join_response = || JoinLobbyResponse {
+      player_id: PLAYER_ID,
+      slot: 2,
+      lobby_players: seats(),
+      max_players: 4,
+      num_spectators: 1,
+      turn_timer: 50,
+      min_human_turn_timer: 90,
+      journal_seq: 17,
+   };
+
+   v.add(
+      "NewLobbyResponse.Ok",
+      &PalaceOutMessage::NewLobbyResponse(Ok(NewLobbyResponse {
+         player_id: PLAYER_ID,
+         lobby_id: LOBBY_ID,
+         max_players: 4,
+      })),
+   );
+   v.add_errors(
+      "NewLobbyResponse",
+      vec![
+         NewLobbyError::LessThanTwoMaxPlayers,
+         NewLobbyError::EmptyLobbyName,
+         NewLobbyError::EmptyPlayerName,
+         NewLobbyError::LobbyNameTooLong,
+         NewLobbyError::PlayerNameTooLong,
+         NewLobbyError::PasswordTooLong,
+         NewLobbyError::DescriptionTooLong,
+         NewLobbyError::ServerDraining,
+         NewLobbyError::ServerFull,
+         NewLobbyError::TooManyLobbies,
+         NewLobbyError::UnknownScenario,
+         NewLobbyError::TurnTimerTooShort,
+         NewLobbyError::TooFewRepetitions,
+      ],
+      |x| PalaceOutMessage::NewLobbyResponse(Err(x)),
+   );
+   v.add(
+      "JoinLobbyResponse.Ok",
+      &PalaceOutMessage::JoinLobbyResponse(Ok(join_response())),
+   );
+   v.add_errors(
+      "JoinLobbyResponse",
+      vec![
+         JoinLobbyError::LobbyNotFound,
+         JoinLobbyError::LobbyFull,
+         JoinLobbyError::BadPassword,
+         JoinLobbyError::GameInProgress,
+         JoinLobbyError::EmptyPlayerName,
+         JoinLobbyError::PlayerNameTooLong,
+         JoinLobbyError::ServerDraining,
+         JoinLobbyError::PracticeLobby,
+      ],
+      |x| PalaceOutMessage::JoinLobbyResponse(Err(x)),
+   );
+   v.add(
+      "JoinWithTokenResponse.Ok",
+      &PalaceOutMessage::JoinWithTokenResponse(Ok(join_response())),
+   );
+   v.add_errors(
+      "JoinWithTokenResponse",
+      vec![
+         JoinWithTokenError::BadToken,
+         JoinWithTokenError::CantJoin(JoinLobbyError::LobbyFull),
+      ],
+      |x| PalaceOutMessage::JoinWithTokenResponse(Err(x)),
+   );
+   v.add(
+      "CreateJoinTokenResponse.Ok",
+      &PalaceOutMessage::CreateJoinTokenResponse(Ok(JOIN_TOKEN)),
+   );
+   v.add_errors(
+      "CreateJoinTokenResponse",
+      vec![
+         CreateJoinTokenError::LobbyNotFound,
+         CreateJoinTokenError::NotLobbyOwner,
+         CreateJoinTokenError::InvalidExpiry,
+         CreateJoinTokenError::InvalidMaxUses,
+         CreateJoinTokenError::TooManyTokens,
+      ],
+      |x| PalaceOutMessage::CreateJoinTokenResponse(Err(x)),
+   );
+   v.add(
+      "ListLobbiesResponse",
+      &PalaceOutMessage::ListLobbiesResponse(ListLobbyResponse {
+         lobbies: &[LobbyDisplay {
+            cur_players: 2,
+            ai_players: 1,
+            max_players: 4,
+            started: false,
+            has_password: true,
+            owner_slot: 0,
+            owner_name: "Ada".to_owned(),
+            name: "Friday night".to_owned(),
+            description: "Bring snacks".to_owned(),
+            age: 90,
+            lobby_id: LOBBY_ID,
+            cur_spectators: 1,
+            turn_timer: 50,
+            games_completed: 3,
+            closed_seats: 1,
+         }],
+         has_next_page: false,
+         total_lobbies: 1,
+      }),
+   );
+   v.add("StartGameResponse.Ok", &PalaceOutMessage::StartGameResponse(Ok(())));
+   v.add_errors(
+      "StartGameResponse",
+      vec![
+         StartGameError::LobbyNotFound,
+         StartGameError::NotLobbyOwner,
+         StartGameError::LessThanTwoPlayers,
+         StartGameError::GameInProgress,
+         StartGameError::StrategyUnavailable,
+         StartGameError::ScenarioSeatsEmpty,
+      ],
+      |x| PalaceOutMessage::StartGameResponse(Err(x)),
+   );
+   v.add("TakeTurnResponse.Ok", &PalaceOutMessage::TakeTurnResponse(Ok(())));
+   v.add_errors(
+      "TakeTurnResponse",
+      vec![
+         TakeTurnError::LobbyNotFound,
+         TakeTurnError::GameNotStarted,
+         TakeTurnError::PlayerNotFound,
+         TakeTurnError::NotYourTurn,
+         TakeTurnError::StalePlaySeq,
+         TakeTurnError::GameError("Have to play at least one card"),
+      ],
+      |x| PalaceOutMessage::TakeTurnResponse(Err(x)),
+   );
+   let reconnect_response = || ReconnectResponse {
+      max_players: 4,
+      num_spectators: 0,
+      turn_timer: 50,
+      journal_seq: 17,
+   };
+   let reconnect_errors = || {
+      vec![
+         ReconnectError::LobbyNotFound,
+         ReconnectError::PlayerNotFound,
+         ReconnectError::PlayerKicked,
+         ReconnectError::TooManyAttempts,
+         ReconnectError::ReplacedByAi,
+      ]
+   };
+   v.add(
+      "ReconnectResponse.Ok",
+      &PalaceOutMessage::ReconnectResponse(Ok(reconnect_response())),
+   );
+   v.add_errors("ReconnectResponse", reconnect_errors(), |x| {
+      PalaceOutMessage::ReconnectResponse(Err(x))
+   });
+   v.add(
+      "AttachToPlayerResponse.Ok",
+      &PalaceOutMessage::AttachToPlayerResponse(Ok(reconnect_response())),
+   );
+   v.add_errors("AttachToPlayerResponse", reconnect_errors(), |x| {
+      PalaceOutMessage::AttachToPlayerResponse(Err(x))
+   });
+   v.add("RequestAiResponse.Ok", &PalaceOutMessage::RequestAiResponse(Ok(())));
+   v.add_errors(
+      "RequestAiResponse",
+      vec![
+         RequestAiError::NotLobbyOwner,
+         RequestAiError::LessThanOneAiRequested,
+         RequestAiError::LobbyNotFound,
+         RequestAiError::LobbyTooSmall,
+         RequestAiError::GameInProgress,
+         RequestAiError::StrategyUnavailable,
+         RequestAiError::NotEnoughVacantSeats,
+         RequestAiError::InvalidMontyParameters,
+      ],
+      |x| PalaceOutMessage::RequestAiResponse(Err(x)),
+   );
+   v.add("KickPlayerResponse.Ok", &PalaceOutMessage::KickPlayerResponse(Ok(())));
+   v.add_errors(
+      "KickPlayerResponse",
+      vec![
+         KickPlayerError::NotLobbyOwner,
+         KickPlayerError::LobbyNotFound,
+         KickPlayerError::TargetPlayerNotFound,
+         KickPlayerError::CantKickLobbyOwner,
+         KickPlayerError::CantKickAiDuringGame,
+      ],
+      |x| PalaceOutMessage::KickPlayerResponse(Err(x)),
+   );
+   v.add(
+      "SwapAiStrategyResponse.Ok",
+      &PalaceOutMessage::SwapAiStrategyResponse(Ok(())),
+   );
+   v.add_errors(
+      "SwapAiStrategyResponse",
+      vec![
+         SwapAiStrategyError::NotLobbyOwner,
+         SwapAiStrategyError::LobbyNotFound,
+         SwapAiStrategyError::TargetPlayerNotFound,
+         SwapAiStrategyError::TargetNotAi,
+         SwapAiStrategyError::StrategyUnavailable,
+      ],
+      |x| PalaceOutMessage::SwapAiStrategyResponse(Err(x)),
+   );
+   v.add(
+      "SetSeatClosedResponse.Ok",
+      &PalaceOutMessage::SetSeatClosedResponse(Ok(())),
+   );
+   v.add_errors(
+      "SetSeatClosedResponse",
+      vec![
+         SetSeatClosedError::NotLobbyOwner,
+         SetSeatClosedError::LobbyNotFound,
+         SetSeatClosedError::GameInProgress,
+         SetSeatClosedError::SeatNotFound,
+         SetSeatClosedError::SeatOccupied,
+         SetSeatClosedError::LessThanTwoOpenSeats,
+      ],
+      |x| PalaceOutMessage::SetSeatClosedResponse(Err(x)),
+   );
+   v.add("AbortGameResponse.Ok", &PalaceOutMessage::AbortGameResponse(Ok(())));
+   v.add_errors(
+      "AbortGameResponse",
+      vec![
+         AbortGameError::LobbyNotFound,
+         AbortGameError::PlayerNotFound,
+         AbortGameError::GameNotStarted,
+      ],
+      |x| PalaceOutMessage::AbortGameResponse(Err(x)),
+   );
+   v.add("ConcedeResponse.Ok", &PalaceOutMessage::ConcedeResponse(Ok(())));
+   v.add_errors(
+      "ConcedeResponse",
+      vec![
+         ConcedeError::LobbyNotFound,
+         ConcedeError::PlayerNotFound,
+         ConcedeError::GameNotStarted,
+         ConcedeError::GameInSetup,
+         ConcedeError::AlreadyOut,
+      ],
+      |x| PalaceOutMessage::ConcedeResponse(Err(x)),
+   );
+   v.add(
+      "VoteSkipTurnResponse.Ok",
+      &PalaceOutMessage::VoteSkipTurnResponse(Ok(())),
+   );
+   v.add_errors(
+      "VoteSkipTurnResponse",
+      vec![
+         VoteSkipTurnError::LobbyNotFound,
+         VoteSkipTurnError::PlayerNotFound,
+         VoteSkipTurnError::GameNotStarted,
+         VoteSkipTurnError::OwnTurn,
+         VoteSkipTurnError::ActivePlayerNotConnected,
+      ],
+      |x| PalaceOutMessage::VoteSkipTurnResponse(Err(x)),
+   );
+   v.add(
+      "TransferOwnershipResponse.Ok",
+      &PalaceOutMessage::TransferOwnershipResponse(Ok(())),
+   );
+   v.add_errors(
+      "TransferOwnershipResponse",
+      vec![
+         TransferOwnershipError::NotLobbyOwner,
+         TransferOwnershipError::LobbyNotFound,
+         TransferOwnershipError::TargetPlayerNotFound,
+         TransferOwnershipError::TargetIsOwner,
+         TransferOwnershipError::TargetNotHuman,
+         TransferOwnershipError::TargetDisconnected,
+      ],
+      |x| PalaceOutMessage::TransferOwnershipResponse(Err(x)),
+   );
+   v.add(
+      "SpectateLobbyResponse.Ok",
+      &PalaceOutMessage::SpectateLobbyResponse(Ok(SpectateLobbyResponse {
+         lobby_players: seats(),
+         max_players: 4,
+         num_spectators: 2,
+         turn_timer: 50,
+      })),
+   );
+   v.add_errors(
+      "SpectateLobbyResponse",
+      vec![
+         SpectateLobbyError::LobbyNotFound,
+         SpectateLobbyError::SpectateLobbyFull,
+         SpectateLobbyError::AlreadyInLobby,
+         SpectateLobbyError::AlreadySpectating,
+      ],
+      |x| PalaceOutMessage::SpectateLobbyResponse(Err(x)),
+   );
+   v.add(
+      "StopSpectatingResponse.Ok",
+      &PalaceOutMessage::StopSpectatingResponse(Ok(())),
+   );
+   v.add_errors(
+      "StopSpectatingResponse",
+      vec![StopSpectatingError::NotSpectating],
+      |x| PalaceOutMessage::StopSpectatingResponse(Err(x)),
+   );
+   v.add(
+      "TakeSeatResponse.Ok",
+      &PalaceOutMessage::TakeSeatResponse(Ok(join_response())),
+   );
+   v.add_errors(
+      "TakeSeatResponse",
+      vec![
+         TakeSeatError::NotSpectating,
+         TakeSeatError::CantJoin(JoinLobbyError::LobbyFull),
+      ],
+      |x| PalaceOutMessage::TakeSeatResponse(Err(x)),
+   );
+   v.add(
+      "QueueForSeatResponse.Ok",
+      &PalaceOutMessage::QueueForSeatResponse(Ok(2)),
+   );
+   v.add_errors(
+      "QueueForSeatResponse",
+      vec![
+         QueueForSeatError::LobbyNotFound,
+         QueueForSeatError::LobbyNotFull,
+         QueueForSeatError::GameInProgress,
+         QueueForSeatError::BadPassword,
+         QueueForSeatError::AlreadyInLobby,
+         QueueForSeatError::QueueFull,
+         QueueForSeatError::EmptyPlayerName,
+         QueueForSeatError::PlayerNameTooLong,
+         QueueForSeatError::ServerDraining,
+         QueueForSeatError::PracticeLobby,
+      ],
+      |x| PalaceOutMessage::QueueForSeatResponse(Err(x)),
+   );
+   v.add(
+      "SetLobbyDescriptionResponse.Ok",
+      &PalaceOutMessage::SetLobbyDescriptionResponse(Ok(())),
+   );
+   v.add_errors(
+      "SetLobbyDescriptionResponse",
+      vec![
+         SetLobbyDescriptionError::LobbyNotFound,
+         SetLobbyDescriptionError::NotLobbyOwner,
+         SetLobbyDescriptionError::DescriptionTooLong,
+      ],
+      |x| PalaceOutMessage::SetLobbyDescriptionResponse(Err(x)),
+   );
+   v.add(
+      "GetLobbyPlayersResponse.Ok",
+      &PalaceOutMessage::GetLobbyPlayersResponse(Ok(seats())),
+   );
+   v.add_errors(
+      "GetLobbyPlayersResponse",
+      vec![GetLobbyPlayersError::LobbyNotFound],
+      |x| PalaceOutMessage::GetLobbyPlayersResponse(Err(x)),
+   );
+   v.add("AnnounceResponse.Ok", &PalaceOutMessage::AnnounceResponse(Ok(())));
+   v.add_errors(
+      "AnnounceResponse",
+      vec![AnnounceError::NotAdmin, AnnounceError::LobbyNotFound],
+      |x| PalaceOutMessage::AnnounceResponse(Err(x)),
+   );
+   v.add(
+      "SetDrainModeResponse.Ok",
+      &PalaceOutMessage::SetDrainModeResponse(Ok(())),
+   );
+   v.add_errors("SetDrainModeResponse", vec![SetDrainModeError::NotAdmin], |x| {
+      PalaceOutMessage::SetDrainModeResponse(Err(x))
+   });
+   v.add(
+      "SubscribeServerStatsResponse.Ok",
+      &PalaceOutMessage::SubscribeServerStatsResponse(Ok(())),
+   );
+   v.add_errors(
+      "SubscribeServerStatsResponse",
+      vec![SubscribeServerStatsError::NotAdmin],
+      |x| PalaceOutMessage::SubscribeServerStatsResponse(Err(x)),
+   );
+   let summary = StatsSummary {
+      period_start: 1_700_438_400,
+      games_played: 12,
+      unique_names: 9,
+      ai_win_rate: Some(0.25),
+      human_win_rate: None,
+   };
+   v.add(
+      "GetStatsSummariesResponse.Ok",
+      &PalaceOutMessage::GetStatsSummariesResponse(Ok(StatsSummaries {
+         daily: vec![summary.clone()],
+         weekly: vec![summary],
+      })),
+   );
+   v.add_errors(
+      "GetStatsSummariesResponse",
+      vec![
+         GetStatsSummariesError::NotAdmin,
+         GetStatsSummariesError::ArchiveDisabled,
+      ],
+      |x| PalaceOutMessage::GetStatsSummariesResponse(Err(x)),
+   );
+   v.add(
+      "GetLobbyStatsResponse.Ok",
+      &PalaceOutMessage::GetLobbyStatsResponse(Ok(LobbyStatsResponse {
+         games_completed: 2,
+         total_players_seen: 3,
+         average_game_secs: 420,
+         seat_placements: vec![SeatPlacements {
+            slot: 0,
+            name: "Ada",
+            placements: &[0, 1],
+         }],
+      })),
+   );
+   v.add_errors(
+      "GetLobbyStatsResponse",
+      vec![GetLobbyStatsError::LobbyNotFound, GetLobbyStatsError::NotLobbyOwner],
+      |x| PalaceOutMessage::GetLobbyStatsResponse(Err(x)),
+   );
+   v.add(
+      "AnalyzeGameResponse.Ok",
+      &PalaceOutMessage::AnalyzeGameResponse(Ok(GameAnalysis {
+         slot: 1,
+         moves: vec![MoveAnnotation {
+            turn: 9,
+            played: Box::new([hand[0]]),
+            preferred: Box::new([hand[1]]),
+            preferred_win_rate: 0.5,
+            played_win_rate: Some(0.25),
+            win_rate_swing: Some(-0.25),
+         }],
+      })),
+   );
+   v.add_errors(
+      "AnalyzeGameResponse",
+      vec![
+         AnalyzeGameError::LobbyNotFound,
+         AnalyzeGameError::NoCompletedGame,
+         AnalyzeGameError::NotInGame,
+         AnalyzeGameError::ServerBusy,
+      ],
+      |x| PalaceOutMessage::AnalyzeGameResponse(Err(x)),
+   );
+   v.add(
+      "RequestHintResponse.Ok",
+      &PalaceOutMessage::RequestHintResponse(Ok(Hint {
+         cards: Box::new([hand[0]]),
+         hints_left: 2,
+      })),
+   );
+   v.add_errors(
+      "RequestHintResponse",
+      vec![
+         RequestHintError::LobbyNotFound,
+         RequestHintError::PlayerNotFound,
+         RequestHintError::GameNotStarted,
+         RequestHintError::NotYourTurn,
+         RequestHintError::HintsDisabled,
+         RequestHintError::NoHintsLeft,
+         RequestHintError::TooSoon,
+      ],
+      |x| PalaceOutMessage::RequestHintResponse(Err(x)),
+   );
+   v.add("RewindTurnResponse.Ok", &PalaceOutMessage::RewindTurnResponse(Ok(())));
+   v.add_errors(
+      "RewindTurnResponse",
+      vec![
+         RewindTurnError::LobbyNotFound,
+         RewindTurnError::PlayerNotFound,
+         RewindTurnError::NotPracticeLobby,
+         RewindTurnError::GameNotStarted,
+         RewindTurnError::ZeroTurns,
+         RewindTurnError::NotEnoughTurns,
+      ],
+      |x| PalaceOutMessage::RewindTurnResponse(Err(x)),
+   );
+   v.add("PredictPlayResponse.Ok", &PalaceOutMessage::PredictPlayResponse(Ok(())));
+   v.add_errors(
+      "PredictPlayResponse",
+      vec![
+         PredictPlayError::LobbyNotFound,
+         PredictPlayError::NotSpectating,
+         PredictPlayError::GameNotStarted,
+         PredictPlayError::NotDuringSetup,
+         PredictPlayError::NoCards,
+      ],
+      |x| PalaceOutMessage::PredictPlayResponse(Err(x)),
+   );
+   v.add("MergeLobbyResponse.Ok", &PalaceOutMessage::MergeLobbyResponse(Ok(())));
+   v.add_errors(
+      "MergeLobbyResponse",
+      vec![
+         MergeLobbyError::LobbyNotFound,
+         MergeLobbyError::NotLobbyOwner,
+         MergeLobbyError::TargetNotFound,
+         MergeLobbyError::SameLobby,
+         MergeLobbyError::GameInProgress,
+         MergeLobbyError::NotMergeable,
+         MergeLobbyError::NotEnoughSeats,
+         MergeLobbyError::AlreadyRequested,
+      ],
+      |x| PalaceOutMessage::MergeLobbyResponse(Err(x)),
+   );
+   v.add("AnswerMergeResponse.Ok", &PalaceOutMessage::AnswerMergeResponse(Ok(())));
+   v.add_errors(
+      "AnswerMergeResponse",
+      vec![
+         AnswerMergeError::LobbyNotFound,
+         AnswerMergeError::NotLobbyOwner,
+         AnswerMergeError::NoSuchRequest,
+         AnswerMergeError::GameInProgress,
+         AnswerMergeError::NotEnoughSeats,
+      ],
+      |x| PalaceOutMessage::AnswerMergeResponse(Err(x)),
+   );
+   v.add(
+      "RotateIdResponse.Ok",
+      &PalaceOutMessage::RotateIdResponse(Ok(RotateIdResponse {
+         player_id: OTHER_PLAYER_ID,
+      })),
+   );
+   v.add_errors(
+      "RotateIdResponse",
+      vec![
+         RotateIdError::LobbyNotFound,
+         RotateIdError::PlayerNotFound,
+         RotateIdError::NotConnectedAsPlayer,
+      ],
+      |x| PalaceOutMessage::RotateIdResponse(Err(x)),
+   );
+   v.add(
+      "CatchUpResponse.Ok",
+      &PalaceOutMessage::CatchUpResponse(Ok(CatchUpResponse { latest_seq: 20 })),
+   );
+   v.add_errors(
+      "CatchUpResponse",
+      vec![
+         CatchUpError::LobbyNotFound,
+         CatchUpError::PlayerNotFound,
+         CatchUpError::TooFarBehind,
+      ],
+      |x| PalaceOutMessage::CatchUpResponse(Err(x)),
+   );
+   v.add(
+      "ObserveLobbyResponse.Ok",
+      &PalaceOutMessage::ObserveLobbyResponse(Ok(())),
+   );
+   v.add_errors(
+      "ObserveLobbyResponse",
+      vec![ObserveLobbyError::LobbyNotFound, ObserveLobbyError::NotAdmin],
+      |x| PalaceOutMessage::ObserveLobbyResponse(Err(x)),
+   );
+   v.add(
+      "StopObservingLobbyResponse.Ok",
+      &PalaceOutMessage::StopObservingLobbyResponse(Ok(())),
+   );
+   v.add_errors(
+      "StopObservingLobbyResponse",
+      vec![StopObservingLobbyError::NotObserving],
+      |x| PalaceOutMessage::StopObservingLobbyResponse(Err(x)),
+   );
+   v.add(
+      "ObservedLobbyEvent",
+      &PalaceOutMessage::ObservedLobbyEvent(ObservedLobbyEvent {
+         lobby_id: LOBBY_ID,
+         event: &PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
+            total_num_players: 1,
+            slot: 1,
+            seq: 20,
+         }),
+      }),
+   );
+   v.add(
+      "ScheduleEventResponse.Ok",
+      &PalaceOutMessage::ScheduleEventResponse(Ok(())),
+   );
+   v.add_errors(
+      "ScheduleEventResponse",
+      vec![
+         ScheduleEventError::NotAdmin,
+         ScheduleEventError::BadOpeningTime,
+         ScheduleEventError::LessThanTwoMaxPlayers,
+         ScheduleEventError::EmptyName,
+         ScheduleEventError::NameTooLong,
+         ScheduleEventError::DescriptionTooLong,
+         ScheduleEventError::TurnTimerTooShort,
+         ScheduleEventError::AnnouncementTooEarly,
+      ],
+      |x| PalaceOutMessage::ScheduleEventResponse(Err(x)),
+   );
+   v.add(
+      "UnscheduleEventResponse.Ok",
+      &PalaceOutMessage::UnscheduleEventResponse(Ok(())),
+   );
+   v.add_errors(
+      "UnscheduleEventResponse",
+      vec![UnscheduleEventError::NotAdmin, UnscheduleEventError::EventNotFound],
+      |x| PalaceOutMessage::UnscheduleEventResponse(Err(x)),
+   );
+   v.add(
+      "ListEventsResponse",
+      &PalaceOutMessage::ListEventsResponse(vec![EventListing {
+         name: "Nightly".into(),
+         description: "Winner takes the crown".into(),
+         max_players: 8,
+         opens_in_secs: 3600,
+         num_registered: 5,
+      }]),
+   );
+   v.add(
+      "RegisterForEventResponse.Ok",
+      &PalaceOutMessage::RegisterForEventResponse(Ok(RegisterForEventResponse { opens_in_secs: 3600 })),
+   );
+   v.add_errors(
+      "RegisterForEventResponse",
+      vec![
+         RegisterForEventError::EventNotFound,
+         RegisterForEventError::EmptyPlayerName,
+         RegisterForEventError::PlayerNameTooLong,
+         RegisterForEventError::EventFull,
+         RegisterForEventError::ServerDraining,
+      ],
+      |x| PalaceOutMessage::RegisterForEventResponse(Err(x)),
+   );
+   v.add(
+      "EventOpenedEvent",
+      &PalaceOutMessage::EventOpenedEvent(EventOpenedEvent {
+         event: "Nightly",
+         lobby_id: LOBBY_ID,
+      }),
+   );
+   v.add("UnknownMessageType", &PalaceOutMessage::UnknownMessageType("Chat"));
+   v.add(
+      "UnknownFields",
+      &PalaceOutMessage::UnknownFields(&["ListLobbies.pagesize".into()]),
+   );
+   let face_up_three: [&[Card]; 2] = [&hand[..1], &[]];
+   v.add(
+      "PublicGameStateEvent",
+      &PalaceOutMessage::PublicGameStateEvent(&PublicGameState {
+         hands: Box::new([3, 5]),
+         face_up_three: Box::new(face_up_three),
+         face_down_three: Box::new([3, 2]),
+         top_card: Some(hand[1]),
+         pile_size: 4,
+         cleared_size: 8,
+         cur_phase: Phase::Play,
+         active_player: 1,
+         last_cards_played: &hand[1..2],
+         last_played_zone: Some(CardZone::Hand),
+         placements: Box::new([None, Some(0)]),
+         turn: 31,
+         elapsed_secs: 254,
+         turn_deadline: Some(42),
+         journal_seq: Some(18),
+      }),
+   );
+   v.add("HandEvent", &PalaceOutMessage::HandEvent(&hand));
+   v.add(
+      "GameStartEvent",
+      &PalaceOutMessage::GameStartEvent(GameStartEvent {
+         hand: &hand,
+         turn_number: 0,
+         players: &players,
+      }),
+   );
+   v.add(
+      "SpectateGameStartEvent",
+      &PalaceOutMessage::SpectateGameStartEvent(SpectateGameStartEvent { players: &players }),
+   );
+   v.add(
+      "PlayerJoinEvent",
+      &PalaceOutMessage::PlayerJoinEvent(PlayerJoinEvent {
+         total_num_players: 2,
+         new_player_name: "Grace",
+         slot: 1,
+         seq: 19,
+      }),
+   );
+   v.add(
+      "PlayerLeaveEvent",
+      &PalaceOutMessage::PlayerLeaveEvent(PlayerLeaveEvent {
+         total_num_players: 1,
+         slot: 1,
+         seq: 20,
+      }),
+   );
+   v.add(
+      "LobbyInfoEvent",
+      &PalaceOutMessage::LobbyInfoEvent(LobbyInfoEvent {
+         description: "Bring snacks",
+      }),
+   );
+   v.add(
+      "LobbySettingsEvent",
+      &PalaceOutMessage::LobbySettingsEvent(LobbySettingsEvent {
+         max_players: 4,
+         closed_seats: &[3],
+      }),
+   );
+   for status in [
+      ConnectionStatus::Connected,
+      ConnectionStatus::Disconnected,
+      ConnectionStatus::TimedOut,
+      ConnectionStatus::Kicked,
+      ConnectionStatus::ReplacedByAi,
+   ] {
+      v.add(
+         format!("PlayerConnectionStatusEvent.{}", variant_name(&status)),
+         &PalaceOutMessage::PlayerConnectionStatusEvent(PlayerConnectionStatusEvent { slot: 1, status }),
+      );
+   }
+   for presence in [Presence::Active, Presence::Idle] {
+      v.add(
+         format!("PlayerPresenceEvent.{}", variant_name(&presence)),
+         &PalaceOutMessage::PlayerPresenceEvent(PlayerPresenceEvent { slot: 1, presence }),
+      );
+   }
+   for announcement in [
+      ServerAnnouncementEvent::info("Welcome!"),
+      ServerAnnouncementEvent::warning("Restarting in 5 minutes"),
+      ServerAnnouncementEvent::critical("Restarting now"),
+   ] {
+      v.add(
+         format!("ServerAnnouncementEvent.{}", variant_name(&announcement.severity)),
+         &PalaceOutMessage::ServerAnnouncementEvent(announcement),
+      );
+   }
+   v.add(
+      "ServerDrainingEvent",
+      &PalaceOutMessage::ServerDrainingEvent(ServerDrainingEvent {
+         draining: true,
+         shutdown_in_secs: Some(300),
+      }),
+   );
+   v.add(
+      "ServerStatsEvent",
+      &PalaceOutMessage::ServerStatsEvent(ServerStatsEvent {
+         lobbies: 7,
+         games_in_progress: 3,
+         players: 14,
+         ai_players: 5,
+         spectators: 2,
+         messages_per_sec: 12.5,
+         ai_queue_depth: 1,
+      }),
+   );
+   v.add(
+      "AiDecisionDebugEvent",
+      &PalaceOutMessage::AiDecisionDebugEvent(AiDecisionDebugEvent {
+         slot: 1,
+         strategy: "Monty",
+         candidates: &[CandidateMove {
+            cards: Box::new([hand[0]]),
+            visits: 800,
+            win_rate: 0.5,
+         }],
+      }),
+   );
+   for event in [
+      LobbyCloseEvent::Kicked,
+      LobbyCloseEvent::OwnerLeft,
+      LobbyCloseEvent::Afk,
+      LobbyCloseEvent::GameOver,
+      LobbyCloseEvent::Expired,
+      LobbyCloseEvent::Merged,
+      LobbyCloseEvent::IdRotated,
+   ] {
+      v.add(
+         format!("LobbyCloseEvent.{}", variant_name(&event)),
+         &PalaceOutMessage::LobbyCloseEvent(event),
+      );
+   }
+   for (code, reason) in [
+      (DisconnectCode::ProtocolError, "expected value at line 1 column 1"),
+      (DisconnectCode::RateLimited, "Too many reconnect attempts"),
+      (DisconnectCode::Kicked, "Kicked by the lobby owner"),
+      (DisconnectCode::Afk, "Took too long to take a turn"),
+      (DisconnectCode::ServerDraining, "The server is shutting down"),
+      (DisconnectCode::Unreachable, "Too many messages failed to send"),
+   ] {
+      v.add(
+         format!("DisconnectEvent.{}", variant_name(&code)),
+         &PalaceOutMessage::DisconnectEvent(DisconnectEvent {
+            code,
+            reason: reason.into(),
+         }),
+      );
+   }
+   v.add("SpectatorJoinEvent", &PalaceOutMessage::SpectatorJoinEvent(()));
+   v.add("SpectatorLeaveEvent", &PalaceOutMessage::SpectatorLeaveEvent(()));
+   v.add("GameCompleteEvent", &PalaceOutMessage::GameCompleteEvent(&[1, 0]));
+   v.add(
+      "AbortVoteEvent",
+      &PalaceOutMessage::AbortVoteEvent(AbortVoteEvent {
+         slot: 0,
+         votes: 1,
+         needed: 2,
+      }),
+   );
+   v.add(
+      "SkipTurnVoteEvent",
+      &PalaceOutMessage::SkipTurnVoteEvent(SkipTurnVoteEvent {
+         slot: 2,
+         votes: 1,
+         needed: 2,
+      }),
+   );
+   v.add(
+      "TurnSkippedEvent",
+      &PalaceOutMessage::TurnSkippedEvent(TurnSkippedEvent { slot: 1 }),
+   );
+   v.add(
+      "PlayerConcededEvent",
+      &PalaceOutMessage::PlayerConcededEvent(PlayerConcededEvent { slot: 2 }),
+   );
+   v.add(
+      "GameRewoundEvent",
+      &PalaceOutMessage::GameRewoundEvent(GameRewoundEvent { turns_taken: 12 }),
+   );
+   v.add(
+      "PredictionResultsEvent",
+      &PalaceOutMessage::PredictionResultsEvent(PredictionResultsEvent {
+         turn: 9,
+         played: PredictedPlay::Cards(CardValue::Seven, 2),
+         tallies: vec![
+            PredictionTally {
+               play: PredictedPlay::Cards(CardValue::Seven, 2),
+               guesses: 2,
+            },
+            PredictionTally {
+               play: PredictedPlay::FaceDown,
+               guesses: 1,
+            },
+         ],
+         correct: 2,
+      }),
+   );
+   v.add(
+      "PredictionScoreEvent",
+      &PalaceOutMessage::PredictionScoreEvent(PredictionScoreEvent {
+         correct: true,
+         score: 3,
+      }),
+   );
+   v.add(
+      "MergeRequestEvent",
+      &PalaceOutMessage::MergeRequestEvent(MergeRequestEvent {
+         from_lobby_id: LOBBY_ID,
+         lobby_name: "Friday Night",
+         player_names: vec!["Ada", "Grace"],
+      }),
+   );
+   v.add(
+      "MergeDeclinedEvent",
+      &PalaceOutMessage::MergeDeclinedEvent(MergeDeclinedEvent {
+         target_lobby_id: OTHER_LOBBY_ID,
+      }),
+   );
+   v.add(
+      "LobbyMergedEvent",
+      &PalaceOutMessage::LobbyMergedEvent(LobbyMergedEvent {
+         lobby_id: OTHER_LOBBY_ID,
+         slot: 2,
+         lobby: join_response(),
+      }),
+   );
+   for reason in [GameAbortReason::Owner, GameAbortReason::Vote] {
+      v.add(
+         format!("GameAbortedEvent.{}", variant_name(&reason)),
+         &PalaceOutMessage::GameAbortedEvent(reason),
+      );
+   }
+   for reason in [DrawReason::Repetition, DrawReason::TurnLimit] {
+      v.add(
+         format!("GameDrawEvent.{}", variant_name(&reason)),
+         &PalaceOutMessage::GameDrawEvent(GameDrawEvent { reason, turns: 212 }),
+      );
+   }
+   v.add(
+      "OwnerChangedEvent",
+      &PalaceOutMessage::OwnerChangedEvent(OwnerChangedEvent { slot: 1 }),
+   );
+   v.add(
+      "LobbyClosingSoonEvent",
+      &PalaceOutMessage::LobbyClosingSoonEvent(LobbyClosingSoonEvent { secs_remaining: 60 }),
+   );
+   v.add(
+      "YourTurnEvent",
+      &PalaceOutMessage::YourTurnEvent(YourTurnEvent { deadline: Some(50) }),
+   );
+   v.add(
+      "TurnTimeWarningEvent",
+      &PalaceOutMessage::TurnTimeWarningEvent(TurnTimeWarningEvent { secs_remaining: 5 }),
+   );
+   for kind in [
+      HighlightKind::BigPickup(12),
+      HighlightKind::FourOfAKind,
+      HighlightKind::WonFromFaceDown,
+   ] {
+      v.add(
+         format!("HighlightEvent.{}", variant_name(&kind)),
+         &PalaceOutMessage::HighlightEvent(Highlight {
+            turn: 17,
+            player: 1,
+            kind,
+         }),
+      );
+   }
+   v.add(
+      "CommentaryEvent",
+      &PalaceOutMessage::CommentaryEvent(&Commentary {
+         turn: 17,
+         player: 1,
+         text: "Alice burned the pile with a 10".to_owned(),
+      }),
+   );
+   v.add(
+      "QueuePositionEvent",
+      &PalaceOutMessage::QueuePositionEvent(QueuePositionEvent { position: 1 }),
+   );
+   v.add("SeatGrantedEvent", &PalaceOutMessage::SeatGrantedEvent(join_response()));
+   // Sent in place of any message that fails to serialize
+   v.add("InternalServerError", &"InternalServerError");
+   v.0
+}
+
+/// Writes every vector to `<dir>/in/<name>.json` or `<dir>/out/<name>.json`, replacing whatever was there
+pub fn write_all(dir: &Path) -> io::Result<()> {
+   for (direction, vectors) in [("in", in_vectors()), ("out", out_vectors())] {
+      let dir = dir.join(direction);
+      if dir.exists() {
+         fs::remove_dir_all(&dir)?;
+      }
+      fs::create_dir_all(&dir)?;
+      for vector in vectors {
+         fs::write(dir.join(format!("{}.json", vector.name)), vector.json)?;
+      }
+   }
+   Ok(())
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::collections::HashSet;
+   #[cfg(test)]
+   use std::path::PathBuf;
+
+   #[cfg(test)]
+   fn vectors_dir() -> PathBuf {
+      Path::new(env!("CARGO_MANIFEST_DIR")).join("../protocol_vectors")
+   }
+
+   /// Variants of a schema definition that is an enum, by their names on the wire
+   #[cfg(test)]
+   fn schema_variants(schema: &Value) -> Vec<String> {
+      let mut variants = Vec::new();
+      let alternatives = match schema.get("oneOf") {
+         Some(Value::Array(alternatives)) => alternatives.clone(),
+         _ => vec![schema.clone()],
+      };
+      for alternative in alternatives {
+         if let Some(Value::Array(names)) = alternative.get("enum") {
+            variants.extend(names.iter().map(|x| x.as_str().unwrap().to_string()));
+         }
+         if let Some(Value::Array(names)) = alternative.get("required") {
+            variants.extend(names.iter().map(|x| x.as_str().unwrap().to_string()));
+         }
+      }
+      variants
+   }
+
+   #[test]
+   fn checked_in_vectors_are_up_to_date() {
+      for (direction, vectors) in [("in", in_vectors()), ("out", out_vectors())] {
+         let dir = vectors_dir().join(direction);
+         let mut names = HashSet::new();
+         for vector in vectors {
+            let path = dir.join(format!("{}.json", vector.name));
+            let checked_in = fs::read_to_string(&path).unwrap_or_default();
+            assert_eq!(
+               checked_in,
+               vector.json,
+               "{} is out of date, regenerate it with --dump-test-vectors",
+               path.display()
+            );
+            assert!(names.insert(vector.name), "{} is used twice", path.display());
+         }
+         assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            names.len(),
+            "{} has stale vectors",
+            dir.display()
+         );
+      }
+   }
+
+   #[test]
+   fn in_vectors_round_trip() {
+      for vector in in_vectors() {
+         let mut unknown_fields = Vec::new();
+         let message = crate::decode_noting_unknown_fields(vector.json.as_bytes(), &mut unknown_fields).unwrap();
+         assert!(unknown_fields.is_empty(), "{}: {:?}", vector.name, unknown_fields);
+         assert_eq!(message.name(), vector.name);
+         assert_eq!(serde_json::to_string(&message).unwrap(), vector.json);
+      }
+   }
+
+   #[test]
+   fn every_variant_and_error_has_a_vector() {
+      let schema = protocol_schema();
+      let expected_in = schema_variants(&schema["PalaceInMessage"]);
+      let in_names: Vec<String> = in_vectors().into_iter().map(|x| x.name).collect();
+      assert_eq!(in_names, expected_in);
+
+      let out_schema = &schema["PalaceOutMessage"];
+      let out_names: Vec<String> = out_vectors().into_iter().map(|x| x.name).collect();
+      let has_vector = |prefix: &str| {
+         out_names
+            .iter()
+            .any(|x| x == prefix || x.starts_with(&format!("{}.", prefix)))
+      };
+      for alternative in out_schema["oneOf"].as_array().unwrap() {
+         let variant = alternative["required"][0].as_str().unwrap();
+         let payload = &alternative["properties"][variant]["$ref"];
+         let definition = payload.as_str().and_then(|x| x.strip_prefix("#/definitions/"));
+         match definition {
+            Some(result) if result.starts_with("Result_of_") => {
+               assert!(has_vector(&format!("{}.Ok", variant)), "{}.Ok has no vector", variant);
+               let error = out_schema["definitions"][result]["oneOf"][1]["properties"]["Err"]["$ref"]
+                  .as_str()
+                  .unwrap()
+                  .trim_start_matches("#/definitions/");
+               for error_variant in schema_variants(&out_schema["definitions"][error]) {
+                  let name = format!("{}.Err.{}", variant, error_variant);
+                  assert!(has_vector(&name), "{} has no vector", name);
+               }
+            }
+            _ => assert!(has_vector(variant), "{} has no vector", variant),
+         }
+      }
+   }
+
+   #[test]
+   fn in_vectors_have_the_fields_the_schema_describes() {
+      let schema = protocol_schema();
+      let in_schema = &schema["PalaceInMessage"];
+      for vector in in_vectors() {
+         let payload = match serde_json::from_str(&vector.json).unwrap() {
+            Value::Object(mut map) => map.remove(&vector.name).unwrap(),
+            _ => continue,
+         };
+         let alternative = in_schema["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["required"][0] == vector.name.as_str())
+            .unwrap();
+         let definition = match alternative["properties"][&vector.name]["$ref"].as_str() {
+            Some(reference) => &in_schema["definitions"][reference.trim_start_matches("#/definitions/")],
+            None => continue,
+         };
+         let (fields, properties) = match (payload, definition["properties"].as_object()) {
+            (Value::Object(fields), Some(properties)) => (fields, properties),
+            _ => continue,
+         };
+         for field in fields.keys() {
+            assert!(properties.contains_key(field), "{} has no field {}", vector.name, field);
+         }
+         for required in definition["required"].as_array().into_iter().flatten() {
+            let required = required.as_str().unwrap();
+            assert!(fields.contains_key(required), "{} is missing {}", vector.name, required);
+         }
+      }
+   }
+}
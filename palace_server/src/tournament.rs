@@ -0,0 +1,916 @@
+//! Pits AI strategies against each other over many games, to compare them
+
+use crate::ai::monty::RolloutPolicy;
+use crate::ai::{self, PalaceAi};
+use crate::data::{GameStartEvent, PlayerProfile};
+use crate::game::{GameState, PlayOutcome};
+use indexmap::IndexMap;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "neural")]
+use std::sync::Arc;
+use std::sync::Mutex;
+
+pub const DEFAULT_GAMES_PER_DUEL: usize = 1000;
+pub const DEFAULT_MAX_TURNS: usize = 1000;
+
+/// A strategy taking part in a tournament
+#[derive(Clone, Deserialize)]
+pub enum Entrant {
+   Random,
+   /// Exploration constant, number of simulations, simulations per determinization and rollout policy
+   Monty(f64, usize, usize, RolloutPolicy),
+   LowAndSteady,
+   #[cfg(feature = "neural")]
+   #[serde(skip)]
+   Neural(Arc<ai::neural::PolicyModel>),
+}
+
+impl Entrant {
+   fn instantiate(&self) -> Box<dyn PalaceAi + Send + Sync> {
+      match self {
+         Entrant::Random => Box::new(ai::random::new()),
+         Entrant::Monty(c, sims, per_determinization, rollout) => {
+            Box::new(ai::monty::with_parameters(*c, *sims, *per_determinization, *rollout))
+         }
+         Entrant::LowAndSteady => Box::new(ai::low_and_steady::new()),
+         #[cfg(feature = "neural")]
+         Entrant::Neural(model) => Box::new(ai::neural::new(model.clone())),
+      }
+   }
+}
+
+impl Display for Entrant {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Entrant::Random => write!(f, "Random"),
+         Entrant::Monty(c, sims, per_determinization, rollout) => {
+            write!(f, "Monty (c={}, {} sims", c, sims)?;
+            if *per_determinization != 1 {
+               write!(f, ", {} per determinization", per_determinization)?;
+            }
+            if *rollout != RolloutPolicy::Random {
+               write!(f, ", {:?} rollouts", rollout)?;
+            }
+            write!(f, ")")
+         }
+         Entrant::LowAndSteady => write!(f, "Low and Steady"),
+         #[cfg(feature = "neural")]
+         Entrant::Neural(_) => write!(f, "Neural"),
+      }
+   }
+}
+
+/// Who plays whom
+pub enum Pairing {
+   /// Exactly these duels, in order
+   Fixed(Vec<(Entrant, Entrant)>),
+   /// Each round, entrants play the closest entrant in the standings that they haven't played yet. With an odd
+   /// number of entrants, the lowest ranked entrant without a bye sits the round out and is scored as winning
+   Swiss { entrants: Vec<Entrant>, rounds: usize },
+}
+
+pub struct TournamentConfig {
+   pub pairing: Pairing,
+   /// Games played by each pair, half with each going first. With early stopping, the most played
+   pub games_per_duel: usize,
+   /// Games still going after this many turns are counted as draws
+   pub max_turns: usize,
+   pub early_stopping: Option<EarlyStopping>,
+}
+
+impl TournamentConfig {
+   fn with_pairing(pairing: Pairing) -> TournamentConfig {
+      TournamentConfig {
+         pairing,
+         games_per_duel: DEFAULT_GAMES_PER_DUEL,
+         max_turns: DEFAULT_MAX_TURNS,
+         early_stopping: None,
+      }
+   }
+
+   /// Every entrant plays every other entrant once
+   pub fn round_robin(entrants: &[Entrant]) -> TournamentConfig {
+      let mut duels = Vec::new();
+      for (i, first) in entrants.iter().enumerate() {
+         for second in entrants[i + 1..].iter() {
+            duels.push((first.clone(), second.clone()));
+         }
+      }
+      TournamentConfig::with_pairing(Pairing::Fixed(duels))
+   }
+
+   pub fn swiss(entrants: &[Entrant], rounds: usize) -> TournamentConfig {
+      TournamentConfig::with_pairing(Pairing::Swiss {
+         entrants: entrants.to_vec(),
+         rounds,
+      })
+   }
+
+   fn duels_total(&self) -> usize {
+      match &self.pairing {
+         Pairing::Fixed(duels) => duels.len(),
+         Pairing::Swiss { entrants, rounds } => entrants.len() / 2 * rounds,
+      }
+   }
+}
+
+/// Ends duels once their decisive games show whether the first entrant wins half of them, or `margin` more or less
+/// than half, using a sequential probability ratio test in each direction
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct EarlyStopping {
+   pub margin: f64,
+   /// Chance of finding a difference that isn't there, in each direction
+   pub false_positive_rate: f64,
+   /// Chance of missing a difference of `margin`
+   pub false_negative_rate: f64,
+}
+
+impl Default for EarlyStopping {
+   fn default() -> EarlyStopping {
+      EarlyStopping {
+         margin: 0.05,
+         false_positive_rate: 0.05,
+         false_negative_rate: 0.05,
+      }
+   }
+}
+
+impl EarlyStopping {
+   /// None while more games are needed
+   fn judge(&self, wins: usize, losses: usize) -> Option<Significance> {
+      let accept_difference = ((1.0 - self.false_negative_rate) / self.false_positive_rate).ln();
+      let accept_equal = (self.false_negative_rate / (1.0 - self.false_positive_rate)).ln();
+      let log_likelihood_ratio = |p: f64| wins as f64 * (p / 0.5).ln() + losses as f64 * ((1.0 - p) / 0.5).ln();
+      let stronger = log_likelihood_ratio(0.5 + self.margin);
+      let weaker = log_likelihood_ratio(0.5 - self.margin);
+      if stronger >= accept_difference {
+         Some(Significance::FirstStronger)
+      } else if weaker >= accept_difference {
+         Some(Significance::SecondStronger)
+      } else if stronger <= accept_equal && weaker <= accept_equal {
+         Some(Significance::NoDifference)
+      } else {
+         None
+      }
+   }
+}
+
+/// What early stopping concluded about a duel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Significance {
+   FirstStronger,
+   SecondStronger,
+   /// Neither entrant wins `margin` more than half of the decisive games
+   NoDifference,
+   /// Every game was played without reaching a conclusion
+   Inconclusive,
+}
+
+/// Passed to the progress callback each time a duel finishes
+pub struct TournamentProgress<'a> {
+   pub duels_finished: usize,
+   pub duels_total: usize,
+   pub duel: &'a DuelReport,
+}
+
+#[derive(Serialize)]
+pub struct DuelReport {
+   /// Always 1 for fixed pairings
+   pub round: usize,
+   pub first: String,
+   pub second: String,
+   pub first_wins: usize,
+   pub second_wins: usize,
+   pub draws: usize,
+   pub total_turns: usize,
+   /// 95% confidence interval of the first entrant's share of points, counting draws as half a point
+   pub score_rate_low: f64,
+   pub score_rate_high: f64,
+   /// Only set with early stopping
+   pub significance: Option<Significance>,
+}
+
+impl DuelReport {
+   fn games(&self) -> usize {
+      self.first_wins + self.second_wins + self.draws
+   }
+
+   /// The first entrant's wins, counting draws as half a win
+   pub fn score(&self) -> f64 {
+      self.first_wins as f64 + self.draws as f64 / 2.0
+   }
+
+   pub fn score_rate(&self) -> f64 {
+      match self.games() {
+         0 => 0.0,
+         games => self.score() / games as f64,
+      }
+   }
+
+   /// Standings points for each side: 1 for winning more games, a half each for winning as many
+   fn points(&self) -> (f64, f64) {
+      match self.first_wins.cmp(&self.second_wins) {
+         std::cmp::Ordering::Greater => (1.0, 0.0),
+         std::cmp::Ordering::Equal => (0.5, 0.5),
+         std::cmp::Ordering::Less => (0.0, 1.0),
+      }
+   }
+}
+
+impl Display for DuelReport {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      let games = self.games().max(1) as f64;
+      write!(
+         f,
+         "{}: {} wins ({:.2}%) // {}: {} ({:.2}%) || {} draws || avg. game length: {:.2} turns || \
+          score rate {:.3}..{:.3}",
+         self.first,
+         self.first_wins,
+         self.first_wins as f64 / games * 100.0,
+         self.second,
+         self.second_wins,
+         self.second_wins as f64 / games * 100.0,
+         self.draws,
+         self.total_turns as f64 / games,
+         self.score_rate_low,
+         self.score_rate_high
+      )?;
+      match self.significance {
+         Some(significance) => write!(f, " || {:?}", significance),
+         None => Ok(()),
+      }
+   }
+}
+
+/// How an entrant fared across all of its duels. Entrants are told apart by name
+#[derive(Serialize)]
+pub struct Standing {
+   pub entrant: String,
+   pub wins: usize,
+   pub draws: usize,
+   pub losses: usize,
+   pub byes: usize,
+   /// Wins and byes, plus a half for each draw
+   pub score: f64,
+   /// The total score of every opponent played, to rank entrants with the same score
+   pub strength_of_schedule: f64,
+}
+
+impl Display for Standing {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(
+         f,
+         "{}: {} points ({}W {}D {}L {} byes) || strength of schedule: {}",
+         self.entrant, self.score, self.wins, self.draws, self.losses, self.byes, self.strength_of_schedule
+      )
+   }
+}
+
+#[derive(Serialize)]
+pub struct TournamentReport {
+   pub duels: Vec<DuelReport>,
+   /// Best first
+   pub standings: Vec<Standing>,
+}
+
+impl TournamentReport {
+   /// One row per duel, from the first entrant's side
+   pub fn write_csv<W: Write>(&self, mut output: W) -> io::Result<()> {
+      writeln!(
+         output,
+         "round,first,second,wins,losses,draws,score,total_turns,score_rate_low,score_rate_high,significance"
+      )?;
+      for duel in self.duels.iter() {
+         writeln!(
+            output,
+            "{},\"{}\",\"{}\",{},{},{},{},{},{},{},{}",
+            duel.round,
+            duel.first,
+            duel.second,
+            duel.first_wins,
+            duel.second_wins,
+            duel.draws,
+            duel.score(),
+            duel.total_turns,
+            duel.score_rate_low,
+            duel.score_rate_high,
+            duel.significance.map(|x| format!("{:?}", x)).unwrap_or_default()
+         )?;
+      }
+      Ok(())
+   }
+}
+
+/// Plays every duel in `config` in order, spreading each duel's games across threads
+pub fn run_tournament<F: FnMut(TournamentProgress)>(config: &TournamentConfig, mut on_progress: F) -> TournamentReport {
+   let duels_total = config.duels_total();
+   let mut duels = Vec::with_capacity(duels_total);
+   let mut byes = Vec::new();
+   let mut play = |round: usize, first: &Entrant, second: &Entrant, duels: &mut Vec<DuelReport>| {
+      let duel = ai_duel(
+         round,
+         first,
+         second,
+         config.games_per_duel,
+         config.max_turns,
+         config.early_stopping,
+      );
+      on_progress(TournamentProgress {
+         duels_finished: duels.len() + 1,
+         duels_total,
+         duel: &duel,
+      });
+      let points = duel.points();
+      duels.push(duel);
+      points
+   };
+   match &config.pairing {
+      Pairing::Fixed(pairs) => {
+         for (first, second) in pairs.iter() {
+            play(1, first, second, &mut duels);
+         }
+      }
+      Pairing::Swiss { entrants, rounds } => {
+         let mut scores = vec![0.0; entrants.len()];
+         let mut had_bye = vec![false; entrants.len()];
+         let mut met = HashSet::new();
+         for round in 1..=*rounds {
+            // Sorting is stable, so ties are broken by the order entrants were given in
+            let mut order: Vec<usize> = (0..entrants.len()).collect();
+            order.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap());
+            if order.len() % 2 == 1 {
+               let bye = order.iter().rposition(|i| !had_bye[*i]).unwrap_or(order.len() - 1);
+               let i = order.remove(bye);
+               had_bye[i] = true;
+               scores[i] += 1.0;
+               byes.push(entrants[i].to_string());
+            }
+            let pairs = pair_without_rematches(&order, &met).unwrap_or_else(|| pair_greedily(&order, &met));
+            for (first, second) in pairs {
+               met.insert((first.min(second), first.max(second)));
+               let (first_points, second_points) = play(round, &entrants[first], &entrants[second], &mut duels);
+               scores[first] += first_points;
+               scores[second] += second_points;
+            }
+         }
+      }
+   }
+   let standings = standings(&duels, &byes);
+   TournamentReport { duels, standings }
+}
+
+/// Pairs each entrant, best first, with the best entrant left that they haven't played, backtracking as needed.
+/// `None` if there's no way to avoid a rematch
+fn pair_without_rematches(order: &[usize], met: &HashSet<(usize, usize)>) -> Option<Vec<(usize, usize)>> {
+   let (first, rest) = match order.split_first() {
+      Some(split) => split,
+      None => return Some(Vec::new()),
+   };
+   for (i, second) in rest.iter().enumerate() {
+      if met.contains(&(*first.min(second), *first.max(second))) {
+         continue;
+      }
+      let mut remaining = rest.to_vec();
+      remaining.remove(i);
+      if let Some(mut pairs) = pair_without_rematches(&remaining, met) {
+         pairs.insert(0, (*first, *second));
+         return Some(pairs);
+      }
+   }
+   None
+}
+
+/// Like `pair_without_rematches`, but falls back to the best entrant left when everyone left has been played
+fn pair_greedily(order: &[usize], met: &HashSet<(usize, usize)>) -> Vec<(usize, usize)> {
+   let mut order = order.to_vec();
+   let mut pairs = Vec::with_capacity(order.len() / 2);
+   while order.len() >= 2 {
+      let first = order.remove(0);
+      let opponent = order
+         .iter()
+         .position(|j| !met.contains(&(first.min(*j), first.max(*j))))
+         .unwrap_or(0);
+      pairs.push((first, order.remove(opponent)));
+   }
+   pairs
+}
+
+fn standings(duels: &[DuelReport], byes: &[String]) -> Vec<Standing> {
+   fn standing<'a, 'b>(standings: &'a mut IndexMap<&'b str, Standing>, entrant: &'b str) -> &'a mut Standing {
+      standings.entry(entrant).or_insert_with(|| Standing {
+         entrant: entrant.to_string(),
+         wins: 0,
+         draws: 0,
+         losses: 0,
+         byes: 0,
+         score: 0.0,
+         strength_of_schedule: 0.0,
+      })
+   }
+
+   let mut standings: IndexMap<&str, Standing> = IndexMap::new();
+   let mut opponents: HashMap<&str, Vec<&str>> = HashMap::new();
+   for duel in duels.iter() {
+      let (first_points, second_points) = duel.points();
+      let sides = [
+         (duel.first.as_str(), duel.second.as_str(), first_points),
+         (duel.second.as_str(), duel.first.as_str(), second_points),
+      ];
+      for (entrant, opponent, points) in sides.iter() {
+         let standing = standing(&mut standings, entrant);
+         standing.score += points;
+         if *points == 1.0 {
+            standing.wins += 1;
+         } else if *points == 0.0 {
+            standing.losses += 1;
+         } else {
+            standing.draws += 1;
+         }
+         opponents.entry(entrant).or_default().push(opponent);
+      }
+   }
+   for entrant in byes.iter() {
+      let standing = standing(&mut standings, entrant);
+      standing.byes += 1;
+      standing.score += 1.0;
+   }
+   let scores: HashMap<&str, f64> = standings
+      .iter()
+      .map(|(name, standing)| (*name, standing.score))
+      .collect();
+   for (name, standing) in standings.iter_mut() {
+      standing.strength_of_schedule = opponents
+         .get(name)
+         .map(|opponents| opponents.iter().map(|opponent| scores[opponent]).sum())
+         .unwrap_or(0.0);
+   }
+   let mut standings: Vec<Standing> = standings.into_iter().map(|(_, standing)| standing).collect();
+   standings.sort_by(|a, b| {
+      (b.score, b.strength_of_schedule)
+         .partial_cmp(&(a.score, a.strength_of_schedule))
+         .unwrap()
+   });
+   standings
+}
+
+/// Monty with every combination of the given parameters plays each opponent. Missing fields keep their defaults
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SweepConfig {
+   pub exploration_constants: Vec<f64>,
+   pub simulation_counts: Vec<usize>,
+   pub simulations_per_determinization: Vec<usize>,
+   pub rollout_policies: Vec<RolloutPolicy>,
+   pub opponents: Vec<Entrant>,
+   /// Games played by each combination against each opponent, half with each going first. With early stopping,
+   /// the most played
+   pub games_per_duel: usize,
+   /// Games still going after this many turns are counted as draws
+   pub max_turns: usize,
+   pub early_stopping: Option<EarlyStopping>,
+}
+
+impl Default for SweepConfig {
+   fn default() -> SweepConfig {
+      SweepConfig {
+         exploration_constants: vec![0.7, std::f64::consts::SQRT_2],
+         simulation_counts: vec![25, 50, 100, 250, 500, 1000, 2000],
+         simulations_per_determinization: vec![1],
+         rollout_policies: vec![RolloutPolicy::Random],
+         opponents: vec![Entrant::LowAndSteady, Entrant::Random],
+         games_per_duel: DEFAULT_GAMES_PER_DUEL,
+         max_turns: DEFAULT_MAX_TURNS,
+         early_stopping: None,
+      }
+   }
+}
+
+/// One combination of parameters against one opponent
+#[derive(Serialize)]
+pub struct SweepRow {
+   pub exploration_constant: f64,
+   pub simulations: usize,
+   pub simulations_per_determinization: usize,
+   pub rollout_policy: RolloutPolicy,
+   pub opponent: String,
+   pub games: usize,
+   pub wins: usize,
+   pub losses: usize,
+   pub draws: usize,
+   /// Wins plus half of draws, over games played
+   pub score_rate: f64,
+   /// 95% confidence interval of `score_rate`
+   pub score_rate_low: f64,
+   pub score_rate_high: f64,
+   pub average_turns: f64,
+   pub significance: Option<Significance>,
+}
+
+#[derive(Serialize)]
+pub struct SweepReport {
+   pub rows: Vec<SweepRow>,
+}
+
+impl SweepReport {
+   pub fn write_csv<W: Write>(&self, mut output: W) -> io::Result<()> {
+      writeln!(
+         output,
+         "exploration_constant,simulations,simulations_per_determinization,rollout_policy,opponent,games,wins,losses,\
+          draws,score_rate,score_rate_low,score_rate_high,average_turns,significance"
+      )?;
+      for row in self.rows.iter() {
+         writeln!(
+            output,
+            "{},{},{},{:?},\"{}\",{},{},{},{},{},{},{},{},{}",
+            row.exploration_constant,
+            row.simulations,
+            row.simulations_per_determinization,
+            row.rollout_policy,
+            row.opponent,
+            row.games,
+            row.wins,
+            row.losses,
+            row.draws,
+            row.score_rate,
+            row.score_rate_low,
+            row.score_rate_high,
+            row.average_turns,
+            row.significance.map(|x| format!("{:?}", x)).unwrap_or_default()
+         )?;
+      }
+      Ok(())
+   }
+}
+
+/// Wilson score interval at 95% confidence for a rate observed over `games` games
+fn confidence_interval(rate: f64, games: usize) -> (f64, f64) {
+   const Z: f64 = 1.96;
+   if games == 0 {
+      return (0.0, 1.0);
+   }
+   let n = games as f64;
+   let centre = rate + Z * Z / (2.0 * n);
+   let margin = Z * (rate * (1.0 - rate) / n + Z * Z / (4.0 * n * n)).sqrt();
+   let denominator = 1.0 + Z * Z / n;
+   ((centre - margin) / denominator, (centre + margin) / denominator)
+}
+
+/// Plays every combination in `config` at once, spreading them and their games across threads. Rows come back in
+/// the order of the config's lists, but progress is reported as each duel finishes
+pub fn run_sweep<F: FnMut(TournamentProgress) + Send>(config: &SweepConfig, on_progress: F) -> SweepReport {
+   let mut cells = Vec::new();
+   for c in config.exploration_constants.iter() {
+      for sims in config.simulation_counts.iter() {
+         for per_determinization in config.simulations_per_determinization.iter() {
+            for rollout in config.rollout_policies.iter() {
+               for opponent in config.opponents.iter() {
+                  cells.push((*c, *sims, *per_determinization, *rollout, opponent));
+               }
+            }
+         }
+      }
+   }
+   let duels_total = cells.len();
+   let progress = Mutex::new((0, on_progress));
+   let rows = cells
+      .into_par_iter()
+      .map(|(c, sims, per_determinization, rollout, opponent)| {
+         let monty = Entrant::Monty(c, sims, per_determinization, rollout);
+         let duel = ai_duel(
+            1,
+            &monty,
+            opponent,
+            config.games_per_duel,
+            config.max_turns,
+            config.early_stopping,
+         );
+         {
+            let mut progress = progress.lock().unwrap();
+            progress.0 += 1;
+            let duels_finished = progress.0;
+            (progress.1)(TournamentProgress {
+               duels_finished,
+               duels_total,
+               duel: &duel,
+            });
+         }
+         let games = duel.games();
+         let score_rate = duel.score_rate();
+         SweepRow {
+            exploration_constant: c,
+            simulations: sims,
+            simulations_per_determinization: per_determinization,
+            rollout_policy: rollout,
+            opponent: duel.second,
+            games,
+            wins: duel.first_wins,
+            losses: duel.second_wins,
+            draws: duel.draws,
+            score_rate,
+            score_rate_low: duel.score_rate_low,
+            score_rate_high: duel.score_rate_high,
+            average_turns: duel.total_turns as f64 / games.max(1) as f64,
+            significance: duel.significance,
+         }
+      })
+      .collect();
+   SweepReport { rows }
+}
+
+fn ai_play(game: &mut GameState, ai_core: &mut (dyn PalaceAi + Send + Sync)) -> PlayOutcome {
+   ai_core.on_your_turn();
+   let cards_to_play = ai::get_turn(game, ai_core);
+   game.take_turn(&cards_to_play).unwrap()
+}
+
+#[derive(PartialEq, Eq)]
+enum Winner {
+   Player1,
+   Player2,
+   TimedOut,
+}
+
+struct GameResult {
+   winner: Winner,
+   num_turns: usize,
+}
+
+fn run_ai_game(first_player: &Entrant, second_player: &Entrant, max_turns: usize) -> GameResult {
+   let mut game = GameState::new(2);
+   assert_eq!(game.active_player, 0);
+   let mut first_player = first_player.instantiate();
+   let mut second_player = second_player.instantiate();
+   let mut players = HashMap::with_capacity(2);
+   players.insert(0, PlayerProfile::new(first_player.strategy_name().into(), true));
+   players.insert(1, PlayerProfile::new(second_player.strategy_name().into(), true));
+   let gse_1 = GameStartEvent {
+      hand: game.get_hand(0),
+      turn_number: 0,
+      players: &players,
+   };
+   let gse_2 = GameStartEvent {
+      hand: game.get_hand(1),
+      turn_number: 1,
+      players: &players,
+   };
+   first_player.on_game_start(gse_1);
+   second_player.on_game_start(gse_2);
+   let pgs = game.public_state();
+   first_player.on_game_state_update(&pgs);
+   second_player.on_game_state_update(&pgs);
+   loop {
+      if game.active_player == 0 {
+         let outcome = ai_play(&mut game, &mut *first_player);
+         if outcome.game_over {
+            return GameResult {
+               winner: Winner::Player1,
+               num_turns: game.turns_taken as usize,
+            };
+         }
+         if let Some(zone) = game.last_played_zone {
+            second_player.on_opponent_play(0, &game.last_cards_played, zone, outcome.picked_up);
+         }
+         let pgs = game.public_state();
+         first_player.on_hand_update(game.get_hand(0));
+         first_player.on_game_state_update(&pgs);
+         second_player.on_game_state_update(&pgs)
+      } else {
+         let outcome = ai_play(&mut game, &mut *second_player);
+         if outcome.game_over {
+            return GameResult {
+               winner: Winner::Player2,
+               num_turns: game.turns_taken as usize,
+            };
+         }
+         if let Some(zone) = game.last_played_zone {
+            first_player.on_opponent_play(1, &game.last_cards_played, zone, outcome.picked_up);
+         }
+         let pgs = game.public_state();
+         second_player.on_hand_update(game.get_hand(1));
+         first_player.on_game_state_update(&pgs);
+         second_player.on_game_state_update(&pgs);
+      }
+      // Counted the same way as the server's `max_game_turns`, so that a cap means the same in both
+      if game.turns_taken as usize >= max_turns {
+         return GameResult {
+            winner: Winner::TimedOut,
+            num_turns: game.turns_taken as usize,
+         };
+      }
+   }
+}
+
+#[derive(Default)]
+struct Tally {
+   first_wins: AtomicUsize,
+   second_wins: AtomicUsize,
+   draws: AtomicUsize,
+   total_turns: AtomicUsize,
+}
+
+impl Tally {
+   /// Plays `pairs` games with each entrant going first
+   fn play(&self, first_ai: &Entrant, second_ai: &Entrant, pairs: usize, max_turns: usize) {
+      (0..pairs).into_par_iter().for_each(|_| {
+         let result = run_ai_game(first_ai, second_ai, max_turns);
+         match result.winner {
+            Winner::Player2 => self.second_wins.fetch_add(1, Ordering::Relaxed),
+            Winner::Player1 => self.first_wins.fetch_add(1, Ordering::Relaxed),
+            Winner::TimedOut => self.draws.fetch_add(1, Ordering::Relaxed),
+         };
+         self.total_turns.fetch_add(result.num_turns, Ordering::Relaxed);
+      });
+      (0..pairs).into_par_iter().for_each(|_| {
+         let result = run_ai_game(second_ai, first_ai, max_turns);
+         match result.winner {
+            Winner::Player2 => self.first_wins.fetch_add(1, Ordering::Relaxed),
+            Winner::Player1 => self.second_wins.fetch_add(1, Ordering::Relaxed),
+            Winner::TimedOut => self.draws.fetch_add(1, Ordering::Relaxed),
+         };
+         self.total_turns.fetch_add(result.num_turns, Ordering::Relaxed);
+      });
+   }
+}
+
+fn ai_duel(
+   round: usize,
+   first_ai: &Entrant,
+   second_ai: &Entrant,
+   games: usize,
+   max_turns: usize,
+   early_stopping: Option<EarlyStopping>,
+) -> DuelReport {
+   let tally = Tally::default();
+   let significance = match early_stopping {
+      None => {
+         tally.play(first_ai, second_ai, games / 2, max_turns);
+         None
+      }
+      Some(early_stopping) => {
+         // Small enough batches to stop soon after the test concludes, big enough to keep every thread busy
+         let batch_pairs = rayon::current_num_threads();
+         let mut pairs_played = 0;
+         loop {
+            let pairs = batch_pairs.min(games / 2 - pairs_played);
+            if pairs == 0 {
+               break Some(Significance::Inconclusive);
+            }
+            tally.play(first_ai, second_ai, pairs, max_turns);
+            pairs_played += pairs;
+            let wins = tally.first_wins.load(Ordering::Relaxed);
+            let losses = tally.second_wins.load(Ordering::Relaxed);
+            if let Some(significance) = early_stopping.judge(wins, losses) {
+               break Some(significance);
+            }
+         }
+      }
+   };
+   let mut duel = DuelReport {
+      round,
+      first: first_ai.to_string(),
+      second: second_ai.to_string(),
+      first_wins: tally.first_wins.into_inner(),
+      second_wins: tally.second_wins.into_inner(),
+      draws: tally.draws.into_inner(),
+      total_turns: tally.total_turns.into_inner(),
+      score_rate_low: 0.0,
+      score_rate_high: 1.0,
+      significance,
+   };
+   let (low, high) = confidence_interval(duel.score_rate(), duel.games());
+   duel.score_rate_low = low;
+   duel.score_rate_high = high;
+   duel
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn every_game_is_reported() {
+      let mut config = TournamentConfig::round_robin(&[Entrant::Random, Entrant::LowAndSteady, Entrant::Random]);
+      config.games_per_duel = 4;
+      let mut progress = Vec::new();
+      let report = run_tournament(&config, |p| progress.push((p.duels_finished, p.duels_total)));
+      assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+      assert_eq!(report.duels[0].first, "Random");
+      assert_eq!(report.duels[0].second, "Low and Steady");
+      assert!(report.duels.iter().all(|duel| duel.games() == 4));
+
+      let mut csv = Vec::new();
+      report.write_csv(&mut csv).unwrap();
+      assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 4);
+   }
+
+   #[test]
+   fn swiss_rounds_avoid_rematches_and_rotate_byes() {
+      let entrants = [
+         Entrant::Random,
+         Entrant::LowAndSteady,
+         Entrant::Monty(0.7, 10, 1, RolloutPolicy::Random),
+         Entrant::Monty(1.4, 10, 5, RolloutPolicy::Random),
+         Entrant::Monty(0.7, 10, 1, RolloutPolicy::LowAndSteady),
+      ];
+      let mut config = TournamentConfig::swiss(&entrants, 2);
+      config.games_per_duel = 2;
+      let report = run_tournament(&config, |_| ());
+      assert_eq!(report.duels.len(), 4);
+      let mut pairs: Vec<(&str, &str)> = report
+         .duels
+         .iter()
+         .map(|duel| {
+            (
+               duel.first.as_str().min(&duel.second),
+               duel.first.as_str().max(&duel.second),
+            )
+         })
+         .collect();
+      pairs.sort();
+      pairs.dedup();
+      assert_eq!(pairs.len(), 4);
+
+      assert_eq!(report.standings.len(), 5);
+      assert_eq!(report.standings.iter().map(|s| s.byes).sum::<usize>(), 2);
+      assert!(report.standings.iter().all(|s| s.byes <= 1));
+      assert!(report
+         .standings
+         .iter()
+         .all(|s| s.wins + s.draws + s.losses + s.byes == 2));
+      assert!(report.standings.windows(2).all(|w| w[0].score >= w[1].score));
+   }
+
+   #[test]
+   fn sweep_reports_every_combination_in_order() {
+      let config: SweepConfig = serde_json::from_str(
+         r#"{
+            "exploration_constants": [0.7, 1.4],
+            "simulation_counts": [10],
+            "rollout_policies": ["Random", "LowAndSteady"],
+            "opponents": ["Random"],
+            "games_per_duel": 2
+         }"#,
+      )
+      .unwrap();
+      assert_eq!(config.max_turns, DEFAULT_MAX_TURNS);
+      let mut finished = 0;
+      let report = run_sweep(&config, |_| finished += 1);
+      assert_eq!(finished, 4);
+      let combinations: Vec<(f64, RolloutPolicy)> = report
+         .rows
+         .iter()
+         .map(|row| (row.exploration_constant, row.rollout_policy))
+         .collect();
+      assert_eq!(
+         combinations,
+         vec![
+            (0.7, RolloutPolicy::Random),
+            (0.7, RolloutPolicy::LowAndSteady),
+            (1.4, RolloutPolicy::Random),
+            (1.4, RolloutPolicy::LowAndSteady),
+         ]
+      );
+      for row in report.rows.iter() {
+         assert_eq!(row.games, 2);
+         assert!(row.score_rate_low <= row.score_rate && row.score_rate <= row.score_rate_high);
+      }
+   }
+
+   #[test]
+   fn confidence_interval_narrows_with_more_games() {
+      let (low, high) = confidence_interval(0.5, 10);
+      let (more_low, more_high) = confidence_interval(0.5, 1000);
+      assert!(low < more_low && more_high < high);
+      assert!((more_low - 0.469).abs() < 0.001);
+   }
+
+   #[test]
+   fn early_stopping_needs_enough_decisive_games() {
+      let early_stopping = EarlyStopping::default();
+      assert_eq!(early_stopping.judge(3, 2), None);
+      assert_eq!(early_stopping.judge(700, 500), Some(Significance::FirstStronger));
+      assert_eq!(early_stopping.judge(500, 700), Some(Significance::SecondStronger));
+      assert_eq!(early_stopping.judge(1000, 1000), Some(Significance::NoDifference));
+   }
+
+   #[test]
+   fn early_stopped_duels_report_a_verdict() {
+      let mut config = TournamentConfig::round_robin(&[Entrant::Random, Entrant::LowAndSteady]);
+      config.games_per_duel = 8;
+      config.early_stopping = Some(EarlyStopping {
+         margin: 0.4,
+         ..EarlyStopping::default()
+      });
+      let report = run_tournament(&config, |_| ());
+      let duel = &report.duels[0];
+      assert!(duel.games() <= 8 && duel.games() % 2 == 0);
+      assert!(duel.significance.is_some());
+      assert!(duel.score_rate_low <= duel.score_rate() && duel.score_rate() <= duel.score_rate_high);
+   }
+}
@@ -1,8 +1,11 @@
+// Mirrors the whole protocol, including fields that no test looks at
+#![allow(dead_code)]
+
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
-enum CardSuit {
+pub enum CardSuit {
    Clubs,
    Diamonds,
    Hearts,
@@ -10,7 +13,7 @@ enum CardSuit {
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
-enum CardValue {
+pub enum CardValue {
    Two,
    Three,
    Four,
@@ -28,8 +31,8 @@ enum CardValue {
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Card {
-   value: CardValue,
-   suit: CardSuit,
+   pub value: CardValue,
+   pub suit: CardSuit,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -48,6 +51,7 @@ pub struct LobbyDisplay {
 pub struct NewLobbyResponse {
    pub player_id: String,
    pub lobby_id: String,
+   pub max_players: u8,
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,6 +59,9 @@ pub enum NewLobbyError {
    LessThanTwoMaxPlayers,
    EmptyLobbyName,
    EmptyPlayerName,
+   ServerDraining,
+   UnknownScenario,
+   TurnTimerTooShort,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,12 +69,14 @@ pub struct PlayerJoinEvent {
    pub total_num_players: u8,
    pub new_player_name: String,
    pub slot: u8,
+   pub seq: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PlayerLeaveEvent {
    pub total_num_players: u8,
    pub slot: u8,
+   pub seq: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -77,6 +86,9 @@ pub enum RequestAiError {
    LobbyNotFound,
    LobbyTooSmall,
    GameInProgress,
+   StrategyUnavailable,
+   NotEnoughVacantSeats,
+   InvalidMontyParameters,
 }
 
 #[derive(Debug, Deserialize)]
@@ -90,9 +102,11 @@ pub enum KickPlayerError {
 #[derive(Debug, Deserialize)]
 pub struct JoinLobbyResponse {
    pub player_id: String,
-   pub lobby_players: Box<[String]>,
+   pub slot: u8,
+   pub lobby_players: Box<[LobbySeat]>,
    pub max_players: u8,
-   pub num_spectators: u8,
+   pub num_spectators: u16,
+   pub journal_seq: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,6 +116,8 @@ pub enum JoinLobbyError {
    BadPassword,
    GameInProgress,
    EmptyPlayerName,
+   ServerDraining,
+   PracticeLobby,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -109,6 +125,10 @@ pub enum LobbyCloseEvent {
    Kicked,
    OwnerLeft,
    Afk,
+   GameOver,
+   Expired,
+   Merged,
+   IdRotated,
 }
 
 #[derive(Debug, Deserialize)]
@@ -117,16 +137,42 @@ pub enum StartGameError {
    NotLobbyOwner,
    LessThanTwoPlayers,
    GameInProgress,
+   StrategyUnavailable,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GameStartEvent {
    pub hand: Box<[Card]>,
    pub turn_number: u8,
-   pub players: HashMap<u8, String>,
+   pub players: HashMap<u8, PlayerProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpectateGameStartEvent {
+   pub players: HashMap<u8, PlayerProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum Presence {
+   Active,
+   Idle,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerPresenceEvent {
+   pub slot: u8,
+   pub presence: Presence,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct PlayerProfile {
+   pub name: String,
+   pub is_ai: bool,
+   pub rating: Option<u32>,
+   pub account_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
 pub struct PublicGameState {
    pub hands: Box<[u16]>,
    pub face_up_three: Box<[Box<[Card]>]>,
@@ -137,11 +183,551 @@ pub struct PublicGameState {
    pub cur_phase: GamePhase,
    pub active_player: u8,
    pub last_cards_played: Box<[Card]>,
+   pub placements: Box<[Option<u8>]>,
+   pub turn: u32,
+   pub turn_deadline: Option<u16>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ListLobbiesResponse {
    pub lobbies: Box<[LobbyDisplay]>,
+   pub has_next_page: bool,
+   pub total_lobbies: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum ConnectionStatus {
+   Connected,
+   Disconnected,
+   TimedOut,
+   Kicked,
+   ReplacedByAi,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerConnectionStatusEvent {
+   pub slot: u8,
+   pub status: ConnectionStatus,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReconnectResponse {
+   pub max_players: u8,
+   pub num_spectators: u16,
+   pub turn_timer: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum ReconnectError {
+   LobbyNotFound,
+   PlayerNotFound,
+   PlayerKicked,
+   TooManyAttempts,
+   ReplacedByAi,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LobbySeat {
+   pub slot: u8,
+   pub name: String,
+   pub is_ai: bool,
+   pub is_owner: bool,
+   pub status: ConnectionStatus,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum GetLobbyPlayersError {
+   LobbyNotFound,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum SetSeatClosedError {
+   NotLobbyOwner,
+   LobbyNotFound,
+   GameInProgress,
+   SeatNotFound,
+   SeatOccupied,
+   LessThanTwoOpenSeats,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum TransferOwnershipError {
+   NotLobbyOwner,
+   LobbyNotFound,
+   TargetPlayerNotFound,
+   TargetIsOwner,
+   TargetNotHuman,
+   TargetDisconnected,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OwnerChangedEvent {
+   pub slot: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum TakeTurnError {
+   LobbyNotFound,
+   GameNotStarted,
+   PlayerNotFound,
+   NotYourTurn,
+   StalePlaySeq,
+   GameError(String),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpectateLobbyResponse {
+   pub lobby_players: Box<[LobbySeat]>,
+   pub max_players: u8,
+   pub num_spectators: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum SpectateLobbyError {
+   LobbyNotFound,
+   SpectateLobbyFull,
+   AlreadyInLobby,
+   AlreadySpectating,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum StopSpectatingError {
+   NotSpectating,
+}
+
+#[derive(Serialize)]
+pub struct TakeSeatMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_name: &'a str,
+   pub password: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum TakeSeatError {
+   NotSpectating,
+   CantJoin(JoinLobbyError),
+}
+
+#[derive(Serialize)]
+pub struct JoinWithTokenMessage<'a> {
+   pub lobby_id: &'a str,
+   pub token: &'a str,
+   pub player_name: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum JoinWithTokenError {
+   BadToken,
+   CantJoin(JoinLobbyError),
+}
+
+#[derive(Serialize)]
+pub struct CreateJoinTokenMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub expires_in_secs: u32,
+   pub max_uses: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum CreateJoinTokenError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   InvalidExpiry,
+   InvalidMaxUses,
+   TooManyTokens,
+}
+
+#[derive(Serialize)]
+pub struct QueueForSeatMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_name: &'a str,
+   pub password: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum QueueForSeatError {
+   LobbyNotFound,
+   LobbyNotFull,
+   GameInProgress,
+   BadPassword,
+   AlreadyInLobby,
+   QueueFull,
+   EmptyPlayerName,
+   PlayerNameTooLong,
+   ServerDraining,
+   PracticeLobby,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueuePositionEvent {
+   pub position: u16,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum DisconnectCode {
+   ProtocolError,
+   RateLimited,
+   Kicked,
+   Afk,
+   ServerDraining,
+   Unreachable,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisconnectEvent {
+   pub code: DisconnectCode,
+   pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum HighlightKind {
+   BigPickup(u16),
+   FourOfAKind,
+   WonFromFaceDown,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Highlight {
+   pub turn: u32,
+   pub player: u8,
+   pub kind: HighlightKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct YourTurnEvent {
+   pub deadline: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TurnTimeWarningEvent {
+   pub secs_remaining: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum AbortGameError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveAnnotation {
+   pub turn: u32,
+   pub played: Vec<Card>,
+   pub preferred: Vec<Card>,
+   pub preferred_win_rate: f64,
+   pub played_win_rate: Option<f64>,
+   pub win_rate_swing: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GameAnalysis {
+   pub slot: u8,
+   pub moves: Vec<MoveAnnotation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum AnalyzeGameError {
+   LobbyNotFound,
+   NoCompletedGame,
+   NotInGame,
+   ServerBusy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Hint {
+   pub cards: Vec<Card>,
+   pub hints_left: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum RequestHintError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   NotYourTurn,
+   HintsDisabled,
+   NoHintsLeft,
+   TooSoon,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum RewindTurnError {
+   LobbyNotFound,
+   PlayerNotFound,
+   NotPracticeLobby,
+   GameNotStarted,
+   ZeroTurns,
+   NotEnoughTurns,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GameRewoundEvent {
+   pub turns_taken: u32,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum PredictedPlay {
+   Cards(CardValue, u8),
+   FaceDown,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum PredictPlayError {
+   LobbyNotFound,
+   NotSpectating,
+   GameNotStarted,
+   NotDuringSetup,
+   NoCards,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PredictionTally {
+   pub play: PredictedPlay,
+   pub guesses: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PredictionResultsEvent {
+   pub turn: u32,
+   pub played: PredictedPlay,
+   pub tallies: Vec<PredictionTally>,
+   pub correct: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PredictionScoreEvent {
+   pub correct: bool,
+   pub score: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum MergeLobbyError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   TargetNotFound,
+   SameLobby,
+   GameInProgress,
+   NotMergeable,
+   NotEnoughSeats,
+   AlreadyRequested,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RotateIdResponse {
+   pub player_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum RotateIdError {
+   LobbyNotFound,
+   PlayerNotFound,
+   NotConnectedAsPlayer,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatchUpResponse {
+   pub latest_seq: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum CatchUpError {
+   LobbyNotFound,
+   PlayerNotFound,
+   TooFarBehind,
+}
+
+#[derive(Serialize)]
+pub enum LobbyEventKind {
+   Leave,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum ObserveLobbyError {
+   LobbyNotFound,
+   NotAdmin,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum StopObservingLobbyError {
+   NotObserving,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObservedLobbyEvent {
+   pub lobby_id: String,
+   pub event: Box<InMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum ScheduleEventError {
+   NotAdmin,
+   BadOpeningTime,
+   LessThanTwoMaxPlayers,
+   EmptyName,
+   NameTooLong,
+   DescriptionTooLong,
+   TurnTimerTooShort,
+   AnnouncementTooEarly,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum UnscheduleEventError {
+   NotAdmin,
+   EventNotFound,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventListing {
+   pub name: String,
+   pub opens_in_secs: u32,
+   pub num_registered: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterForEventResponse {
+   pub opens_in_secs: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum RegisterForEventError {
+   EventNotFound,
+   EmptyPlayerName,
+   PlayerNameTooLong,
+   EventFull,
+   ServerDraining,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventOpenedEvent {
+   pub event: String,
+   pub lobby_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub enum AnnouncementSeverity {
+   Info,
+   Warning,
+   Critical,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerAnnouncementEvent {
+   pub severity: AnnouncementSeverity,
+   pub text: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum AnnounceError {
+   NotAdmin,
+   LobbyNotFound,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum SetDrainModeError {
+   NotAdmin,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerDrainingEvent {
+   pub draining: bool,
+   pub shutdown_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum SubscribeServerStatsError {
+   NotAdmin,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerStatsEvent {
+   pub lobbies: u64,
+   pub games_in_progress: u64,
+   pub players: u64,
+   pub ai_players: u64,
+   pub spectators: u64,
+   pub messages_per_sec: f64,
+   pub ai_queue_depth: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum AnswerMergeError {
+   LobbyNotFound,
+   NotLobbyOwner,
+   NoSuchRequest,
+   GameInProgress,
+   NotEnoughSeats,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeRequestEvent {
+   pub from_lobby_id: String,
+   pub lobby_name: String,
+   pub player_names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeDeclinedEvent {
+   pub target_lobby_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LobbyMergedEvent {
+   pub lobby_id: String,
+   pub slot: u8,
+   pub lobby: JoinLobbyResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum ConcedeError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   GameInSetup,
+   AlreadyOut,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerConcededEvent {
+   pub slot: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum VoteSkipTurnError {
+   LobbyNotFound,
+   PlayerNotFound,
+   GameNotStarted,
+   OwnTurn,
+   ActivePlayerNotConnected,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TurnSkippedEvent {
+   pub slot: u8,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum GameAbortReason {
+   Owner,
+   Vote,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub enum DrawReason {
+   TurnLimit,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GameDrawEvent {
+   pub reason: DrawReason,
+   pub turns: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LobbySettingsEvent {
+   pub max_players: u8,
+   pub closed_seats: Vec<u8>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -156,8 +742,70 @@ pub enum InMessage {
    PlayerLeaveEvent(PlayerLeaveEvent),
    LobbyCloseEvent(LobbyCloseEvent),
    GameStartEvent(GameStartEvent),
+   SpectateGameStartEvent(SpectateGameStartEvent),
+   PlayerPresenceEvent(PlayerPresenceEvent),
    PublicGameStateEvent(PublicGameState),
    HandEvent(Vec<Card>),
+   GameCompleteEvent(Vec<u8>),
+   ReconnectResponse(Result<ReconnectResponse, ReconnectError>),
+   PlayerConnectionStatusEvent(PlayerConnectionStatusEvent),
+   GetLobbyPlayersResponse(Result<Vec<LobbySeat>, GetLobbyPlayersError>),
+   UnknownMessageType(String),
+   SetSeatClosedResponse(Result<(), SetSeatClosedError>),
+   LobbySettingsEvent(LobbySettingsEvent),
+   AbortGameResponse(Result<(), AbortGameError>),
+   ConcedeResponse(Result<(), ConcedeError>),
+   PlayerConcededEvent(PlayerConcededEvent),
+   VoteSkipTurnResponse(Result<(), VoteSkipTurnError>),
+   TurnSkippedEvent(TurnSkippedEvent),
+   GameAbortedEvent(GameAbortReason),
+   GameDrawEvent(GameDrawEvent),
+   TransferOwnershipResponse(Result<(), TransferOwnershipError>),
+   OwnerChangedEvent(OwnerChangedEvent),
+   YourTurnEvent(YourTurnEvent),
+   TurnTimeWarningEvent(TurnTimeWarningEvent),
+   HighlightEvent(Highlight),
+   AnalyzeGameResponse(Result<GameAnalysis, AnalyzeGameError>),
+   RequestHintResponse(Result<Hint, RequestHintError>),
+   RewindTurnResponse(Result<(), RewindTurnError>),
+   GameRewoundEvent(GameRewoundEvent),
+   PredictPlayResponse(Result<(), PredictPlayError>),
+   PredictionResultsEvent(PredictionResultsEvent),
+   PredictionScoreEvent(PredictionScoreEvent),
+   MergeLobbyResponse(Result<(), MergeLobbyError>),
+   AnswerMergeResponse(Result<(), AnswerMergeError>),
+   AttachToPlayerResponse(Result<ReconnectResponse, ReconnectError>),
+   RotateIdResponse(Result<RotateIdResponse, RotateIdError>),
+   CatchUpResponse(Result<CatchUpResponse, CatchUpError>),
+   ObserveLobbyResponse(Result<(), ObserveLobbyError>),
+   StopObservingLobbyResponse(Result<(), StopObservingLobbyError>),
+   ObservedLobbyEvent(ObservedLobbyEvent),
+   ScheduleEventResponse(Result<(), ScheduleEventError>),
+   UnscheduleEventResponse(Result<(), UnscheduleEventError>),
+   ListEventsResponse(Vec<EventListing>),
+   RegisterForEventResponse(Result<RegisterForEventResponse, RegisterForEventError>),
+   EventOpenedEvent(EventOpenedEvent),
+   ServerAnnouncementEvent(ServerAnnouncementEvent),
+   AnnounceResponse(Result<(), AnnounceError>),
+   SetDrainModeResponse(Result<(), SetDrainModeError>),
+   ServerDrainingEvent(ServerDrainingEvent),
+   SubscribeServerStatsResponse(Result<(), SubscribeServerStatsError>),
+   ServerStatsEvent(ServerStatsEvent),
+   MergeRequestEvent(MergeRequestEvent),
+   MergeDeclinedEvent(MergeDeclinedEvent),
+   LobbyMergedEvent(LobbyMergedEvent),
+   DisconnectEvent(DisconnectEvent),
+   TakeTurnResponse(Result<(), TakeTurnError>),
+   SpectateLobbyResponse(Result<SpectateLobbyResponse, SpectateLobbyError>),
+   StopSpectatingResponse(Result<(), StopSpectatingError>),
+   TakeSeatResponse(Result<JoinLobbyResponse, TakeSeatError>),
+   JoinWithTokenResponse(Result<JoinLobbyResponse, JoinWithTokenError>),
+   CreateJoinTokenResponse(Result<String, CreateJoinTokenError>),
+   QueueForSeatResponse(Result<u16, QueueForSeatError>),
+   QueuePositionEvent(QueuePositionEvent),
+   SeatGrantedEvent(JoinLobbyResponse),
+   SpectatorJoinEvent(()),
+   SpectatorLeaveEvent(()),
 }
 
 #[derive(Serialize)]
@@ -166,7 +814,25 @@ pub struct NewLobbyMessage<'a> {
    pub password: &'a str,
    pub lobby_name: &'a str,
    pub player_name: &'a str,
-   pub turn_timer_secs: u8,
+   pub turn_timer: u16,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub hints: Option<HintPolicy>,
+   pub practice: bool,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub scenario: Option<&'a str>,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub draw_rules: Option<DrawRules>,
+}
+
+#[derive(Serialize)]
+pub struct DrawRules {
+   pub max_repetitions: Option<u8>,
+   pub max_turns: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub enum HintPolicy {
+   PerGame(u16),
 }
 
 #[derive(Serialize)]
@@ -183,6 +849,158 @@ pub struct KickPlayerMessage<'a> {
    pub slot: u8,
 }
 
+#[derive(Serialize)]
+pub struct SetSeatClosedMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub slot: u8,
+   pub closed: bool,
+}
+
+#[derive(Serialize)]
+pub struct TakeTurnMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub cards: &'a [Card],
+   pub play_seq: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct TransferOwnershipMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub slot: u8,
+}
+
+#[derive(Serialize)]
+pub struct AbortGameMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct AnalyzeGameMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct RequestHintMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct RewindTurnMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub turns: u16,
+}
+
+#[derive(Serialize)]
+pub struct PredictPlayMessage<'a> {
+   pub lobby_id: &'a str,
+   pub play: PredictedPlay,
+}
+
+#[derive(Serialize)]
+pub struct MergeLobbyMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub target_lobby_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct AnswerMergeMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub from_lobby_id: &'a str,
+   pub accept: bool,
+}
+
+#[derive(Serialize)]
+pub struct RotateIdMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct CatchUpMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+   pub since_seq: u64,
+}
+
+#[derive(Serialize)]
+pub struct ObserveLobbyMessage<'a> {
+   pub admin_key: &'a str,
+   pub lobby_id: &'a str,
+   pub kinds: Vec<LobbyEventKind>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledEvent<'a> {
+   pub name: &'a str,
+   pub opens_at_utc: &'a str,
+   pub max_players: u8,
+}
+
+#[derive(Serialize)]
+pub struct ScheduleEventMessage<'a> {
+   pub admin_key: &'a str,
+   pub event: ScheduledEvent<'a>,
+}
+
+#[derive(Serialize)]
+pub struct AnnounceMessage<'a> {
+   pub admin_key: &'a str,
+   pub lobby_id: Option<&'a str>,
+   pub severity: AnnouncementSeverity,
+   pub text: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct SetDrainModeMessage<'a> {
+   pub admin_key: &'a str,
+   pub draining: bool,
+   pub shutdown_in_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct SubscribeServerStatsMessage<'a> {
+   pub admin_key: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct UnscheduleEventMessage<'a> {
+   pub admin_key: &'a str,
+   pub name: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct RegisterForEventMessage<'a> {
+   pub event: &'a str,
+   pub player_name: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct ConcedeMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct VoteSkipTurnMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct ReconnectMessage<'a> {
+   pub lobby_id: &'a str,
+   pub player_id: &'a str,
+}
+
 #[derive(Serialize)]
 pub struct JoinLobbyMessage<'a> {
    pub lobby_id: &'a str,
@@ -194,11 +1012,13 @@ pub struct JoinLobbyMessage<'a> {
 pub struct StartGameMessage<'a> {
    pub lobby_id: &'a str,
    pub player_id: &'a str,
+   pub fill_with_ai: Option<&'a str>,
 }
 
 #[derive(Serialize)]
 pub struct ListLobbiesMessage {
    pub page: u64,
+   pub page_size: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -207,6 +1027,38 @@ pub enum OutMessage<'a> {
    RequestAi(RequestAiMessage<'a>),
    KickPlayer(KickPlayerMessage<'a>),
    JoinLobby(JoinLobbyMessage<'a>),
+   Reconnect(ReconnectMessage<'a>),
+   JoinWithToken(JoinWithTokenMessage<'a>),
+   CreateJoinToken(CreateJoinTokenMessage<'a>),
    StartGame(StartGameMessage<'a>),
    ListLobbies(ListLobbiesMessage),
+   GetLobbyPlayers(&'a str),
+   SetSeatClosed(SetSeatClosedMessage<'a>),
+   AbortGame(AbortGameMessage<'a>),
+   Concede(ConcedeMessage<'a>),
+   VoteSkipTurn(VoteSkipTurnMessage<'a>),
+   TransferOwnership(TransferOwnershipMessage<'a>),
+   TakeTurn(TakeTurnMessage<'a>),
+   SpectateLobby(&'a str),
+   StopSpectating(&'a str),
+   TakeSeat(TakeSeatMessage<'a>),
+   QueueForSeat(QueueForSeatMessage<'a>),
+   AnalyzeGame(AnalyzeGameMessage<'a>),
+   RequestHint(RequestHintMessage<'a>),
+   RewindTurn(RewindTurnMessage<'a>),
+   PredictPlay(PredictPlayMessage<'a>),
+   MergeLobby(MergeLobbyMessage<'a>),
+   AnswerMerge(AnswerMergeMessage<'a>),
+   AttachToPlayer(ReconnectMessage<'a>),
+   RotateId(RotateIdMessage<'a>),
+   CatchUp(CatchUpMessage<'a>),
+   ObserveLobby(ObserveLobbyMessage<'a>),
+   StopObservingLobby(&'a str),
+   ScheduleEvent(ScheduleEventMessage<'a>),
+   UnscheduleEvent(UnscheduleEventMessage<'a>),
+   ListEvents(()),
+   RegisterForEvent(RegisterForEventMessage<'a>),
+   Announce(AnnounceMessage<'a>),
+   SetDrainMode(SetDrainModeMessage<'a>),
+   SubscribeServerStats(SubscribeServerStatsMessage<'a>),
 }
@@ -10,7 +10,7 @@ use ws::{Handler, Handshake, Message, Sender};
 pub struct TestClientInner {
    out: Sender,
    recvd_messages: mpsc::Sender<InMessage>,
-   to_send_messages: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
+   to_send_messages: Arc<Mutex<mpsc::Receiver<Message>>>,
 }
 
 impl Handler for TestClientInner {
@@ -20,8 +20,8 @@ impl Handler for TestClientInner {
 
    fn on_timeout(&mut self, event: ws::util::Token) -> ws::Result<()> {
       if event == ws::util::Token(1) {
-         if let Ok(bytes) = self.to_send_messages.lock().try_recv() {
-            self.out.send(bytes).unwrap();
+         if let Ok(message) = self.to_send_messages.lock().try_recv() {
+            self.out.send(message).unwrap();
          }
          self.out.timeout(100, ws::util::Token(1))
       } else {
@@ -40,16 +40,21 @@ impl Handler for TestClientInner {
 
 pub struct TestClient {
    recvd_messages: mpsc::Receiver<InMessage>,
-   to_send_messages: mpsc::Sender<Vec<u8>>,
+   to_send_messages: mpsc::Sender<Message>,
 }
 
 impl TestClient {
    pub fn new() -> TestClient {
+      TestClient::new_at("127.0.0.1:3013")
+   }
+
+   pub fn new_at(address: &str) -> TestClient {
+      let url = format!("ws://{}", address);
       let (tx, rx) = mpsc::channel();
       let (tx2, rx2) = mpsc::channel();
       let to_send_messages = Arc::new(Mutex::new(rx2));
       std::thread::spawn(move || {
-         ws::connect("ws://127.0.0.1:3013", |out| TestClientInner {
+         ws::connect(url, |out| TestClientInner {
             out,
             recvd_messages: tx.clone(),
             to_send_messages: to_send_messages.clone(),
@@ -65,18 +70,35 @@ impl TestClient {
    pub fn send(&mut self, message: &OutMessage) {
       self
          .to_send_messages
-         .send(serde_json::to_vec(message).unwrap())
+         .send(Message::binary(serde_json::to_vec(message).unwrap()))
          .unwrap();
    }
 
+   pub fn send_text(&mut self, message: &OutMessage) {
+      self
+         .to_send_messages
+         .send(Message::text(serde_json::to_string(message).unwrap()))
+         .unwrap();
+   }
+
+   pub fn send_raw(&mut self, message: &[u8]) {
+      self.to_send_messages.send(Message::binary(message)).unwrap();
+   }
+
    pub fn get(&mut self) -> InMessage {
       self.recvd_messages.recv().unwrap()
    }
 
+   /// None if nothing arrives in time, or if the connection has closed
+   #[cfg(feature = "chaos")]
+   pub fn get_timeout(&mut self, timeout: Duration) -> Option<InMessage> {
+      self.recvd_messages.recv_timeout(timeout).ok()
+   }
+
    pub fn disconnect(&mut self) {
       self
          .to_send_messages
-         .send(Vec::from(
+         .send(Message::binary(
             "This message will be unrecognized, causing the connection to end",
          ))
          .unwrap();
@@ -92,7 +114,11 @@ impl TestClient {
          lobby_name: name,
          password: "",
          max_players: 4,
-         turn_timer_secs: 50,
+         turn_timer: 50,
+         hints: None,
+         practice: false,
+         scenario: None,
+         draw_rules: None,
       }));
       let nlr = self.get();
       match nlr {
@@ -8,10 +8,29 @@ use crate::common::*;
 use std::time::Duration;
 use timebomb::timeout_ms;
 
+const SECOND_ADDRESS: &str = "127.0.0.1:3014";
+const OVERLAY_ADDRESS: &str = "127.0.0.1:3015";
+const ADMIN_KEY: &str = "test admin key";
+/// Seconds to wait for new lobbies to show up in the lobby list
+const LOBBY_LIST_ATTEMPTS: usize = 5;
+
+fn local_socket_path() -> std::path::PathBuf {
+   std::env::temp_dir().join(format!("palace_server_test_{}.sock", std::process::id()))
+}
+
 fn testrunner(cases: &[&dyn Fn()]) {
    env_logger::init();
    std::thread::spawn(move || {
-      palace_server::run_server("127.0.0.1:3013");
+      palace_server::run_server_with_config(
+         "127.0.0.1:3013",
+         palace_server::ServerConfig {
+            additional_addresses: vec![SECOND_ADDRESS.to_owned()],
+            local_socket_path: Some(local_socket_path()),
+            overlay_address: Some(OVERLAY_ADDRESS.to_owned()),
+            admin_key: Some(ADMIN_KEY.to_owned()),
+            ..palace_server::ServerConfig::default()
+         },
+      );
    });
    // TODO ideally this would be a retry ready check
    std::thread::sleep(Duration::from_secs(5));
@@ -19,62 +38,1760 @@ fn testrunner(cases: &[&dyn Fn()]) {
    for t in cases {
       t();
    }
-   println!("executed {} tests", cases.len());
+   println!("executed {} tests", cases.len());
+}
+
+#[test_case]
+fn lobbies_clean_up() {
+   const JUNK_LOBBY_NAME: &str = "JunkLobby";
+
+   // Create a lobby
+   {
+      let mut tc = TestClient::new();
+      tc.new_lobby_named(JUNK_LOBBY_NAME);
+      tc.disconnect();
+   }
+
+   std::thread::sleep(Duration::from_secs(30));
+
+   // Ensure lobby is cleaned up
+   {
+      let mut tc = TestClient::new();
+      tc.send(&OutMessage::ListLobbies(ListLobbiesMessage {
+         page: 0,
+         page_size: None,
+      }));
+      let llr = tc.get();
+      match llr {
+         InMessage::ListLobbiesResponse(r) => assert!(r.lobbies.iter().find(|x| x.name == JUNK_LOBBY_NAME).is_none()),
+         _ => panic!("Expected list lobbies response"),
+      }
+   }
+}
+
+#[test_case]
+fn bots_join_lobby_after_request() {
+   let mut tc = TestClient::new();
+   // Create a lobby
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   // Request 3 AI
+   {
+      tc.send(&OutMessage::RequestAi(RequestAiMessage {
+         num_ai: 3,
+         player_id: &player_id,
+         lobby_id: &lobby_id,
+      }));
+
+      // Ensure that three AI join
+      for _ in 0..3 {
+         match tc.get() {
+            InMessage::PlayerJoinEvent(_) => (),
+            _ => panic!("Expected PlayerJoinEvent"),
+         }
+      }
+
+      // Ensure the AI response is OK
+      match tc.get() {
+         InMessage::RequestAiResponse(r) => assert!(r.is_ok()),
+         _ => panic!("Expected RequestAiResponse"),
+      }
+   }
+}
+
+#[test_case]
+fn lobby_players_are_listed_by_slot() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   // Request an AI
+   {
+      tc.send(&OutMessage::RequestAi(RequestAiMessage {
+         num_ai: 1,
+         player_id: &player_id,
+         lobby_id: &lobby_id,
+      }));
+      let _ = tc.get(); // PJE
+      let _ = tc.get(); // RAR
+   }
+
+   tc.send(&OutMessage::GetLobbyPlayers(&lobby_id));
+   match tc.get() {
+      InMessage::GetLobbyPlayersResponse(r) => {
+         let seats = r.expect("Get lobby players failed");
+         assert_eq!(seats.len(), 2);
+         assert_eq!(seats[0].slot, 0);
+         assert!(seats[0].is_owner);
+         assert!(!seats[0].is_ai);
+         assert_eq!(seats[0].status, ConnectionStatus::Connected);
+         assert_eq!(seats[1].slot, 1);
+         assert!(seats[1].is_ai);
+      }
+      _ => panic!("Expected GetLobbyPlayersResponse"),
+   }
+}
+
+#[test_case]
+fn players_sharing_a_name_are_told_apart_by_slot() {
+   let mut owner_tc = TestClient::new();
+   let (_owner_id, lobby_id) = owner_tc.new_lobby();
+
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "TestClient",
+      password: "",
+   }));
+   match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => {
+         let r = r.expect("Joining lobby failed");
+         assert_eq!(r.slot, 1);
+         assert_eq!(r.lobby_players.len(), 1);
+         assert_eq!(r.lobby_players[0].slot, 0);
+         assert_eq!(r.lobby_players[0].name, "TestClient");
+         assert!(r.lobby_players[0].is_owner);
+      }
+      _ => panic!("Expected JoinLobbyResponse"),
+   }
+   match owner_tc.get() {
+      InMessage::PlayerJoinEvent(e) => assert_eq!(e.slot, 1),
+      _ => panic!("Expected PlayerJoinEvent"),
+   }
+}
+
+#[test_case]
+fn unknown_message_types_keep_connection_open() {
+   let mut tc = TestClient::new();
+   tc.send_raw(br#"{"SomeFutureFeature":{"enabled":true}}"#);
+   match tc.get() {
+      InMessage::UnknownMessageType(message_type) => assert_eq!(message_type, "SomeFutureFeature"),
+      _ => panic!("Expected UnknownMessageType"),
+   }
+
+   // Still connected
+   tc.new_lobby();
+}
+
+#[test_case]
+fn undecodable_messages_explain_the_disconnect() {
+   let mut tc = TestClient::new();
+   tc.send_raw(b"not json");
+   match tc.get() {
+      InMessage::DisconnectEvent(e) => assert_eq!(e.code, DisconnectCode::ProtocolError),
+      x => panic!("Expected DisconnectEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn text_frames_are_accepted() {
+   let mut tc = TestClient::new();
+   tc.send_text(&OutMessage::ListLobbies(ListLobbiesMessage {
+      page: 0,
+      page_size: None,
+   }));
+   match tc.get() {
+      InMessage::ListLobbiesResponse(_) => (),
+      _ => panic!("Expected ListLobbiesResponse"),
+   }
+}
+
+#[test_case]
+fn closed_seats_reduce_capacity() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   for &slot in &[3, 2] {
+      tc.send(&OutMessage::SetSeatClosed(SetSeatClosedMessage {
+         lobby_id: &lobby_id,
+         player_id: &player_id,
+         slot,
+         closed: true,
+      }));
+      match tc.get() {
+         InMessage::LobbySettingsEvent(e) => assert!(e.closed_seats.contains(&slot)),
+         _ => panic!("Expected LobbySettingsEvent"),
+      }
+      match tc.get() {
+         InMessage::SetSeatClosedResponse(r) => r.expect("Closing seat failed"),
+         _ => panic!("Expected SetSeatClosedResponse"),
+      }
+   }
+
+   // Only one seat is left open
+   tc.send(&OutMessage::RequestAi(RequestAiMessage {
+      num_ai: 2,
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   match tc.get() {
+      InMessage::RequestAiResponse(Err(RequestAiError::LobbyTooSmall)) => (),
+      _ => panic!("Expected LobbyTooSmall"),
+   }
+}
+
+#[test_case]
+fn start_game_fills_open_seats_with_ai() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   loop {
+      match tc.get() {
+         InMessage::PlayerJoinEvent(_) => continue,
+         InMessage::GameStartEvent(e) => {
+            assert_eq!(e.players.len(), 4);
+            assert_eq!(e.players.values().filter(|p| p.is_ai).count(), 3);
+            let me = &e.players[&e.turn_number];
+            assert_eq!(me.name, "TestClient");
+            assert!(!me.is_ai);
+            assert_eq!(me.rating, None);
+            assert_eq!(me.account_id, None);
+            break;
+         }
+         _ => panic!("Expected PlayerJoinEvent or GameStartEvent"),
+      }
+   }
+}
+
+#[test_case]
+fn owner_can_abort_game() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   tc.send(&OutMessage::AbortGame(AbortGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   match tc.get() {
+      InMessage::AbortGameResponse(Err(AbortGameError::GameNotStarted)) => (),
+      _ => panic!("Expected GameNotStarted"),
+   }
+
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   tc.send(&OutMessage::AbortGame(AbortGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::GameAbortedEvent(reason) => {
+            assert_eq!(reason, GameAbortReason::Owner);
+            break;
+         }
+         _ => continue,
+      }
+   }
+   match tc.get() {
+      InMessage::AbortGameResponse(r) => r.expect("Aborting game failed"),
+      _ => panic!("Expected AbortGameResponse"),
+   }
+}
+
+#[test_case]
+fn listeners_share_lobbies() {
+   let mut owner_tc = TestClient::new();
+   let (_owner_id, lobby_id) = owner_tc.new_lobby();
+
+   let mut guest_tc = TestClient::new_at(SECOND_ADDRESS);
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => {
+         let lobby_players = r.expect("Joining lobby failed").lobby_players;
+         assert_eq!(lobby_players.len(), 1);
+         assert_eq!(lobby_players[0].name, "TestClient");
+      }
+      _ => panic!("Expected JoinLobbyResponse"),
+   }
+   match owner_tc.get() {
+      InMessage::PlayerJoinEvent(e) => assert_eq!(e.new_player_name, "Guest"),
+      _ => panic!("Expected PlayerJoinEvent"),
+   }
+}
+
+#[cfg(unix)]
+#[test_case]
+fn local_socket_speaks_the_protocol() {
+   use std::io::{BufRead, BufReader, Write};
+
+   let mut stream = std::os::unix::net::UnixStream::connect(local_socket_path()).unwrap();
+   let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+   let mut message = serde_json::to_vec(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "LocalClient",
+      lobby_name: "LocalLobby",
+      password: "",
+      max_players: 4,
+      turn_timer: 50,
+      hints: None,
+      practice: false,
+      scenario: None,
+      draw_rules: None,
+   }))
+   .unwrap();
+   message.push(b'\n');
+   stream.write_all(&message).unwrap();
+   let lobby_id = match serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() {
+      InMessage::NewLobbyResponse(r) => r.expect("New lobby failed").lobby_id,
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => {
+         let lobby_players = r.expect("Joining lobby failed").lobby_players;
+         assert_eq!(lobby_players.len(), 1);
+         assert_eq!(lobby_players[0].name, "LocalClient");
+      }
+      _ => panic!("Expected JoinLobbyResponse"),
+   }
+   match serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() {
+      InMessage::PlayerJoinEvent(e) => assert_eq!(e.new_player_name, "Guest"),
+      _ => panic!("Expected PlayerJoinEvent"),
+   }
+}
+
+#[test_case]
+fn overlays_show_lobbies() {
+   use std::io::{Read, Write};
+
+   let get = |path: &str| {
+      let mut stream = std::net::TcpStream::connect(OVERLAY_ADDRESS).unwrap();
+      let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+      stream.write_all(request.as_bytes()).unwrap();
+      let mut response = String::new();
+      stream.read_to_string(&mut response).unwrap();
+      response
+   };
+   let mut tc = TestClient::new();
+   let (_, lobby_id) = tc.new_lobby_named("OverlayLobby");
+   // Long enough for the lobby to be sampled
+   std::thread::sleep(Duration::from_secs(1));
+
+   let response = get(&format!("/lobbies/{}", lobby_id));
+   assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+   let body = response.split("\r\n\r\n").nth(1).unwrap();
+   let feed: serde_json::Value = serde_json::from_str(body).unwrap();
+   assert_eq!(feed["lobby_name"], "OverlayLobby");
+   assert_eq!(feed["seats"][0]["name"], "TestClient");
+   assert!(feed["game"].is_null());
+   assert!(get("/lobbies/0").starts_with("HTTP/1.1 404"));
+}
+
+#[test_case]
+fn cannot_concede_before_play() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   tc.send(&OutMessage::Concede(ConcedeMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   match tc.get() {
+      InMessage::ConcedeResponse(Err(ConcedeError::GameNotStarted)) => (),
+      _ => panic!("Expected GameNotStarted"),
+   }
+
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   // We haven't chosen our face up cards, so the game is still in setup
+   tc.send(&OutMessage::Concede(ConcedeMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::ConcedeResponse(Err(ConcedeError::GameInSetup)) => break,
+         InMessage::ConcedeResponse(r) => panic!("Expected GameInSetup, got {:?}", r),
+         _ => continue,
+      }
+   }
+}
+
+const MAX_FULL_GAME_PLAYS: u32 = 1000;
+
+struct Seat {
+   tc: TestClient,
+   player_id: String,
+   turn_number: u8,
+   hand: Vec<Card>,
+}
+
+impl Seat {
+   /// Waits for the deal, returning the first state
+   fn deal_in(tc: TestClient, player_id: String) -> (Seat, PublicGameState) {
+      let mut seat = Seat {
+         tc,
+         player_id,
+         turn_number: 0,
+         hand: vec![],
+      };
+      loop {
+         match seat.tc.get() {
+            InMessage::GameStartEvent(e) => {
+               seat.turn_number = e.turn_number;
+               seat.hand = e.hand.into_vec();
+            }
+            InMessage::PublicGameStateEvent(state) => return (seat, state),
+            _ => continue,
+         }
+      }
+   }
+
+   /// Reads what the seat is sent about a play: the state, then the placements if the game is over.
+   /// Whoever made the play is sent their hand before the state, and the response after everything else
+   fn read_play(&mut self, made_play: bool) -> (PublicGameState, Option<Vec<u8>>) {
+      let mut state: Option<PublicGameState> = None;
+      let mut complete = None;
+      loop {
+         match self.tc.get() {
+            InMessage::HandEvent(hand) => {
+               assert!(made_play, "Only the player who played should be sent their hand");
+               assert!(state.is_none(), "The hand should come before the state");
+               self.hand = hand;
+            }
+            InMessage::PublicGameStateEvent(s) => {
+               assert!(state.is_none(), "One state per play");
+               state = Some(s);
+            }
+            InMessage::GameCompleteEvent(out_players) => {
+               assert!(state.is_some(), "The final state should come before the game ends");
+               complete = Some(out_players);
+            }
+            InMessage::TakeTurnResponse(r) => {
+               r.expect("Play failed");
+               assert!(made_play);
+               break;
+            }
+            InMessage::YourTurnEvent(_) => continue,
+            other => panic!("Unexpected message during play: {:?}", other),
+         }
+         match state {
+            Some(ref s) if !made_play && (!game_over(s) || complete.is_some()) => break,
+            _ => (),
+         }
+      }
+      (state.expect("Expected PublicGameStateEvent"), complete)
+   }
+}
+
+fn game_over(state: &PublicGameState) -> bool {
+   state.placements.iter().all(Option::is_some)
+}
+
+fn cards_in_play(state: &PublicGameState) -> usize {
+   state.hands.iter().map(|x| *x as usize).sum::<usize>()
+      + state.face_up_three.iter().map(|x| x.len()).sum::<usize>()
+      + state.face_down_three.iter().map(|x| *x as usize).sum::<usize>()
+      + state.pile_size as usize
+      + state.cleared_size as usize
+}
+
+/// All of the lowest value that should go on the pile, or of the lowest value if none will.
+/// Fours and sevens are only roughly accounted for, which at worst means picking up the pile
+fn choose_play(held: &[Card], top_card: Option<Card>) -> Vec<Card> {
+   let playable = |card: &Card| match top_card.map(|x| x.value) {
+      None | Some(CardValue::Two) | Some(CardValue::Four) => true,
+      Some(CardValue::Seven) => card.value <= CardValue::Seven,
+      Some(top) => card.value >= top || card.value == CardValue::Two || card.value == CardValue::Ten,
+   };
+   let mut held = held.to_vec();
+   held.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+   let value = held.iter().find(|x| playable(x)).unwrap_or(&held[0]).value;
+   held.into_iter().filter(|x| x.value == value).collect()
+}
+
+/// The active player's turn: keeping the highest cards face up during setup, then playing low
+fn choose_turn(hand: &[Card], state: &PublicGameState) -> Vec<Card> {
+   let face_up = &state.face_up_three[state.active_player as usize];
+   match state.cur_phase {
+      GamePhase::Setup => {
+         let mut held: Vec<Card> = hand.iter().chain(face_up.iter()).cloned().collect();
+         held.sort_by(|a, b| b.value.partial_cmp(&a.value).unwrap());
+         held.truncate(3);
+         held
+      }
+      _ if !hand.is_empty() => choose_play(hand, state.top_card),
+      _ if !face_up.is_empty() => choose_play(face_up, state.top_card),
+      _ => vec![],
+   }
+}
+
+#[test_case]
+fn two_players_play_a_full_game() {
+   let mut owner_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   let guest_id = match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+
+   timeout_ms(
+      move || {
+         let (mut owner, mut state) = Seat::deal_in(owner_tc, owner_id);
+         loop {
+            match owner.tc.get() {
+               InMessage::StartGameResponse(r) => break r.expect("Starting game failed"),
+               _ => continue,
+            }
+         }
+         let (guest, guest_state) = Seat::deal_in(guest_tc, guest_id);
+         assert_eq!(state, guest_state);
+         let total_cards = cards_in_play(&state);
+         let mut seats = [owner, guest];
+         for play in 0.. {
+            assert!(play < MAX_FULL_GAME_PLAYS, "The game should be over by now");
+            let active = seats.iter().position(|x| x.turn_number == state.active_player).unwrap();
+            let cards = choose_turn(&seats[active].hand, &state);
+            seats[active].tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+               lobby_id: &lobby_id,
+               player_id: &seats[active].player_id,
+               cards: &cards,
+               play_seq: None,
+            }));
+
+            let (active_state, active_complete) = seats[active].read_play(true);
+            let (other_state, other_complete) = seats[1 - active].read_play(false);
+            assert_eq!(active_state, other_state, "Everyone should see the same state");
+            assert_eq!(active_complete, other_complete);
+            state = active_state;
+            assert_eq!(cards_in_play(&state), total_cards);
+            for seat in seats.iter() {
+               assert_eq!(seat.hand.len(), state.hands[seat.turn_number as usize] as usize);
+            }
+
+            assert_eq!(game_over(&state), active_complete.is_some());
+            if let Some(out_players) = active_complete {
+               let mut slots = out_players.clone();
+               slots.sort_unstable();
+               assert_eq!(slots, [0, 1]);
+               for (place, slot) in out_players.iter().enumerate() {
+                  assert_eq!(state.placements[*slot as usize], Some(place as u8));
+               }
+               break;
+            }
+         }
+
+         let guest = &mut seats[1];
+         guest.tc.send(&OutMessage::AnalyzeGame(AnalyzeGameMessage {
+            lobby_id: &lobby_id,
+            player_id: &guest.player_id,
+         }));
+         let analysis = loop {
+            match guest.tc.get() {
+               InMessage::AnalyzeGameResponse(r) => break r.expect("Analyzing game failed"),
+               _ => continue,
+            }
+         };
+         assert_eq!(analysis.slot, guest.turn_number);
+         assert!(!analysis.moves.is_empty());
+         for annotation in analysis.moves {
+            assert!((0.0..=1.0).contains(&annotation.preferred_win_rate));
+            match annotation.played_win_rate {
+               // The rates are rounded going through JSON, so the swing is only close to their difference
+               Some(played) => {
+                  let swing = annotation
+                     .win_rate_swing
+                     .expect("Expected a swing alongside the played win rate");
+                  assert!((swing - (played - annotation.preferred_win_rate)).abs() < 1e-9);
+               }
+               None => assert!(annotation.win_rate_swing.is_none()),
+            }
+         }
+      },
+      120000,
+   );
+}
+
+#[test_case]
+fn analysis_needs_a_completed_game() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   tc.send(&OutMessage::AnalyzeGame(AnalyzeGameMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   match tc.get() {
+      InMessage::AnalyzeGameResponse(Err(AnalyzeGameError::NoCompletedGame)) => (),
+      _ => panic!("Expected NoCompletedGame"),
+   }
+}
+
+#[test_case]
+fn hints_are_off_unless_the_lobby_allows_them() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   tc.send(&OutMessage::RequestHint(RequestHintMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   match tc.get() {
+      InMessage::RequestHintResponse(Err(RequestHintError::HintsDisabled)) => (),
+      _ => panic!("Expected HintsDisabled"),
+   }
+}
+
+#[test_case]
+fn hints_run_out() {
+   let mut tc = TestClient::new();
+   tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "HintLobby",
+      password: "",
+      max_players: 2,
+      turn_timer: 50,
+      hints: Some(HintPolicy::PerGame(1)),
+      practice: false,
+      scenario: None,
+      draw_rules: None,
+   }));
+   let (player_id, lobby_id) = match tc.get() {
+      InMessage::NewLobbyResponse(r) => {
+         let r = r.expect("New lobby failed");
+         (r.player_id, r.lobby_id)
+      }
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   loop {
+      match tc.get() {
+         InMessage::YourTurnEvent(_) => break,
+         _ => continue,
+      }
+   }
+
+   tc.send(&OutMessage::RequestHint(RequestHintMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   let hint = loop {
+      match tc.get() {
+         InMessage::RequestHintResponse(r) => break r.expect("Requesting hint failed"),
+         _ => continue,
+      }
+   };
+   // The first turn is choosing face up cards
+   assert_eq!(hint.cards.len(), 3);
+   assert_eq!(hint.hints_left, 0);
+
+   tc.send(&OutMessage::RequestHint(RequestHintMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::RequestHintResponse(Err(RequestHintError::NoHintsLeft)) => break,
+         InMessage::RequestHintResponse(r) => panic!("Expected NoHintsLeft, got {:?}", r),
+         _ => continue,
+      }
+   }
+}
+
+#[test_case]
+fn lobby_turn_limit_draws_the_game() {
+   let mut tc = TestClient::new();
+   tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "ShortLobby",
+      password: "",
+      max_players: 2,
+      turn_timer: 50,
+      hints: None,
+      practice: false,
+      scenario: None,
+      draw_rules: Some(DrawRules {
+         max_repetitions: None,
+         max_turns: Some(1),
+      }),
+   }));
+   let (player_id, lobby_id) = match tc.get() {
+      InMessage::NewLobbyResponse(r) => {
+         let r = r.expect("New lobby failed");
+         (r.player_id, r.lobby_id)
+      }
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   let mut hand = vec![];
+   let mut state = None;
+   loop {
+      match tc.get() {
+         InMessage::GameStartEvent(e) => hand = e.hand.into_vec(),
+         InMessage::PublicGameStateEvent(s) => state = Some(s),
+         InMessage::YourTurnEvent(_) => break,
+         _ => continue,
+      }
+   }
+   let state = state.expect("Expected PublicGameStateEvent");
+   tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      cards: &choose_turn(&hand, &state),
+      play_seq: None,
+   }));
+   // Choosing face up cards is the first turn, and the last
+   loop {
+      match tc.get() {
+         InMessage::GameDrawEvent(e) => {
+            assert_eq!(e.reason, DrawReason::TurnLimit);
+            assert_eq!(e.turns, 1);
+            break;
+         }
+         InMessage::GameCompleteEvent(_) => panic!("Expected GameDrawEvent"),
+         _ => continue,
+      }
+   }
+}
+
+#[test_case]
+fn others_can_vote_to_skip_a_turn() {
+   let mut owner_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   let guest_id = match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+   let owner_slot = loop {
+      match owner_tc.get() {
+         InMessage::GameStartEvent(e) => break e.turn_number,
+         _ => continue,
+      }
+   };
+   // Setup starts with slot 0
+   let (mut on_turn_tc, on_turn_id, mut voter_tc, voter_id) = if owner_slot == 0 {
+      (owner_tc, owner_id, guest_tc, guest_id)
+   } else {
+      (guest_tc, guest_id, owner_tc, owner_id)
+   };
+
+   on_turn_tc.send(&OutMessage::VoteSkipTurn(VoteSkipTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &on_turn_id,
+   }));
+   loop {
+      match on_turn_tc.get() {
+         InMessage::VoteSkipTurnResponse(Err(VoteSkipTurnError::OwnTurn)) => break,
+         InMessage::VoteSkipTurnResponse(r) => panic!("Expected OwnTurn, got {:?}", r),
+         _ => continue,
+      }
+   }
+
+   // The only other human's vote is enough
+   voter_tc.send(&OutMessage::VoteSkipTurn(VoteSkipTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &voter_id,
+   }));
+   let mut skipped = None;
+   loop {
+      match voter_tc.get() {
+         InMessage::TurnSkippedEvent(e) => skipped = Some(e.slot),
+         InMessage::VoteSkipTurnResponse(r) => break r.expect("Voting to skip the turn failed"),
+         _ => continue,
+      }
+   }
+   assert_eq!(skipped, Some(0));
+
+   // The skipped player is still seated, and sees the turn move on
+   loop {
+      match on_turn_tc.get() {
+         InMessage::PublicGameStateEvent(s) if s.active_player == 1 => break,
+         InMessage::LobbyCloseEvent(e) => panic!("Expected to keep our seat, got {:?}", e),
+         _ => continue,
+      }
+   }
+}
+
+#[test_case]
+fn practice_lobby_rewinds_turns() {
+   let mut tc = TestClient::new();
+   tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "PracticeLobby",
+      password: "",
+      max_players: 2,
+      turn_timer: 50,
+      hints: None,
+      practice: true,
+      scenario: None,
+      draw_rules: None,
+   }));
+   let (player_id, lobby_id) = match tc.get() {
+      InMessage::NewLobbyResponse(r) => {
+         let r = r.expect("New lobby failed");
+         (r.player_id, r.lobby_id)
+      }
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   match guest_tc.get() {
+      InMessage::JoinLobbyResponse(Err(JoinLobbyError::PracticeLobby)) => (),
+      _ => panic!("Expected PracticeLobby"),
+   }
+
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   let mut turn_number = 0;
+   let mut hand = vec![];
+   let mut state = None;
+   loop {
+      match tc.get() {
+         InMessage::GameStartEvent(e) => {
+            turn_number = e.turn_number;
+            hand = e.hand.into_vec();
+         }
+         InMessage::PublicGameStateEvent(s) => state = Some(s),
+         InMessage::YourTurnEvent(_) => break,
+         _ => continue,
+      }
+   }
+   let state = state.expect("Expected PublicGameStateEvent");
+   tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      cards: &choose_turn(&hand, &state),
+      play_seq: None,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::TakeTurnResponse(r) => break r.expect("Play failed"),
+         _ => continue,
+      }
+   }
+
+   tc.send(&OutMessage::RewindTurn(RewindTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      turns: 2,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::RewindTurnResponse(Err(RewindTurnError::NotEnoughTurns)) => break,
+         InMessage::RewindTurnResponse(r) => panic!("Expected NotEnoughTurns, got {:?}", r),
+         _ => continue,
+      }
+   }
+
+   tc.send(&OutMessage::RewindTurn(RewindTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      turns: 1,
+   }));
+   let mut rewound = false;
+   loop {
+      match tc.get() {
+         InMessage::GameRewoundEvent(e) => {
+            // Each player makes one choice during setup, in turn
+            assert_eq!(e.turns_taken, u32::from(turn_number));
+            rewound = true;
+         }
+         InMessage::HandEvent(rewound_hand) if rewound => assert_eq!(rewound_hand, hand),
+         InMessage::PublicGameStateEvent(s) if rewound => {
+            assert_eq!(s.active_player, turn_number);
+            assert_eq!(s.face_up_three, state.face_up_three);
+            assert_eq!(s.turn, state.turn);
+         }
+         InMessage::RewindTurnResponse(r) => break r.expect("Rewinding failed"),
+         _ => continue,
+      }
+   }
+   assert!(rewound);
+}
+
+#[test_case]
+fn scenario_deals_its_layout() {
+   let mut tc = TestClient::new();
+   tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "SevensLobby",
+      password: "",
+      max_players: 4,
+      turn_timer: 50,
+      hints: None,
+      practice: false,
+      scenario: Some("Sevens"),
+      draw_rules: None,
+   }));
+   let (player_id, lobby_id) = match tc.get() {
+      InMessage::NewLobbyResponse(r) => {
+         let r = r.expect("New lobby failed");
+         assert_eq!(r.max_players, 2);
+         (r.player_id, r.lobby_id)
+      }
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+   let card = |value, suit| Card { value, suit };
+   loop {
+      match tc.get() {
+         InMessage::GameStartEvent(e) => {
+            assert_eq!(e.turn_number, 0);
+            assert_eq!(e.hand[0], card(CardValue::Three, CardSuit::Clubs));
+         }
+         InMessage::PublicGameStateEvent(state) => {
+            assert_eq!(state.cur_phase, GamePhase::Play);
+            assert_eq!(state.top_card, Some(card(CardValue::Seven, CardSuit::Spades)));
+         }
+         InMessage::YourTurnEvent(_) => break,
+         _ => continue,
+      }
+   }
+
+   // The AI opponent's first play is scripted
+   tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      cards: &[card(CardValue::Three, CardSuit::Clubs)],
+      play_seq: None,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::PublicGameStateEvent(state) if state.active_player == 0 => {
+            assert_eq!(state.top_card, Some(card(CardValue::Seven, CardSuit::Diamonds)));
+            break;
+         }
+         _ => continue,
+      }
+   }
+}
+
+#[test_case]
+fn unknown_scenarios_are_rejected() {
+   let mut tc = TestClient::new();
+   tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "NoSuchLobby",
+      password: "",
+      max_players: 4,
+      turn_timer: 50,
+      hints: None,
+      practice: false,
+      scenario: Some("NoSuchScenario"),
+      draw_rules: None,
+   }));
+   match tc.get() {
+      InMessage::NewLobbyResponse(Err(NewLobbyError::UnknownScenario)) => (),
+      _ => panic!("Expected UnknownScenario"),
+   }
+}
+
+#[test_case]
+fn only_practice_lobbies_rewind() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   tc.send(&OutMessage::RewindTurn(RewindTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      turns: 1,
+   }));
+   match tc.get() {
+      InMessage::RewindTurnResponse(Err(RewindTurnError::NotPracticeLobby)) => (),
+      _ => panic!("Expected NotPracticeLobby"),
+   }
+}
+
+#[test_case]
+fn spectators_predict_plays() {
+   let mut owner_tc = TestClient::new();
+   let mut spectator_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+   let predict = |play| {
+      OutMessage::PredictPlay(PredictPlayMessage {
+         lobby_id: &lobby_id,
+         play,
+      })
+   };
+
+   // Only spectators guess
+   owner_tc.send(&predict(PredictedPlay::FaceDown));
+   match owner_tc.get() {
+      InMessage::PredictPlayResponse(Err(PredictPlayError::NotSpectating)) => (),
+      x => panic!("Expected NotSpectating, got {:?}", x),
+   }
+   spectator_tc.send(&OutMessage::SpectateLobby(&lobby_id));
+   match spectator_tc.get() {
+      InMessage::SpectateLobbyResponse(r) => r.expect("Spectating failed"),
+      x => panic!("Expected SpectateLobbyResponse, got {:?}", x),
+   };
+   spectator_tc.send(&predict(PredictedPlay::FaceDown));
+   match spectator_tc.get() {
+      InMessage::PredictPlayResponse(Err(PredictPlayError::GameNotStarted)) => (),
+      x => panic!("Expected GameNotStarted, got {:?}", x),
+   }
+
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   let mut hand = vec![];
+   let mut state = None;
+   // Play through setup until the owner's first real play
+   loop {
+      match owner_tc.get() {
+         InMessage::GameStartEvent(e) => hand = e.hand.into_vec(),
+         InMessage::HandEvent(new_hand) => hand = new_hand,
+         InMessage::PublicGameStateEvent(s) => state = Some(s),
+         InMessage::YourTurnEvent(_) => {
+            let state = state.as_ref().expect("Expected PublicGameStateEvent");
+            if state.cur_phase == GamePhase::Play {
+               break;
+            }
+            owner_tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+               lobby_id: &lobby_id,
+               player_id: &owner_id,
+               cards: &choose_turn(&hand, state),
+               play_seq: None,
+            }));
+         }
+         _ => continue,
+      }
+   }
+   let cards = choose_turn(&hand, &state.unwrap());
+   let play = match cards.first() {
+      Some(card) => PredictedPlay::Cards(card.value, cards.len() as u8),
+      None => PredictedPlay::FaceDown,
+   };
+
+   spectator_tc.send(&predict(PredictedPlay::Cards(CardValue::Two, 0)));
+   loop {
+      match spectator_tc.get() {
+         InMessage::PredictPlayResponse(Err(PredictPlayError::NoCards)) => break,
+         InMessage::PredictPlayResponse(r) => panic!("Expected NoCards, got {:?}", r),
+         _ => continue,
+      }
+   }
+   spectator_tc.send(&predict(play));
+   loop {
+      match spectator_tc.get() {
+         InMessage::PredictPlayResponse(r) => break r.expect("Predicting failed"),
+         _ => continue,
+      }
+   }
+   owner_tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      cards: &cards,
+      play_seq: None,
+   }));
+
+   loop {
+      match owner_tc.get() {
+         InMessage::PredictionResultsEvent(e) => {
+            assert_eq!(e.played, play);
+            assert_eq!(e.correct, 1);
+            assert_eq!(e.tallies.len(), 1);
+            break;
+         }
+         InMessage::PredictionScoreEvent(e) => panic!("Only guessers are scored, got {:?}", e),
+         _ => continue,
+      }
+   }
+   loop {
+      match spectator_tc.get() {
+         InMessage::PredictionScoreEvent(e) => {
+            assert!(e.correct);
+            assert_eq!(e.score, 1);
+            break;
+         }
+         _ => continue,
+      }
+   }
+}
+
+#[test_case]
+fn join_token_runs_out_of_uses() {
+   let mut owner_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+
+   owner_tc.send(&OutMessage::CreateJoinToken(CreateJoinTokenMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      expires_in_secs: 60,
+      max_uses: 1,
+   }));
+   let token = match owner_tc.get() {
+      InMessage::CreateJoinTokenResponse(r) => r.expect("Creating join token failed"),
+      _ => panic!("Expected CreateJoinTokenResponse"),
+   };
+
+   let mut first_tc = TestClient::new();
+   first_tc.send(&OutMessage::JoinWithToken(JoinWithTokenMessage {
+      lobby_id: &lobby_id,
+      token: &token,
+      player_name: "First",
+   }));
+   match first_tc.get() {
+      InMessage::JoinWithTokenResponse(r) => {
+         r.expect("Joining with token failed");
+      }
+      _ => panic!("Expected JoinWithTokenResponse"),
+   }
+
+   let mut second_tc = TestClient::new();
+   second_tc.send(&OutMessage::JoinWithToken(JoinWithTokenMessage {
+      lobby_id: &lobby_id,
+      token: &token,
+      player_name: "Second",
+   }));
+   match second_tc.get() {
+      InMessage::JoinWithTokenResponse(Err(JoinWithTokenError::BadToken)) => (),
+      _ => panic!("Expected BadToken"),
+   }
+}
+
+#[test_case]
+fn owners_merge_lobbies() {
+   let mut owner_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby_named("SmallLobby");
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed"),
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let mut target_tc = TestClient::new();
+   let (target_owner_id, target_lobby_id) = target_tc.new_lobby_named("TargetLobby");
+
+   let merge = OutMessage::MergeLobby(MergeLobbyMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      target_lobby_id: &target_lobby_id,
+   });
+   let answer = |accept| {
+      OutMessage::AnswerMerge(AnswerMergeMessage {
+         lobby_id: &target_lobby_id,
+         player_id: &target_owner_id,
+         from_lobby_id: &lobby_id,
+         accept,
+      })
+   };
+   let expect_request = |tc: &mut TestClient| match tc.get() {
+      InMessage::MergeRequestEvent(e) => {
+         assert_eq!(e.from_lobby_id, lobby_id);
+         assert_eq!(e.lobby_name, "SmallLobby");
+         assert_eq!(e.player_names.len(), 2);
+      }
+      x => panic!("Expected MergeRequestEvent, got {:?}", x),
+   };
+
+   // Declined first, then accepted
+   owner_tc.send(&merge);
+   loop {
+      match owner_tc.get() {
+         InMessage::MergeLobbyResponse(r) => break r.expect("Merge request failed"),
+         _ => continue,
+      }
+   }
+   expect_request(&mut target_tc);
+   target_tc.send(&answer(false));
+   match target_tc.get() {
+      InMessage::AnswerMergeResponse(r) => r.expect("Declining failed"),
+      x => panic!("Expected AnswerMergeResponse, got {:?}", x),
+   }
+   match owner_tc.get() {
+      InMessage::MergeDeclinedEvent(e) => assert_eq!(e.target_lobby_id, target_lobby_id),
+      x => panic!("Expected MergeDeclinedEvent, got {:?}", x),
+   }
+   target_tc.send(&answer(true));
+   match target_tc.get() {
+      InMessage::AnswerMergeResponse(Err(AnswerMergeError::NoSuchRequest)) => (),
+      x => panic!("Expected NoSuchRequest, got {:?}", x),
+   }
+
+   owner_tc.send(&merge);
+   match owner_tc.get() {
+      InMessage::MergeLobbyResponse(r) => r.expect("Merge request failed"),
+      x => panic!("Expected MergeLobbyResponse, got {:?}", x),
+   }
+   expect_request(&mut target_tc);
+   target_tc.send(&answer(true));
+   let mut joins = 0;
+   loop {
+      match target_tc.get() {
+         InMessage::PlayerJoinEvent(_) => joins += 1,
+         InMessage::AnswerMergeResponse(r) => break r.expect("Accepting failed"),
+         x => panic!("Expected AnswerMergeResponse, got {:?}", x),
+      }
+   }
+   assert_eq!(joins, 2);
+   for tc in [&mut owner_tc, &mut guest_tc] {
+      loop {
+         match tc.get() {
+            InMessage::LobbyMergedEvent(e) => {
+               assert_eq!(e.lobby_id, target_lobby_id);
+               // The target's owner keeps the first seat
+               assert_ne!(e.slot, 0);
+               break;
+            }
+            _ => continue,
+         }
+      }
+   }
+
+   // The old lobby is gone, and the moved players act in the new one under their old ids
+   guest_tc.send(&OutMessage::GetLobbyPlayers(&lobby_id));
+   loop {
+      match guest_tc.get() {
+         InMessage::GetLobbyPlayersResponse(Err(GetLobbyPlayersError::LobbyNotFound)) => break,
+         InMessage::GetLobbyPlayersResponse(r) => panic!("Expected LobbyNotFound, got {:?}", r),
+         _ => continue,
+      }
+   }
+   owner_tc.send(&OutMessage::GetLobbyPlayers(&target_lobby_id));
+   loop {
+      match owner_tc.get() {
+         InMessage::GetLobbyPlayersResponse(r) => break assert_eq!(r.expect("Listing players failed").len(), 3),
+         _ => continue,
+      }
+   }
+   owner_tc.disconnect();
+   match target_tc.get() {
+      InMessage::PlayerLeaveEvent(e) => assert_eq!(e.total_num_players, 2),
+      x => panic!("Expected PlayerLeaveEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn owner_can_transfer_ownership() {
+   let mut owner_tc = TestClient::new();
+   let mut player_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+
+   player_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "NewOwner",
+      password: "",
+   }));
+   let player_id = match player_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let _ = owner_tc.get(); // PlayerJoinEvent
+
+   owner_tc.send(&OutMessage::TransferOwnership(TransferOwnershipMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      slot: 1,
+   }));
+   match owner_tc.get() {
+      InMessage::OwnerChangedEvent(e) => assert_eq!(e.slot, 1),
+      _ => panic!("Expected OwnerChangedEvent"),
+   }
+   match owner_tc.get() {
+      InMessage::TransferOwnershipResponse(r) => r.expect("Transferring ownership failed"),
+      _ => panic!("Expected TransferOwnershipResponse"),
+   }
+   match player_tc.get() {
+      InMessage::OwnerChangedEvent(e) => assert_eq!(e.slot, 1),
+      _ => panic!("Expected OwnerChangedEvent"),
+   }
+
+   // The old owner can no longer act as owner
+   owner_tc.send(&OutMessage::KickPlayer(KickPlayerMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      slot: 1,
+   }));
+   match owner_tc.get() {
+      InMessage::KickPlayerResponse(Err(KickPlayerError::NotLobbyOwner)) => (),
+      _ => panic!("Expected NotLobbyOwner"),
+   }
+
+   // But the new one can
+   player_tc.send(&OutMessage::KickPlayer(KickPlayerMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      slot: 0,
+   }));
+   let _ = player_tc.get(); // PlayerLeaveEvent
+   match player_tc.get() {
+      InMessage::KickPlayerResponse(r) => r.expect("Kicking old owner failed"),
+      x => panic!("Expected KickPlayerResponse, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn kicked_players_are_disconnected() {
+   let mut owner_tc = TestClient::new();
+   let mut player_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+
+   player_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Second",
+      password: "",
+   }));
+   match player_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed"),
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let _ = owner_tc.get(); // PlayerJoinEvent
+
+   owner_tc.send(&OutMessage::KickPlayer(KickPlayerMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      slot: 1,
+   }));
+   match player_tc.get() {
+      InMessage::LobbyCloseEvent(e) => assert_eq!(e, LobbyCloseEvent::Kicked),
+      x => panic!("Expected LobbyCloseEvent, got {:?}", x),
+   }
+   match player_tc.get() {
+      InMessage::DisconnectEvent(e) => assert_eq!(e.code, DisconnectCode::Kicked),
+      x => panic!("Expected DisconnectEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn reconnect_floods_are_cut_off() {
+   let mut owner_tc = TestClient::new();
+   let mut player_tc = TestClient::new();
+   let (_, lobby_id) = owner_tc.new_lobby();
+
+   player_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Second",
+      password: "",
+   }));
+   let player_id = match player_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let mut reconnect = || {
+      player_tc.send(&OutMessage::Reconnect(ReconnectMessage {
+         lobby_id: &lobby_id,
+         player_id: &player_id,
+      }));
+      loop {
+         match player_tc.get() {
+            InMessage::ReconnectResponse(r) => break r,
+            _ => continue,
+         }
+      }
+   };
+
+   // Five attempts are allowed every ten seconds
+   for _ in 0..5 {
+      reconnect().expect("Reconnecting failed");
+   }
+   match reconnect() {
+      Err(ReconnectError::TooManyAttempts) => (),
+      x => panic!("Expected TooManyAttempts, got {:?}", x),
+   }
+   match player_tc.get() {
+      InMessage::DisconnectEvent(e) => assert_eq!(e.code, DisconnectCode::RateLimited),
+      x => panic!("Expected DisconnectEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn opponents_see_connection_status_changes() {
+   let mut owner_tc = TestClient::new();
+   let mut player_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+
+   player_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Second",
+      password: "",
+   }));
+   let player_id = match player_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let _ = owner_tc.get(); // PlayerJoinEvent
+
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+   let slot = loop {
+      match player_tc.get() {
+         InMessage::GameStartEvent(e) => break e.turn_number,
+         _ => continue,
+      }
+   };
+   let next_status = |tc: &mut TestClient| loop {
+      match tc.get() {
+         InMessage::PlayerConnectionStatusEvent(e) => break e,
+         _ => continue,
+      }
+   };
+
+   player_tc.disconnect();
+   let e = next_status(&mut owner_tc);
+   assert_eq!((e.slot, e.status), (slot, ConnectionStatus::Disconnected));
+
+   let mut returning_tc = TestClient::new();
+   returning_tc.send(&OutMessage::Reconnect(ReconnectMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   loop {
+      match returning_tc.get() {
+         InMessage::ReconnectResponse(r) => {
+            r.expect("Reconnecting failed");
+            break;
+         }
+         _ => continue,
+      }
+   }
+   let e = next_status(&mut owner_tc);
+   assert_eq!((e.slot, e.status), (slot, ConnectionStatus::Connected));
+}
+
+#[test_case]
+fn active_player_is_told_its_their_turn() {
+   let mut owner_tc = TestClient::new();
+   let mut player_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+
+   player_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Second",
+      password: "",
+   }));
+   match player_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed"),
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   let _ = owner_tc.get(); // PlayerJoinEvent
+
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+   for tc in [&mut owner_tc, &mut player_tc].iter_mut() {
+      let turn_number = match tc.get() {
+         InMessage::GameStartEvent(e) => e.turn_number,
+         x => panic!("Expected GameStartEvent, got {:?}", x),
+      };
+      match tc.get() {
+         InMessage::PublicGameStateEvent(e) => assert!(e.turn_deadline.unwrap() <= 50),
+         x => panic!("Expected PublicGameStateEvent, got {:?}", x),
+      }
+      if turn_number == 0 {
+         match tc.get() {
+            InMessage::YourTurnEvent(e) => assert!(e.deadline.unwrap() <= 50),
+            x => panic!("Expected YourTurnEvent, got {:?}", x),
+         }
+      }
+   }
+}
+
+#[test_case]
+fn resent_plays_are_only_played_once() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+   let hand = loop {
+      match tc.get() {
+         InMessage::GameStartEvent(e) => break e.hand,
+         _ => continue,
+      }
+   };
+   // wait for the bots to finish choosing before us
+   loop {
+      match tc.get() {
+         InMessage::YourTurnEvent(_) => break,
+         _ => continue,
+      }
+   }
+
+   let take_turn = |play_seq| {
+      OutMessage::TakeTurn(TakeTurnMessage {
+         lobby_id: &lobby_id,
+         player_id: &player_id,
+         cards: &hand[..3],
+         play_seq: Some(play_seq),
+      })
+   };
+   let get_response = |tc: &mut TestClient| loop {
+      match tc.get() {
+         InMessage::TakeTurnResponse(r) => break r,
+         _ => continue,
+      }
+   };
+   tc.send(&take_turn(1));
+   get_response(&mut tc).expect("Choosing face up cards failed");
+   // if the play had been made again, it would no longer be our turn
+   tc.send(&take_turn(1));
+   get_response(&mut tc).expect("Resent play was not acknowledged");
+   tc.send(&take_turn(0));
+   match get_response(&mut tc) {
+      Err(TakeTurnError::StalePlaySeq) => (),
+      x => panic!("Expected StalePlaySeq, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn players_can_spectate_other_lobbies() {
+   let mut watched_tc = TestClient::new();
+   let mut tc = TestClient::new();
+   let (_, watched_lobby_id) = watched_tc.new_lobby();
+   let (_, lobby_id) = tc.new_lobby();
+
+   tc.send(&OutMessage::SpectateLobby(&lobby_id));
+   match tc.get() {
+      InMessage::SpectateLobbyResponse(Err(SpectateLobbyError::AlreadyInLobby)) => (),
+      x => panic!("Expected AlreadyInLobby, got {:?}", x),
+   }
+
+   tc.send(&OutMessage::SpectateLobby(&watched_lobby_id));
+   match tc.get() {
+      InMessage::SpectateLobbyResponse(r) => assert_eq!(r.expect("Spectating failed").num_spectators, 1),
+      x => panic!("Expected SpectateLobbyResponse, got {:?}", x),
+   }
+   match watched_tc.get() {
+      InMessage::SpectatorJoinEvent(()) => (),
+      x => panic!("Expected SpectatorJoinEvent, got {:?}", x),
+   }
+
+   // We still hold our seat
+   let mut joining_tc = TestClient::new();
+   joining_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Joiner",
+      password: "",
+   }));
+   match tc.get() {
+      InMessage::PlayerJoinEvent(_) => (),
+      x => panic!("Expected PlayerJoinEvent, got {:?}", x),
+   }
+
+   tc.send(&OutMessage::StopSpectating(&watched_lobby_id));
+   match tc.get() {
+      InMessage::StopSpectatingResponse(r) => r.expect("Stopping spectating failed"),
+      x => panic!("Expected StopSpectatingResponse, got {:?}", x),
+   }
+   match watched_tc.get() {
+      InMessage::SpectatorLeaveEvent(()) => (),
+      x => panic!("Expected SpectatorLeaveEvent, got {:?}", x),
+   }
+   tc.send(&OutMessage::StopSpectating(&watched_lobby_id));
+   match tc.get() {
+      InMessage::StopSpectatingResponse(Err(StopSpectatingError::NotSpectating)) => (),
+      x => panic!("Expected NotSpectating, got {:?}", x),
+   }
 }
 
 #[test_case]
-fn lobbies_clean_up() {
-   const JUNK_LOBBY_NAME: &str = "JunkLobby";
+fn spectators_can_take_a_seat() {
+   let mut owner_tc = TestClient::new();
+   let mut tc = TestClient::new();
+   let (_, lobby_id) = owner_tc.new_lobby();
 
-   // Create a lobby
-   {
-      let mut tc = TestClient::new();
-      tc.new_lobby_named(JUNK_LOBBY_NAME);
-      tc.disconnect();
+   let take_seat = OutMessage::TakeSeat(TakeSeatMessage {
+      lobby_id: &lobby_id,
+      player_name: "Watcher",
+      password: "",
+   });
+   tc.send(&take_seat);
+   match tc.get() {
+      InMessage::TakeSeatResponse(Err(TakeSeatError::NotSpectating)) => (),
+      x => panic!("Expected NotSpectating, got {:?}", x),
    }
 
-   std::thread::sleep(Duration::from_secs(30));
+   tc.send(&OutMessage::SpectateLobby(&lobby_id));
+   match tc.get() {
+      InMessage::SpectateLobbyResponse(r) => r.expect("Spectating failed"),
+      x => panic!("Expected SpectateLobbyResponse, got {:?}", x),
+   };
+   tc.send(&take_seat);
+   match tc.get() {
+      InMessage::TakeSeatResponse(r) => assert_eq!(r.expect("Taking a seat failed").lobby_players.len(), 1),
+      x => panic!("Expected TakeSeatResponse, got {:?}", x),
+   }
 
-   // Ensure lobby is cleaned up
-   {
-      let mut tc = TestClient::new();
-      tc.send(&OutMessage::ListLobbies(ListLobbiesMessage { page: 0 }));
-      let llr = tc.get();
-      match llr {
-         InMessage::ListLobbiesResponse(r) => assert!(r.lobbies.iter().find(|x| x.name == JUNK_LOBBY_NAME).is_none()),
-         _ => panic!("Expected list lobbies response"),
-      }
+   match owner_tc.get() {
+      InMessage::SpectatorJoinEvent(()) => (),
+      x => panic!("Expected SpectatorJoinEvent, got {:?}", x),
+   }
+   match owner_tc.get() {
+      InMessage::SpectatorLeaveEvent(()) => (),
+      x => panic!("Expected SpectatorLeaveEvent, got {:?}", x),
+   }
+   match owner_tc.get() {
+      InMessage::PlayerJoinEvent(e) => assert_eq!(e.new_player_name, "Watcher"),
+      x => panic!("Expected PlayerJoinEvent, got {:?}", x),
    }
 }
 
 #[test_case]
-fn bots_join_lobby_after_request() {
+fn queued_players_get_freed_seats() {
+   let mut owner_tc = TestClient::new();
    let mut tc = TestClient::new();
-   // Create a lobby
-   let (player_id, lobby_id) = tc.new_lobby();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
 
-   // Request 3 AI
-   {
-      tc.send(&OutMessage::RequestAi(RequestAiMessage {
-         num_ai: 3,
-         player_id: &player_id,
+   let queue = OutMessage::QueueForSeat(QueueForSeatMessage {
+      lobby_id: &lobby_id,
+      player_name: "Patient",
+      password: "",
+   });
+   tc.send(&queue);
+   match tc.get() {
+      InMessage::QueueForSeatResponse(Err(QueueForSeatError::LobbyNotFull)) => (),
+      x => panic!("Expected LobbyNotFull, got {:?}", x),
+   }
+
+   for &slot in &[3, 2] {
+      owner_tc.send(&OutMessage::SetSeatClosed(SetSeatClosedMessage {
          lobby_id: &lobby_id,
+         player_id: &owner_id,
+         slot,
+         closed: true,
       }));
+      loop {
+         match owner_tc.get() {
+            InMessage::LobbySettingsEvent(_) => continue,
+            InMessage::SetSeatClosedResponse(r) => break r.expect("Closing seat failed"),
+            x => panic!("Expected SetSeatClosedResponse, got {:?}", x),
+         }
+      }
+   }
+   owner_tc.send(&OutMessage::RequestAi(RequestAiMessage {
+      num_ai: 1,
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+   }));
+   loop {
+      match owner_tc.get() {
+         InMessage::PlayerJoinEvent(_) => continue,
+         InMessage::RequestAiResponse(r) => break r.expect("Requesting AI failed"),
+         x => panic!("Expected RequestAiResponse, got {:?}", x),
+      }
+   }
 
-      // Ensure that three AI join
-      for _ in 0..3 {
+   tc.send(&queue);
+   match tc.get() {
+      InMessage::QueueForSeatResponse(r) => assert_eq!(r.expect("Queueing failed"), 1),
+      x => panic!("Expected QueueForSeatResponse, got {:?}", x),
+   }
+
+   owner_tc.send(&OutMessage::KickPlayer(KickPlayerMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      slot: 1,
+   }));
+   match tc.get() {
+      InMessage::SeatGrantedEvent(r) => assert_eq!(r.lobby_players.len(), 1),
+      x => panic!("Expected SeatGrantedEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn lobby_list_pages_in_creation_order() {
+   let mut tc = TestClient::new();
+   let names = ["PageLobbyA", "PageLobbyB", "PageLobbyC"];
+   let mut clients: Vec<TestClient> = names.iter().map(|_| TestClient::new()).collect();
+   for (client, name) in clients.iter_mut().zip(names.iter()) {
+      client.new_lobby_named(name);
+   }
+
+   // The lobby list is rebuilt in the background, which can take a while on a busy server
+   let mut ours = vec![];
+   let mut total_lobbies = 0;
+   for _ in 0..LOBBY_LIST_ATTEMPTS {
+      std::thread::sleep(Duration::from_secs(1));
+      let mut listed = vec![];
+      let mut page = 0;
+      loop {
+         tc.send(&OutMessage::ListLobbies(ListLobbiesMessage {
+            page,
+            page_size: Some(1),
+         }));
          match tc.get() {
-            InMessage::PlayerJoinEvent(_) => (),
-            _ => panic!("Expected PlayerJoinEvent"),
+            InMessage::ListLobbiesResponse(r) => {
+               assert!(r.lobbies.len() <= 1);
+               total_lobbies = r.total_lobbies;
+               listed.extend(r.lobbies.iter().map(|x| x.name.clone()));
+               if !r.has_next_page {
+                  break;
+               }
+            }
+            _ => panic!("Expected ListLobbiesResponse"),
          }
+         page += 1;
       }
-
-      // Ensure the AI response is OK
-      match tc.get() {
-         InMessage::RequestAiResponse(r) => assert!(r.is_ok()),
-         _ => panic!("Expected RequestAiResponse"),
+      ours = listed.into_iter().filter(|x| x.starts_with("PageLobby")).collect();
+      if ours.len() == names.len() {
+         break;
       }
    }
+   assert!(total_lobbies >= names.len() as u64);
+   assert_eq!(ours, names);
 }
 
 #[test_case]
@@ -208,6 +1925,7 @@ fn afk_kick() {
       tc.send(&OutMessage::StartGame(StartGameMessage {
          player_id: &player_id,
          lobby_id: &lobby_id,
+         fill_with_ai: None,
       }));
    }
 
@@ -225,6 +1943,470 @@ fn afk_kick() {
    );
 }
 
+#[test_case]
+fn short_turns_are_warned_before_timing_out() {
+   let mut tc = TestClient::new();
+   let new_lobby = |turn_timer| {
+      OutMessage::NewLobby(NewLobbyMessage {
+         player_name: "TestClient",
+         lobby_name: "QuickLobby",
+         password: "",
+         max_players: 2,
+         turn_timer,
+         hints: None,
+         practice: false,
+         scenario: None,
+         draw_rules: None,
+      })
+   };
+   tc.send(&new_lobby(3));
+   match tc.get() {
+      InMessage::NewLobbyResponse(Err(NewLobbyError::TurnTimerTooShort)) => (),
+      x => panic!("Expected TurnTimerTooShort, got {:?}", x),
+   }
+
+   tc.send(&new_lobby(5));
+   let (player_id, lobby_id) = match tc.get() {
+      InMessage::NewLobbyResponse(r) => {
+         let r = r.expect("New lobby failed");
+         (r.player_id, r.lobby_id)
+      }
+      _ => panic!("Expected NewLobbyResponse"),
+   };
+   tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: Some("Random"),
+   }));
+
+   timeout_ms(
+      move || {
+         let mut warned = false;
+         loop {
+            match tc.get() {
+               InMessage::TurnTimeWarningEvent(e) => {
+                  assert!(e.secs_remaining <= 5);
+                  warned = true;
+               }
+               InMessage::LobbyCloseEvent(reason) => {
+                  assert!(reason == LobbyCloseEvent::Afk);
+                  break;
+               }
+               _ => continue,
+            }
+         }
+         assert!(warned);
+      },
+      20000,
+   );
+}
+
+#[test_case]
+fn attached_devices_share_a_seat() {
+   let mut owner_tc = TestClient::new();
+   let (owner_id, lobby_id) = owner_tc.new_lobby();
+   let mut other_device = TestClient::new();
+   other_device.send(&OutMessage::AttachToPlayer(ReconnectMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+   }));
+   match other_device.get() {
+      InMessage::AttachToPlayerResponse(r) => {
+         r.expect("Attaching failed");
+      }
+      x => panic!("Expected AttachToPlayerResponse, got {:?}", x),
+   }
+   let mut guest_tc = TestClient::new();
+   guest_tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+      lobby_id: &lobby_id,
+      player_name: "Guest",
+      password: "",
+   }));
+   let guest_id = match guest_tc.get() {
+      InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").player_id,
+      _ => panic!("Expected JoinLobbyResponse"),
+   };
+   owner_tc.send(&OutMessage::StartGame(StartGameMessage {
+      player_id: &owner_id,
+      lobby_id: &lobby_id,
+      fill_with_ai: None,
+   }));
+
+   timeout_ms(
+      move || {
+         let (mut owner, mut state) = Seat::deal_in(owner_tc, owner_id.clone());
+         let (mut other, other_state) = Seat::deal_in(other_device, owner_id);
+         assert_eq!(owner.hand, other.hand);
+         assert_eq!(state, other_state);
+         let (mut guest, _) = Seat::deal_in(guest_tc, guest_id);
+         if state.active_player == guest.turn_number {
+            guest.tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+               lobby_id: &lobby_id,
+               player_id: &guest.player_id,
+               cards: &choose_turn(&guest.hand, &state),
+               play_seq: None,
+            }));
+            guest.read_play(true);
+            state = other.read_play(false).0;
+         }
+
+         // Either device can play for the seat, and both are sent the hand that's left
+         other.tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+            lobby_id: &lobby_id,
+            player_id: &other.player_id,
+            cards: &choose_turn(&other.hand, &state),
+            play_seq: None,
+         }));
+         other.read_play(true);
+         let hand = loop {
+            match owner.tc.get() {
+               InMessage::HandEvent(hand) => break hand,
+               _ => continue,
+            }
+         };
+         assert_eq!(hand, other.hand);
+      },
+      20000,
+   );
+}
+
+#[test_case]
+fn rotated_ids_detach_other_connections() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+
+   let mut other_device = TestClient::new();
+   other_device.send(&OutMessage::AttachToPlayer(ReconnectMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   match other_device.get() {
+      InMessage::AttachToPlayerResponse(r) => {
+         r.expect("Attaching failed");
+      }
+      x => panic!("Expected AttachToPlayerResponse, got {:?}", x),
+   }
+
+   let mut stranger = TestClient::new();
+   stranger.send(&OutMessage::RotateId(RotateIdMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   match stranger.get() {
+      InMessage::RotateIdResponse(Err(RotateIdError::NotConnectedAsPlayer)) => (),
+      x => panic!("Expected NotConnectedAsPlayer, got {:?}", x),
+   }
+
+   tc.send(&OutMessage::RotateId(RotateIdMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+   }));
+   let new_player_id = loop {
+      match tc.get() {
+         InMessage::RotateIdResponse(r) => break r.expect("Rotating failed").player_id,
+         _ => continue,
+      }
+   };
+   assert_ne!(new_player_id, player_id);
+   loop {
+      match other_device.get() {
+         InMessage::LobbyCloseEvent(reason) => {
+            assert!(reason == LobbyCloseEvent::IdRotated);
+            break;
+         }
+         _ => continue,
+      }
+   }
+
+   // Only the new id works from now on
+   let attach = |player_id| {
+      OutMessage::AttachToPlayer(ReconnectMessage {
+         lobby_id: &lobby_id,
+         player_id,
+      })
+   };
+   stranger.send(&attach(&player_id));
+   match stranger.get() {
+      InMessage::AttachToPlayerResponse(Err(ReconnectError::PlayerNotFound)) => (),
+      x => panic!("Expected PlayerNotFound, got {:?}", x),
+   }
+   stranger.send(&attach(&new_player_id));
+   match stranger.get() {
+      InMessage::AttachToPlayerResponse(r) => {
+         r.expect("Attaching with the new id failed");
+      }
+      x => panic!("Expected AttachToPlayerResponse, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn missed_events_can_be_caught_up_on() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   let join = |tc: &mut TestClient, player_name| {
+      tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+         lobby_id: &lobby_id,
+         player_name,
+         password: "",
+      }));
+      match tc.get() {
+         InMessage::JoinLobbyResponse(r) => r.expect("Joining lobby failed").journal_seq,
+         x => panic!("Expected JoinLobbyResponse, got {:?}", x),
+      }
+   };
+   let mut ada = TestClient::new();
+   let mut grace = TestClient::new();
+   assert_eq!(join(&mut ada, "Ada"), 0);
+   assert_eq!(join(&mut grace, "Grace"), 1);
+   let mut joins_seen = Vec::new();
+   while joins_seen.len() < 2 {
+      if let InMessage::PlayerJoinEvent(e) = tc.get() {
+         joins_seen.push((e.seq, e.new_player_name));
+      }
+   }
+
+   // Pretend to have missed Grace joining
+   tc.send(&OutMessage::CatchUp(CatchUpMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      since_seq: 1,
+   }));
+   let mut caught_up = Vec::new();
+   loop {
+      match tc.get() {
+         InMessage::PlayerJoinEvent(e) => caught_up.push((e.seq, e.new_player_name)),
+         InMessage::CatchUpResponse(r) => {
+            assert_eq!(r.expect("Catching up failed").latest_seq, 2);
+            break;
+         }
+         _ => continue,
+      }
+   }
+   assert_eq!(caught_up, joins_seen[1..]);
+   assert_eq!(caught_up, [(2, "Grace".to_owned())]);
+}
+
+#[test_case]
+fn admins_observe_several_lobbies_at_once() {
+   let mut first_owner = TestClient::new();
+   let (_, first_lobby_id) = first_owner.new_lobby_named("First");
+   let mut second_owner = TestClient::new();
+   let (_, second_lobby_id) = second_owner.new_lobby_named("Second");
+   let mut admin = TestClient::new();
+   let mut observe = |admin_key, lobby_id, kinds| {
+      admin.send(&OutMessage::ObserveLobby(ObserveLobbyMessage {
+         admin_key,
+         lobby_id,
+         kinds,
+      }));
+      match admin.get() {
+         InMessage::ObserveLobbyResponse(r) => r,
+         x => panic!("Expected ObserveLobbyResponse, got {:?}", x),
+      }
+   };
+   match observe("wrong key", &first_lobby_id, vec![]) {
+      Err(ObserveLobbyError::NotAdmin) => (),
+      x => panic!("Expected NotAdmin, got {:?}", x),
+   }
+   observe(ADMIN_KEY, &first_lobby_id, vec![]).expect("Observing failed");
+   observe(ADMIN_KEY, &second_lobby_id, vec![LobbyEventKind::Leave]).expect("Observing failed");
+
+   let join = |lobby_id| {
+      let mut tc = TestClient::new();
+      tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+         lobby_id,
+         player_name: "Guest",
+         password: "",
+      }));
+      match tc.get() {
+         InMessage::JoinLobbyResponse(r) => {
+            r.expect("Joining lobby failed");
+         }
+         x => panic!("Expected JoinLobbyResponse, got {:?}", x),
+      }
+      tc
+   };
+   let _first_guest = join(&first_lobby_id);
+   // Only its leaving is passed on
+   join(&second_lobby_id).disconnect();
+
+   let mut observed = Vec::new();
+   while observed.len() < 2 {
+      if let InMessage::ObservedLobbyEvent(e) = admin.get() {
+         observed.push(e);
+      }
+   }
+   assert_eq!(observed[0].lobby_id, first_lobby_id);
+   assert!(matches!(*observed[0].event, InMessage::PlayerJoinEvent(_)));
+   assert_eq!(observed[1].lobby_id, second_lobby_id);
+   assert!(matches!(*observed[1].event, InMessage::PlayerLeaveEvent(_)));
+
+   for expected in &[Ok(()), Err(StopObservingLobbyError::NotObserving)] {
+      admin.send(&OutMessage::StopObservingLobby(&first_lobby_id));
+      match admin.get() {
+         InMessage::StopObservingLobbyResponse(r) => assert_eq!(&r, expected),
+         x => panic!("Expected StopObservingLobbyResponse, got {:?}", x),
+      }
+   }
+}
+
+#[test_case]
+fn admins_announce_to_a_lobby() {
+   let mut tc = TestClient::new();
+   let (_, lobby_id) = tc.new_lobby();
+   let mut admin = TestClient::new();
+   let announce = |admin_key| {
+      OutMessage::Announce(AnnounceMessage {
+         admin_key,
+         lobby_id: Some(&lobby_id),
+         severity: AnnouncementSeverity::Warning,
+         text: "Restarting soon",
+      })
+   };
+
+   admin.send(&announce("not the admin key"));
+   match admin.get() {
+      InMessage::AnnounceResponse(Err(AnnounceError::NotAdmin)) => (),
+      x => panic!("Expected NotAdmin, got {:?}", x),
+   }
+   admin.send(&announce(ADMIN_KEY));
+   match admin.get() {
+      InMessage::AnnounceResponse(r) => r.expect("Announcing failed"),
+      x => panic!("Expected AnnounceResponse, got {:?}", x),
+   }
+   match tc.get() {
+      InMessage::ServerAnnouncementEvent(e) => {
+         assert_eq!(e.severity, AnnouncementSeverity::Warning);
+         assert_eq!(e.text, "Restarting soon");
+      }
+      x => panic!("Expected ServerAnnouncementEvent, got {:?}", x),
+   }
+}
+
+#[test_case]
+fn draining_servers_turn_away_new_players() {
+   let mut tc = TestClient::new();
+   let (_, lobby_id) = tc.new_lobby();
+   let mut admin = TestClient::new();
+   let set_drain_mode = |admin: &mut TestClient, admin_key, draining| {
+      admin.send(&OutMessage::SetDrainMode(SetDrainModeMessage {
+         admin_key,
+         draining,
+         shutdown_in_secs: None,
+      }));
+      // The event is broadcast to every connection, including the admin's
+      loop {
+         match admin.get() {
+            InMessage::SetDrainModeResponse(r) => break r,
+            InMessage::ServerDrainingEvent(_) => (),
+            x => panic!("Expected SetDrainModeResponse, got {:?}", x),
+         }
+      }
+   };
+   let join = |tc: &mut TestClient| {
+      tc.send(&OutMessage::JoinLobby(JoinLobbyMessage {
+         lobby_id: &lobby_id,
+         player_name: "TestClient",
+         password: "",
+      }));
+      match tc.get() {
+         InMessage::JoinLobbyResponse(r) => r,
+         x => panic!("Expected JoinLobbyResponse, got {:?}", x),
+      }
+   };
+
+   assert_eq!(
+      set_drain_mode(&mut admin, "not the admin key", true),
+      Err(SetDrainModeError::NotAdmin)
+   );
+   set_drain_mode(&mut admin, ADMIN_KEY, true).expect("Draining failed");
+   match tc.get() {
+      InMessage::ServerDrainingEvent(e) => {
+         assert!(e.draining);
+         assert_eq!(e.shutdown_in_secs, None);
+      }
+      x => panic!("Expected ServerDrainingEvent, got {:?}", x),
+   }
+
+   // Connections opened while draining are told straight away
+   let mut guest_tc = TestClient::new();
+   match guest_tc.get() {
+      InMessage::ServerDrainingEvent(e) => assert!(e.draining),
+      x => panic!("Expected ServerDrainingEvent, got {:?}", x),
+   }
+   guest_tc.send(&OutMessage::NewLobby(NewLobbyMessage {
+      player_name: "TestClient",
+      lobby_name: "TestLobby",
+      password: "",
+      max_players: 4,
+      turn_timer: 50,
+      hints: None,
+      practice: false,
+      scenario: None,
+      draw_rules: None,
+   }));
+   match guest_tc.get() {
+      InMessage::NewLobbyResponse(Err(NewLobbyError::ServerDraining)) => (),
+      x => panic!("Expected ServerDraining, got {:?}", x),
+   }
+   match join(&mut guest_tc) {
+      Err(JoinLobbyError::ServerDraining) => (),
+      x => panic!("Expected ServerDraining, got {:?}", x),
+   }
+
+   set_drain_mode(&mut admin, ADMIN_KEY, false).expect("Undraining failed");
+   match guest_tc.get() {
+      InMessage::ServerDrainingEvent(e) => assert!(!e.draining),
+      x => panic!("Expected ServerDrainingEvent, got {:?}", x),
+   }
+   join(&mut guest_tc).expect("Joining lobby failed");
+}
+
+#[test_case]
+fn admins_are_sent_server_stats() {
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   tc.send(&OutMessage::RequestAi(RequestAiMessage {
+      lobby_id: &lobby_id,
+      player_id: &player_id,
+      num_ai: 1,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::RequestAiResponse(r) => break r.expect("Requesting AI failed"),
+         InMessage::PlayerJoinEvent(_) => (),
+         x => panic!("Expected RequestAiResponse, got {:?}", x),
+      }
+   }
+
+   let mut admin = TestClient::new();
+   admin.send(&OutMessage::SubscribeServerStats(SubscribeServerStatsMessage {
+      admin_key: "not the admin key",
+   }));
+   match admin.get() {
+      InMessage::SubscribeServerStatsResponse(r) => assert_eq!(r, Err(SubscribeServerStatsError::NotAdmin)),
+      x => panic!("Expected SubscribeServerStatsResponse, got {:?}", x),
+   }
+   admin.send(&OutMessage::SubscribeServerStats(SubscribeServerStatsMessage {
+      admin_key: ADMIN_KEY,
+   }));
+   match admin.get() {
+      InMessage::SubscribeServerStatsResponse(r) => r.expect("Subscribing failed"),
+      x => panic!("Expected SubscribeServerStatsResponse, got {:?}", x),
+   }
+   // Other tests' lobbies may still be around, so only lower bounds are known
+   match admin.get() {
+      InMessage::ServerStatsEvent(stats) => {
+         assert!(stats.lobbies >= 1);
+         assert!(stats.players >= 2);
+         assert!(stats.ai_players >= 1);
+         assert!(stats.players > stats.ai_players);
+         assert!(stats.messages_per_sec > 0.0);
+      }
+      x => panic!("Expected ServerStatsEvent, got {:?}", x),
+   }
+}
+
 #[test_case]
 fn clandestine_bots_join_lobby() {
    let mut tc = TestClient::new();
@@ -232,14 +2414,260 @@ fn clandestine_bots_join_lobby() {
    let (_player_id, _lobby_id) = tc.new_lobby();
 
    timeout_ms(
-      move || loop {
-         match tc.get() {
-            InMessage::PlayerJoinEvent(_) => {
-               break;
+      move || match tc.get() {
+         InMessage::PlayerJoinEvent(_) => (),
+         _ => panic!(),
+      },
+      21000,
+   );
+}
+
+/// Plays as a human who resyncs by reconnecting whenever the server goes quiet
+#[cfg(feature = "chaos")]
+struct ResyncingPlayer {
+   tc: TestClient,
+   lobby_id: String,
+   player_id: String,
+   slot: Option<u8>,
+   hand: Vec<Card>,
+   state: Option<PublicGameState>,
+   play_seq: u32,
+}
+
+#[cfg(feature = "chaos")]
+impl ResyncingPlayer {
+   fn resync(&mut self) {
+      self.tc = TestClient::new();
+      self.tc.send(&OutMessage::Reconnect(ReconnectMessage {
+         lobby_id: &self.lobby_id,
+         player_id: &self.player_id,
+      }));
+      self.state = None;
+   }
+
+   /// Plays whenever it's our turn, until `max_plays` plays or the game ends, returning the placements if it ended.
+   /// A `reliable` connection must never go quiet, and our hand must always agree with the state
+   fn play(&mut self, max_plays: u32, reliable: bool) -> Option<Vec<u8>> {
+      let mut plays = 0;
+      while plays < max_plays {
+         match self.tc.get_timeout(Duration::from_secs(3)) {
+            Some(InMessage::GameStartEvent(e)) => {
+               self.slot = Some(e.turn_number);
+               self.hand = e.hand.into_vec();
+            }
+            Some(InMessage::HandEvent(hand)) => self.hand = hand,
+            Some(InMessage::PublicGameStateEvent(state)) => self.state = Some(state),
+            Some(InMessage::GameCompleteEvent(out_players)) => return Some(out_players),
+            Some(InMessage::TakeTurnResponse(r)) if reliable => r.expect("Play failed"),
+            Some(InMessage::ReconnectResponse(r)) if reliable => {
+               r.expect("Reconnecting failed");
             }
-            _ => panic!(),
+            Some(_) => (),
+            None => {
+               assert!(!reliable, "The server went quiet");
+               self.resync();
+            }
+         }
+         let (slot, state) = match (self.slot, &self.state) {
+            (Some(slot), Some(state)) if state.active_player == slot && !game_over(state) => (slot, state),
+            _ => continue,
+         };
+         if reliable {
+            assert_eq!(self.hand.len(), state.hands[slot as usize] as usize);
+         }
+         let cards = choose_turn(&self.hand, state);
+         self.play_seq += 1;
+         self.tc.send(&OutMessage::TakeTurn(TakeTurnMessage {
+            lobby_id: &self.lobby_id,
+            player_id: &self.player_id,
+            cards: &cards,
+            play_seq: Some(self.play_seq),
+         }));
+         // Wait to hear how it went before playing again
+         self.state = None;
+         plays += 1;
+      }
+      None
+   }
+}
+
+/// Turns chaos off even if the test fails, so that it doesn't spill into other tests
+#[cfg(feature = "chaos")]
+struct ChaosGuard;
+
+#[cfg(feature = "chaos")]
+impl Drop for ChaosGuard {
+   fn drop(&mut self) {
+      palace_server::chaos::set_chaos(None);
+   }
+}
+
+#[cfg(feature = "chaos")]
+#[test_case]
+fn game_recovers_from_connection_churn() {
+   use palace_server::chaos::{set_chaos, ChaosConfig};
+
+   const CHAOS_MAX_DELAY_MILLIS: u64 = 300;
+   // Each player has twelve cards and can play at most a pair at a time, so we can't finish in this many plays
+   const CHURN_PLAYS: u32 = 5;
+
+   let mut tc = TestClient::new();
+   let (player_id, lobby_id) = tc.new_lobby();
+   tc.send(&OutMessage::RequestAi(RequestAiMessage {
+      num_ai: 1,
+      player_id: &player_id,
+      lobby_id: &lobby_id,
+   }));
+   loop {
+      match tc.get() {
+         InMessage::RequestAiResponse(r) => break r.expect("Requesting AI failed"),
+         _ => continue,
+      }
+   }
+
+   let mut player = ResyncingPlayer {
+      tc,
+      lobby_id,
+      player_id,
+      slot: None,
+      hand: vec![],
+      state: None,
+      play_seq: 0,
+   };
+   timeout_ms(
+      move || {
+         let guard = ChaosGuard;
+         set_chaos(Some(ChaosConfig {
+            drop_chance: 0.1,
+            delay_chance: 0.1,
+            max_delay_millis: CHAOS_MAX_DELAY_MILLIS,
+            disconnect_chance: 0.02,
+         }));
+         player.tc.send(&OutMessage::StartGame(StartGameMessage {
+            player_id: &player.player_id,
+            lobby_id: &player.lobby_id,
+            fill_with_ai: None,
+         }));
+         let early_finish = player.play(CHURN_PLAYS, false);
+         assert!(early_finish.is_none(), "The game ended during the churn");
+         drop(guard);
+
+         // Let delayed messages land and our reconnect attempts age out before resyncing for good
+         std::thread::sleep(Duration::from_secs(10));
+         player.resync();
+         let out_players = player
+            .play(MAX_FULL_GAME_PLAYS, true)
+            .expect("The game should be over by now");
+         let state = player.state.expect("The final state should come before the game ends");
+         for (place, slot) in out_players.iter().enumerate() {
+            assert_eq!(state.placements[*slot as usize], Some(place as u8));
          }
       },
-      21000,
+      240000,
+   );
+}
+
+#[test_case]
+fn scheduled_events_seat_their_registrants() {
+   const EVENT_NAME: &str = "Test Nightly";
+   // A few seconds from now, so that the test needn't wait for a set time of day
+   let opens_at = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap()
+      .as_secs()
+      + 5;
+   let opens_at_utc = format!(
+      "{:02}:{:02}:{:02}",
+      opens_at / 3600 % 24,
+      opens_at / 60 % 60,
+      opens_at % 60
    );
+   let mut admin = TestClient::new();
+   admin.send(&OutMessage::ScheduleEvent(ScheduleEventMessage {
+      admin_key: ADMIN_KEY,
+      event: ScheduledEvent {
+         name: EVENT_NAME,
+         opens_at_utc: &opens_at_utc,
+         max_players: 2,
+      },
+   }));
+   match admin.get() {
+      InMessage::ScheduleEventResponse(r) => r.expect("Scheduling failed"),
+      x => panic!("Expected ScheduleEventResponse, got {:?}", x),
+   }
+
+   let register = |player_name| {
+      let mut tc = TestClient::new();
+      tc.send(&OutMessage::RegisterForEvent(RegisterForEventMessage {
+         event: EVENT_NAME,
+         player_name,
+      }));
+      match tc.get() {
+         InMessage::RegisterForEventResponse(r) => assert!(r.expect("Registering failed").opens_in_secs <= 5),
+         x => panic!("Expected RegisterForEventResponse, got {:?}", x),
+      }
+      tc
+   };
+   let mut owner = register("Owner");
+   let mut second = register("Second");
+   let mut third = register("Third");
+   admin.send(&OutMessage::ListEvents(()));
+   match admin.get() {
+      InMessage::ListEventsResponse(listings) => {
+         let listing = listings.iter().find(|x| x.name == EVENT_NAME).unwrap();
+         assert_eq!(listing.num_registered, 3);
+      }
+      x => panic!("Expected ListEventsResponse, got {:?}", x),
+   }
+
+   let opened = |tc: &mut TestClient| loop {
+      if let InMessage::EventOpenedEvent(e) = tc.get() {
+         assert_eq!(e.event, EVENT_NAME);
+         break e.lobby_id;
+      }
+   };
+   let seat_granted = |tc: &mut TestClient| loop {
+      if let InMessage::SeatGrantedEvent(r) = tc.get() {
+         break r.player_id;
+      }
+   };
+   let lobby_id = opened(&mut owner);
+   let owner_id = seat_granted(&mut owner);
+   assert_eq!(opened(&mut second), lobby_id);
+   seat_granted(&mut second);
+   assert_eq!(opened(&mut third), lobby_id);
+   loop {
+      if let InMessage::QueuePositionEvent(e) = third.get() {
+         assert_eq!(e.position, 1);
+         break;
+      }
+   }
+
+   // Leaving gives up the seat, even without having sent anything since the event opened
+   second.disconnect();
+   seat_granted(&mut third);
+   owner.send(&OutMessage::StartGame(StartGameMessage {
+      lobby_id: &lobby_id,
+      player_id: &owner_id,
+      fill_with_ai: None,
+   }));
+   loop {
+      if let InMessage::StartGameResponse(r) = owner.get() {
+         r.expect("Starting the event's game failed");
+         break;
+      }
+   }
+
+   for expected in &[Ok(()), Err(UnscheduleEventError::EventNotFound)] {
+      admin.send(&OutMessage::UnscheduleEvent(UnscheduleEventMessage {
+         admin_key: ADMIN_KEY,
+         name: EVENT_NAME,
+      }));
+      loop {
+         if let InMessage::UnscheduleEventResponse(r) = admin.get() {
+            assert_eq!(&r, expected);
+            break;
+         }
+      }
+   }
 }
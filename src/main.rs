@@ -1,12 +1,69 @@
 mod ai_tournament;
 
+use palace_server::commentary::{Commentator, EnglishCommentator};
+use std::sync::Arc;
+
 fn main() {
    pretty_env_logger::init();
    if std::env::args().any(|x| x == "--ai") {
       ai_tournament::go();
    } else if std::env::args().any(|x| x == "--monty") {
       ai_tournament::monty_report();
+   } else if std::env::args().any(|x| x == "--dump-schema") {
+      let schema = palace_server::data::protocol_schema();
+      println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+   } else if std::env::args().any(|x| x == "--dump-test-vectors") {
+      palace_server::test_vectors::write_all(std::path::Path::new("protocol_vectors")).unwrap();
    } else {
-      palace_server::run_server("0.0.0.0:3012");
+      palace_server::run_server_with_config(
+         "0.0.0.0:3012",
+         palace_server::ServerConfig {
+            admin_key: std::env::var("PALACE_ADMIN_KEY").ok(),
+            motd: std::env::var("PALACE_MOTD").ok(),
+            explain_ai_decisions: std::env::var("PALACE_EXPLAIN_AI").is_ok(),
+            policy_model_path: std::env::var_os("PALACE_POLICY_MODEL").map(Into::into),
+            metrics_address: std::env::var("PALACE_METRICS_ADDR").ok(),
+            overlay_address: std::env::var("PALACE_OVERLAY_ADDR").ok(),
+            overlay_delay_secs: std::env::var("PALACE_OVERLAY_DELAY_SECS")
+               .ok()
+               .and_then(|x| x.parse().ok())
+               .unwrap_or(30),
+            game_archive_path: std::env::var_os("PALACE_GAME_ARCHIVE").map(Into::into),
+            strict_protocol: std::env::var("PALACE_STRICT_PROTOCOL").is_ok(),
+            legacy_protocol: std::env::var("PALACE_LEGACY_PROTOCOL").is_ok(),
+            max_waiting_lobby_secs: std::env::var("PALACE_MAX_WAITING_LOBBY_SECS")
+               .ok()
+               .and_then(|x| x.parse().ok()),
+            max_lobby_lifetime_secs: std::env::var("PALACE_MAX_LOBBY_LIFETIME_SECS")
+               .ok()
+               .and_then(|x| x.parse().ok()),
+            max_lobbies: std::env::var("PALACE_MAX_LOBBIES").ok().and_then(|x| x.parse().ok()),
+            max_lobbies_per_address_per_hour: std::env::var("PALACE_MAX_LOBBIES_PER_ADDRESS_PER_HOUR")
+               .ok()
+               .and_then(|x| x.parse().ok()),
+            trusted_proxies: std::env::var("PALACE_TRUSTED_PROXIES")
+               .map(|x| x.split(',').filter_map(|x| x.trim().parse().ok()).collect())
+               .unwrap_or_default(),
+            bot_max_humans: std::env::var("PALACE_BOT_MAX_HUMANS").ok().and_then(|x| x.parse().ok()),
+            bot_max_load: std::env::var("PALACE_BOT_MAX_LOAD").ok().and_then(|x| x.parse().ok()),
+            ai_latency_budget_millis: std::env::var("PALACE_AI_LATENCY_BUDGET_MILLIS")
+               .ok()
+               .and_then(|x| x.parse().ok()),
+            degraded_ai_strength: std::env::var("PALACE_DEGRADED_AI_STRENGTH")
+               .ok()
+               .and_then(|x| x.parse().ok())
+               .unwrap_or_default(),
+            additional_addresses: std::env::var("PALACE_ADDITIONAL_ADDRESSES")
+               .map(|x| x.split(',').filter(|x| !x.is_empty()).map(str::to_owned).collect())
+               .unwrap_or_default(),
+            local_socket_path: std::env::var_os("PALACE_LOCAL_SOCKET").map(Into::into),
+            commentator: std::env::var_os("PALACE_COMMENTARY")
+               .map(|_| Arc::new(EnglishCommentator) as Arc<dyn Commentator>),
+            scheduled_events: std::env::var_os("PALACE_SCHEDULE")
+               .map(|x| palace_server::schedule::read(x.as_ref()).unwrap_or_else(|e| panic!("{}", e)))
+               .unwrap_or_default(),
+            max_game_turns: std::env::var("PALACE_MAX_GAME_TURNS").ok().and_then(|x| x.parse().ok()),
+         },
+      );
    }
 }